@@ -0,0 +1,314 @@
+//! Aggregator indexing the syscall events to Elasticsearch or OpenSearch through the `_bulk`
+//! API. Events are indexed to date based indices, whose names are rendered from the index
+//! name template by the timestamp of the event, e.g. `cubostratus-%Y.%m.%d`.
+//!
+//! The bulk requests are issued by the worker thread. Items rejected due to back pressure
+//! (`429`) or server errors are retried with exponential backoff, while items failing for
+//! other reasons, such as mapping conflicts, are dropped. Optionally, the index template
+//! with the mappings of the event fields is installed on start.
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use serde_json;
+use error::{Error, Result};
+use http::{Client, Url};
+use config::ElasticsearchConfig;
+use serializer::Encoded;
+use syscall::SyscallInfo;
+use super::Aggregator;
+
+/// upper bound of the delay between two consecutive retries
+const MAX_RETRY_BACKOFF_MS: u64 = 30000;
+
+/// index names and bodies of the documents
+type Docs = Vec<(String, Arc<[u8]>)>;
+
+/// Mappings of the event fields. Timestamps are mapped as dates, identifiers and names as
+/// keywords, and the syscall parameters by their JSON type, except for the IP addresses of
+/// the socket parameters, e.g. `params.tuple.sip`.
+const MAPPINGS: &'static str = r#"{
+    "dynamic_templates": [
+        { "params_ip": { "path_match": "params.*.ip", "mapping": { "type": "ip" } } },
+        { "params_sip": { "path_match": "params.*.sip", "mapping": { "type": "ip" } } },
+        { "params_dip": { "path_match": "params.*.dip", "mapping": { "type": "ip" } } },
+        { "params_numbers": { "path_match": "params.*", "match_mapping_type": "long", "mapping": { "type": "long" } } },
+        { "params_strings": { "path_match": "params.*", "match_mapping_type": "string", "mapping": { "type": "keyword", "ignore_above": 4096 } } },
+        { "strings": { "match_mapping_type": "string", "mapping": { "type": "keyword", "ignore_above": 1024 } } }
+    ],
+    "properties": {
+        "ts": { "type": "date" },
+        "tid": { "type": "long" },
+        "pid": { "type": "long" },
+        "vtid": { "type": "long" },
+        "vpid": { "type": "long" },
+        "cpu": { "type": "integer" },
+        "dir": { "type": "keyword" },
+        "comm": { "type": "keyword" },
+        "name": { "type": "keyword" },
+        "resolved_path": { "type": "keyword", "ignore_above": 4096 },
+        "user": { "properties": { "id": { "type": "long" }, "name": { "type": "keyword" } } },
+        "group": { "properties": { "id": { "type": "long" }, "name": { "type": "keyword" } } },
+        "ancestry": { "properties": { "pid": { "type": "long" }, "comm": { "type": "keyword" } } }
+    }
+}"#;
+
+#[derive(Deserialize)]
+struct BulkResponse {
+    errors: bool,
+    /// results of the items, keyed by the action
+    items: Vec<HashMap<String, BulkItem>>
+}
+
+#[derive(Deserialize)]
+struct BulkItem {
+    status: u16,
+    error: Option<serde_json::Value>
+}
+
+/// Whether the failed request or item is worth retrying.
+fn is_retryable(status: u16) -> bool {
+    status == 429 || status >= 500
+}
+
+/// Settings of the worker thread.
+struct Indexer {
+    /// URL of the `_bulk` endpoint
+    bulk_url: Url,
+    headers: Vec<(String, String)>,
+    client: Client,
+    max_retries: u32,
+    retry_backoff: Duration
+}
+
+impl Indexer {
+
+    /// Indexes the documents, given as the index name and the document, retrying the
+    /// rejected items.
+    fn index(&self, mut docs: Docs) {
+        let mut backoff = self.retry_backoff;
+        let mut attempt = 0;
+        loop {
+            let mut body = Vec::new();
+            for (index, doc) in &docs {
+                body.extend_from_slice(format!("{{\"index\":{{\"_index\":\"{}\"}}}}\n", index).as_bytes());
+                body.extend_from_slice(doc);
+                body.push(b'\n');
+            }
+            let error = match self.client.post(&self.bulk_url, &self.headers, &body) {
+                Ok((status, resp)) if (200..300).contains(&status) => {
+                    match serde_json::from_slice::<BulkResponse>(&resp) {
+                        Ok(ref bulk) if !bulk.errors => return,
+                        Ok(bulk) => {
+                            docs = self.rejected(docs, bulk);
+                            if docs.is_empty() {
+                                return;
+                            }
+                            format!("{} items rejected", docs.len())
+                        },
+                        Err(e) => {
+                            log_error!("unable to parse the bulk response: {}", e);
+                            return;
+                        }
+                    }
+                },
+                Ok((status, resp)) => {
+                    let error = format!("status {}: {}", status, String::from_utf8_lossy(&resp).trim());
+                    if !is_retryable(status) {
+                        log_error!("dropped {} events rejected by elasticsearch: {}", docs.len(), error);
+                        return;
+                    }
+                    error
+                },
+                Err(e) => e.to_string()
+            };
+            if attempt >= self.max_retries {
+                log_error!("dropped {} events after {} attempts: {}", docs.len(), attempt + 1, error);
+                return;
+            }
+            attempt += 1;
+            thread::sleep(backoff);
+            backoff = ::std::cmp::min(backoff * 2, Duration::from_millis(MAX_RETRY_BACKOFF_MS));
+        }
+    }
+
+    /// Returns the documents whose items are worth retrying. The items that failed
+    /// for good are dropped.
+    fn rejected(&self, docs: Docs, bulk: BulkResponse) -> Docs {
+        let mut retry = Vec::new();
+        let mut failed = 0;
+        let mut reason = None;
+        for (doc, item) in docs.into_iter().zip(bulk.items.iter()) {
+            let item = match item.values().next() {
+                Some(item) => item,
+                None => continue
+            };
+            if item.status < 300 {
+                continue;
+            }
+            if is_retryable(item.status) {
+                retry.push(doc);
+            } else {
+                failed += 1;
+                if reason.is_none() {
+                    reason = item.error.as_ref().map(|e| e.to_string());
+                }
+            }
+        }
+        if failed > 0 {
+            log_error!("dropped {} events rejected by elasticsearch: {}", failed, reason.unwrap_or_default());
+        }
+        retry
+    }
+}
+
+pub struct ElasticsearchAggregator {
+    /// elasticsearch configuration
+    config: ElasticsearchConfig,
+    /// index names and documents waiting to be indexed
+    batch: Docs,
+    /// size of the documents in the batch
+    batch_bytes: usize,
+    /// time the first document of the batch was added at
+    batch_started: Option<Instant>,
+    /// queue of the batches consumed by the worker
+    sender: Option<SyncSender<Docs>>,
+    worker: Option<JoinHandle<()>>,
+    /// events dropped since the queue became full
+    dropped: u64
+}
+
+impl Aggregator<Encoded> for ElasticsearchAggregator {
+
+    fn do_aggregate(&mut self, info: &SyscallInfo, body: Encoded) {
+        let body = body.json(info);
+        let index = info.ts.format(&self.config.index).to_string();
+        self.batch_bytes += index.len() + body.len();
+        self.batch.push((index, body));
+        if self.batch_started.is_none() {
+            self.batch_started = Some(Instant::now());
+        }
+        if self.batch.len() >= self.config.batch_size || self.batch_bytes >= self.config.batch_bytes {
+            self.flush();
+        }
+    }
+
+    fn poll(&mut self) {
+        let linger = Duration::from_millis(self.config.linger_ms);
+        match self.batch_started {
+            Some(started) if started.elapsed() >= linger => self.flush(),
+            _ => {}
+        }
+    }
+}
+
+impl ElasticsearchAggregator {
+
+    pub fn new(config: ElasticsearchConfig) -> ElasticsearchAggregator {
+        ElasticsearchAggregator {
+            config: config,
+            batch: Vec::new(),
+            batch_bytes: 0,
+            batch_started: None,
+            sender: None,
+            worker: None,
+            dropped: 0
+        }
+    }
+
+    /// Installs the index template, if configured, and spawns the worker thread.
+    pub fn start(&mut self) -> Result<()> {
+        let url = Url::parse(&self.config.url).map_err(|e| Error::ConfigParseError(e.to_string()))?;
+        let base = url.path.trim_end_matches('/').to_string();
+        let client = Client::new(self.config.ca_file.as_deref(),
+                                      Duration::from_secs(self.config.timeout))
+            .map_err(|e| Error::AggregatorError(format!("unable to set up the elasticsearch client: {}", e)))?;
+        let mut headers = vec![("Content-Type".to_string(), "application/x-ndjson".to_string())];
+        for (name, value) in &self.config.headers {
+            headers.push((name.clone(), value.clone()));
+        }
+        if let Some(ref name) = self.config.template {
+            let template_url = Url { path: format!("{}/_index_template/{}", base, name), ..url.clone() };
+            self.install_template(&client, &template_url, &headers)?;
+        }
+        let indexer = Indexer {
+            bulk_url: Url { path: format!("{}/_bulk", base), ..url },
+            headers: headers,
+            client: client,
+            max_retries: self.config.max_retries,
+            retry_backoff: Duration::from_millis(self.config.retry_backoff_ms)
+        };
+        let (sender, receiver) = mpsc::sync_channel(self.config.queue_size);
+        let worker = thread::Builder::new()
+            .name("elasticsearch-sink".to_string())
+            .spawn(move || work(indexer, receiver))
+            .map_err(|e| Error::AggregatorError(e.to_string()))?;
+        self.sender = Some(sender);
+        self.worker = Some(worker);
+        Ok(())
+    }
+
+    /// Puts the index template matching the indices the events are indexed to.
+    fn install_template(&self, client: &Client, url: &Url, headers: &[(String, String)]) -> Result<()> {
+        // ~ the pattern covers the indices of all dates
+        let prefix = self.config.index.split('%').next().unwrap_or("");
+        let template = format!("{{\"index_patterns\":[\"{}*\"],\"template\":{{\"mappings\":{}}}}}",
+                               prefix, MAPPINGS);
+        let headers = headers.iter()
+            .map(|(name, value)| if name == "Content-Type" {
+                (name.clone(), "application/json".to_string())
+            } else {
+                (name.clone(), value.clone())
+            })
+            .collect::<Vec<_>>();
+        match client.put(url, &headers, template.as_bytes()) {
+            Ok((status, _)) if (200..300).contains(&status) => Ok(()),
+            Ok((status, resp)) => Err(Error::AggregatorError(format!("unable to install the index template, \
+                                                                     status {}: {}", status,
+                                                                     String::from_utf8_lossy(&resp).trim()))),
+            Err(e) => Err(Error::AggregatorError(format!("unable to install the index template: {}", e)))
+        }
+    }
+
+    /// Hands the batch over to the worker.
+    pub fn flush(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        let batch = std::mem::take(&mut self.batch);
+        self.batch_bytes = 0;
+        self.batch_started = None;
+        if let Some(ref sender) = self.sender {
+            match sender.try_send(batch) {
+                Ok(()) => {
+                    if self.dropped > 0 {
+                        log_error!("dropped {} events while elasticsearch was falling behind", self.dropped);
+                        self.dropped = 0;
+                    }
+                },
+                Err(TrySendError::Full(batch)) | Err(TrySendError::Disconnected(batch)) => {
+                    self.dropped += batch.len() as u64;
+                }
+            }
+        }
+    }
+}
+
+/// Indexes the queued batches until the aggregator is dropped.
+fn work(indexer: Indexer, receiver: Receiver<Docs>) {
+    while let Ok(batch) = receiver.recv() {
+        indexer.index(batch);
+    }
+}
+
+impl Drop for ElasticsearchAggregator {
+    /// Indexes the pending events and waits for the worker to finish.
+    fn drop(&mut self) {
+        self.flush();
+        self.sender = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,408 @@
+//! Aggregator writing the stream of syscall events to newline delimited JSON files in the local
+//! directory. The events are appended to a hidden file, e.g. `.events.ndjson`, which is rotated
+//! once it grows beyond the size limit or gets older than the age limit. The rotated file is
+//! renamed to e.g. `events-20170301T120000-000003.ndjson`. When compressed, it's renamed to the
+//! hidden `.events-20170301T120000-000003.ndjson` instead, so the event loop isn't held up, and
+//! compressed in the background to `events-20170301T120000-000003.ndjson.gz`. The compressed
+//! file is written under a temporary hidden name and renamed once complete. Since the renames
+//! are atomic, log shippers watching the directory only ever see complete files. The rotated
+//! files whose compression was interrupted are compressed on the next start.
+//!
+//! Alternatively, the events are written as Avro object container files, e.g.
+//! `events-20170301T120000-000003.avro`, whose blocks are compressed by the Avro codec
+//! matching the configured compression.
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+use chrono::UTC;
+use flate2;
+use flate2::write::GzEncoder;
+use zstd;
+use config::FileConfig;
+use serializer::Encoded;
+use serializer::avro::{AvroSerializer, ObjectContainer};
+use syscall::SyscallInfo;
+use super::Aggregator;
+
+/// interval between two consecutive flushes of the buffered events to the file
+const FLUSH_INTERVAL_MS: u64 = 1000;
+/// size of the avro block written regardless of the flush interval
+const AVRO_BLOCK_BYTES: usize = 64 * 1024;
+
+/// rotated file waiting for its compression, and the path of the compressed file
+type Pending = (PathBuf, PathBuf);
+
+pub struct FileAggregator {
+    /// local event files configuration
+    config: FileConfig,
+    dir: PathBuf,
+    /// writer of the active file
+    writer: Option<BufWriter<File>>,
+    /// size of the active file
+    bytes: u64,
+    /// time the active file was opened at
+    opened_at: Option<Instant>,
+    /// time the buffered events were last flushed at
+    flushed_at: Instant,
+    /// sequence number of the next rotated file, continuing the sequence of the previous run
+    seq: u64,
+    /// encoder and the pending block of the events in the `avro` format
+    avro: Option<(AvroSerializer, ObjectContainer)>,
+    /// queue of the compression worker, absent unless the rotated files are compressed
+    compressor: Option<Sender<Pending>>,
+    worker: Option<JoinHandle<()>>
+}
+
+impl Aggregator<Encoded> for FileAggregator {
+
+    fn do_aggregate(&mut self, info: &SyscallInfo, body: Encoded) {
+        let result = if self.avro.is_some() {
+            self.append(info, &body)
+        } else {
+            self.write(&body.json(info))
+        };
+        if let Err(e) = result {
+            log_error!("unable to write the event to {}: {}", self.active_path().display(), e);
+        }
+    }
+
+    fn poll(&mut self) {
+        let max_age = Duration::from_secs(self.config.max_age);
+        match self.opened_at {
+            Some(opened_at) if self.config.max_age > 0 && opened_at.elapsed() >= max_age => {
+                if let Err(e) = self.rotate() {
+                    log_error!("unable to rotate {}: {}", self.active_path().display(), e);
+                }
+            },
+            _ => {}
+        }
+        if self.flushed_at.elapsed() >= Duration::from_millis(FLUSH_INTERVAL_MS) {
+            self.flushed_at = Instant::now();
+            if let Err(e) = self.write_block() {
+                log_error!("unable to write the events to {}: {}", self.active_path().display(), e);
+            }
+            if let Some(ref mut writer) = self.writer {
+                if let Err(e) = writer.flush() {
+                    log_error!("unable to flush the events: {}", e);
+                }
+            }
+        }
+    }
+}
+
+impl FileAggregator {
+
+    pub fn new(config: FileConfig) -> FileAggregator {
+        let dir = PathBuf::from(&config.dir);
+        let avro = if config.format == "avro" {
+            let serializer = AvroSerializer::new(None);
+            let codec = match config.compression.as_str() {
+                "gzip" => "deflate",
+                "zstd" => "zstandard",
+                _ => "null"
+            };
+            let container = ObjectContainer::new(serializer.schema(), codec);
+            Some((serializer, container))
+        } else {
+            None
+        };
+        FileAggregator {
+            config: config,
+            dir: dir,
+            writer: None,
+            bytes: 0,
+            opened_at: None,
+            flushed_at: Instant::now(),
+            seq: 0,
+            avro: avro,
+            compressor: None,
+            worker: None
+        }
+    }
+
+    /// Creates the event directory and starts the compression worker. The rotated files left
+    /// uncompressed by the previous run are queued, and its active file is rotated right away,
+    /// so its events aren't mixed with the events of this run.
+    pub fn start(&mut self) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut hidden = sequenced(&self.dir, &format!(".{}-", self.config.prefix), self.ext())?;
+        let last = self.rotated_files()?.into_iter().chain(hidden.iter().cloned()).map(|(seq, _)| seq).max();
+        self.seq = last.map_or(0, |seq| seq + 1);
+        // ~ the compression was interrupted, the worker writes the file again
+        let is = |path: &Path, ext: &str| path.extension().is_some_and(|e| e == ext);
+        for (_, path) in hidden.iter().filter(|(_, path)| is(path, "tmp")) {
+            fs::remove_file(path)?;
+        }
+        hidden.retain(|(_, path)| is(path, self.ext()));
+        if self.config.compression != "none" && self.avro.is_none() {
+            let (sender, receiver) = mpsc::channel();
+            let config = self.config.clone();
+            self.worker = Some(thread::Builder::new()
+                .name("file-compressor".to_string())
+                .spawn(move || work(config, receiver))?);
+            self.compressor = Some(sender);
+        }
+        for (_, path) in hidden {
+            let name = path.file_name().map(|n| n.to_string_lossy()[1..].to_string()).unwrap_or_default();
+            self.rotated(path, &name)?;
+        }
+        if self.active_path().exists() {
+            self.rotate()?;
+        }
+        self.enforce_retention();
+        Ok(())
+    }
+
+    fn ext(&self) -> &'static str {
+        if self.avro.is_some() { "avro" } else { "ndjson" }
+    }
+
+    fn active_path(&self) -> PathBuf {
+        self.dir.join(format!(".{}.{}", self.config.prefix, self.ext()))
+    }
+
+    /// Opens the active file, unless it's already open. The header of the avro file is
+    /// written to the new file.
+    fn open(&mut self) -> io::Result<()> {
+        if self.writer.is_some() {
+            return Ok(());
+        }
+        let file = OpenOptions::new().create(true).append(true).open(self.active_path())?;
+        self.bytes = file.metadata()?.len();
+        let mut writer = BufWriter::new(file);
+        if let (0, Some((_, container))) = (self.bytes, self.avro.as_ref()) {
+            let header = container.header();
+            writer.write_all(&header)?;
+            self.bytes = header.len() as u64;
+        }
+        self.writer = Some(writer);
+        self.opened_at = Some(Instant::now());
+        Ok(())
+    }
+
+    fn write(&mut self, body: &[u8]) -> io::Result<()> {
+        self.open()?;
+        if let Some(ref mut writer) = self.writer {
+            writer.write_all(body)?;
+            writer.write_all(b"\n")?;
+        }
+        self.bytes += body.len() as u64 + 1;
+        if self.bytes >= self.config.max_bytes {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    /// Adds the event to the pending avro block, which is written once it's full.
+    fn append(&mut self, info: &SyscallInfo, body: &Encoded) -> io::Result<()> {
+        let full = match self.avro {
+            Some((ref serializer, ref mut container)) => {
+                container.append(&body.get("avro", serializer, info));
+                container.block_bytes() >= AVRO_BLOCK_BYTES
+            },
+            None => false
+        };
+        if full {
+            self.write_block()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the pending avro block to the active file.
+    fn write_block(&mut self) -> io::Result<()> {
+        let block = match self.avro {
+            Some((_, ref mut container)) if !container.is_empty() => container.take_block()?,
+            _ => return Ok(())
+        };
+        self.open()?;
+        if let Some(ref mut writer) = self.writer {
+            writer.write_all(&block)?;
+        }
+        self.bytes += block.len() as u64;
+        if self.bytes >= self.config.max_bytes {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    /// Closes the active file and moves it to the next rotated file. Empty files are removed.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        self.bytes = 0;
+        self.opened_at = None;
+
+        let active = self.active_path();
+        if fs::metadata(&active)?.len() == 0 {
+            return fs::remove_file(&active);
+        }
+        let name = self.next_rotated_name();
+        match self.compressor {
+            // ~ the rotated file is hidden until it's compressed
+            Some(_) => {
+                let hidden = self.dir.join(format!(".{}", name));
+                fs::rename(&active, &hidden)?;
+                self.rotated(hidden, &name)
+            },
+            None => {
+                fs::rename(&active, self.dir.join(&name))?;
+                self.enforce_retention();
+                Ok(())
+            }
+        }
+    }
+
+    /// Hands the hidden rotated file over to the compression worker, or reveals it when the
+    /// rotated files aren't compressed.
+    fn rotated(&self, hidden: PathBuf, name: &str) -> io::Result<()> {
+        let ext = match self.config.compression.as_str() {
+            "gzip" => ".gz",
+            "zstd" => ".zst",
+            _ => ""
+        };
+        match self.compressor {
+            Some(ref sender) => {
+                let target = self.dir.join(format!("{}{}", name, ext));
+                sender.send((hidden, target))
+                    .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the compression worker is gone"))
+            },
+            None => fs::rename(&hidden, self.dir.join(name))
+        }
+    }
+
+    /// Returns the name of the next rotated file, without the extension of the compression.
+    fn next_rotated_name(&mut self) -> String {
+        let ext = match self.config.compression.as_str() {
+            _ if self.avro.is_some() => "",
+            "gzip" => ".gz",
+            "zstd" => ".zst",
+            _ => ""
+        };
+        let ts = UTC::now().format("%Y%m%dT%H%M%S");
+        loop {
+            let name = format!("{}-{}-{:06}.{}", self.config.prefix, ts, self.seq, self.ext());
+            self.seq += 1;
+            let taken = |name: &str| self.dir.join(name).exists();
+            if !taken(&format!("{}{}", name, ext)) && !taken(&format!(".{}", name)) {
+                return name;
+            }
+        }
+    }
+
+    fn rotated_files(&self) -> io::Result<Vec<(u64, PathBuf)>> {
+        sequenced(&self.dir, &format!("{}-", self.config.prefix), self.ext())
+    }
+
+    fn enforce_retention(&self) {
+        enforce_retention(&self.dir, &self.config, self.ext());
+    }
+}
+
+/// Returns the sequence numbers and the paths of the files named with the prefix, e.g. the
+/// rotated files, in the order they were rotated. Unlike the timestamps of the names, the
+/// sequence doesn't follow the clock when it's set back.
+fn sequenced(dir: &Path, prefix: &str, ext: &str) -> io::Result<Vec<(u64, PathBuf)>> {
+    let ext = format!(".{}", ext);
+    let mut files = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter_map(|path| {
+            // ~ e.g. `events-20170301T120000-000003.ndjson.gz`
+            let seq = {
+                let name = path.file_name().and_then(|n| n.to_str())?;
+                let rest = name.strip_prefix(prefix)?;
+                rest[..rest.find(&ext)?].rsplit('-').next()?.parse::<u64>().ok()?
+            };
+            Some((seq, path))
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+/// Removes the oldest rotated files beyond the file count limit, and the
+/// rotated files older than the retention period.
+fn enforce_retention(dir: &Path, config: &FileConfig, ext: &str) {
+    let rotated = match sequenced(dir, &format!("{}-", config.prefix), ext) {
+        Ok(rotated) => rotated.into_iter().map(|(_, path)| path).collect::<Vec<_>>(),
+        Err(e) => {
+            log_error!("unable to list {}: {}", dir.display(), e);
+            return;
+        }
+    };
+    let excess = if config.max_files > 0 && rotated.len() > config.max_files {
+        rotated.len() - config.max_files
+    } else {
+        0
+    };
+    let retention = Duration::from_secs(config.retention);
+    let now = SystemTime::now();
+    for (i, path) in rotated.iter().enumerate() {
+        let expired = config.retention > 0 && fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age > retention);
+        if i < excess || expired {
+            if let Err(e) = fs::remove_file(path) {
+                log_error!("unable to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Compresses the rotated files until the aggregator is dropped. A file failing to compress
+/// stays hidden, and is compressed again on the next start.
+fn work(config: FileConfig, receiver: Receiver<Pending>) {
+    let dir = PathBuf::from(&config.dir);
+    for (src, dst) in receiver {
+        // ~ the compressed file is hidden until it's complete
+        let name = dst.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let tmp = dir.join(format!(".{}.tmp", name));
+        let result = compress(&config.compression, &src, &tmp)
+            .and_then(|_| fs::rename(&tmp, &dst))
+            .and_then(|_| fs::remove_file(&src));
+        if let Err(e) = result {
+            log_error!("unable to compress {}: {}", src.display(), e);
+            let _ = fs::remove_file(&tmp);
+            continue;
+        }
+        // ~ only the ndjson files are compressed by the worker
+        enforce_retention(&dir, &config, "ndjson");
+    }
+}
+
+fn compress(compression: &str, src: &Path, dst: &Path) -> io::Result<()> {
+    let mut input = File::open(src)?;
+    let output = File::create(dst)?;
+    let output = if compression == "zstd" {
+        let mut encoder = zstd::stream::Encoder::new(output, 0)?;
+        io::copy(&mut input, &mut encoder)?;
+        encoder.finish()?
+    } else {
+        let mut encoder = GzEncoder::new(output, flate2::Compression::Default);
+        io::copy(&mut input, &mut encoder)?;
+        encoder.finish()?
+    };
+    output.sync_all()
+}
+
+impl Drop for FileAggregator {
+    /// Writes the pending avro block, the buffered events are flushed as the writer is dropped.
+    /// Waits for the worker to compress the rotated files.
+    fn drop(&mut self) {
+        if let Err(e) = self.write_block() {
+            log_error!("unable to write the events to {}: {}", self.active_path().display(), e);
+        }
+        self.compressor = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
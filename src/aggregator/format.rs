@@ -0,0 +1,199 @@
+//! Renders the syscall events as single lines of text, in the style of sysdig's output, e.g.
+//!
+//! ```text
+//! 10:32:01.000000000 3 cat (2817) < open fd=3 name=/etc/passwd
+//! ```
+//!
+//! The line is described by the format string, where the `%` prefixed fields are replaced
+//! with the attributes of the event, e.g. `%evt.type` with the name of the syscall. `%%`
+//! stands for the literal `%`. Attributes the event doesn't have are rendered as `<NA>`.
+use std::collections::HashMap;
+use error::{Error, Result};
+use syscall::{SyscallInfo, Direction};
+use syscall::syscall_table::SyscallTable;
+
+/// format of the line used when none is configured
+pub const DEFAULT_FORMAT: &'static str = "%evt.time %evt.cpu %proc.name (%thread.tid) %evt.dir %evt.type %evt.args";
+
+const NA: &'static str = "<NA>";
+
+enum Field {
+    /// time of the day the event was captured at
+    Time,
+    /// RFC 3339 date and time the event was captured at
+    DateTime,
+    Cpu,
+    /// `>` for enter and `<` for exit events
+    Dir,
+    Type,
+    /// all parameters as `name=value` pairs
+    Args,
+    /// single parameter referred by name
+    Arg(String),
+    ProcName,
+    Pid,
+    Vpid,
+    Tid,
+    Vtid,
+    UserName,
+    GroupName,
+    ContainerId,
+    ContainerName,
+    PodName,
+    /// absolute path of the file the syscall operates on
+    FdName
+}
+
+enum Token {
+    Literal(String),
+    Field(Field)
+}
+
+pub struct Formatter {
+    tokens: Vec<Token>,
+    /// names of the enter and exit parameters of each syscall in the order of the syscall
+    /// table, only looked up when the format renders `%evt.args`
+    params: HashMap<&'static str, [Vec<&'static str>; 2]>
+}
+
+fn parse_field(name: &str) -> Option<Field> {
+    if name.starts_with("evt.arg.") && name.len() > "evt.arg.".len() {
+        return Some(Field::Arg(name["evt.arg.".len()..].to_string()));
+    }
+    let field = match name {
+        "evt.time" => Field::Time,
+        "evt.datetime" => Field::DateTime,
+        "evt.cpu" => Field::Cpu,
+        "evt.dir" => Field::Dir,
+        "evt.type" => Field::Type,
+        "evt.args" => Field::Args,
+        "proc.name" => Field::ProcName,
+        "proc.pid" => Field::Pid,
+        "proc.vpid" => Field::Vpid,
+        "thread.tid" => Field::Tid,
+        "thread.vtid" => Field::Vtid,
+        "user.name" => Field::UserName,
+        "group.name" => Field::GroupName,
+        "container.id" => Field::ContainerId,
+        "container.name" => Field::ContainerName,
+        "k8s.pod.name" => Field::PodName,
+        "fd.name" => Field::FdName,
+        _ => return None
+    };
+    Some(field)
+}
+
+fn or_na<T: ToString>(v: Option<T>) -> String {
+    v.map(|v| v.to_string()).unwrap_or(NA.to_string())
+}
+
+impl Formatter {
+
+    /// Parses the format string. Fails if the format string refers to an unknown field.
+    pub fn new(format: &str) -> Result<Formatter> {
+        let mut tokens = Vec::new();
+        let mut literal = String::new();
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                literal.push(c);
+                continue;
+            }
+            if chars.peek() == Some(&'%') {
+                chars.next();
+                literal.push('%');
+                continue;
+            }
+            let mut name = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '.' || c == '_') {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            // ~ the trailing dot ends the sentence rather than the field
+            let dot = name.ends_with('.');
+            if dot {
+                name.pop();
+            }
+            match parse_field(&name) {
+                Some(field) => {
+                    if !literal.is_empty() {
+                        tokens.push(Token::Literal(literal.clone()));
+                        literal.clear();
+                    }
+                    tokens.push(Token::Field(field));
+                },
+                None => return Err(Error::ConfigParseError(format!("unknown format field `%{}`", name)))
+            }
+            if dot {
+                literal.push('.');
+            }
+        }
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(literal));
+        }
+        let mut params = HashMap::<&'static str, [Vec<&'static str>; 2]>::new();
+        if tokens.iter().any(|t| matches!(*t, Token::Field(Field::Args))) {
+            for (id, meta) in SyscallTable::default().iter().enumerate() {
+                // ~ even ids are the enter events, the first entry of the syscall wins
+                let entry = params.entry(meta.name).or_insert([Vec::new(), Vec::new()]);
+                if entry[id % 2].is_empty() {
+                    entry[id % 2] = meta.params.iter().map(|p| p.name).collect();
+                }
+            }
+        }
+        Ok(Formatter { tokens: tokens, params: params })
+    }
+
+    /// Renders the event as a line of text, without the line terminator.
+    pub fn format(&self, info: &SyscallInfo) -> String {
+        let mut line = String::new();
+        for token in &self.tokens {
+            match *token {
+                Token::Literal(ref s) => line.push_str(s),
+                Token::Field(ref field) => line.push_str(&self.render(field, info))
+            }
+        }
+        line
+    }
+
+    fn render(&self, field: &Field, info: &SyscallInfo) -> String {
+        match *field {
+            Field::Time => info.ts.format("%H:%M:%S%.9f").to_string(),
+            Field::DateTime => info.ts.to_rfc3339(),
+            Field::Cpu => info.cpu.to_string(),
+            Field::Dir => if info.dir == Direction::Enter { ">" } else { "<" }.to_string(),
+            Field::Type => info.name.clone(),
+            Field::Args => {
+                // ~ the parameters missing from the table follow in the order of their names
+                let dir = if info.dir == Direction::Enter { 0 } else { 1 };
+                let known = self.params.get(info.name.as_str()).map(|p| &p[dir][..]).unwrap_or(&[]);
+                let mut rest = info.params.keys()
+                    .map(|name| name.as_str())
+                    .filter(|name| !known.contains(name))
+                    .collect::<Vec<_>>();
+                rest.sort();
+                known.iter()
+                    .filter(|name| info.params.contains_key(**name))
+                    .chain(rest.iter())
+                    .map(|name| format!("{}={}", name, info.params[*name]))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            },
+            Field::Arg(ref name) => or_na(info.params.get(name)),
+            Field::ProcName => or_na(info.comm.as_ref()),
+            Field::Pid => or_na(info.pid),
+            Field::Vpid => or_na(info.vpid),
+            Field::Tid => info.tid.to_string(),
+            Field::Vtid => or_na(info.vtid),
+            Field::UserName => or_na(info.user.as_ref().map(|u| u.name.clone().unwrap_or(u.id.to_string()))),
+            Field::GroupName => or_na(info.group.as_ref().map(|g| g.name.clone().unwrap_or(g.id.to_string()))),
+            Field::ContainerId => or_na(info.container.as_ref().map(|c| &c.id)),
+            Field::ContainerName => or_na(info.container.as_ref().map(|c| &c.name)),
+            Field::PodName => or_na(info.pod.as_ref().map(|p| &p.name)),
+            Field::FdName => or_na(info.resolved_path.as_ref())
+        }
+    }
+}
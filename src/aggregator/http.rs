@@ -0,0 +1,241 @@
+//! Aggregator pushing batches of syscall events to an HTTP intake, such as the HTTP sources of
+//! log collectors. Each batch is sent as the body of a `POST` request, either as newline
+//! delimited JSON or as the JSON array, optionally gzip compressed.
+//!
+//! The requests are issued by a fixed number of worker threads, which bounds the number of
+//! concurrent requests. Requests failing with server errors, `429 Too Many Requests` or I/O
+//! errors such as timeouts are retried with exponential backoff. When all workers are busy
+//! and the queue of the pending batches is full, new batches are dropped rather than
+//! stalling the collector.
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use flate2;
+use flate2::write::GzEncoder;
+use http::{Client, Url};
+use config::HttpConfig;
+use serializer::Encoded;
+use syscall::SyscallInfo;
+use super::Aggregator;
+
+/// upper bound of the delay between two consecutive retries
+const MAX_RETRY_BACKOFF_MS: u64 = 30000;
+
+/// JSON bodies of the batched events
+type Batch = Vec<Arc<[u8]>>;
+
+/// Settings shared by the worker threads.
+struct Intake {
+    url: Url,
+    /// request headers, including the content type and encoding
+    headers: Vec<(String, String)>,
+    json_array: bool,
+    gzip: bool,
+    client: Client,
+    max_retries: u32,
+    retry_backoff: Duration
+}
+
+impl Intake {
+
+    /// Renders the events as the request body.
+    fn body(&self, batch: &[Arc<[u8]>]) -> io::Result<Vec<u8>> {
+        let mut body = Vec::new();
+        if self.json_array {
+            body.push(b'[');
+            for (i, event) in batch.iter().enumerate() {
+                if i > 0 {
+                    body.push(b',');
+                }
+                body.extend_from_slice(event);
+            }
+            body.push(b']');
+        } else {
+            for event in batch {
+                body.extend_from_slice(event);
+                body.push(b'\n');
+            }
+        }
+        if !self.gzip {
+            return Ok(body);
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::Default);
+        encoder.write_all(&body)?;
+        encoder.finish()
+    }
+
+    /// Posts the batch, retrying the transient failures.
+    fn send(&self, batch: &[Arc<[u8]>]) {
+        let body = match self.body(batch) {
+            Ok(body) => body,
+            Err(e) => {
+                log_error!("unable to encode {} events: {}", batch.len(), e);
+                return;
+            }
+        };
+        let mut backoff = self.retry_backoff;
+        let mut attempt = 0;
+        loop {
+            let error = match self.client.post(&self.url, &self.headers, &body) {
+                Ok((status, _)) if (200..300).contains(&status) => return,
+                Ok((status, resp)) => {
+                    let error = format!("status {}: {}", status, String::from_utf8_lossy(&resp).trim());
+                    if status < 500 && status != 429 {
+                        log_error!("dropped {} events rejected by {}: {}", batch.len(), self.url.host, error);
+                        return;
+                    }
+                    error
+                },
+                Err(e) => e.to_string()
+            };
+            if attempt >= self.max_retries {
+                log_error!("dropped {} events after {} attempts: {}", batch.len(), attempt + 1, error);
+                return;
+            }
+            attempt += 1;
+            thread::sleep(backoff);
+            backoff = ::std::cmp::min(backoff * 2, Duration::from_millis(MAX_RETRY_BACKOFF_MS));
+        }
+    }
+}
+
+pub struct HttpAggregator {
+    /// HTTP intake configuration
+    config: HttpConfig,
+    /// events waiting to be sent
+    batch: Batch,
+    /// size of the events in the batch
+    batch_bytes: usize,
+    /// time the first event of the batch was added at
+    batch_started: Option<Instant>,
+    /// queue of the batches consumed by the workers
+    sender: Option<SyncSender<Batch>>,
+    workers: Vec<JoinHandle<()>>,
+    /// events dropped since the queue became full
+    dropped: u64
+}
+
+impl Aggregator<Encoded> for HttpAggregator {
+
+    fn do_aggregate(&mut self, info: &SyscallInfo, body: Encoded) {
+        let body = body.json(info);
+        self.batch_bytes += body.len();
+        self.batch.push(body);
+        if self.batch_started.is_none() {
+            self.batch_started = Some(Instant::now());
+        }
+        if self.batch.len() >= self.config.batch_size || self.batch_bytes >= self.config.batch_bytes {
+            self.flush();
+        }
+    }
+
+    fn poll(&mut self) {
+        let linger = Duration::from_millis(self.config.linger_ms);
+        match self.batch_started {
+            Some(started) if started.elapsed() >= linger => self.flush(),
+            _ => {}
+        }
+    }
+}
+
+impl HttpAggregator {
+
+    pub fn new(config: HttpConfig) -> HttpAggregator {
+        HttpAggregator {
+            config: config,
+            batch: Vec::new(),
+            batch_bytes: 0,
+            batch_started: None,
+            sender: None,
+            workers: Vec::new(),
+            dropped: 0
+        }
+    }
+
+    /// Spawns the worker threads issuing the requests.
+    pub fn start(&mut self) -> io::Result<()> {
+        let mut headers = vec![("Content-Type".to_string(), if self.config.format == "json" {
+            "application/json".to_string()
+        } else {
+            "application/x-ndjson".to_string()
+        })];
+        if self.config.gzip {
+            headers.push(("Content-Encoding".to_string(), "gzip".to_string()));
+        }
+        for (name, value) in &self.config.headers {
+            headers.push((name.clone(), value.clone()));
+        }
+        let intake = Arc::new(Intake {
+            url: Url::parse(&self.config.url)?,
+            headers: headers,
+            json_array: self.config.format == "json",
+            gzip: self.config.gzip,
+            client: Client::new(self.config.ca_file.as_deref(),
+                                     Duration::from_secs(self.config.timeout))?,
+            max_retries: self.config.max_retries,
+            retry_backoff: Duration::from_millis(self.config.retry_backoff_ms)
+        });
+        let (sender, receiver) = mpsc::sync_channel(self.config.queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..self.config.concurrency {
+            let intake = intake.clone();
+            let receiver = receiver.clone();
+            let worker = thread::Builder::new()
+                .name(format!("http-sink-{}", i))
+                .spawn(move || work(intake, receiver))?;
+            self.workers.push(worker);
+        }
+        self.sender = Some(sender);
+        Ok(())
+    }
+
+    /// Hands the batch over to the workers.
+    pub fn flush(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        let batch = std::mem::take(&mut self.batch);
+        self.batch_bytes = 0;
+        self.batch_started = None;
+        if let Some(ref sender) = self.sender {
+            match sender.try_send(batch) {
+                Ok(()) => {
+                    if self.dropped > 0 {
+                        log_error!("dropped {} events while the http intake was falling behind", self.dropped);
+                        self.dropped = 0;
+                    }
+                },
+                Err(TrySendError::Full(batch)) | Err(TrySendError::Disconnected(batch)) => {
+                    self.dropped += batch.len() as u64;
+                }
+            }
+        }
+    }
+}
+
+/// Sends the queued batches until the aggregator is dropped.
+fn work(intake: Arc<Intake>, receiver: Arc<Mutex<Receiver<Batch>>>) {
+    loop {
+        let batch = match receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => return
+        };
+        match batch {
+            Ok(batch) => intake.send(&batch),
+            Err(_) => return
+        }
+    }
+}
+
+impl Drop for HttpAggregator {
+    /// Sends the pending events and waits for the workers to finish.
+    fn drop(&mut self) {
+        self.flush();
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
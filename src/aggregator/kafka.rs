@@ -0,0 +1,464 @@
+//! Aggregator emitting the stream of syscall events to Kafka brokers, optionally over TLS
+//! connections authenticated with SASL. The certificate of each broker is verified against
+//! the address the connection is made to, be it one of the configured hosts or the leader
+//! address learned from the metadata.
+//!
+//! The batches are produced by a worker thread fed by a bounded queue, which also reconnects
+//! to the brokers and replays the spool, so the collector doesn't wait on the brokers. When
+//! the queue is full, new batches are dropped.
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use openssl::ssl::{SslConnector, SslFiletype, SslMethod};
+use openssl::x509::X509;
+use openssl::x509::store::X509StoreBuilder;
+use config::{KafkaConfig, TlsConfig};
+use kafka::{Compression, Producer, Sasl, Settings, Undelivered};
+use serializer::{self, Encoded, Serializer, AvroSerializer};
+use spool::{Spool, SpoolStats};
+use syscall::SyscallInfo;
+use super::{Aggregator, hostname};
+
+/// bounds of the delay between two consecutive reconnection attempts
+const MIN_RECONNECT_BACKOFF_MS: u64 = 500;
+const MAX_RECONNECT_BACKOFF_MS: u64 = 60000;
+/// interval the worker checks the reconnection and the spool at while no batch is queued
+const POLL_INTERVAL_MS: u64 = 100;
+
+/// Counters of the aggregator and its worker.
+#[derive(Default)]
+struct Stats {
+    /// number of the events that were neither delivered nor spooled
+    dropped: u64,
+    /// depth and counters of the spool, if it's configured
+    spool: Option<SpoolStats>
+}
+
+/// Messages queued for the worker.
+enum Batch {
+    /// keys and values of the events
+    Events(Vec<(String, Arc<[u8]>)>),
+    /// records of the state snapshot
+    Snapshot(Vec<String>)
+}
+
+/// Producer of the worker thread. It sends the queued batches, reconnects to the brokers
+/// with backoff and replays the spool, so the collector never waits on the brokers.
+struct Sink {
+    /// an instance of the Kafka producer
+    producer: Option<Producer>,
+    /// kafka configuration
+    config: KafkaConfig,
+    /// name of the host used as message key
+    hostname: String,
+    /// spool buffering the messages while the brokers are unreachable
+    spool: Option<Spool>,
+    /// time of the next reconnection attempt
+    reconnect_at: Option<Instant>,
+    /// delay between the reconnection attempts, doubled on each failure
+    backoff: Duration,
+    stats: Arc<Mutex<Stats>>
+}
+
+impl Sink {
+
+    /// Sends the events. While the brokers are unreachable, or the spool still holds messages
+    /// that have to be delivered first, the events are spooled. So are the events of the
+    /// partitions whose leaders rejected them, while the ones the other partitions took aren't
+    /// sent again. Without the spool, events that couldn't be delivered are dropped.
+    fn send(&mut self, mut batch: Vec<(String, Arc<[u8]>)>) {
+        let spooling = self.spool.as_ref().is_some_and(|s| !s.is_empty());
+        if self.producer.is_some() && !spooling {
+            let topic = self.config.topic.clone();
+            match self.send_all(&topic, &batch) {
+                Ok(()) => return,
+                Err(undelivered) => {
+                    batch = undelivered.take(batch);
+                    self.disconnect(undelivered.error);
+                }
+            }
+        }
+        self.spool(batch);
+    }
+
+    /// Appends the undelivered messages to the spool, or drops them without the spool.
+    fn spool<B: AsRef<[u8]>>(&mut self, batch: Vec<(String, B)>) {
+        let dropped = match self.spool {
+            Some(ref mut spool) => match spool.append(&batch) {
+                Ok(()) => 0,
+                Err(e) => {
+                    log_error!("unable to spool {} events: {}", batch.len(), e);
+                    batch.len()
+                }
+            },
+            None => {
+                log_error!("dropped {} events while kafka is unavailable", batch.len());
+                batch.len()
+            }
+        };
+        self.stats.lock().unwrap().dropped += dropped as u64;
+    }
+
+    fn snapshot(&mut self, records: &[String]) {
+        if let Some(topic) = self.config.snapshot_topic.clone() {
+            // ~ keyed by the host so the records of the snapshot stay in order on one partition
+            for chunk in records.chunks(self.config.batch_size) {
+                let messages = chunk.iter()
+                    .map(|record| (self.hostname.clone(), record.clone().into_bytes()))
+                    .collect::<Vec<_>>();
+                if let Err(undelivered) = self.send_all(&topic, &messages) {
+                    log_error!("unable to emit the state snapshot: {}", undelivered.error);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Sends the messages, and returns the ones that weren't delivered.
+    fn send_all<B: AsRef<[u8]>>(&mut self, topic: &str, messages: &[(String, B)]) -> Result<(), Undelivered> {
+        match self.producer {
+            Some(ref mut p) => p.send(topic, messages),
+            None => Err(Undelivered::all(io::Error::new(io::ErrorKind::NotConnected, "kafka is unreachable"),
+                                         messages.len()))
+        }
+    }
+
+    /// Reconnects once the backoff elapses, replays a batch of the spool, and publishes the
+    /// counters of the spool.
+    fn poll(&mut self) {
+        match self.reconnect_at {
+            Some(at) if Instant::now() >= at => self.reconnect(),
+            _ => {}
+        }
+        self.replay();
+        if let Some(ref spool) = self.spool {
+            self.stats.lock().unwrap().spool = Some(spool.stats());
+        }
+    }
+
+    /// Returns how long the worker waits for the next batch. It doesn't while the spool
+    /// is being replayed.
+    fn idle(&self) -> Duration {
+        let replaying = self.producer.is_some() && self.spool.as_ref().is_some_and(|s| !s.is_empty());
+        if replaying {
+            Duration::from_millis(0)
+        } else {
+            Duration::from_millis(POLL_INTERVAL_MS)
+        }
+    }
+
+    /// Drops the producer and schedules the reconnection.
+    fn disconnect(&mut self, e: io::Error) {
+        log_error!("kafka is unavailable, retrying in {:?}: {}", self.backoff, e);
+        self.producer = None;
+        self.reconnect_at = Some(Instant::now() + self.backoff);
+    }
+
+    fn reconnect(&mut self) {
+        self.reconnect_at = None;
+        match self.connect() {
+            Ok(producer) => {
+                self.producer = Some(producer);
+                self.backoff = Duration::from_millis(MIN_RECONNECT_BACKOFF_MS);
+            },
+            Err(e) => {
+                let backoff = self.backoff * 2;
+                self.backoff = ::std::cmp::min(backoff, Duration::from_millis(MAX_RECONNECT_BACKOFF_MS));
+                self.disconnect(e);
+            }
+        }
+    }
+
+    /// Sends a batch of the spooled messages, in the order they were spooled. The messages
+    /// of the partitions whose leaders rejected them are spooled again, behind the others.
+    fn replay(&mut self) {
+        if self.producer.is_none() {
+            return;
+        }
+        let messages = match self.spool {
+            Some(ref mut spool) if !spool.is_empty() => {
+                match spool.peek(self.config.batch_size) {
+                    Ok(messages) => messages,
+                    Err(e) => {
+                        log_error!("unable to read the spool: {}", e);
+                        return;
+                    }
+                }
+            },
+            _ => return
+        };
+        let topic = self.config.topic.clone();
+        let count = messages.len();
+        let undelivered = match self.send_all(&topic, &messages) {
+            Ok(()) => Vec::new(),
+            Err(undelivered) => {
+                if undelivered.positions.len() == count {
+                    self.disconnect(undelivered.error);
+                    return;
+                }
+                let messages = undelivered.take(messages);
+                self.disconnect(undelivered.error);
+                messages
+            }
+        };
+        if let Some(ref mut spool) = self.spool {
+            if let Err(e) = spool.commit(count - undelivered.len()) {
+                log_error!("unable to save the replay position of the spool: {}", e);
+            }
+        }
+        if !undelivered.is_empty() {
+            self.spool(undelivered);
+        }
+    }
+
+    fn connect(&self) -> io::Result<Producer> {
+        let compression = match self.config.compression.as_str() {
+            "gzip" => Compression::Gzip,
+            "snappy" => Compression::Snappy,
+            _ => Compression::None
+        };
+        let required_acks = match self.config.required_acks.as_str() {
+            "none" => 0,
+            "all" => -1,
+            _ => 1
+        };
+        let tls = match self.config.tls {
+            Some(ref tls) => Some((connector(tls)?, tls.verify_hostname)),
+            None => None
+        };
+        let sasl = self.config.sasl.as_ref().map(|sasl| Sasl {
+            mechanism: sasl.mechanism.clone(),
+            username: sasl.username.clone(),
+            password: sasl.password.clone()
+        });
+        Producer::connect(Settings {
+            hosts: self.config.hosts.clone(),
+            client_id: "cubostratusc".to_string(),
+            tls: tls,
+            sasl: sasl,
+            required_acks: required_acks,
+            ack_timeout: (self.config.ack_timeout * 1000) as i32,
+            compression: compression,
+            timeout: Duration::from_secs(self.config.timeout)
+        })
+    }
+}
+
+/// Sends the queued batches and replays the spool until the aggregator is dropped.
+fn work(mut sink: Sink, receiver: Receiver<Batch>) {
+    loop {
+        match receiver.recv_timeout(sink.idle()) {
+            Ok(Batch::Events(batch)) => sink.send(batch),
+            Ok(Batch::Snapshot(records)) => sink.snapshot(&records),
+            Err(RecvTimeoutError::Timeout) => {},
+            Err(RecvTimeoutError::Disconnected) => return
+        }
+        sink.poll();
+    }
+}
+
+pub struct KafkaAggregator {
+    /// kafka configuration
+    config: KafkaConfig,
+    /// keys and values of the messages waiting to be sent
+    batch: Vec<(String, Arc<[u8]>)>,
+    /// size of the messages in the batch
+    batch_bytes: usize,
+    /// time the first message of the batch was added at
+    batch_started: Option<Instant>,
+    /// name of the host used as message key
+    hostname: String,
+    /// queue of the batches consumed by the worker
+    sender: Option<SyncSender<Batch>>,
+    worker: Option<JoinHandle<()>>,
+    /// counters shared with the worker
+    stats: Arc<Mutex<Stats>>,
+    /// encoder of the messages, absent for JSON, and the name of its format
+    serializer: Option<(&'static str, Box<Serializer + Send>)>
+}
+
+/// Implementation of the syscall's aggregator which emits the stream of syscall events
+/// to Kafka brokers. The events are batched, and the batch is handed over to the worker
+/// once it's full or its linger time elapses.
+impl Aggregator<Encoded> for KafkaAggregator {
+
+    fn do_aggregate(&mut self, info: &SyscallInfo, body: Encoded) {
+        // ~ messages with empty keys are spread over the partitions
+        let key = match self.config.partition_key.as_str() {
+            "host" => self.hostname.clone(),
+            "pid" => info.pid.map(|pid| pid.to_string()).unwrap_or_default(),
+            "container" => info.container.as_ref().map(|c| c.id.clone()).unwrap_or_default(),
+            _ => String::new()
+        };
+        let body = match self.serializer {
+            Some((format, ref serializer)) => body.get(format, &**serializer, info),
+            None => body.json(info)
+        };
+        self.batch_bytes += key.len() + body.len();
+        self.batch.push((key, body));
+        if self.batch_started.is_none() {
+            self.batch_started = Some(Instant::now());
+        }
+        if self.batch.len() >= self.config.batch_size || self.batch_bytes >= self.config.batch_bytes {
+            self.flush();
+        }
+    }
+
+    fn poll(&mut self) {
+        let linger = Duration::from_millis(self.config.linger_ms);
+        match self.batch_started {
+            Some(started) if started.elapsed() >= linger => self.flush(),
+            _ => {}
+        }
+    }
+
+    fn snapshot(&mut self, records: &[String]) {
+        if self.config.snapshot_topic.is_none() {
+            return;
+        }
+        if let Some(ref sender) = self.sender {
+            if sender.try_send(Batch::Snapshot(records.to_vec())).is_err() {
+                log_error!("dropped the state snapshot while kafka was falling behind");
+            }
+        }
+    }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        let mut stats = vec![("kafka.dropped", self.stats.lock().unwrap().dropped)];
+        if let Some(spool) = self.spool_stats() {
+            stats.extend_from_slice(&[("kafka.spool.segments", spool.segments as u64),
+                                      ("kafka.spool.bytes", spool.bytes),
+                                      ("kafka.spool.appended", spool.appended),
+                                      ("kafka.spool.replayed", spool.replayed),
+                                      ("kafka.spool.dropped_segments", spool.dropped_segments)]);
+        }
+        stats
+    }
+}
+
+impl KafkaAggregator {
+
+    pub fn new(config: KafkaConfig) -> KafkaAggregator {
+        // ~ the Confluent framing makes a format of its own for the other aggregators
+        let serializer: Option<(&'static str, Box<Serializer + Send>)> = match config.serializer.as_str() {
+            "json" => None,
+            "avro" if config.schema_id.is_some() =>
+                Some(("avro+confluent", Box::new(AvroSerializer::new(config.schema_id)))),
+            "avro" => Some(("avro", Box::new(AvroSerializer::new(None)))),
+            name => serializer::FORMATS.iter()
+                .find(|&&format| format == name)
+                .and_then(|&format| serializer::from_name(format).map(|s| (format, s)))
+        };
+        KafkaAggregator {
+            config: config,
+            batch: Vec::new(),
+            batch_bytes: 0,
+            batch_started: None,
+            hostname: hostname(),
+            sender: None,
+            worker: None,
+            stats: Arc::new(Mutex::new(Stats::default())),
+            serializer: serializer
+        }
+    }
+
+    /// Hands the batched messages over to the worker. The batches that don't fit the queue
+    /// are dropped.
+    pub fn flush(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        let batch = std::mem::take(&mut self.batch);
+        self.batch_bytes = 0;
+        self.batch_started = None;
+        let batch = match self.sender {
+            Some(ref sender) => match sender.try_send(Batch::Events(batch)) {
+                Ok(()) => return,
+                Err(TrySendError::Full(batch)) | Err(TrySendError::Disconnected(batch)) => batch
+            },
+            None => Batch::Events(batch)
+        };
+        if let Batch::Events(batch) = batch {
+            log_error!("dropped {} events while kafka was falling behind", batch.len());
+            self.stats.lock().unwrap().dropped += batch.len() as u64;
+        }
+    }
+
+    /// Returns the depth and the counters of the spool, if it's configured.
+    pub fn spool_stats(&self) -> Option<SpoolStats> {
+        self.stats.lock().unwrap().spool.clone()
+    }
+
+    /// Spawns the worker producing to the brokers. Without the spool the brokers have to be
+    /// reachable on start. With the spool, the aggregator starts even if they're unreachable,
+    /// and the worker keeps connecting in the background.
+    pub fn start(&mut self) -> io::Result<()> {
+        if let Some(ref path) = self.config.schema_file {
+            let mut file = File::create(path)?;
+            file.write_all(AvroSerializer::new(None).schema().as_bytes())?;
+        }
+        let mut sink = Sink {
+            producer: None,
+            config: self.config.clone(),
+            hostname: self.hostname.clone(),
+            spool: None,
+            reconnect_at: None,
+            backoff: Duration::from_millis(MIN_RECONNECT_BACKOFF_MS),
+            stats: self.stats.clone()
+        };
+        match self.config.spool {
+            Some(ref config) => {
+                let spool = Spool::open(config)?;
+                self.stats.lock().unwrap().spool = Some(spool.stats());
+                sink.spool = Some(spool);
+                sink.reconnect_at = Some(Instant::now());
+            },
+            None => sink.producer = Some(sink.connect()?)
+        }
+        let (sender, receiver) = mpsc::sync_channel(self.config.queue_size);
+        let worker = thread::Builder::new()
+            .name("kafka-sink".to_string())
+            .spawn(move || work(sink, receiver))?;
+        self.sender = Some(sender);
+        self.worker = Some(worker);
+        Ok(())
+    }
+}
+
+impl Drop for KafkaAggregator {
+    /// Hands the batched messages over to the worker, and waits for the worker to send or
+    /// spool them.
+    fn drop(&mut self) {
+        self.flush();
+        self.sender = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Builds the TLS connector of the broker connections. The broker certificates are verified
+/// against the CA bundle, and the client certificate is presented to the brokers requiring
+/// the client authentication.
+fn connector(tls: &TlsConfig) -> io::Result<SslConnector> {
+    let mut builder = SslConnector::builder(SslMethod::tls())?;
+    if let Some(ref path) = tls.ca_file {
+        // ~ the connector trusts the system CA certificates by default, which the bundle replaces
+        let mut pem = Vec::new();
+        File::open(path)?.read_to_end(&mut pem)?;
+        let mut store = X509StoreBuilder::new()?;
+        for cert in X509::stack_from_pem(&pem)? {
+            store.add_cert(cert)?;
+        }
+        builder.set_cert_store(store.build());
+    }
+    if let (Some(cert), Some(key)) = (&tls.cert_file, &tls.key_file) {
+        builder.set_certificate_chain_file(cert)?;
+        builder.set_private_key_file(key, SslFiletype::PEM)?;
+        builder.check_private_key()?;
+    }
+    Ok(builder.build())
+}
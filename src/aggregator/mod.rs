@@ -0,0 +1,58 @@
+//! Syscall's stream aggregators used to ingest the flow of syscall events from the collector
+//! to messaging systems and local files.
+use std::ffi::CStr;
+use libc;
+use syscall::SyscallInfo;
+
+pub mod kafka;
+pub mod file;
+pub mod parquet;
+pub mod stdout;
+pub mod socket;
+pub mod syslog;
+pub mod http;
+pub mod elasticsearch;
+pub mod format;
+
+pub use self::kafka::KafkaAggregator;
+pub use self::file::FileAggregator;
+pub use self::parquet::ParquetAggregator;
+pub use self::stdout::StdoutAggregator;
+pub use self::socket::SocketAggregator;
+pub use self::syslog::SyslogAggregator;
+pub use self::http::HttpAggregator;
+pub use self::elasticsearch::ElasticsearchAggregator;
+
+pub trait Aggregator<T> {
+
+    /// Ingests the syscall event along with its encoded body.
+    fn do_aggregate(&mut self, info: &SyscallInfo, body: T);
+
+    /// Gives the aggregator the chance to flush the buffered events
+    /// when there are no new events.
+    fn poll(&mut self) {}
+
+    /// Emits the records of the thread and process state snapshot, the header record
+    /// followed by the process records. Aggregators without a destination for the
+    /// snapshots ignore them.
+    fn snapshot(&mut self, _records: &[String]) {}
+
+    /// Returns the counters of the aggregator, e.g. the depth of its spool, named after
+    /// the aggregator. They're attached to the state snapshots.
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        Vec::new()
+    }
+}
+
+/// Returns the name of the host.
+fn hostname() -> String {
+    let mut buf = [0 as libc::c_char; 256];
+    unsafe {
+        if libc::gethostname(buf.as_mut_ptr(), buf.len()) != 0 {
+            return String::new();
+        }
+        // ~ the name isn't terminated if it's truncated
+        buf[buf.len() - 1] = 0;
+        CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned()
+    }
+}
@@ -0,0 +1,151 @@
+//! Aggregator writing the syscall events to Parquet files in the local directory, for the
+//! offline analysis with query engines such as DuckDB or Spark. The rows are written to a
+//! hidden file, e.g. `.events.parquet`, which is finished and renamed to
+//! e.g. `events-20170301T120000-000003.parquet` once it grows beyond the size limit or gets
+//! older than the age limit. The footer is appended after each row group, so the hidden file
+//! left by the crashed run is truncated after its last footer and rotated on start.
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use chrono::UTC;
+use config::ParquetConfig;
+use parquet::{self, ParquetWriter, Row};
+use serializer::Encoded;
+use syscall::SyscallInfo;
+use super::Aggregator;
+
+const FILE_EXT: &'static str = "parquet";
+
+pub struct ParquetAggregator {
+    /// parquet files configuration
+    config: ParquetConfig,
+    dir: PathBuf,
+    /// writer of the active file
+    writer: Option<ParquetWriter<BufWriter<File>>>,
+    /// time the active file was opened at
+    opened_at: Option<Instant>,
+    /// sequence number of the next rotated file
+    seq: u64
+}
+
+impl Aggregator<Encoded> for ParquetAggregator {
+
+    fn do_aggregate(&mut self, info: &SyscallInfo, _body: Encoded) {
+        if let Err(e) = self.write(&Row::from_info(info)) {
+            log_error!("unable to write the event to {}: {}", self.active_path().display(), e);
+        }
+    }
+
+    fn poll(&mut self) {
+        let max_age = Duration::from_secs(self.config.max_age);
+        match self.opened_at {
+            Some(opened_at) if self.config.max_age > 0 && opened_at.elapsed() >= max_age => {
+                if let Err(e) = self.rotate() {
+                    log_error!("unable to rotate {}: {}", self.active_path().display(), e);
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+impl ParquetAggregator {
+
+    pub fn new(config: ParquetConfig) -> ParquetAggregator {
+        let dir = PathBuf::from(&config.dir);
+        ParquetAggregator {
+            config: config,
+            dir: dir,
+            writer: None,
+            opened_at: None,
+            seq: 0
+        }
+    }
+
+    /// Creates the parquet directory and recovers the file left by the previous run.
+    pub fn start(&mut self) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let active = self.active_path();
+        if active.exists() {
+            self.recover(&active)?;
+        }
+        Ok(())
+    }
+
+    /// Truncates the file after its last footer, dropping the row group that was being
+    /// written, and rotates it. The file without any complete row group holds no rows,
+    /// and it's removed.
+    fn recover(&mut self, path: &Path) -> io::Result<()> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        match parquet::readable_len(&mut file)? {
+            Some(len) => {
+                file.set_len(len)?;
+                file.sync_all()?;
+                let target = self.next_rotated_path();
+                fs::rename(path, target)
+            },
+            None => fs::remove_file(path)
+        }
+    }
+
+    fn active_path(&self) -> PathBuf {
+        self.dir.join(format!(".{}.{}", self.config.prefix, FILE_EXT))
+    }
+
+    /// Adds the row to the active file, which is rotated once it exceeds the size limit.
+    pub fn write(&mut self, row: &Row) -> io::Result<()> {
+        if self.writer.is_none() {
+            let file = File::create(self.active_path())?;
+            self.writer = Some(ParquetWriter::new(BufWriter::new(file),
+                                                       &self.config.compression,
+                                                       self.config.row_group_size)?);
+            self.opened_at = Some(Instant::now());
+        }
+        let size = match self.writer {
+            Some(ref mut writer) => {
+                writer.write(row)?;
+                let (written, buffered) = writer.bytes();
+                written + buffered
+            },
+            None => 0
+        };
+        if size >= self.config.max_bytes {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    /// Finishes the active file and moves it to the next rotated file.
+    pub fn rotate(&mut self) -> io::Result<()> {
+        let writer = match self.writer.take() {
+            Some(writer) => writer,
+            None => return Ok(())
+        };
+        self.opened_at = None;
+        let out = writer.finish()?;
+        out.get_ref().sync_all()?;
+        let target = self.next_rotated_path();
+        fs::rename(self.active_path(), &target)
+    }
+
+    fn next_rotated_path(&mut self) -> PathBuf {
+        let ts = UTC::now().format("%Y%m%dT%H%M%S");
+        loop {
+            let path = self.dir.join(format!("{}-{}-{:06}.{}", self.config.prefix, ts, self.seq, FILE_EXT));
+            self.seq += 1;
+            if !path.exists() {
+                return path;
+            }
+        }
+    }
+}
+
+impl Drop for ParquetAggregator {
+    /// Finishes the active file, so the rows written so far are readable.
+    fn drop(&mut self) {
+        if let Err(e) = self.rotate() {
+            log_error!("unable to finish {}: {}", self.active_path().display(), e);
+        }
+    }
+}
@@ -0,0 +1,364 @@
+//! Aggregator streaming the syscall events to the clients connected over the unix domain
+//! socket or TCP listener. Right after connecting, the client sends the handshake line picking
+//! the format and the framing of the events, e.g.
+//!
+//! ```text
+//! format=json framing=length
+//! ```
+//!
+//! The format is either `json`, `text` or one of the binary formats `msgpack`, `cbor` and
+//! `protobuf`, and the framing either `newline`, where each event is terminated by the newline,
+//! or `length`, where each event is prefixed by its length as u32 in big endian. The binary
+//! formats require the `length` framing. Omitted settings default to `json` and `newline`. The server replies
+//! with `OK` or `ERR <reason>`, both terminated by the newline, and starts streaming the events.
+//!
+//! Each client has a bounded buffer of the events waiting to be written. When the client
+//! doesn't keep up and its buffer is full, it's either disconnected or its events are dropped
+//! until the buffer drains, depending on the slow client policy.
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::time::{Duration, Instant};
+use config::SocketConfig;
+use serializer::{Encoded, MsgpackSerializer, CborSerializer, ProtobufSerializer};
+use syscall::SyscallInfo;
+use super::Aggregator;
+use super::format::{Formatter, DEFAULT_FORMAT};
+
+/// maximum length of the handshake line
+const MAX_HANDSHAKE_LEN: usize = 256;
+/// interval between two consecutive writes of the buffered events
+const FLUSH_INTERVAL_MS: u64 = 10;
+/// size of the buffered events written regardless of the flush interval
+const FLUSH_BYTES: usize = 64 * 1024;
+
+enum Stream {
+    Unix(UnixStream),
+    Tcp(TcpStream)
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Unix(ref mut s) => s.read(buf),
+            Stream::Tcp(ref mut s) => s.read(buf)
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Unix(ref mut s) => s.write(buf),
+            Stream::Tcp(ref mut s) => s.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Stream::Unix(ref mut s) => s.flush(),
+            Stream::Tcp(ref mut s) => s.flush()
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Json,
+    Text,
+    Msgpack,
+    Cbor,
+    Protobuf
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Framing {
+    Newline,
+    Length
+}
+
+enum State {
+    /// waiting for the handshake line, accumulated in the buffer
+    Handshake(Vec<u8>),
+    Streaming(Format, Framing),
+    /// the pending reply is written before the client is disconnected
+    Closing
+}
+
+struct Client {
+    stream: Stream,
+    /// address of the peer used in the log messages
+    peer: String,
+    state: State,
+    connected_at: Instant,
+    /// bytes waiting to be written
+    out: VecDeque<u8>,
+    /// events dropped due to the full buffer
+    dropped: u64
+}
+
+/// Parses the handshake line.
+fn parse_handshake(line: &str) -> Result<(Format, Framing), String> {
+    let mut format = Format::Json;
+    let mut framing = Framing::Newline;
+    for setting in line.split_whitespace() {
+        let mut kv = setting.splitn(2, '=');
+        match (kv.next(), kv.next()) {
+            (Some("format"), Some("json")) => format = Format::Json,
+            (Some("format"), Some("text")) => format = Format::Text,
+            (Some("format"), Some("msgpack")) => format = Format::Msgpack,
+            (Some("format"), Some("cbor")) => format = Format::Cbor,
+            (Some("format"), Some("protobuf")) => format = Format::Protobuf,
+            (Some("framing"), Some("newline")) => framing = Framing::Newline,
+            (Some("framing"), Some("length")) => framing = Framing::Length,
+            _ => return Err(format!("unknown setting `{}`", setting))
+        }
+    }
+    let binary = format != Format::Json && format != Format::Text;
+    if binary && framing != Framing::Length {
+        return Err("binary formats require the length framing".to_string());
+    }
+    Ok((format, framing))
+}
+
+impl Client {
+
+    fn new(stream: Stream, peer: String) -> Client {
+        Client {
+            stream: stream,
+            peer: peer,
+            state: State::Handshake(Vec::new()),
+            connected_at: Instant::now(),
+            out: VecDeque::new(),
+            dropped: 0
+        }
+    }
+
+    /// Reads the handshake line. Returns `false` if the client should be disconnected.
+    fn read_handshake(&mut self, timeout: Duration) -> bool {
+        let mut buf = [0u8; MAX_HANDSHAKE_LEN];
+        let line = match self.state {
+            State::Handshake(ref mut line) => {
+                match self.stream.read(&mut buf) {
+                    Ok(0) => return false,
+                    Ok(n) => line.extend_from_slice(&buf[..n]),
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {},
+                    Err(_) => return false
+                }
+                match line.iter().position(|&b| b == b'\n') {
+                    Some(end) => String::from_utf8_lossy(&line[..end]).trim().to_string(),
+                    None if line.len() >= MAX_HANDSHAKE_LEN => {
+                        log_error!("handshake of client {} is too long", self.peer);
+                        return false;
+                    },
+                    None => {
+                        if self.connected_at.elapsed() >= timeout {
+                            log_error!("client {} didn't complete the handshake in time", self.peer);
+                            return false;
+                        }
+                        return true;
+                    }
+                }
+            },
+            _ => return true
+        };
+        match parse_handshake(&line) {
+            Ok((format, framing)) => {
+                self.out.extend(b"OK\n");
+                self.state = State::Streaming(format, framing);
+            },
+            Err(e) => {
+                log_error!("rejected client {}: {}", self.peer, e);
+                self.out.extend(format!("ERR {}\n", e).as_bytes());
+                self.state = State::Closing;
+            }
+        }
+        true
+    }
+
+    /// Writes the buffered bytes without blocking. Returns `false` if the client should be
+    /// disconnected, either because the connection broke or the client is being closed.
+    fn write_pending(&mut self) -> bool {
+        while !self.out.is_empty() {
+            let written = {
+                let (head, _) = self.out.as_slices();
+                self.stream.write(head)
+            };
+            match written {
+                Ok(0) => return false,
+                Ok(n) => { self.out.drain(..n); },
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return true,
+                Err(_) => return false
+            }
+        }
+        !matches!(self.state, State::Closing)
+    }
+}
+
+pub struct SocketAggregator {
+    /// socket streaming configuration
+    config: SocketConfig,
+    unix: Option<UnixListener>,
+    tcp: Option<TcpListener>,
+    clients: Vec<Client>,
+    /// formatter of the events in the `text` format
+    formatter: Formatter,
+    flushed_at: Instant
+}
+
+impl Aggregator<Encoded> for SocketAggregator {
+
+    fn do_aggregate(&mut self, info: &SyscallInfo, body: Encoded) {
+        // ~ the event is only encoded in the formats some client asked for
+        let (json, text, msgpack, cbor, protobuf) = {
+            let wants = |format| self.clients.iter().any(|c| match c.state {
+                State::Streaming(f, _) => f == format,
+                _ => false
+            });
+            (if wants(Format::Json) { Some(body.json(info)) } else { None },
+             if wants(Format::Text) { Some(self.formatter.format(info).into_bytes().into()) } else { None },
+             if wants(Format::Msgpack) { Some(body.get("msgpack", &MsgpackSerializer, info)) } else { None },
+             if wants(Format::Cbor) { Some(body.get("cbor", &CborSerializer, info)) } else { None },
+             if wants(Format::Protobuf) { Some(body.get("protobuf", &ProtobufSerializer, info)) } else { None })
+        };
+        let max_bytes = self.config.buffer_bytes;
+        let drop_events = self.config.slow_client == "drop";
+        let mut slow = Vec::new();
+        for (i, client) in self.clients.iter_mut().enumerate() {
+            let (event, framing) = match client.state {
+                State::Streaming(format, framing) => {
+                    let event = match format {
+                        Format::Json => json.as_ref(),
+                        Format::Text => text.as_ref(),
+                        Format::Msgpack => msgpack.as_ref(),
+                        Format::Cbor => cbor.as_ref(),
+                        Format::Protobuf => protobuf.as_ref()
+                    };
+                    match event {
+                        Some(event) => (&event[..], framing),
+                        None => continue
+                    }
+                },
+                _ => continue
+            };
+            let len = event.len() + if framing == Framing::Length { 4 } else { 1 };
+            if client.out.len() + len > max_bytes {
+                if drop_events {
+                    client.dropped += 1;
+                } else {
+                    slow.push(i);
+                }
+                continue;
+            }
+            if client.dropped > 0 {
+                log_error!("dropped {} events of slow client {}", client.dropped, client.peer);
+                client.dropped = 0;
+            }
+            if framing == Framing::Length {
+                let n = event.len() as u32;
+                client.out.extend(&[(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+                client.out.extend(event);
+            } else {
+                client.out.extend(event);
+                client.out.push_back(b'\n');
+            }
+        }
+        for i in slow.into_iter().rev() {
+            let client = self.clients.remove(i);
+            log_error!("disconnected slow client {}", client.peer);
+        }
+    }
+
+    fn poll(&mut self) {
+        self.accept();
+        let timeout = Duration::from_secs(self.config.handshake_timeout);
+        let flush = self.flushed_at.elapsed() >= Duration::from_millis(FLUSH_INTERVAL_MS);
+        if flush {
+            self.flushed_at = Instant::now();
+        }
+        let mut i = 0;
+        while i < self.clients.len() {
+            let alive = {
+                let client = &mut self.clients[i];
+                client.read_handshake(timeout) &&
+                    (!flush && client.out.len() < FLUSH_BYTES || client.write_pending())
+            };
+            if alive {
+                i += 1;
+            } else {
+                let client = self.clients.remove(i);
+                log_error!("client {} disconnected", client.peer);
+            }
+        }
+    }
+}
+
+impl SocketAggregator {
+
+    pub fn new(config: SocketConfig) -> SocketAggregator {
+        SocketAggregator {
+            config: config,
+            unix: None,
+            tcp: None,
+            clients: Vec::new(),
+            formatter: Formatter::new(DEFAULT_FORMAT).unwrap(),
+            flushed_at: Instant::now()
+        }
+    }
+
+    /// Binds the listeners. The socket file left by the previous run is replaced.
+    pub fn start(&mut self) -> io::Result<()> {
+        if let Some(ref path) = self.config.unix_path {
+            if fs::symlink_metadata(path).map(|m| m.file_type().is_socket()).unwrap_or(false) {
+                fs::remove_file(path)?;
+            }
+            let listener = UnixListener::bind(path)?;
+            listener.set_nonblocking(true)?;
+            self.unix = Some(listener);
+        }
+        if let Some(ref addr) = self.config.tcp_addr {
+            let listener = TcpListener::bind(addr.as_str())?;
+            listener.set_nonblocking(true)?;
+            self.tcp = Some(listener);
+        }
+        Ok(())
+    }
+
+    fn accept(&mut self) {
+        let mut accepted = Vec::new();
+        if let (Some(listener), Some(ref path)) = (self.unix.as_ref(), self.config.unix_path.as_ref()) {
+            while let Ok((stream, _)) = listener.accept() {
+                if stream.set_nonblocking(true).is_ok() {
+                    accepted.push(Client::new(Stream::Unix(stream), path.to_string()));
+                }
+            }
+        }
+        if let Some(ref listener) = self.tcp {
+            while let Ok((stream, addr)) = listener.accept() {
+                if stream.set_nonblocking(true).is_ok() {
+                    accepted.push(Client::new(Stream::Tcp(stream), addr.to_string()));
+                }
+            }
+        }
+        for mut client in accepted {
+            if self.config.max_clients > 0 && self.clients.len() >= self.config.max_clients {
+                log_error!("rejected client {}: too many clients", client.peer);
+                client.out.extend(b"ERR too many clients\n");
+                client.write_pending();
+                continue;
+            }
+            self.clients.push(client);
+        }
+    }
+}
+
+impl Drop for SocketAggregator {
+    fn drop(&mut self) {
+        if let (Some(_), Some(ref path)) = (self.unix.take(), self.config.unix_path.as_ref()) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Aggregator printing the stream of syscall events to the standard output, either as
+//! newline delimited JSON or as lines of text rendered by the [Formatter](../format/struct.
+//! Formatter.html). Useful for watching the events interactively while debugging.
+use std::io::{self, Write};
+use error::Result;
+use config::StdoutConfig;
+use serializer::Encoded;
+use syscall::SyscallInfo;
+use super::Aggregator;
+use super::format::{Formatter, DEFAULT_FORMAT};
+
+pub struct StdoutAggregator {
+    /// standard output configuration
+    config: StdoutConfig,
+    /// formatter of the text lines, absent in JSON mode
+    formatter: Option<Formatter>
+}
+
+impl Aggregator<Encoded> for StdoutAggregator {
+
+    fn do_aggregate(&mut self, info: &SyscallInfo, body: Encoded) {
+        let line = match self.formatter {
+            Some(ref formatter) => formatter.format(info).into_bytes(),
+            None => body.json(info).to_vec()
+        };
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        // ~ a closed pipe, e.g. `cubostratusc | head`, isn't worth reporting per event
+        let _ = out.write_all(&line).and_then(|_| out.write_all(b"\n"));
+    }
+}
+
+impl StdoutAggregator {
+
+    pub fn new(config: StdoutConfig) -> StdoutAggregator {
+        StdoutAggregator {
+            config: config,
+            formatter: None
+        }
+    }
+
+    /// Parses the format string of the text mode.
+    pub fn start(&mut self) -> Result<()> {
+        if self.config.format == "text" {
+            let format = self.config.template.clone().unwrap_or(DEFAULT_FORMAT.to_string());
+            self.formatter = Some(Formatter::new(&format)?);
+        }
+        Ok(())
+    }
+}
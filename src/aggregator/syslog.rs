@@ -0,0 +1,294 @@
+//! Aggregator sending the syscall events to syslog as RFC 5424 messages, e.g.
+//!
+//! ```text
+//! <14>1 2017-03-01T10:32:01.000000Z host cubostratusc 812 open [syscall@32473 tid="2817"
+//! pid="2817" comm="cat" category="file" path="/etc/passwd"] 10:32:01.000000000 3 cat ...
+//! ```
+//!
+//! The messages go to the local syslog daemon over the `/dev/log` socket, or to the remote
+//! server over UDP or TCP, where the messages are framed by octet counting. The facility and
+//! severity of the message are picked by the category of the syscall. Events whose severity
+//! is below the minimum severity aren't sent, so syslog can be limited to the alerts.
+//!
+//! When sending fails the socket is dropped and recreated with exponential backoff, whatever
+//! the transport, so a restarted syslog daemon or server is picked up again. The events are
+//! dropped meanwhile, and their count is reported once the socket is back.
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::os::unix::net::UnixDatagram;
+use std::time::{Duration, Instant};
+use libc;
+use error::{Error, Result};
+use config::SyslogConfig;
+use serializer::Encoded;
+use syscall::{SyscallInfo, Direction, CATEGORIES};
+use super::{Aggregator, hostname};
+use super::format::{Formatter, DEFAULT_FORMAT};
+
+/// identifier of the structured data element holding the event attributes, under the
+/// private enterprise number reserved for documentation
+const SD_ID: &'static str = "syscall@32473";
+const MIN_RECONNECT_BACKOFF_MS: u64 = 500;
+const MAX_RECONNECT_BACKOFF_MS: u64 = 60000;
+
+const FACILITIES: &'static [(&'static str, u8)] = &[
+    ("kern", 0), ("user", 1), ("mail", 2), ("daemon", 3), ("auth", 4), ("syslog", 5),
+    ("lpr", 6), ("news", 7), ("uucp", 8), ("cron", 9), ("authpriv", 10), ("ftp", 11),
+    ("local0", 16), ("local1", 17), ("local2", 18), ("local3", 19),
+    ("local4", 20), ("local5", 21), ("local6", 22), ("local7", 23)
+];
+
+const SEVERITIES: &'static [(&'static str, u8)] = &[
+    ("emerg", 0), ("alert", 1), ("crit", 2), ("err", 3),
+    ("warning", 4), ("notice", 5), ("info", 6), ("debug", 7)
+];
+
+fn code(codes: &[(&str, u8)], kind: &str, name: &str) -> Result<u8> {
+    codes.iter()
+        .find(|&&(n, _)| n == name)
+        .map(|&(_, code)| code)
+        .ok_or(Error::ConfigParseError(format!("unknown syslog {} `{}`", kind, name)))
+}
+
+/// Escapes the characters RFC 5424 reserves in the structured data parameter values.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == '"' || c == '\\' || c == ']' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Substitutes the NILVALUE to the empty header fields.
+fn nil(value: &str) -> &str {
+    if value.is_empty() { "-" } else { value }
+}
+
+enum Transport {
+    Unix(UnixDatagram),
+    Udp(UdpSocket),
+    Tcp(TcpStream)
+}
+
+pub struct SyslogAggregator {
+    /// syslog configuration
+    config: SyslogConfig,
+    /// the socket is absent while syslog is unreachable
+    transport: Option<Transport>,
+    /// facilities and severities indexed by the category name
+    facilities: HashMap<String, u8>,
+    severities: HashMap<String, u8>,
+    facility: u8,
+    severity: u8,
+    min_severity: u8,
+    /// formatter of the message text
+    formatter: Formatter,
+    hostname: String,
+    procid: String,
+    /// time of the next reconnection attempt
+    reconnect_at: Option<Instant>,
+    backoff: Duration,
+    /// messages dropped while syslog was unreachable, since the socket was lost and overall
+    dropped: u64,
+    total_dropped: u64
+}
+
+impl Aggregator<Encoded> for SyslogAggregator {
+
+    fn do_aggregate(&mut self, info: &SyscallInfo, body: Encoded) {
+        let category = info.category.name();
+        let severity = self.severities.get(category).cloned().unwrap_or(self.severity);
+        if severity > self.min_severity {
+            return;
+        }
+        let facility = self.facilities.get(category).cloned().unwrap_or(self.facility);
+        let text = if self.config.message == "json" {
+            String::from_utf8_lossy(&body.json(info)).into_owned()
+        } else {
+            self.formatter.format(info)
+        };
+        let message = self.message(info, facility * 8 + severity, &text);
+        self.send(message.as_bytes());
+    }
+
+    fn poll(&mut self) {
+        match self.reconnect_at {
+            Some(at) if Instant::now() >= at => self.reconnect(),
+            _ => {}
+        }
+    }
+
+    fn stats(&self) -> Vec<(&'static str, u64)> {
+        vec![("syslog.dropped", self.total_dropped)]
+    }
+}
+
+impl SyslogAggregator {
+
+    pub fn new(config: SyslogConfig) -> SyslogAggregator {
+        let hostname = config.hostname.clone().unwrap_or_else(hostname);
+        SyslogAggregator {
+            config: config,
+            transport: None,
+            facilities: HashMap::new(),
+            severities: HashMap::new(),
+            facility: 0,
+            severity: 0,
+            min_severity: 0,
+            formatter: Formatter::new(DEFAULT_FORMAT).unwrap(),
+            hostname: hostname,
+            procid: unsafe { libc::getpid() }.to_string(),
+            reconnect_at: None,
+            backoff: Duration::from_millis(MIN_RECONNECT_BACKOFF_MS),
+            dropped: 0,
+            total_dropped: 0
+        }
+    }
+
+    /// Resolves the facility and severity names, and connects to the syslog daemon.
+    pub fn start(&mut self) -> Result<()> {
+        self.facility = code(FACILITIES, "facility", &self.config.facility)?;
+        self.severity = code(SEVERITIES, "severity", &self.config.severity)?;
+        self.min_severity = code(SEVERITIES, "severity", &self.config.min_severity)?;
+        let categories = self.config.facilities.keys().chain(self.config.severities.keys());
+        for category in categories {
+            if !CATEGORIES.iter().any(|c| c.name() == category) {
+                return Err(Error::ConfigParseError(format!("unknown syscall category `{}`", category)));
+            }
+        }
+        for (category, name) in &self.config.facilities {
+            self.facilities.insert(category.clone(), code(FACILITIES, "facility", name)?);
+        }
+        for (category, name) in &self.config.severities {
+            self.severities.insert(category.clone(), code(SEVERITIES, "severity", name)?);
+        }
+        match self.connect() {
+            Ok(transport) => self.transport = Some(transport),
+            Err(e) => return Err(Error::AggregatorError(format!("unable to connect to syslog at {}: {}",
+                                                                self.config.address, e)))
+        }
+        Ok(())
+    }
+
+    /// Creates the socket of the configured transport. The TCP connection and writes are
+    /// bounded by the timeout, so an unresponsive server doesn't stall the event loop.
+    fn connect(&self) -> io::Result<Transport> {
+        let address = self.config.address.as_str();
+        match self.config.transport.as_str() {
+            "udp" => {
+                let socket = UdpSocket::bind("0.0.0.0:0")?;
+                socket.connect(address)?;
+                Ok(Transport::Udp(socket))
+            },
+            "tcp" => {
+                let timeout = Duration::from_secs(self.config.timeout);
+                let mut last_error = io::Error::new(io::ErrorKind::InvalidInput,
+                                                    format!("no address resolved for {}", address));
+                for addr in address.to_socket_addrs()? {
+                    match TcpStream::connect_timeout(&addr, timeout) {
+                        Ok(stream) => {
+                            stream.set_write_timeout(Some(timeout))?;
+                            return Ok(Transport::Tcp(stream));
+                        },
+                        Err(e) => last_error = e
+                    }
+                }
+                Err(last_error)
+            },
+            _ => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(address)?;
+                Ok(Transport::Unix(socket))
+            }
+        }
+    }
+
+    /// Renders the RFC 5424 message. The time is limited to microseconds as the RFC requires.
+    fn message(&self, info: &SyscallInfo, pri: u8, text: &str) -> String {
+        let mut sd = format!("[{} tid=\"{}\"", SD_ID, info.tid);
+        {
+            let mut param = |name: &str, value: &str| {
+                sd.push_str(&format!(" {}=\"{}\"", name, escape(value)));
+            };
+            if let Some(pid) = info.pid {
+                param("pid", &pid.to_string());
+            }
+            if let Some(ref comm) = info.comm {
+                param("comm", comm);
+            }
+            param("dir", if info.dir == Direction::Enter { ">" } else { "<" });
+            param("category", info.category.name());
+            if let Some(name) = info.user.as_ref().and_then(|u| u.name.as_ref()) {
+                param("user", name);
+            }
+            if let Some(ref container) = info.container {
+                param("container", &container.id);
+            }
+            if let Some(ref path) = info.resolved_path {
+                param("path", path);
+            }
+        }
+        sd.push(']');
+        format!("<{}>1 {} {} {} {} {} {} {}",
+                pri,
+                info.ts.format("%Y-%m-%dT%H:%M:%S%.6fZ"),
+                nil(&self.hostname),
+                nil(&self.config.app_name),
+                self.procid,
+                info.name,
+                sd,
+                text)
+    }
+
+    /// Sends the message, or drops it while syslog is unreachable. The first failure drops
+    /// the socket and schedules the reconnection.
+    fn send(&mut self, message: &[u8]) {
+        let result = match self.transport {
+            Some(Transport::Unix(ref socket)) => socket.send(message).map(|_| ()),
+            Some(Transport::Udp(ref socket)) => socket.send(message).map(|_| ()),
+            Some(Transport::Tcp(ref mut stream)) => {
+                // ~ octet counting framing
+                let frame = format!("{} ", message.len());
+                stream.write_all(frame.as_bytes()).and_then(|_| stream.write_all(message))
+            },
+            None => {
+                self.drop_message();
+                return;
+            }
+        };
+        if let Err(e) = result {
+            log_error!("syslog is unavailable, retrying in {:?}: {}", self.backoff, e);
+            self.transport = None;
+            self.reconnect_at = Some(Instant::now() + self.backoff);
+            self.drop_message();
+        }
+    }
+
+    fn drop_message(&mut self) {
+        self.dropped += 1;
+        self.total_dropped += 1;
+    }
+
+    fn reconnect(&mut self) {
+        self.reconnect_at = None;
+        match self.connect() {
+            Ok(transport) => {
+                if self.dropped > 0 {
+                    log_error!("dropped {} events while syslog was unreachable", self.dropped);
+                    self.dropped = 0;
+                }
+                self.transport = Some(transport);
+                self.backoff = Duration::from_millis(MIN_RECONNECT_BACKOFF_MS);
+            },
+            Err(_) => {
+                let backoff = self.backoff * 2;
+                self.backoff = ::std::cmp::min(backoff, Duration::from_millis(MAX_RECONNECT_BACKOFF_MS));
+                self.reconnect_at = Some(Instant::now() + self.backoff);
+            }
+        }
+    }
+}
@@ -1,21 +1,41 @@
-#[macro_use]
-extern crate serde_derive;
+extern crate libc;
 extern crate serde_json;
 
 extern crate cubostratusc;
 
-use std::default::Default;
+use std::collections::BTreeMap;
 use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use cubostratusc::collector::Collector;
 use cubostratusc::collector::RingBufferCollector;
-use cubostratusc::syscall::syscall_table::SyscallTable;
-use cubostratusc::aggregator::{Aggregator, KafkaAggregator};
+use cubostratusc::aggregator::{Aggregator, KafkaAggregator, FileAggregator, ParquetAggregator,
+                                 StdoutAggregator, SocketAggregator, SyslogAggregator,
+                                 HttpAggregator, ElasticsearchAggregator};
+use cubostratusc::serializer::Encoded;
+use cubostratusc::enricher::Enricher;
+use cubostratusc::enricher::kubernetes::KubernetesEnricher;
+use cubostratusc::enricher::docker::DockerEnricher;
+use cubostratusc::state::thread::ThreadRegistry;
+use cubostratusc::state::resync::Resync;
 use cubostratusc::config;
+use serde_json::Value;
+
+/// set by the termination signals to stop collecting the events
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_shutdown(_signum: libc::c_int) {
+    SHUTDOWN.store(true, Ordering::SeqCst);
+}
 
 fn main() {
 
-    let syscall_table = SyscallTable::default();
+    // ~ the aggregators are dropped on termination, so they finish and flush their output
+    let handler = request_shutdown as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGTERM, handler);
+        libc::signal(libc::SIGINT, handler);
+    }
 
     let config = match config::read_config() {
         Ok(config) => config,
@@ -24,24 +44,158 @@ fn main() {
         }
     };
 
-    let mut aggregator = KafkaAggregator::new(config.kafka);
-    match aggregator.start() {
-        Ok(()) => {},
-        Err(e) => {
-            exit_process(e.to_string());
+    let mut aggregators: Vec<Box<Aggregator<Encoded>>> = Vec::new();
+    if let Some(kafka) = config.kafka {
+        let mut aggregator = KafkaAggregator::new(kafka);
+        match aggregator.start() {
+            Ok(()) => aggregators.push(Box::new(aggregator)),
+            Err(e) => {
+                exit_process(e.to_string());
+            }
+        }
+    }
+
+    if let Some(file) = config.file {
+        let mut aggregator = FileAggregator::new(file);
+        match aggregator.start() {
+            Ok(()) => aggregators.push(Box::new(aggregator)),
+            Err(e) => {
+                exit_process(e.to_string());
+            }
+        }
+    }
+
+    if let Some(parquet) = config.parquet {
+        let mut aggregator = ParquetAggregator::new(parquet);
+        match aggregator.start() {
+            Ok(()) => aggregators.push(Box::new(aggregator)),
+            Err(e) => {
+                exit_process(e.to_string());
+            }
         }
     }
 
+    if let Some(stdout) = config.stdout {
+        let mut aggregator = StdoutAggregator::new(stdout);
+        match aggregator.start() {
+            Ok(()) => aggregators.push(Box::new(aggregator)),
+            Err(e) => {
+                exit_process(e.to_string());
+            }
+        }
+    }
+
+    if let Some(socket) = config.socket {
+        let mut aggregator = SocketAggregator::new(socket);
+        match aggregator.start() {
+            Ok(()) => aggregators.push(Box::new(aggregator)),
+            Err(e) => {
+                exit_process(e.to_string());
+            }
+        }
+    }
+
+    if let Some(syslog) = config.syslog {
+        let mut aggregator = SyslogAggregator::new(syslog);
+        match aggregator.start() {
+            Ok(()) => aggregators.push(Box::new(aggregator)),
+            Err(e) => {
+                exit_process(e.to_string());
+            }
+        }
+    }
+
+    if let Some(http) = config.http {
+        let mut aggregator = HttpAggregator::new(http);
+        match aggregator.start() {
+            Ok(()) => aggregators.push(Box::new(aggregator)),
+            Err(e) => {
+                exit_process(e.to_string());
+            }
+        }
+    }
+
+    if let Some(elasticsearch) = config.elasticsearch {
+        let mut aggregator = ElasticsearchAggregator::new(elasticsearch);
+        match aggregator.start() {
+            Ok(()) => aggregators.push(Box::new(aggregator)),
+            Err(e) => {
+                exit_process(e.to_string());
+            }
+        }
+    }
+
+    let mut enrichers: Vec<Box<Enricher>> = Vec::new();
+    if let Some(kubernetes) = config.kubernetes {
+        let mut enricher = KubernetesEnricher::new(kubernetes);
+        match enricher.start() {
+            Ok(()) => enrichers.push(Box::new(enricher)),
+            Err(e) => {
+                exit_process(e.to_string());
+            }
+        }
+    }
+
+    if let Some(docker) = config.docker {
+        let mut enricher = DockerEnricher::new(docker);
+        match enricher.start() {
+            Ok(()) => enrichers.push(Box::new(enricher)),
+            Err(e) => {
+                exit_process(e.to_string());
+            }
+        }
+    }
+
+    let resync = if config.state.resync_interval > 0 {
+        match Resync::start(config.state.clone()) {
+            Ok(resync) => Some(resync),
+            Err(e) => {
+                exit_process(e.to_string());
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut registry = ThreadRegistry::with_config(config.state);
+    let report = registry.collect();
+    for failure in &report.failed {
+        eprintln!("{}", failure.reason);
+    }
+    emit_snapshot(&mut aggregators, &registry);
+
     let mut collector = RingBufferCollector::new();
     match collector.start() {
-        Ok(num_devs) => {
-            loop {
-                match collector.next() {
-                    Some(syscall_info) => {
-                        let json = serde_json::to_string(&syscall_info).unwrap();
-                        aggregator.do_aggregate(json);
-                    },
-                    None => {}
+        Ok(_num_devs) => {
+            while !SHUTDOWN.load(Ordering::SeqCst) {
+                if let Some(fresh) = resync.as_ref().and_then(|r| r.poll()) {
+                    registry.reconcile(fresh);
+                    emit_snapshot(&mut aggregators, &registry);
+                }
+                if let Some(mut syscall_info) = collector.next() {
+                    registry.update(&mut syscall_info);
+                    if let Some((thread, process)) = registry.get_or_collect(syscall_info.tid) {
+                        syscall_info.pid = Some(thread.pid);
+                        syscall_info.vtid = Some(thread.vtid);
+                        syscall_info.vpid = Some(process.vpid);
+                        syscall_info.comm = Some(thread.comm.clone());
+                        syscall_info.user = thread.user.clone();
+                        syscall_info.group = thread.group.clone();
+                        for enricher in enrichers.iter_mut() {
+                            enricher.enrich(thread, &mut syscall_info);
+                        }
+                    }
+                    if let Some(pid) = syscall_info.pid {
+                        syscall_info.ancestry = registry.ancestry(pid);
+                    }
+                    // ~ the clones share the encodings, each format is encoded once on demand
+                    let encoded = Encoded::new();
+                    for aggregator in aggregators.iter_mut() {
+                        aggregator.do_aggregate(&syscall_info, encoded.clone());
+                    }
+                }
+                for aggregator in aggregators.iter_mut() {
+                    aggregator.poll();
                 }
             }
         },
@@ -51,7 +205,28 @@ fn main() {
     }
 }
 
+/// Emits the snapshot of the thread registry to the aggregators. The counters of the
+/// aggregators are attached to the header record.
+fn emit_snapshot(aggregators: &mut Vec<Box<Aggregator<Encoded>>>, registry: &ThreadRegistry) {
+    let snapshot = registry.snapshot();
+    let stats = aggregators.iter().flat_map(|a| a.stats()).collect::<BTreeMap<_, _>>();
+    let records = snapshot.records().iter()
+        .map(|record| {
+            let mut record = serde_json::to_value(record).unwrap();
+            if let Value::Object(ref mut fields) = record {
+                if fields.get("record").and_then(|r| r.as_str()) == Some("header") {
+                    fields.insert("aggregators".to_string(), serde_json::to_value(&stats).unwrap());
+                }
+            }
+            serde_json::to_string(&record).unwrap()
+        })
+        .collect::<Vec<_>>();
+    for aggregator in aggregators.iter_mut() {
+        aggregator.snapshot(&records);
+    }
+}
+
 fn exit_process(e: String) -> ! {
     println!("{}", e);
     process::exit(0)
-}
\ No newline at end of file
+}
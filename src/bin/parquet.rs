@@ -0,0 +1,130 @@
+//! Converts the newline delimited JSON events, such as the files written by the file aggregator
+//! or the stream of the socket aggregator, to Parquet files, e.g.
+//!
+//! ```text
+//! cubostratusc-parquet --compression zstd /data/parquet /var/log/cubostratusc/events-*.ndjson.gz
+//! (echo format=json; cat) | nc -U /var/run/cubostratusc.sock | cubostratusc-parquet /data/parquet -
+//! ```
+//!
+//! The gzip and zstd compressed files are decompressed by their extension, and `-` reads the
+//! events from the standard input.
+extern crate serde_json;
+extern crate flate2;
+extern crate zstd;
+
+extern crate cubostratusc;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::process;
+
+use cubostratusc::aggregator::{Aggregator, ParquetAggregator};
+use cubostratusc::config::ParquetConfig;
+use cubostratusc::parquet::Row;
+use cubostratusc::syscall::syscall_table::SyscallTable;
+
+const USAGE: &'static str = "usage: cubostratusc-parquet [--prefix <prefix>] [--compression none|gzip|zstd] \
+                             [--row-group-size <rows>] [--max-bytes <bytes>] [--max-age <seconds>] \
+                             <output dir> <file|->...";
+
+fn main() {
+    let mut config = ParquetConfig {
+        dir: String::new(),
+        prefix: "events".to_string(),
+        row_group_size: 100000,
+        max_bytes: 256 * 1024 * 1024,
+        max_age: 0,
+        compression: "zstd".to_string()
+    };
+    let mut inputs = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            inputs.push(arg);
+            continue;
+        }
+        let value = args.next().unwrap_or_else(|| exit_process(USAGE.to_string()));
+        match arg.as_str() {
+            "--prefix" => config.prefix = value,
+            "--compression" => config.compression = value,
+            "--row-group-size" => config.row_group_size = parse(&arg, &value),
+            "--max-bytes" => config.max_bytes = parse(&arg, &value),
+            "--max-age" => config.max_age = parse(&arg, &value),
+            _ => exit_process(USAGE.to_string())
+        }
+    }
+    if inputs.len() < 2 {
+        exit_process(USAGE.to_string());
+    }
+    if !["none", "gzip", "zstd"].contains(&config.compression.as_str()) || config.row_group_size == 0 {
+        exit_process(USAGE.to_string());
+    }
+    config.dir = inputs.remove(0);
+
+    let table = SyscallTable::default();
+    let mut aggregator = ParquetAggregator::new(config);
+    if let Err(e) = aggregator.start() {
+        exit_process(e.to_string());
+    }
+    let mut rows = 0u64;
+    let mut skipped = 0u64;
+    for input in &inputs {
+        let reader = match open(input) {
+            Ok(reader) => reader,
+            Err(e) => exit_process(format!("unable to open {}: {}", input, e))
+        };
+        for line in reader.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    eprintln!("unable to read {}: {}", input, e);
+                    break;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let row = serde_json::from_str(&line).ok().and_then(|event| Row::from_json(&event, &table));
+            match row {
+                Some(row) => {
+                    if let Err(e) = aggregator.write(&row) {
+                        exit_process(format!("unable to write the parquet file: {}", e));
+                    }
+                    rows += 1;
+                },
+                None => skipped += 1
+            }
+            aggregator.poll();
+        }
+    }
+    if let Err(e) = aggregator.rotate() {
+        exit_process(format!("unable to finish the parquet file: {}", e));
+    }
+    eprintln!("converted {} events, skipped {} malformed lines", rows, skipped);
+}
+
+/// Opens the input, decompressing it by the file extension.
+fn open(input: &str) -> io::Result<Box<BufRead>> {
+    if input == "-" {
+        return Ok(Box::new(BufReader::new(io::stdin())));
+    }
+    let file = File::open(input)?;
+    let reader: Box<Read> = if input.ends_with(".gz") {
+        Box::new(flate2::read::GzDecoder::new(file)?)
+    } else if input.ends_with(".zst") {
+        Box::new(zstd::stream::Decoder::new(file)?)
+    } else {
+        Box::new(file)
+    };
+    Ok(Box::new(BufReader::new(reader)))
+}
+
+fn parse<T: ::std::str::FromStr>(arg: &str, value: &str) -> T {
+    value.parse().unwrap_or_else(|_| exit_process(format!("invalid value `{}` of {}", value, arg)))
+}
+
+fn exit_process(e: String) -> ! {
+    eprintln!("{}", e);
+    process::exit(1)
+}
@@ -1,8 +1,8 @@
-///! Encapsulates the implementation of the syscall collectors.
+//! Encapsulates the implementation of the syscall collectors.
 
 use libc;
 use num_cpus;
-use chrono::{NaiveDateTime, DateTime, UTC, TimeZone};
+use chrono::{NaiveDateTime, DateTime, UTC};
 use nix::Error as NixError;
 use nix::errno;
 use nix::fcntl::{open, O_RDWR, O_SYNC};
@@ -13,7 +13,7 @@ use nix::sys::mman::{MAP_SHARED, PROT_READ, PROT_WRITE};
 use std::ptr;
 use std::mem::size_of;
 
-use syscall::{Syscall, SyscallInfo};
+use syscall::{Syscall, SyscallInfo, Direction};
 use syscall::syscall_table::SyscallTable;
 use error::{Error, Result};
 
@@ -21,7 +21,7 @@ const RING_BUF_SIZE: usize = 8 * 1024 * 1024;
 const BUFFER_EMPTY_WAIT_TIME_MS: u32 = 30;
 const MAX_N_CONSECUTIVE_WAITS: usize = 4;
 
-const PPM_IOCTL_MAGIC: u8 = 's' as u8;
+const PPM_IOCTL_MAGIC: u8 = b's';
 const PPM_IOCTL_DISABLE_CAPTURE: u8 = 1;
 const PPM_IOCTL_ENABLE_CAPTURE: u8 = 1;
 
@@ -83,8 +83,9 @@ impl Collector for RingBufferCollector {
     ///
     /// # Example
     ///
-    /// ```
-    /// let collector = RingBufferCollector::new();
+    /// ```no_run
+    /// # use cubostratusc::collector::{Collector, RingBufferCollector};
+    /// let mut collector = RingBufferCollector::new();
     /// match collector.start() {
     ///     Ok(num_devs) => {
     ///
@@ -111,8 +112,13 @@ impl Collector for RingBufferCollector {
                                            PROT_READ | PROT_WRITE,
                                            MAP_SHARED, fd, 0);
                     if buffer.is_err() || buffer_info.is_err() {
-                        munmap(buffer.unwrap() as *mut libc::c_void, len);
-                        close(fd);
+                        if let Ok(buffer) = buffer {
+                            let _ = munmap(buffer, len);
+                        }
+                        if let Ok(buffer_info) = buffer_info {
+                            let _ = munmap(buffer_info, size_of::<RingBufferInfo>());
+                        }
+                        let _ = close(fd);
                         return Err(Error::RingBufferMapping);
                     }
                     let dev = RingBufferDev {
@@ -126,7 +132,7 @@ impl Collector for RingBufferCollector {
 
                     self.devs.push(dev);
                     // ~ send the ioctl code to start the capture
-                    unsafe { ioctl_start(fd); }
+                    unsafe { let _ = ioctl_start(fd); }
 
                 },
                 Err(e) => {
@@ -151,10 +157,10 @@ impl Collector for RingBufferCollector {
     fn stop(&mut self) -> Result<()> {
         let len = RING_BUF_SIZE * 2;
         for dev in &self.devs {
-            unsafe { ioctl_stop(dev.fd); }
-            munmap(dev.buffer as *mut libc::c_void, len);
-            munmap(dev.buffer_info as *mut libc::c_void, size_of::<RingBufferInfo>());
-            close(dev.fd);
+            unsafe { let _ = ioctl_stop(dev.fd); }
+            let _ = munmap(dev.buffer as *mut libc::c_void, len);
+            let _ = munmap(dev.buffer_info as *mut libc::c_void, size_of::<RingBufferInfo>());
+            let _ = close(dev.fd);
         }
         Ok(())
     }
@@ -170,8 +176,9 @@ impl Collector for RingBufferCollector {
     ///
     /// # Example
     ///
-    /// ```
-    /// let collector = RingBufferCollector::new();
+    /// ```no_run
+    /// # use cubostratusc::collector::{Collector, RingBufferCollector};
+    /// let mut collector = RingBufferCollector::new();
     /// loop {
     ///     match collector.next() {
     ///         Some(syscall_info) => {
@@ -194,8 +201,7 @@ impl Collector for RingBufferCollector {
                 cpu = Some(j);
             }
         }
-        if cpu != None {
-            let cpuid = cpu.unwrap();
+        if let Some(cpuid) = cpu {
             unsafe {
                 assert!(self.devs[cpuid].len >= (*syscall).len);
                 let len = (*syscall).len as isize;
@@ -209,7 +215,7 @@ impl Collector for RingBufferCollector {
                 self.consecutive_waits += 1;
             }
 
-            for mut dev in &mut self.devs {
+            for dev in &mut self.devs {
                 let buffer_info = dev.buffer_info;
                 let ttail: usize = unsafe { ((*buffer_info).tail + dev.last_readsize) as usize };
                 if ttail < RING_BUF_SIZE {
@@ -222,7 +228,7 @@ impl Collector for RingBufferCollector {
                 dev.last_readsize = read_size;
                 dev.len = read_size;
 
-                unsafe { dev.next_syscall = dev.buffer.offset(ttail as isize); }
+                unsafe { dev.next_syscall = dev.buffer.add(ttail); }
             }
         }
 
@@ -231,14 +237,30 @@ impl Collector for RingBufferCollector {
         } else {
             let id = unsafe { (*syscall).id };
             let ts = unsafe { (*syscall).ts };
+            let tid = unsafe { (*syscall).tid };
 
             match self.syscall_table.get_syscall_meta(id as usize) {
                 Some(meta) => {
-                    let timestamp = NaiveDateTime::from_timestamp((ts / 1000000000) as i64, 0);
+                    let timestamp = NaiveDateTime::from_timestamp((ts / 1000000000) as i64, (ts % 1000000000) as u32);
                     let syscall_info = SyscallInfo {
                         ts: DateTime::<UTC>::from_utc(timestamp, UTC),
+                        tid: tid,
+                        cpu: cpu.unwrap_or(0),
+                        // ~ enter events have even and exit events odd identifiers
+                        dir: if id % 2 == 0 { Direction::Enter } else { Direction::Exit },
+                        comm: None,
+                        pid: None,
+                        vtid: None,
+                        vpid: None,
+                        user: None,
+                        group: None,
+                        ancestry: Vec::new(),
                         name: meta.name.to_string(),
-                        params: meta.build_params(syscall),
+                        category: meta.category,
+                        params: unsafe { meta.build_params(syscall) },
+                        resolved_path: None,
+                        pod: None,
+                        container: None
                     };
                     return Some(syscall_info);
 
@@ -272,7 +294,7 @@ impl RingBufferCollector {
                 res = false;
             }
         }
-        if res == false {
+        if !res {
             return false
         }
 
@@ -1,23 +1,798 @@
 //! Implementation of the TOML based configuration descriptor reader.
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 use std::env;
+use std::fmt::Write;
+use chrono::UTC;
+use chrono::format::{Item, StrftimeItems};
 use toml;
 
 use error::{Error, Result};
+use http::Url;
+use serializer;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
+pub struct SpoolConfig {
+    /// directory the segment files are stored in
+    pub dir: String,
+    /// size of a segment file in bytes after which a new segment is started
+    #[serde(default = "default_segment_bytes")]
+    pub segment_bytes: u64,
+    /// maximum size of all segment files in bytes
+    #[serde(default = "default_spool_max_bytes")]
+    pub max_bytes: u64,
+    /// maximum age of a segment file in seconds
+    #[serde(default = "default_spool_max_age")]
+    pub max_age: u64
+}
+
+#[derive(Deserialize, Clone)]
+pub struct TlsConfig {
+    /// path of the PEM bundle of the CA certificates the broker certificates are verified
+    /// against, the system CA certificates are used when absent
+    pub ca_file: Option<String>,
+    /// path of the PEM client certificate, optionally followed by its intermediate certificates
+    pub cert_file: Option<String>,
+    /// path of the PEM private key of the client certificate
+    pub key_file: Option<String>,
+    /// whether the certificate of each broker has to be issued for the address it's reached at
+    #[serde(default = "default_verify_hostname")]
+    pub verify_hostname: bool
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SaslConfig {
+    /// SASL mechanism, either `PLAIN`, `SCRAM-SHA-256` or `SCRAM-SHA-512`
+    pub mechanism: String,
+    pub username: String,
+    pub password: String
+}
+
+#[derive(Deserialize, Clone)]
 pub struct KafkaConfig {
     pub hosts: Vec<String>,
     pub ack_timeout: u64,
-    pub topic: String
+    pub topic: String,
+    /// topic the thread and process state snapshots are emitted to
+    pub snapshot_topic: Option<String>,
+    /// maximum number of messages in a batch
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// maximum size of the messages in a batch in bytes
+    #[serde(default = "default_batch_bytes")]
+    pub batch_bytes: usize,
+    /// maximum time in milliseconds a message waits in the batch before it's sent
+    #[serde(default = "default_linger_ms")]
+    pub linger_ms: u64,
+    /// compression codec of the message sets, either `none`, `gzip` or `snappy`
+    #[serde(default = "default_compression")]
+    pub compression: String,
+    /// acknowledgement required from the brokers, either `none`, `one` or `all`
+    #[serde(default = "default_required_acks")]
+    pub required_acks: String,
+    /// attribute the messages are keyed by, either `none`, `host`, `pid` or `container`
+    #[serde(default = "default_partition_key")]
+    pub partition_key: String,
+    /// encoding of the messages, either `json`, `msgpack`, `cbor`, `protobuf` or `avro`
+    #[serde(default = "default_serializer")]
+    pub serializer: String,
+    /// id of the avro schema in the schema registry, the messages are framed in the
+    /// Confluent wire format when set
+    pub schema_id: Option<u32>,
+    /// path the generated avro schema is written to on start
+    pub schema_file: Option<String>,
+    /// timeout in seconds of the connections to the brokers, and of their reads and writes
+    /// on top of the ack_timeout
+    #[serde(default = "default_kafka_timeout")]
+    pub timeout: u64,
+    /// maximum number of batches waiting for the producer
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize,
+    /// spool buffering the messages while the brokers are unreachable
+    pub spool: Option<SpoolConfig>,
+    /// TLS connections to the brokers
+    pub tls: Option<TlsConfig>,
+    /// SASL authentication of the connections to the brokers
+    pub sasl: Option<SaslConfig>
+}
+
+impl KafkaConfig {
+
+    fn validate(&self) -> Result<()> {
+        let check = |name: &str, value: &str, allowed: &[&str]| {
+            if allowed.contains(&value) {
+                Ok(())
+            } else {
+                Err(Error::ConfigParseError(format!("unknown kafka {} `{}`, expected one of {}",
+                                                    name, value, allowed.join(", "))))
+            }
+        };
+        check("compression", &self.compression, &["none", "gzip", "snappy"])?;
+        check("required_acks", &self.required_acks, &["none", "one", "all"])?;
+        check("partition_key", &self.partition_key, &["none", "host", "pid", "container"])?;
+        check("serializer", &self.serializer, serializer::FORMATS)?;
+        if self.queue_size == 0 {
+            return Err(Error::ConfigParseError("kafka queue_size must be at least 1".to_string()));
+        }
+        if self.serializer != "avro" && (self.schema_id.is_some() || self.schema_file.is_some()) {
+            return Err(Error::ConfigParseError("kafka schema_id and schema_file require the avro serializer"
+                                                   .to_string()));
+        }
+        if self.timeout == 0 {
+            return Err(Error::ConfigParseError("kafka timeout must be positive".to_string()));
+        }
+        if let Some(ref sasl) = self.sasl {
+            check("sasl mechanism", &sasl.mechanism, &["PLAIN", "SCRAM-SHA-256", "SCRAM-SHA-512"])?;
+            if sasl.username.is_empty() {
+                return Err(Error::ConfigParseError("kafka sasl username must not be empty".to_string()));
+            }
+        }
+        if let Some(ref tls) = self.tls {
+            if tls.cert_file.is_some() != tls.key_file.is_some() {
+                return Err(Error::ConfigParseError("kafka tls cert_file and key_file have to be set together"
+                                                       .to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct FileConfig {
+    /// directory the event files are written to
+    pub dir: String,
+    /// prefix of the event file names
+    #[serde(default = "default_file_prefix")]
+    pub prefix: String,
+    /// size of the event file in bytes after which the file is rotated
+    #[serde(default = "default_file_max_bytes")]
+    pub max_bytes: u64,
+    /// time in seconds after which the event file is rotated, 0 disables the time based rotation
+    #[serde(default = "default_file_max_age")]
+    pub max_age: u64,
+    /// format of the event files, either `ndjson` or `avro`
+    #[serde(default = "default_file_format")]
+    pub format: String,
+    /// compression codec of the rotated files, either `none`, `gzip` or `zstd`. Avro files
+    /// compress their blocks with the `deflate` or `zstandard` codec instead
+    #[serde(default = "default_compression")]
+    pub compression: String,
+    /// maximum number of rotated files kept, 0 keeps all of them
+    #[serde(default = "default_file_max_files")]
+    pub max_files: usize,
+    /// time in seconds the rotated files are kept for, 0 keeps them regardless of their age
+    #[serde(default)]
+    pub retention: u64
+}
+
+impl FileConfig {
+
+    fn validate(&self) -> Result<()> {
+        let allowed = ["ndjson", "avro"];
+        if !allowed.contains(&self.format.as_str()) {
+            return Err(Error::ConfigParseError(format!("unknown file format `{}`, expected one of {}",
+                                                       self.format, allowed.join(", "))));
+        }
+        let allowed = ["none", "gzip", "zstd"];
+        if allowed.contains(&self.compression.as_str()) {
+            Ok(())
+        } else {
+            Err(Error::ConfigParseError(format!("unknown file compression `{}`, expected one of {}",
+                                                self.compression, allowed.join(", "))))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ParquetConfig {
+    /// directory the parquet files are written to
+    pub dir: String,
+    /// prefix of the parquet file names
+    #[serde(default = "default_file_prefix")]
+    pub prefix: String,
+    /// number of rows in a row group
+    #[serde(default = "default_row_group_size")]
+    pub row_group_size: usize,
+    /// approximate size of the parquet file in bytes after which the file is rotated
+    #[serde(default = "default_file_max_bytes")]
+    pub max_bytes: u64,
+    /// time in seconds after which the parquet file is rotated, 0 disables the time based rotation
+    #[serde(default = "default_file_max_age")]
+    pub max_age: u64,
+    /// compression codec of the pages, either `none`, `gzip` or `zstd`
+    #[serde(default = "default_compression")]
+    pub compression: String
+}
+
+impl ParquetConfig {
+
+    fn validate(&self) -> Result<()> {
+        let allowed = ["none", "gzip", "zstd"];
+        if !allowed.contains(&self.compression.as_str()) {
+            return Err(Error::ConfigParseError(format!("unknown parquet compression `{}`, expected one of {}",
+                                                       self.compression, allowed.join(", "))));
+        }
+        if self.row_group_size == 0 {
+            return Err(Error::ConfigParseError("parquet row_group_size must be positive".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct StdoutConfig {
+    /// output format of the events, either `json` or `text`
+    #[serde(default = "default_stdout_format")]
+    pub format: String,
+    /// format string of the lines in the `text` format, e.g. `%evt.time %proc.name %evt.type`
+    pub template: Option<String>
+}
+
+impl StdoutConfig {
+
+    fn validate(&self) -> Result<()> {
+        let allowed = ["json", "text"];
+        if allowed.contains(&self.format.as_str()) {
+            Ok(())
+        } else {
+            Err(Error::ConfigParseError(format!("unknown stdout format `{}`, expected one of {}",
+                                                self.format, allowed.join(", "))))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SocketConfig {
+    /// path of the unix domain socket the clients connect to
+    pub unix_path: Option<String>,
+    /// address of the TCP listener the clients connect to, e.g. `127.0.0.1:7070`
+    pub tcp_addr: Option<String>,
+    /// maximum size in bytes of the events buffered for a client
+    #[serde(default = "default_socket_buffer_bytes")]
+    pub buffer_bytes: usize,
+    /// what happens to the client whose buffer is full, either `disconnect` or `drop` its events
+    #[serde(default = "default_slow_client")]
+    pub slow_client: String,
+    /// maximum number of connected clients, 0 doesn't limit the clients
+    #[serde(default = "default_max_clients")]
+    pub max_clients: usize,
+    /// time in seconds the client has to send the handshake in
+    #[serde(default = "default_handshake_timeout")]
+    pub handshake_timeout: u64
+}
+
+impl SocketConfig {
+
+    fn validate(&self) -> Result<()> {
+        if self.unix_path.is_none() && self.tcp_addr.is_none() {
+            return Err(Error::ConfigParseError("socket requires unix_path or tcp_addr".to_string()));
+        }
+        let allowed = ["disconnect", "drop"];
+        if allowed.contains(&self.slow_client.as_str()) {
+            Ok(())
+        } else {
+            Err(Error::ConfigParseError(format!("unknown socket slow_client `{}`, expected one of {}",
+                                                self.slow_client, allowed.join(", "))))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SyslogConfig {
+    /// transport of the messages, either `unix`, `udp` or `tcp`
+    #[serde(default = "default_syslog_transport")]
+    pub transport: String,
+    /// path of the unix socket, or address of the syslog server, e.g. `10.0.0.1:514`
+    #[serde(default = "default_syslog_address")]
+    pub address: String,
+    /// host name of the messages, the name of the host when absent, an empty name sends
+    /// the NILVALUE
+    pub hostname: Option<String>,
+    /// application name of the messages
+    #[serde(default = "default_app_name")]
+    pub app_name: String,
+    /// message text of the events, either `text` or `json`
+    #[serde(default = "default_syslog_message")]
+    pub message: String,
+    /// facility of the messages whose syscall category has no facility of its own
+    #[serde(default = "default_syslog_facility")]
+    pub facility: String,
+    /// severity of the messages whose syscall category has no severity of its own
+    #[serde(default = "default_syslog_severity")]
+    pub severity: String,
+    /// facilities by syscall category, e.g. `process = "auth"`
+    #[serde(default)]
+    pub facilities: HashMap<String, String>,
+    /// severities by syscall category, e.g. `net = "warning"`
+    #[serde(default)]
+    pub severities: HashMap<String, String>,
+    /// least important severity of the sent messages, e.g. `warning` only sends the alerts
+    #[serde(default = "default_syslog_min_severity")]
+    pub min_severity: String,
+    /// timeout in seconds of the TCP connection and writes
+    #[serde(default = "default_syslog_timeout")]
+    pub timeout: u64
+}
+
+impl SyslogConfig {
+
+    fn validate(&self) -> Result<()> {
+        let check = |name: &str, value: &str, allowed: &[&str]| {
+            if allowed.contains(&value) {
+                Ok(())
+            } else {
+                Err(Error::ConfigParseError(format!("unknown syslog {} `{}`, expected one of {}",
+                                                    name, value, allowed.join(", "))))
+            }
+        };
+        check("transport", &self.transport, &["unix", "udp", "tcp"])?;
+        check("message", &self.message, &["text", "json"])?;
+        if self.timeout == 0 {
+            return Err(Error::ConfigParseError("syslog timeout must be positive".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct HttpConfig {
+    /// URL the batches are posted to, e.g. `http://localhost:8080/events`
+    pub url: String,
+    /// path of the PEM bundle of the CA certificates the `https` server certificate is verified
+    /// against, the system CA certificates are used when absent
+    pub ca_file: Option<String>,
+    /// body of the requests, either `ndjson` or `json` for the JSON array
+    #[serde(default = "default_http_format")]
+    pub format: String,
+    /// whether the request bodies are gzip compressed
+    #[serde(default)]
+    pub gzip: bool,
+    /// additional request headers, e.g. `Authorization = "Bearer ..."`
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// maximum number of events in a batch
+    #[serde(default = "default_http_batch_size")]
+    pub batch_size: usize,
+    /// maximum size of the events in a batch in bytes
+    #[serde(default = "default_batch_bytes")]
+    pub batch_bytes: usize,
+    /// maximum time in milliseconds an event waits in the batch before it's sent
+    #[serde(default = "default_http_linger_ms")]
+    pub linger_ms: u64,
+    /// request timeout in seconds
+    #[serde(default = "default_http_timeout")]
+    pub timeout: u64,
+    /// number of times the failed request is retried
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// delay in milliseconds before the first retry, doubled on each retry
+    #[serde(default = "default_retry_backoff_ms")]
+    pub retry_backoff_ms: u64,
+    /// maximum number of concurrent requests
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    /// maximum number of batches waiting for a request
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize
+}
+
+impl HttpConfig {
+
+    fn validate(&self) -> Result<()> {
+        Url::parse(&self.url).map_err(|e| Error::ConfigParseError(e.to_string()))?;
+        if self.concurrency == 0 {
+            return Err(Error::ConfigParseError("http concurrency must be at least 1".to_string()));
+        }
+        let allowed = ["ndjson", "json"];
+        if allowed.contains(&self.format.as_str()) {
+            Ok(())
+        } else {
+            Err(Error::ConfigParseError(format!("unknown http format `{}`, expected one of {}",
+                                                self.format, allowed.join(", "))))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ElasticsearchConfig {
+    /// URL of the cluster, e.g. `http://localhost:9200`
+    pub url: String,
+    /// path of the PEM bundle of the CA certificates the `https` server certificate is verified
+    /// against, the system CA certificates are used when absent
+    pub ca_file: Option<String>,
+    /// name template of the indices, rendered by the event timestamp, e.g. `cubostratus-%Y.%m.%d`
+    #[serde(default = "default_index")]
+    pub index: String,
+    /// name of the index template installed on start, no template is installed if absent
+    pub template: Option<String>,
+    /// additional request headers, e.g. `Authorization = "Basic ..."`
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// maximum number of events in a bulk request
+    #[serde(default = "default_http_batch_size")]
+    pub batch_size: usize,
+    /// maximum size of the events in a bulk request in bytes
+    #[serde(default = "default_batch_bytes")]
+    pub batch_bytes: usize,
+    /// maximum time in milliseconds an event waits in the batch before it's indexed
+    #[serde(default = "default_http_linger_ms")]
+    pub linger_ms: u64,
+    /// request timeout in seconds
+    #[serde(default = "default_http_timeout")]
+    pub timeout: u64,
+    /// number of times the rejected items are retried
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// delay in milliseconds before the first retry, doubled on each retry
+    #[serde(default = "default_retry_backoff_ms")]
+    pub retry_backoff_ms: u64,
+    /// maximum number of batches waiting for a bulk request
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize
+}
+
+impl ElasticsearchConfig {
+
+    fn validate(&self) -> Result<()> {
+        Url::parse(&self.url).map_err(|e| Error::ConfigParseError(e.to_string()))?;
+        if self.batch_size == 0 {
+            return Err(Error::ConfigParseError("elasticsearch batch_size must be at least 1".to_string()));
+        }
+        if self.queue_size == 0 {
+            return Err(Error::ConfigParseError("elasticsearch queue_size must be at least 1".to_string()));
+        }
+        if StrftimeItems::new(&self.index).any(|item| item == Item::Error) {
+            return Err(Error::ConfigParseError(format!("invalid strftime specifier in elasticsearch index `{}`",
+                                                       self.index)));
+        }
+        // ~ the index names of all dates are rendered the same way, so checking one is enough
+        let mut index = String::new();
+        if write!(index, "{}", UTC::now().format(&self.index)).is_err() {
+            return Err(Error::ConfigParseError(format!("unable to render elasticsearch index `{}`", self.index)));
+        }
+        let forbidden = ['\\', '/', '*', '?', '"', '<', '>', '|', ' ', ',', '#', ':'];
+        if index.is_empty() || index == "." || index == ".." || index.len() > 255 ||
+                index.starts_with(['-', '_', '+']) || index.contains(&forbidden[..]) {
+            return Err(Error::ConfigParseError(format!("elasticsearch index `{}` renders to the invalid index \
+                                                        name `{}`", self.index, index)));
+        }
+        // ~ the index template matches the indices of all dates by the static prefix
+        if self.index.starts_with('%') {
+            return Err(Error::ConfigParseError(format!("elasticsearch index `{}` must start with a static prefix, \
+                                                        e.g. `events-%Y.%m.%d`", self.index)));
+        }
+        if index != index.to_lowercase() {
+            return Err(Error::ConfigParseError(format!("elasticsearch index `{}` renders to the index name `{}`, \
+                                                        which must be lowercase", self.index, index)));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct KubernetesConfig {
+    /// URL of the pod list endpoint, e.g. `http://localhost:10255/pods`
+    pub url: String,
+    /// path of the PEM bundle of the CA certificates the `https` server certificate is verified
+    /// against, the system CA certificates are used when absent
+    pub ca_file: Option<String>,
+    /// interval in seconds between two consecutive pod list refreshes
+    #[serde(default = "default_refresh_interval")]
+    pub refresh_interval: u64,
+    /// path of the file with the bearer token used to authenticate the requests
+    pub token_file: Option<String>,
+    /// request timeout in seconds
+    #[serde(default = "default_kubernetes_timeout")]
+    pub timeout: u64
+}
+
+impl KubernetesConfig {
+
+    fn validate(&self) -> Result<()> {
+        Url::parse(&self.url).map_err(|e| Error::ConfigParseError(e.to_string()))?;
+        if self.timeout == 0 {
+            return Err(Error::ConfigParseError("kubernetes timeout must be positive".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DockerConfig {
+    /// path of the Docker Engine API unix domain socket
+    #[serde(default = "default_docker_socket")]
+    pub socket: String
+}
+
+#[derive(Deserialize, Clone)]
+pub struct TableConfig {
+    /// maximum number of entries in the table, or 0 for an unbounded table
+    #[serde(default = "default_table_capacity")]
+    pub capacity: usize,
+    /// grace period in seconds the entries of exited entities are kept for
+    #[serde(default = "default_exited_ttl")]
+    pub exited_ttl: u64
+}
+
+impl Default for TableConfig {
+    fn default() -> TableConfig {
+        TableConfig {
+            capacity: default_table_capacity(),
+            exited_ttl: default_exited_ttl()
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct StateConfig {
+    /// names of the environment variables captured from processes
+    #[serde(default)]
+    pub environ: Vec<String>,
+    /// maximum number of processes in the ancestry chain attached to events
+    #[serde(default = "default_ancestry_depth")]
+    pub ancestry_depth: usize,
+    /// interval in seconds between two consecutive rescans of `/proc`, or 0 to disable them
+    #[serde(default = "default_resync_interval")]
+    pub resync_interval: u64,
+    /// limits of the thread table
+    #[serde(default)]
+    pub threads: TableConfig,
+    /// limits of the process table
+    #[serde(default)]
+    pub processes: TableConfig,
+    /// maximum number of open files tracked per process, or 0 for no limit
+    #[serde(default = "default_max_fds")]
+    pub max_fds: usize,
+    /// mount point of the host `/proc` file system
+    #[serde(default = "default_proc_root")]
+    pub proc_root: String,
+    /// location of the host `/etc` directory
+    #[serde(default = "default_etc_root")]
+    pub etc_root: String
+}
+
+impl Default for StateConfig {
+    fn default() -> StateConfig {
+        StateConfig {
+            environ: Vec::new(),
+            ancestry_depth: default_ancestry_depth(),
+            resync_interval: default_resync_interval(),
+            threads: TableConfig::default(),
+            processes: TableConfig::default(),
+            max_fds: default_max_fds(),
+            proc_root: default_proc_root(),
+            etc_root: default_etc_root()
+        }
+    }
 }
 
 #[derive(Deserialize)]
 pub struct Config {
     /// kafka broker related configuration
-    pub kafka: KafkaConfig
+    pub kafka: Option<KafkaConfig>,
+    /// local event files configuration
+    pub file: Option<FileConfig>,
+    /// parquet files configuration
+    pub parquet: Option<ParquetConfig>,
+    /// standard output configuration
+    pub stdout: Option<StdoutConfig>,
+    /// socket streaming configuration
+    pub socket: Option<SocketConfig>,
+    /// syslog configuration
+    pub syslog: Option<SyslogConfig>,
+    /// HTTP intake configuration
+    pub http: Option<HttpConfig>,
+    /// elasticsearch configuration
+    pub elasticsearch: Option<ElasticsearchConfig>,
+    /// thread and process state configuration
+    #[serde(default)]
+    pub state: StateConfig,
+    /// kubernetes pod metadata enrichment configuration
+    pub kubernetes: Option<KubernetesConfig>,
+    /// docker container metadata enrichment configuration
+    pub docker: Option<DockerConfig>
+}
+
+fn default_batch_size() -> usize {
+    1000
+}
+
+fn default_batch_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_linger_ms() -> u64 {
+    100
+}
+
+fn default_compression() -> String {
+    "none".to_string()
+}
+
+fn default_required_acks() -> String {
+    "one".to_string()
+}
+
+fn default_partition_key() -> String {
+    "none".to_string()
+}
+
+fn default_serializer() -> String {
+    "json".to_string()
+}
+
+fn default_verify_hostname() -> bool {
+    true
+}
+
+fn default_segment_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_spool_max_bytes() -> u64 {
+    1024 * 1024 * 1024
+}
+
+fn default_spool_max_age() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_file_prefix() -> String {
+    "events".to_string()
+}
+
+fn default_file_format() -> String {
+    "ndjson".to_string()
+}
+
+fn default_row_group_size() -> usize {
+    100000
+}
+
+fn default_file_max_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+
+fn default_file_max_age() -> u64 {
+    60 * 60
+}
+
+fn default_file_max_files() -> usize {
+    24
+}
+
+fn default_stdout_format() -> String {
+    "json".to_string()
+}
+
+fn default_socket_buffer_bytes() -> usize {
+    4 * 1024 * 1024
+}
+
+fn default_slow_client() -> String {
+    "disconnect".to_string()
+}
+
+fn default_max_clients() -> usize {
+    16
+}
+
+fn default_handshake_timeout() -> u64 {
+    5
+}
+
+fn default_syslog_transport() -> String {
+    "unix".to_string()
+}
+
+fn default_syslog_address() -> String {
+    "/dev/log".to_string()
+}
+
+fn default_app_name() -> String {
+    "cubostratusc".to_string()
+}
+
+fn default_syslog_message() -> String {
+    "text".to_string()
+}
+
+fn default_syslog_facility() -> String {
+    "user".to_string()
+}
+
+fn default_syslog_severity() -> String {
+    "info".to_string()
+}
+
+fn default_syslog_min_severity() -> String {
+    "debug".to_string()
+}
+
+fn default_syslog_timeout() -> u64 {
+    5
+}
+
+fn default_http_format() -> String {
+    "ndjson".to_string()
+}
+
+fn default_http_batch_size() -> usize {
+    500
+}
+
+fn default_http_linger_ms() -> u64 {
+    1000
+}
+
+fn default_http_timeout() -> u64 {
+    10
+}
+
+fn default_kubernetes_timeout() -> u64 {
+    5
+}
+
+fn default_max_retries() -> u32 {
+    5
+}
+
+fn default_retry_backoff_ms() -> u64 {
+    200
+}
+
+fn default_concurrency() -> usize {
+    2
+}
+
+fn default_queue_size() -> usize {
+    16
+}
+
+fn default_kafka_timeout() -> u64 {
+    10
+}
+
+fn default_index() -> String {
+    "cubostratus-%Y.%m.%d".to_string()
+}
+
+fn default_refresh_interval() -> u64 {
+    30
+}
+
+fn default_docker_socket() -> String {
+    "/var/run/docker.sock".to_string()
+}
+
+fn default_ancestry_depth() -> usize {
+    5
+}
+
+fn default_resync_interval() -> u64 {
+    300
+}
+
+fn default_table_capacity() -> usize {
+    65536
+}
+
+fn default_exited_ttl() -> u64 {
+    5
+}
+
+fn default_max_fds() -> usize {
+    1024
+}
+
+fn default_proc_root() -> String {
+    "/proc".to_string()
+}
+
+fn default_etc_root() -> String {
+    "/etc".to_string()
 }
 
 /// Reads the configuration descriptor from the TOML file. It first scans the list of well known
@@ -30,20 +805,60 @@ pub struct Config {
 ///
 pub fn read_config() -> Result<Config> {
     let mut content = String::new();
-    let locations = vec!["/etc/cubostratusc.toml", "/var/lib/cubostratusc/cubostratusc.toml"];
+    let locations = ["/etc/cubostratusc.toml", "/var/lib/cubostratusc/cubostratusc.toml"];
     let mut path = locations.iter()
             .find(|loco| Path::new(*loco).exists())
             .map(|l| l.to_string());
-    if path == None {
-        path = env::var("CUBOSTRATUSC_CONFIG").map(|e| Some(e)).unwrap_or(None);
+    if path.is_none() {
+        path = env::var("CUBOSTRATUSC_CONFIG").map(Some).unwrap_or(None);
     }
     if let Some(path) = path {
         File::open(path)
                 .unwrap()
                 .read_to_string(&mut content)
                 .expect("unable to open configuration file");
-        toml::from_str(&content).map_err(|e| Error::ConfigParseError(e.to_string()))
+        parse_config(&content)
     } else {
         Err(Error::UnknownConfigPathError)
     }
 }
+
+/// Parses and validates the configuration descriptor.
+pub fn parse_config(content: &str) -> Result<Config> {
+    let config: Config = toml::from_str(content).map_err(|e| Error::ConfigParseError(e.to_string()))?;
+    if config.kafka.is_none() && config.file.is_none() && config.parquet.is_none() &&
+            config.stdout.is_none() && config.socket.is_none() && config.syslog.is_none() &&
+            config.http.is_none() && config.elasticsearch.is_none() {
+        return Err(Error::ConfigParseError("no aggregator configured, expected a kafka, file, \
+                                            parquet, stdout, socket, syslog, http or \
+                                            elasticsearch section".to_string()));
+    }
+    if let Some(ref kafka) = config.kafka {
+        kafka.validate()?;
+    }
+    if let Some(ref file) = config.file {
+        file.validate()?;
+    }
+    if let Some(ref parquet) = config.parquet {
+        parquet.validate()?;
+    }
+    if let Some(ref stdout) = config.stdout {
+        stdout.validate()?;
+    }
+    if let Some(ref socket) = config.socket {
+        socket.validate()?;
+    }
+    if let Some(ref syslog) = config.syslog {
+        syslog.validate()?;
+    }
+    if let Some(ref http) = config.http {
+        http.validate()?;
+    }
+    if let Some(ref elasticsearch) = config.elasticsearch {
+        elasticsearch.validate()?;
+    }
+    if let Some(ref kubernetes) = config.kubernetes {
+        kubernetes.validate()?;
+    }
+    Ok(config)
+}
@@ -0,0 +1,246 @@
+//! Enriches syscall events with container metadata obtained from the Docker Engine API. The
+//! API is served over the runtime's unix domain socket. Running containers are listed and
+//! inspected on startup, and kept up to date by listening to the runtime's event stream, so
+//! containers started afterwards are picked up without polling. Containers that are still
+//! unknown when an event references them are inspected on demand.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, BufReader};
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, RwLock};
+use std::sync::mpsc::{sync_channel, SyncSender, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json::{self, Value};
+
+use config::DockerConfig;
+use http;
+use state::cgroups::container_id;
+use state::thread::ThreadInfo;
+use syscall::SyscallInfo;
+use super::Enricher;
+
+const REQUEST_TIMEOUT_SECS: u64 = 5;
+/// time to wait before reconnecting to the event stream
+const RECONNECT_INTERVAL_SECS: u64 = 5;
+/// time during which containers that couldn't be inspected aren't queried again
+const NEGATIVE_TTL_SECS: u64 = 30;
+/// maximum number of pending container lookups
+const RESOLVE_QUEUE_SIZE: usize = 64;
+/// only container lifecycle events are of interest (URL encoded `{"type":["container"]}`)
+const EVENTS_PATH: &'static str = "/events?filters=%7B%22type%22%3A%5B%22container%22%5D%7D";
+
+type ContainerMap = Arc<RwLock<HashMap<String, Arc<ContainerInfo>>>>;
+
+#[derive(Serialize, Debug)]
+pub struct ContainerInfo {
+    /// full identifier of the container
+    pub id: String,
+    /// name of the container
+    pub name: String,
+    /// image name the container was created from
+    pub image: String,
+    /// content addressable digest of the image
+    pub image_digest: Option<String>,
+    /// container labels
+    pub labels: BTreeMap<String, String>
+}
+
+pub struct DockerEnricher {
+    /// container metadata indexed by container identifier
+    containers: ContainerMap,
+    /// channel used to request the inspection of unknown containers
+    resolve_tx: Option<SyncSender<String>>,
+    /// docker configuration
+    config: DockerConfig
+}
+
+/// Implementation of the enricher which attaches the container metadata
+/// to events produced by containerized threads.
+impl Enricher for DockerEnricher {
+
+    fn enrich(&mut self, thread: &ThreadInfo, info: &mut SyscallInfo) {
+        let id = match thread.cgroups {
+            Some(ref cgroups) => container_id(cgroups),
+            None => None
+        };
+        if let Some(id) = id {
+            let containers = self.containers.read().unwrap();
+            match containers.get(&id) {
+                Some(container) => info.container = Some(container.clone()),
+                None => {
+                    if let Some(ref tx) = self.resolve_tx {
+                        let _ = tx.try_send(id);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl DockerEnricher {
+
+    pub fn new(config: DockerConfig) -> DockerEnricher {
+        DockerEnricher {
+            containers: Arc::new(RwLock::new(HashMap::new())),
+            resolve_tx: None,
+            config: config
+        }
+    }
+
+    /// Loads the metadata of the running containers and spawns the threads that
+    /// listen for runtime events and resolve unknown containers.
+    pub fn start(&mut self) -> io::Result<()> {
+        let socket = self.config.socket.clone();
+        sync_containers(&socket, &self.containers)?;
+
+        let containers = self.containers.clone();
+        thread::Builder::new()
+                .name("docker-events".to_string())
+                .spawn(move || listen(socket, containers))?;
+
+        let (tx, rx) = sync_channel(RESOLVE_QUEUE_SIZE);
+        let socket = self.config.socket.clone();
+        let containers = self.containers.clone();
+        thread::Builder::new()
+                .name("docker-resolver".to_string())
+                .spawn(move || resolve(socket, containers, rx))?;
+        self.resolve_tx = Some(tx);
+        Ok(())
+    }
+}
+
+/// Inspects the containers requested by the enricher. The misses are forgotten once they
+/// can be queried again, so the containers of other runtimes coming and going don't pile up.
+fn resolve(socket: String, containers: ContainerMap, rx: Receiver<String>) {
+    let ttl = Duration::from_secs(NEGATIVE_TTL_SECS);
+    let mut misses: HashMap<String, Instant> = HashMap::new();
+    for id in rx.iter() {
+        if containers.read().unwrap().contains_key(&id) {
+            continue;
+        }
+        misses.retain(|_, ts| ts.elapsed() < ttl);
+        if misses.contains_key(&id) {
+            continue;
+        }
+        match inspect(&socket, &id) {
+            Ok(container) => {
+                misses.remove(&id);
+                containers.write().unwrap().insert(id, Arc::new(container));
+            },
+            Err(_) => {
+                // ~ the container is most likely managed by other runtime
+                misses.insert(id, Instant::now());
+            }
+        }
+    }
+}
+
+/// Follows the runtime's event stream and updates the containers as they
+/// come and go. The stream is reopened when the connection to the daemon
+/// is lost, and the containers are resynced to catch up with missed events.
+fn listen(socket: String, containers: ContainerMap) {
+    loop {
+        if let Err(e) = follow_events(&socket, &containers) {
+            log_error!("docker event stream interrupted: {}", e);
+        }
+        thread::sleep(Duration::from_secs(RECONNECT_INTERVAL_SECS));
+        if let Err(e) = sync_containers(&socket, &containers) {
+            log_error!("unable to sync docker containers: {}", e);
+        }
+    }
+}
+
+fn follow_events(socket: &str, containers: &ContainerMap) -> io::Result<()> {
+    let stream = UnixStream::connect(socket)?;
+    let resp = http::request(stream, "GET", "docker", EVENTS_PATH, &[], &[])?;
+    if resp.status != 200 {
+        return Err(io::Error::other(format!("event stream returned status {}", resp.status)));
+    }
+    let reader = BufReader::new(resp.chunked());
+    for line in reader.lines() {
+        let line = line?;
+        let event: Value = match serde_json::from_str(&line) {
+            Ok(event) => event,
+            Err(_) => continue
+        };
+        let id = match event.pointer("/Actor/ID").and_then(|id| id.as_str()) {
+            Some(id) => id.to_string(),
+            None => continue
+        };
+        match event.get("Action").and_then(|a| a.as_str()) {
+            Some("start") | Some("rename") => {
+                if let Ok(container) = inspect(socket, &id) {
+                    containers.write().unwrap().insert(id, Arc::new(container));
+                }
+            },
+            Some("destroy") => {
+                containers.write().unwrap().remove(&id);
+            },
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the known containers with the currently running ones.
+fn sync_containers(socket: &str, containers: &ContainerMap) -> io::Result<()> {
+    let list = get_json(socket, "/containers/json")?;
+    let mut running = HashMap::new();
+    if let Some(list) = list.as_array() {
+        for c in list {
+            if let Some(id) = c.get("Id").and_then(|id| id.as_str()) {
+                if let Ok(container) = inspect(socket, id) {
+                    running.insert(id.to_string(), Arc::new(container));
+                }
+            }
+        }
+    }
+    *containers.write().unwrap() = running;
+    Ok(())
+}
+
+/// Inspects the container and its image.
+fn inspect(socket: &str, id: &str) -> io::Result<ContainerInfo> {
+    let c = get_json(socket, &format!("/containers/{}/json", id))?;
+    let str_field = |pointer: &str| {
+        c.pointer(pointer).and_then(|v| v.as_str()).unwrap_or("").to_string()
+    };
+    let labels = c.pointer("/Config/Labels")
+        .and_then(|l| l.as_object())
+        .map(|l| l.iter()
+                  .filter_map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_string())))
+                  .collect())
+        .unwrap_or(BTreeMap::new());
+
+    // ~ prefer the registry digest which identifies the image across hosts,
+    // and fallback to the local image id for images that were never pushed
+    let image_id = str_field("/Image");
+    let image_digest = get_json(socket, &format!("/images/{}/json", image_id)).ok()
+        .and_then(|i| i.pointer("/RepoDigests/0")
+                      .and_then(|d| d.as_str())
+                      .and_then(|d| d.rsplit('@').next())
+                      .map(|d| d.to_string()))
+        .or(if image_id.is_empty() { None } else { Some(image_id) });
+
+    Ok(ContainerInfo {
+        id: id.to_string(),
+        name: str_field("/Name").trim_start_matches('/').to_string(),
+        image: str_field("/Config/Image"),
+        image_digest: image_digest,
+        labels: labels
+    })
+}
+
+fn get_json(socket: &str, path: &str) -> io::Result<Value> {
+    let stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(Duration::from_secs(REQUEST_TIMEOUT_SECS)))?;
+    let resp = http::request(stream, "GET", "docker", path, &[], &[])?;
+    if resp.status != 200 {
+        return Err(io::Error::new(io::ErrorKind::NotFound,
+                                  format!("GET {} returned status {}", path, resp.status)));
+    }
+    let body = resp.body()?;
+    serde_json::from_slice(&body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData,
+                                                              e.to_string()))
+}
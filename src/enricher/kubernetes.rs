@@ -0,0 +1,187 @@
+//! Enriches syscall events with Kubernetes pod metadata. The container identifier is derived
+//! from the cgroup pathname of the thread that produced the event, and resolved to the pod
+//! the container belongs to. Pods are fetched from the pod list endpoint, which is either the
+//! kubelet's `/pods` endpoint (read-only port) or the API server's `/api/v1/pods` resource
+//! (e.g. exposed through `kubectl proxy`). Both answer with a `PodList` document:
+//!
+//! ```text
+//! {"items": [{"metadata": {"name": "nginx-5d8f", "namespace": "default", "uid": "..",
+//!                          "labels": {"app": "nginx"}},
+//!             "status": {"containerStatuses": [{"containerID": "docker://3f4a.."}]}}]}
+//! ```
+//!
+//! The pod list is cached and refreshed from a background thread on a regular basis, or
+//! earlier when an event references a container that isn't in the cache yet.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, Read};
+use std::sync::{Arc, RwLock};
+use std::sync::mpsc::{sync_channel, SyncSender, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json::{self, Value};
+
+use config::KubernetesConfig;
+use http::{Client, Url};
+use state::cgroups::container_id;
+use state::thread::ThreadInfo;
+use syscall::SyscallInfo;
+use super::Enricher;
+
+/// minimum time between two refreshes triggered by cache misses
+const MIN_REFRESH_INTERVAL_SECS: u64 = 2;
+
+#[derive(Serialize, Debug)]
+pub struct PodInfo {
+    /// name of the pod
+    pub name: String,
+    /// namespace the pod lives in
+    pub namespace: String,
+    /// unique identifier of the pod
+    pub uid: String,
+    /// pod labels
+    pub labels: BTreeMap<String, String>
+}
+
+pub struct KubernetesEnricher {
+    /// pod metadata indexed by container identifier
+    pods: Arc<RwLock<HashMap<String, Arc<PodInfo>>>>,
+    /// channel used to wake up the refresher thread before the refresh interval elapses
+    refresh_tx: Option<SyncSender<()>>,
+    /// kubernetes configuration
+    config: KubernetesConfig
+}
+
+/// Implementation of the enricher which attaches the metadata of the pod
+/// to events produced by containerized threads.
+impl Enricher for KubernetesEnricher {
+
+    fn enrich(&mut self, thread: &ThreadInfo, info: &mut SyscallInfo) {
+        let id = match thread.cgroups {
+            Some(ref cgroups) => container_id(cgroups),
+            None => None
+        };
+        if let Some(id) = id {
+            let pods = self.pods.read().unwrap();
+            match pods.get(&id) {
+                Some(pod) => info.pod = Some(pod.clone()),
+                None => {
+                    if let Some(ref tx) = self.refresh_tx {
+                        // ~ the refresher is either busy or already notified
+                        // when the channel is full
+                        let _ = tx.try_send(());
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl KubernetesEnricher {
+
+    pub fn new(config: KubernetesConfig) -> KubernetesEnricher {
+        KubernetesEnricher {
+            pods: Arc::new(RwLock::new(HashMap::new())),
+            refresh_tx: None,
+            config: config
+        }
+    }
+
+    /// Fetches the initial pod list and spawns the thread which periodically refreshes it.
+    pub fn start(&mut self) -> io::Result<()> {
+        let url = Url::parse(&self.config.url)?;
+        let client = Client::new(self.config.ca_file.as_deref(),
+                                      Duration::from_secs(self.config.timeout))?;
+        let mut headers = Vec::new();
+        if let Some(ref path) = self.config.token_file {
+            let mut token = String::new();
+            File::open(path)?.read_to_string(&mut token)?;
+            headers.push(("Authorization".to_string(), format!("Bearer {}", token.trim())));
+        }
+
+        *self.pods.write().unwrap() = fetch_pods(&client, &url, &headers)?;
+
+        let (tx, rx) = sync_channel(1);
+        let pods = self.pods.clone();
+        let interval = Duration::from_secs(self.config.refresh_interval);
+        thread::Builder::new()
+                .name("k8s-refresher".to_string())
+                .spawn(move || refresh(client, url, headers, interval, pods, rx))?;
+        self.refresh_tx = Some(tx);
+        Ok(())
+    }
+}
+
+fn refresh(client: Client,
+           url: Url,
+           headers: Vec<(String, String)>,
+           interval: Duration,
+           pods: Arc<RwLock<HashMap<String, Arc<PodInfo>>>>,
+           rx: Receiver<()>) {
+    let mut last_refresh = Instant::now();
+    loop {
+        match rx.recv_timeout(interval) {
+            Ok(()) => {
+                if last_refresh.elapsed() < Duration::from_secs(MIN_REFRESH_INTERVAL_SECS) {
+                    continue;
+                }
+            },
+            Err(RecvTimeoutError::Timeout) => {},
+            Err(RecvTimeoutError::Disconnected) => break
+        }
+        match fetch_pods(&client, &url, &headers) {
+            Ok(p) => *pods.write().unwrap() = p,
+            Err(e) => log_error!("unable to refresh pod list from {}: {}", url.path, e)
+        }
+        last_refresh = Instant::now();
+    }
+}
+
+fn fetch_pods(client: &Client, url: &Url, headers: &[(String, String)]) -> io::Result<HashMap<String, Arc<PodInfo>>> {
+    let body = client.get(url, headers)?;
+    parse_pods(&body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// Parses the `PodList` document and indexes the pods by the identifiers
+/// of their (init) containers.
+pub fn parse_pods(body: &[u8]) -> serde_json::Result<HashMap<String, Arc<PodInfo>>> {
+    let list: Value = serde_json::from_slice(body)?;
+    let mut pods = HashMap::new();
+    let items = match list.get("items").and_then(|i| i.as_array()) {
+        Some(items) => items,
+        None => return Ok(pods)
+    };
+    for item in items {
+        let str_field = |pointer: &str| {
+            item.pointer(pointer).and_then(|v| v.as_str()).unwrap_or("").to_string()
+        };
+        let labels = item.pointer("/metadata/labels")
+            .and_then(|l| l.as_object())
+            .map(|l| l.iter()
+                      .filter_map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_string())))
+                      .collect())
+            .unwrap_or(BTreeMap::new());
+        let pod = Arc::new(PodInfo {
+            name: str_field("/metadata/name"),
+            namespace: str_field("/metadata/namespace"),
+            uid: str_field("/metadata/uid"),
+            labels: labels
+        });
+
+        for statuses in &["/status/containerStatuses", "/status/initContainerStatuses"] {
+            let statuses = match item.pointer(statuses).and_then(|s| s.as_array()) {
+                Some(statuses) => statuses,
+                None => continue
+            };
+            for status in statuses {
+                // ~ container ids are prefixed by the runtime scheme, e.g. `docker://`
+                if let Some(id) = status.get("containerID").and_then(|id| id.as_str()) {
+                    let id = id.rsplit("://").next().unwrap_or(id);
+                    pods.insert(id.to_string(), pod.clone());
+                }
+            }
+        }
+    }
+    Ok(pods)
+}
@@ -0,0 +1,12 @@
+//! Syscall's event enrichers used to decorate the stream of syscall events with metadata
+//! coming from outside of the kernel, such as the orchestrator or the container runtime.
+pub mod kubernetes;
+pub mod docker;
+
+use syscall::SyscallInfo;
+use state::thread::ThreadInfo;
+
+pub trait Enricher {
+
+    fn enrich(&mut self, thread: &ThreadInfo, info: &mut SyscallInfo);
+}
@@ -2,6 +2,8 @@
 
 use std::result;
 use std::fmt;
+use std::io;
+use libc;
 
 pub type Result<T> = result::Result<T, Error>;
 
@@ -12,7 +14,31 @@ pub enum Error {
     TooManyCollectors,
     DeviceError,
     UnknownConfigPathError,
-    ConfigParseError(String)
+    ConfigParseError(String),
+    /// the aggregator couldn't connect to its destination
+    AggregatorError(String),
+    /// the process exited while its `/proc` entry was being read
+    ProcessVanished(u64),
+    /// insufficient privileges to read the `/proc` entry of the process
+    ProcPermissionDenied(u64),
+    /// the `/proc` entry of the process couldn't be read
+    ProcIoError(u64, String),
+    /// a file from the `/proc` entry of the process is malformed
+    ProcParseError(u64, String)
+}
+
+impl Error {
+    /// Classifies the I/O error raised while reading the `/proc` entry of the process. Reading
+    /// the files of a process that is exiting fails with `ESRCH`, while the files of a process
+    /// that is already reaped are gone.
+    pub fn from_proc_io(pid: u64, e: io::Error) -> Error {
+        match e.kind() {
+            io::ErrorKind::NotFound => Error::ProcessVanished(pid),
+            io::ErrorKind::PermissionDenied => Error::ProcPermissionDenied(pid),
+            _ if e.raw_os_error() == Some(libc::ESRCH) => Error::ProcessVanished(pid),
+            _ => Error::ProcIoError(pid, e.to_string())
+        }
+    }
 }
 
 impl fmt::Display for Error {
@@ -25,7 +51,16 @@ impl fmt::Display for Error {
             Error::UnknownConfigPathError => write!(f, "Unable to resolve \
                                                 configuration file path"),
             Error::ConfigParseError(ref e) => write!(f, "Invalid configuration descriptor. \
-                                               Reason: {}", e)
+                                               Reason: {}", e),
+            Error::AggregatorError(ref e) => write!(f, "Unable to start the aggregator. \
+                                              Reason: {}", e),
+            Error::ProcessVanished(pid) => write!(f, "Process {} exited while being scanned", pid),
+            Error::ProcPermissionDenied(pid) => write!(f, "Insufficient privileges to read \
+                                                      the proc entry of process {}", pid),
+            Error::ProcIoError(pid, ref e) => write!(f, "Unable to read the proc entry of \
+                                                    process {}. Reason: {}", pid, e),
+            Error::ProcParseError(pid, ref e) => write!(f, "Unable to parse the proc entry of \
+                                                       process {}. Reason: {}", pid, e)
         }
     }
 }
@@ -0,0 +1,359 @@
+//! Minimal HTTP/1.1 client used to talk to local REST endpoints such as the kubelet or
+//! container runtime daemons, and to push the events to HTTP intakes. It speaks HTTP, and
+//! HTTPS verifying the server certificate against the system or the configured CA
+//! certificates. It supports fixed length, chunked and connection delimited response bodies,
+//! which is all we need to consume small JSON documents and event streams.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write, Error, ErrorKind};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use openssl::error::ErrorStack;
+use openssl::ssl::{SslConnector, SslMethod, SslStream};
+use openssl::x509::X509;
+use openssl::x509::store::X509StoreBuilder;
+
+const DEFAULT_PORT: u16 = 80;
+const DEFAULT_TLS_PORT: u16 = 443;
+
+/// Decomposed `http[s]://host[:port]/path` URL.
+#[derive(Clone, Debug)]
+pub struct Url {
+    /// whether the endpoint is reached over TLS
+    pub tls: bool,
+    /// host name or IP address of the endpoint
+    pub host: String,
+    /// TCP port of the endpoint
+    pub port: u16,
+    /// request path including the query string
+    pub path: String
+}
+
+/// Connection to the endpoint of the URL.
+pub enum Stream {
+    Plain(TcpStream),
+    Tls(SslStream<TcpStream>)
+}
+
+/// Client issuing the requests over fresh connections. The connection attempt, and each
+/// read and write of the request, give up after the timeout.
+#[derive(Clone)]
+pub struct Client {
+    connector: SslConnector,
+    timeout: Duration
+}
+
+pub struct Response<R> {
+    /// status code of the response
+    pub status: u16,
+    /// response headers with lowercased names
+    pub headers: Vec<(String, String)>,
+    /// reader positioned at the beginning of the body
+    reader: BufReader<R>
+}
+
+/// Body reader that strips the framing of the `chunked` transfer encoding.
+pub struct ChunkedReader<R> {
+    reader: BufReader<R>,
+    remaining: usize,
+    done: bool
+}
+
+impl Url {
+
+    pub fn parse(url: &str) -> io::Result<Url> {
+        let (tls, rest) = match (url.strip_prefix("http://"), url.strip_prefix("https://")) {
+            (Some(rest), _) => (false, rest),
+            (_, Some(rest)) => (true, rest),
+            _ => return Err(Error::new(ErrorKind::InvalidInput,
+                                       format!("unsupported URL scheme in {}", url)))
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/")
+        };
+        // ~ IPv6 addresses are enclosed in brackets
+        let (host, port) = match authority.rfind(':') {
+            Some(i) if !authority[i..].contains(']') => {
+                let port = (authority[i + 1..].parse::<u16>()
+                        .map_err(|_| Error::new(ErrorKind::InvalidInput,
+                                                format!("invalid port in {}", url))))?;
+                (&authority[..i], port)
+            },
+            _ => (authority, if tls { DEFAULT_TLS_PORT } else { DEFAULT_PORT })
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("missing host in {}", url)));
+        }
+        Ok(Url {
+            tls: tls,
+            host: host.to_string(),
+            port: port,
+            path: path.to_string()
+        })
+    }
+
+    /// Returns the `host[:port]` value of the `Host` header, which omits the default port.
+    pub fn authority(&self) -> String {
+        let host = if self.host.contains(':') { format!("[{}]", self.host) } else { self.host.clone() };
+        let default_port = if self.tls { DEFAULT_TLS_PORT } else { DEFAULT_PORT };
+        if self.port == default_port {
+            host
+        } else {
+            format!("{}:{}", host, self.port)
+        }
+    }
+}
+
+impl Read for Stream {
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Plain(ref mut s) => s.read(buf),
+            Stream::Tls(ref mut s) => s.read(buf)
+        }
+    }
+}
+
+impl Write for Stream {
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Plain(ref mut s) => s.write(buf),
+            Stream::Tls(ref mut s) => s.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Stream::Plain(ref mut s) => s.flush(),
+            Stream::Tls(ref mut s) => s.flush()
+        }
+    }
+}
+
+impl Client {
+
+    /// Creates the client verifying the server certificates against the PEM bundle of the
+    /// CA certificates, or against the system CA certificates when absent.
+    pub fn new(ca_file: Option<&str>, timeout: Duration) -> io::Result<Client> {
+        let mut builder = SslConnector::builder(SslMethod::tls()).map_err(ssl_error)?;
+        if let Some(path) = ca_file {
+            // ~ the connector trusts the system CA certificates by default, which the bundle replaces
+            let mut pem = Vec::new();
+            File::open(path)?.read_to_end(&mut pem)?;
+            let mut store = X509StoreBuilder::new().map_err(ssl_error)?;
+            for cert in X509::stack_from_pem(&pem).map_err(ssl_error)? {
+                store.add_cert(cert).map_err(ssl_error)?;
+            }
+            builder.set_cert_store(store.build());
+        }
+        Ok(Client {
+            connector: builder.build(),
+            timeout: timeout
+        })
+    }
+
+    /// Opens a connection to the endpoint, and performs the TLS handshake of `https` URLs.
+    pub fn connect(&self, url: &Url) -> io::Result<Stream> {
+        let mut last_error = Error::new(ErrorKind::InvalidInput,
+                                        format!("no address resolved for {}", url.host));
+        for addr in (url.host.as_str(), url.port).to_socket_addrs()? {
+            let stream = match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    last_error = e;
+                    continue;
+                }
+            };
+            stream.set_read_timeout(Some(self.timeout))?;
+            stream.set_write_timeout(Some(self.timeout))?;
+            if !url.tls {
+                return Ok(Stream::Plain(stream));
+            }
+            return self.connector.connect(&url.host, stream)
+                .map(Stream::Tls)
+                .map_err(|e| Error::other(format!("TLS handshake with {} failed: {}", url.host, e)));
+        }
+        Err(last_error)
+    }
+
+    /// Issues a `GET` request and returns the response body if the server answered with a
+    /// successful status code.
+    pub fn get(&self, url: &Url, headers: &[(String, String)]) -> io::Result<Vec<u8>> {
+        let stream = self.connect(url)?;
+        let resp = request(stream, "GET", &url.authority(), &url.path, headers, &[])?;
+        if resp.status < 200 || resp.status >= 300 {
+            return Err(Error::other(format!("GET {} returned status {}", url.path, resp.status)));
+        }
+        resp.body()
+    }
+
+    /// Issues a `POST` request and returns the status code and the body of the response,
+    /// regardless of the status code.
+    pub fn post(&self, url: &Url, headers: &[(String, String)], body: &[u8]) -> io::Result<(u16, Vec<u8>)> {
+        self.send(url, "POST", headers, body)
+    }
+
+    /// Issues a `PUT` request and returns the status code and the body of the response,
+    /// regardless of the status code.
+    pub fn put(&self, url: &Url, headers: &[(String, String)], body: &[u8]) -> io::Result<(u16, Vec<u8>)> {
+        self.send(url, "PUT", headers, body)
+    }
+
+    fn send(&self, url: &Url, method: &str, headers: &[(String, String)], body: &[u8]) -> io::Result<(u16, Vec<u8>)> {
+        let stream = self.connect(url)?;
+        let resp = request(stream, method, &url.authority(), &url.path, headers, body)?;
+        let status = resp.status;
+        resp.body().map(|body| (status, body))
+    }
+}
+
+fn ssl_error(e: ErrorStack) -> Error {
+    Error::other(e.to_string())
+}
+
+impl<R: Read> Response<R> {
+
+    /// Returns the value of the header with the given (lowercased) name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|&(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn is_chunked(&self) -> bool {
+        self.header("transfer-encoding")
+            .map(|te| te.to_lowercase().contains("chunked"))
+            .unwrap_or(false)
+    }
+
+    /// Consumes the response and reads the whole body to memory.
+    pub fn body(self) -> io::Result<Vec<u8>> {
+        let mut body = Vec::new();
+        if self.is_chunked() {
+            self.chunked().read_to_end(&mut body)?;
+        } else {
+            match self.header("content-length").and_then(|l| l.parse::<u64>().ok()) {
+                Some(len) => {
+                    self.reader.take(len).read_to_end(&mut body)?;
+                },
+                None => {
+                    let mut reader = self.reader;
+                    reader.read_to_end(&mut body)?;
+                }
+            }
+        }
+        Ok(body)
+    }
+
+    /// Consumes the response and returns a reader over the chunked body. This is
+    /// useful for long lived streaming responses.
+    pub fn chunked(self) -> ChunkedReader<R> {
+        ChunkedReader {
+            reader: self.reader,
+            remaining: 0,
+            done: false
+        }
+    }
+}
+
+impl<R: Read> ChunkedReader<R> {
+
+    fn read_chunk_size(&mut self) -> io::Result<usize> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "connection closed mid stream"));
+        }
+        // ~ ignore any chunk extensions
+        let size = line.trim().split(';').next().unwrap_or("");
+        usize::from_str_radix(size, 16)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "invalid chunk size"))
+    }
+
+    fn read_crlf(&mut self) -> io::Result<()> {
+        let mut line = String::new();
+        self.reader.read_line(&mut line)?;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for ChunkedReader<R> {
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            self.remaining = self.read_chunk_size()?;
+            if self.remaining == 0 {
+                self.done = true;
+                self.read_crlf()?;
+                return Ok(0);
+            }
+        }
+        let max = if buf.len() < self.remaining { buf.len() } else { self.remaining };
+        let n = self.reader.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "connection closed mid chunk"));
+        }
+        self.remaining -= n;
+        if self.remaining == 0 {
+            self.read_crlf()?;
+        }
+        Ok(n)
+    }
+}
+
+/// Writes the request to the stream and parses the status line and headers of the response.
+/// The connection is always closed by the server after the response is transmitted, so
+/// the stream can't be reused.
+pub fn request<S: Read + Write>(mut stream: S,
+                                method: &str,
+                                host: &str,
+                                path: &str,
+                                headers: &[(String, String)],
+                                body: &[u8]) -> io::Result<Response<S>> {
+    let mut req = format!("{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+                          method, path, host);
+    for (name, value) in headers {
+        req.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if !body.is_empty() || method == "POST" || method == "PUT" {
+        req.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    req.push_str("\r\n");
+    stream.write_all(req.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()?;
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let status = line.split_whitespace()
+            .nth(1)
+            .and_then(|s| s.parse::<u16>().ok())
+            .ok_or(Error::new(ErrorKind::InvalidData, "malformed HTTP status line"))?;
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "connection closed in headers"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(i) = header.find(':') {
+            headers.push((header[..i].trim().to_lowercase(), header[i + 1..].trim().to_string()));
+        }
+    }
+
+    Ok(Response {
+        status: status,
+        headers: headers,
+        reader: reader
+    })
+}
@@ -0,0 +1,633 @@
+//! Minimal Kafka producer client used to emit the events to the brokers. It speaks version 0
+//! of the metadata and produce APIs, and produces uncompressed, gzip or snappy compressed
+//! message sets, which all the brokers before Kafka 4.0 take. The connections are plaintext
+//! or TLS, the certificate of each broker being verified against the address it's reached
+//! at, and are authenticated with SASL `PLAIN`, `SCRAM-SHA-256` or `SCRAM-SHA-512` following
+//! the version 0 `SaslHandshake` request. The connection attempts, and each read and write,
+//! give up after the timeout.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write, Error, ErrorKind};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use flate2;
+use flate2::Crc;
+use flate2::write::GzEncoder;
+use openssl::base64;
+use openssl::hash::{self, MessageDigest};
+use openssl::pkcs5;
+use openssl::pkey::PKey;
+use openssl::rand;
+use openssl::sign::Signer;
+use openssl::ssl::SslConnector;
+use snap;
+use http::Stream;
+
+const API_KEY_PRODUCE: i16 = 0;
+const API_KEY_METADATA: i16 = 3;
+const API_KEY_SASL_HANDSHAKE: i16 = 17;
+/// attributes of the messages wrapping the compressed message sets
+const CODEC_GZIP: i8 = 1;
+const CODEC_SNAPPY: i8 = 2;
+/// header of the snappy framing of the Java client, followed by the version of the framing
+/// and the oldest compatible one
+const SNAPPY_MAGIC: [u8; 8] = [0x82, b'S', b'N', b'A', b'P', b'P', b'Y', 0];
+/// size of the uncompressed blocks of the snappy framing
+const SNAPPY_BLOCK_BYTES: usize = 32 * 1024;
+/// largest response taken, so a garbled size doesn't exhaust the memory
+const MAX_RESPONSE_BYTES: usize = 64 * 1024 * 1024;
+/// error code of the unsupported SASL mechanism
+const UNSUPPORTED_SASL_MECHANISM: i16 = 33;
+
+/// Compression codec of the message sets.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Snappy
+}
+
+/// SASL mechanism and the credentials the connections are authenticated with.
+#[derive(Clone)]
+pub struct Sasl {
+    /// either `PLAIN`, `SCRAM-SHA-256` or `SCRAM-SHA-512`
+    pub mechanism: String,
+    pub username: String,
+    pub password: String
+}
+
+#[derive(Clone)]
+pub struct Settings {
+    /// `host:port` addresses of the brokers the metadata is loaded from
+    pub hosts: Vec<String>,
+    /// client id sent along the requests
+    pub client_id: String,
+    /// TLS connector, and whether the broker certificates have to be issued for the
+    /// addresses they're reached at, the connections are plaintext when absent
+    pub tls: Option<(SslConnector, bool)>,
+    pub sasl: Option<Sasl>,
+    /// acknowledgements the brokers wait for, 0 for none, 1 for the leader and -1 for
+    /// all the in-sync replicas
+    pub required_acks: i16,
+    /// time in milliseconds the brokers wait for the acknowledgements
+    pub ack_timeout: i32,
+    pub compression: Compression,
+    /// bound of the connection attempts, and of each read and write
+    pub timeout: Duration
+}
+
+/// Messages the brokers didn't take, either all of them when the brokers are unreachable,
+/// or the messages of the partitions whose leaders failed or rejected them.
+pub struct Undelivered {
+    pub error: Error,
+    /// positions of the messages in the batch, in ascending order
+    pub positions: Vec<usize>
+}
+
+/// Partition of a topic, and the node id of its leader, negative without a leader.
+struct Partition {
+    id: i32,
+    leader: i32
+}
+
+/// Producer connected lazily to the leaders of the partitions.
+pub struct Producer {
+    settings: Settings,
+    /// addresses of the brokers by their node id, as they advertise them
+    brokers: HashMap<i32, (String, u16)>,
+    /// partitions of the topics, in ascending order of their ids
+    topics: HashMap<String, Vec<Partition>>,
+    /// connections to the leaders by their node id
+    connections: HashMap<i32, Stream>,
+    correlation_id: i32,
+    /// counter spreading the messages without a key over the partitions
+    round_robin: usize
+}
+
+impl Undelivered {
+
+    /// Returns all the messages of the batch as undelivered.
+    pub fn all(error: Error, count: usize) -> Undelivered {
+        Undelivered { error: error, positions: (0..count).collect() }
+    }
+
+    /// Keeps the undelivered messages of the batch.
+    pub fn take<T>(&self, messages: Vec<T>) -> Vec<T> {
+        messages.into_iter()
+            .enumerate()
+            .filter(|&(i, _)| self.positions.binary_search(&i).is_ok())
+            .map(|(_, message)| message)
+            .collect()
+    }
+}
+
+impl Producer {
+
+    /// Loads the metadata of the topics from the first broker that answers.
+    pub fn connect(settings: Settings) -> io::Result<Producer> {
+        let mut producer = Producer {
+            settings: settings,
+            brokers: HashMap::new(),
+            topics: HashMap::new(),
+            connections: HashMap::new(),
+            correlation_id: 0,
+            round_robin: 0
+        };
+        let mut last_error = Error::new(ErrorKind::InvalidInput, "no kafka hosts configured");
+        for host in producer.settings.hosts.clone() {
+            match producer.load_metadata(&host) {
+                Ok(()) => return Ok(producer),
+                Err(e) => last_error = Error::new(e.kind(), format!("{}: {}", host, e))
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Sends the keyed messages to the partitions of the topic, one produce request to each
+    /// leader. The messages with a key go to the partition of its murmur2 hash, like the Java
+    /// client puts them, the ones with an empty key are spread over the partitions that have
+    /// a leader.
+    pub fn send<B: AsRef<[u8]>>(&mut self, topic: &str, messages: &[(String, B)]) -> Result<(), Undelivered> {
+        // ~ positions of the messages by the leader and the partition they're sent to
+        let mut leaders = BTreeMap::<i32, BTreeMap<i32, Vec<usize>>>::new();
+        {
+            let partitions = match self.topics.get(topic) {
+                Some(partitions) if !partitions.is_empty() => partitions,
+                _ => return Err(Undelivered::all(Error::other(format!("unknown kafka topic {}", topic)),
+                                                 messages.len()))
+            };
+            let available = partitions.iter().filter(|p| p.leader >= 0).collect::<Vec<_>>();
+            for (i, (key, _)) in messages.iter().enumerate() {
+                let partition = if key.is_empty() {
+                    if available.is_empty() {
+                        return Err(Undelivered::all(Error::other(format!("no leader for kafka topic {}", topic)),
+                                                    messages.len()));
+                    }
+                    let partition = available[self.round_robin % available.len()];
+                    self.round_robin = self.round_robin.wrapping_add(1);
+                    partition
+                } else {
+                    &partitions[(murmur2(key.as_bytes()) & 0x7fffffff) as usize % partitions.len()]
+                };
+                leaders.entry(partition.leader).or_default().entry(partition.id).or_default().push(i);
+            }
+        }
+        let mut error = None;
+        let mut positions = Vec::new();
+        for (leader, partitions) in leaders {
+            let rejected = if leader < 0 {
+                partitions.keys()
+                    .map(|&id| (id, Error::other(format!("no leader for partition {} of {}", id, topic))))
+                    .collect()
+            } else {
+                match self.produce(leader, topic, messages, &partitions) {
+                    Ok(rejected) => rejected,
+                    Err(e) => {
+                        // ~ the connection is in an unknown state, the messages might have been taken
+                        self.connections.remove(&leader);
+                        partitions.keys().map(|&id| (id, Error::new(e.kind(), e.to_string()))).collect()
+                    }
+                }
+            };
+            for (id, e) in rejected {
+                error = error.or(Some(e));
+                positions.extend_from_slice(&partitions[&id]);
+            }
+        }
+        match error {
+            Some(error) => {
+                positions.sort();
+                Err(Undelivered { error: error, positions: positions })
+            },
+            None => Ok(())
+        }
+    }
+
+    /// Sends the produce request to the leader, and returns the partitions it rejected.
+    fn produce<B: AsRef<[u8]>>(&mut self, leader: i32, topic: &str, messages: &[(String, B)],
+                               partitions: &BTreeMap<i32, Vec<usize>>) -> io::Result<Vec<(i32, Error)>> {
+        let mut body = Vec::new();
+        put_i16(&mut body, self.settings.required_acks);
+        put_i32(&mut body, self.settings.ack_timeout);
+        put_i32(&mut body, 1);
+        put_str(&mut body, topic);
+        put_i32(&mut body, partitions.len() as i32);
+        for (&id, positions) in partitions {
+            let set = positions.iter()
+                .map(|&i| (messages[i].0.as_bytes(), messages[i].1.as_ref()))
+                .collect::<Vec<_>>();
+            let set = message_set(&set, self.settings.compression)?;
+            put_i32(&mut body, id);
+            put_bytes(&mut body, Some(&set));
+        }
+        if !self.connections.contains_key(&leader) {
+            let (host, port) = match self.brokers.get(&leader) {
+                Some(&(ref host, port)) => (host.clone(), port),
+                None => return Err(Error::other(format!("unknown kafka broker {}", leader)))
+            };
+            let stream = connect(&self.settings, &host, port)?;
+            self.connections.insert(leader, stream);
+        }
+        let correlation_id = self.next_correlation_id();
+        let stream = self.connections.get_mut(&leader).unwrap();
+        request(stream, API_KEY_PRODUCE, correlation_id, &self.settings.client_id, &body)?;
+        if self.settings.required_acks == 0 {
+            return Ok(Vec::new());
+        }
+        let response = response(stream, correlation_id)?;
+        let mut r = Decoder(&response);
+        let mut acknowledged = Vec::new();
+        let mut rejected = Vec::new();
+        for _ in 0..r.i32()? {
+            let _topic = r.string()?;
+            for _ in 0..r.i32()? {
+                let id = r.i32()?;
+                let code = r.i16()?;
+                let _offset = r.i64()?;
+                if code != 0 {
+                    rejected.push((id, Error::other(format!("partition {} of {} rejected the messages with \
+                                                             the error code {}", id, topic, code))));
+                }
+                acknowledged.push(id);
+            }
+        }
+        for &id in partitions.keys() {
+            if !acknowledged.contains(&id) {
+                rejected.push((id, Error::other(format!("partition {} of {} wasn't acknowledged", id, topic))));
+            }
+        }
+        Ok(rejected)
+    }
+
+    /// Loads the brokers and the partitions of all the topics from the broker.
+    fn load_metadata(&mut self, host: &str) -> io::Result<()> {
+        let (host, port) = parse_address(host)?;
+        let mut stream = connect(&self.settings, &host, port)?;
+        let correlation_id = self.next_correlation_id();
+        // ~ an empty list of topics asks for all of them
+        let mut body = Vec::new();
+        put_i32(&mut body, 0);
+        request(&mut stream, API_KEY_METADATA, correlation_id, &self.settings.client_id, &body)?;
+        let response = response(&mut stream, correlation_id)?;
+        let mut r = Decoder(&response);
+        let mut brokers = HashMap::new();
+        for _ in 0..r.i32()? {
+            let node_id = r.i32()?;
+            let host = r.string()?;
+            let port = r.i32()?;
+            brokers.insert(node_id, (host, port as u16));
+        }
+        let mut topics = HashMap::new();
+        for _ in 0..r.i32()? {
+            let code = r.i16()?;
+            let name = r.string()?;
+            let mut partitions = Vec::new();
+            for _ in 0..r.i32()? {
+                let _code = r.i16()?;
+                let id = r.i32()?;
+                let leader = r.i32()?;
+                for _ in 0..2 {
+                    // ~ replicas and in-sync replicas
+                    for _ in 0..r.i32()? {
+                        r.i32()?;
+                    }
+                }
+                partitions.push(Partition { id: id, leader: leader });
+            }
+            // ~ the topics that errored, e.g. while they're created, are left out
+            if code == 0 {
+                partitions.sort_by_key(|p| p.id);
+                topics.insert(name, partitions);
+            }
+        }
+        self.brokers = brokers;
+        self.topics = topics;
+        Ok(())
+    }
+
+    fn next_correlation_id(&mut self) -> i32 {
+        self.correlation_id = self.correlation_id.wrapping_add(1);
+        self.correlation_id
+    }
+}
+
+/// Splits the `host:port` address, the IPv6 addresses being enclosed in brackets.
+fn parse_address(address: &str) -> io::Result<(String, u16)> {
+    let invalid = || Error::new(ErrorKind::InvalidInput, format!("invalid kafka host {}", address));
+    let i = address.rfind(':').ok_or_else(invalid)?;
+    let port = address[i + 1..].parse::<u16>().map_err(|_| invalid())?;
+    let host = address[..i].trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    Ok((host.to_string(), port))
+}
+
+/// Opens a connection to the broker, performs the TLS handshake and authenticates.
+fn connect(settings: &Settings, host: &str, port: u16) -> io::Result<Stream> {
+    let mut last_error = Error::new(ErrorKind::InvalidInput, format!("no address resolved for {}", host));
+    for addr in (host, port).to_socket_addrs()? {
+        let stream = match TcpStream::connect_timeout(&addr, settings.timeout) {
+            Ok(stream) => stream,
+            Err(e) => {
+                last_error = e;
+                continue;
+            }
+        };
+        // ~ the brokers hold the produce responses until the replicas acknowledge the messages
+        let ack_timeout = Duration::from_millis(settings.ack_timeout.max(0) as u64);
+        stream.set_read_timeout(Some(settings.timeout + ack_timeout))?;
+        stream.set_write_timeout(Some(settings.timeout))?;
+        let mut stream = match settings.tls {
+            Some((ref connector, verify_hostname)) => {
+                let tls = connector.configure()?
+                    .verify_hostname(verify_hostname)
+                    .connect(host, stream)
+                    .map_err(|e| Error::other(format!("TLS handshake with {} failed: {}", host, e)))?;
+                Stream::Tls(tls)
+            },
+            None => Stream::Plain(stream)
+        };
+        if let Some(ref sasl) = settings.sasl {
+            authenticate(&mut stream, &settings.client_id, sasl)
+                .map_err(|e| Error::new(e.kind(), format!("SASL authentication with {} failed: {}", host, e)))?;
+        }
+        return Ok(stream);
+    }
+    Err(last_error)
+}
+
+/// Agrees on the SASL mechanism with the broker, which then exchanges the SASL tokens
+/// framed by their size, and closes the connection if the authentication fails.
+fn authenticate(stream: &mut Stream, client_id: &str, sasl: &Sasl) -> io::Result<()> {
+    let mut body = Vec::new();
+    put_str(&mut body, &sasl.mechanism);
+    request(stream, API_KEY_SASL_HANDSHAKE, 0, client_id, &body)?;
+    let response = response(stream, 0)?;
+    let mut r = Decoder(&response);
+    let code = r.i16()?;
+    if code != 0 {
+        let enabled = (0..r.i32().unwrap_or(0))
+            .filter_map(|_| r.string().ok())
+            .collect::<Vec<_>>();
+        return Err(match code {
+            UNSUPPORTED_SASL_MECHANISM =>
+                Error::other(format!("the broker doesn't enable {}, only {}", sasl.mechanism, enabled.join(", "))),
+            _ => Error::other(format!("the handshake failed with the error code {}", code))
+        });
+    }
+    let result = match sasl.mechanism.as_str() {
+        "PLAIN" => {
+            let token = format!("\0{}\0{}", sasl.username, sasl.password);
+            exchange(stream, token.as_bytes()).map(|_| ())
+        },
+        "SCRAM-SHA-256" => scram(stream, sasl, MessageDigest::sha256()),
+        "SCRAM-SHA-512" => scram(stream, sasl, MessageDigest::sha512()),
+        mechanism => Err(Error::new(ErrorKind::InvalidInput, format!("unsupported mechanism {}", mechanism)))
+    };
+    // ~ the broker rejects the credentials by closing the connection
+    result.map_err(|e| match e.kind() {
+        ErrorKind::UnexpectedEof | ErrorKind::ConnectionReset =>
+            Error::new(ErrorKind::PermissionDenied, "the broker rejected the credentials"),
+        _ => e
+    })
+}
+
+/// Authenticates with the SCRAM exchange of RFC 5802, without channel binding, and verifies
+/// the signature proving the broker knows the credentials too.
+fn scram(stream: &mut Stream, sasl: &Sasl, digest: MessageDigest) -> io::Result<()> {
+    let mut nonce = [0u8; 24];
+    rand::rand_bytes(&mut nonce)?;
+    let nonce = base64::encode_block(&nonce);
+    let username = sasl.username.replace('=', "=3D").replace(',', "=2C");
+    let client_first = format!("n={},r={}", username, nonce);
+    let server_first = exchange(stream, format!("n,,{}", client_first).as_bytes())?;
+    let server_first = String::from_utf8(server_first)
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "malformed server-first-message"))?;
+    let server_nonce = scram_attribute(&server_first, 'r')?;
+    if !server_nonce.starts_with(&nonce) {
+        return Err(Error::new(ErrorKind::InvalidData, "the server nonce doesn't extend the client nonce"));
+    }
+    let salt = base64::decode_block(scram_attribute(&server_first, 's')?)?;
+    let iterations = scram_attribute(&server_first, 'i')?.parse::<usize>()
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "malformed iteration count"))?;
+    let mut salted = vec![0u8; digest.size()];
+    pkcs5::pbkdf2_hmac(sasl.password.as_bytes(), &salt, iterations, digest, &mut salted)?;
+    let client_key = hmac(digest, &salted, b"Client Key")?;
+    let stored_key = hash::hash(digest, &client_key)?;
+    // ~ `biws` is the base64 encoded `n,,` header
+    let client_final = format!("c=biws,r={}", server_nonce);
+    let auth_message = format!("{},{},{}", client_first, server_first, client_final);
+    let signature = hmac(digest, &stored_key, auth_message.as_bytes())?;
+    let proof = client_key.iter().zip(signature).map(|(k, s)| k ^ s).collect::<Vec<_>>();
+    let server_final = exchange(stream, format!("{},p={}", client_final, base64::encode_block(&proof)).as_bytes())?;
+    let server_final = String::from_utf8(server_final)
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "malformed server-final-message"))?;
+    if let Ok(e) = scram_attribute(&server_final, 'e') {
+        return Err(Error::new(ErrorKind::PermissionDenied, format!("the broker rejected the credentials: {}", e)));
+    }
+    let server_key = hmac(digest, &salted, b"Server Key")?;
+    let expected = base64::encode_block(&hmac(digest, &server_key, auth_message.as_bytes())?);
+    if scram_attribute(&server_final, 'v')? != expected {
+        return Err(Error::new(ErrorKind::PermissionDenied, "the broker signature doesn't match the credentials"));
+    }
+    Ok(())
+}
+
+/// Returns the value of the attribute of the SCRAM message.
+fn scram_attribute(message: &str, name: char) -> io::Result<&str> {
+    message.split(',')
+        .find(|attribute| attribute.starts_with(name) && attribute[name.len_utf8()..].starts_with('='))
+        .map(|attribute| &attribute[name.len_utf8() + 1..])
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("missing `{}` in {}", name, message)))
+}
+
+fn hmac(digest: MessageDigest, key: &[u8], data: &[u8]) -> io::Result<Vec<u8>> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(digest, &key)?;
+    signer.update(data)?;
+    Ok(signer.sign_to_vec()?)
+}
+
+/// Sends the SASL token framed by its size, and returns the token the broker answers with.
+fn exchange(stream: &mut Stream, token: &[u8]) -> io::Result<Vec<u8>> {
+    let mut frame = Vec::with_capacity(token.len() + 4);
+    put_bytes(&mut frame, Some(token));
+    stream.write_all(&frame)?;
+    stream.flush()?;
+    read_frame(stream)
+}
+
+/// Writes the request with the header of the API.
+fn request(stream: &mut Stream, api_key: i16, correlation_id: i32, client_id: &str, body: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(body.len() + client_id.len() + 14);
+    put_i32(&mut frame, 0);
+    put_i16(&mut frame, api_key);
+    put_i16(&mut frame, 0);
+    put_i32(&mut frame, correlation_id);
+    put_str(&mut frame, client_id);
+    frame.extend_from_slice(body);
+    let size = (frame.len() - 4) as i32;
+    frame[..4].copy_from_slice(&size.to_be_bytes());
+    stream.write_all(&frame)?;
+    stream.flush()
+}
+
+/// Reads the response to the request, and returns its body.
+fn response(stream: &mut Stream, correlation_id: i32) -> io::Result<Vec<u8>> {
+    let mut response = read_frame(stream)?;
+    if response.len() < 4 || response[..4] != correlation_id.to_be_bytes() {
+        return Err(Error::new(ErrorKind::InvalidData, "the response doesn't match the request"));
+    }
+    response.drain(..4);
+    Ok(response)
+}
+
+fn read_frame(stream: &mut Stream) -> io::Result<Vec<u8>> {
+    let mut size = [0u8; 4];
+    stream.read_exact(&mut size)?;
+    let size = i32::from_be_bytes(size);
+    if size < 0 || size as usize > MAX_RESPONSE_BYTES {
+        return Err(Error::new(ErrorKind::InvalidData, format!("invalid response size {}", size)));
+    }
+    let mut frame = vec![0u8; size as usize];
+    stream.read_exact(&mut frame)?;
+    Ok(frame)
+}
+
+/// Encodes the keyed messages as the message set, wrapped in a single message when it's
+/// compressed. The empty keys are encoded as null keys.
+fn message_set(messages: &[(&[u8], &[u8])], compression: Compression) -> io::Result<Vec<u8>> {
+    let mut set = Vec::new();
+    for (offset, &(key, value)) in messages.iter().enumerate() {
+        let key = if key.is_empty() { None } else { Some(key) };
+        put_message(&mut set, offset as i64, 0, key, value);
+    }
+    let (codec, value) = match compression {
+        Compression::None => return Ok(set),
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::Default);
+            encoder.write_all(&set)?;
+            (CODEC_GZIP, encoder.finish()?)
+        },
+        Compression::Snappy => (CODEC_SNAPPY, snappy(&set)?)
+    };
+    let mut wrapper = Vec::new();
+    put_message(&mut wrapper, 0, codec, None, &value);
+    Ok(wrapper)
+}
+
+/// Compresses the message set in the snappy framing of the Java client, which the brokers
+/// expect: the header followed by the compressed blocks prefixed by their size.
+fn snappy(set: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = SNAPPY_MAGIC.to_vec();
+    put_i32(&mut out, 1);
+    put_i32(&mut out, 1);
+    let mut encoder = snap::raw::Encoder::new();
+    for block in set.chunks(SNAPPY_BLOCK_BYTES) {
+        let compressed = encoder.compress_vec(block).map_err(|e| Error::other(e.to_string()))?;
+        put_bytes(&mut out, Some(&compressed));
+    }
+    Ok(out)
+}
+
+/// Appends the message, version 0 of the format, at the offset of the message set.
+fn put_message(out: &mut Vec<u8>, offset: i64, attributes: i8, key: Option<&[u8]>, value: &[u8]) {
+    let mut message = vec![0u8, attributes as u8];
+    put_bytes(&mut message, key);
+    put_bytes(&mut message, Some(value));
+    let mut crc = Crc::new();
+    crc.update(&message);
+    out.extend_from_slice(&offset.to_be_bytes());
+    put_i32(out, message.len() as i32 + 4);
+    out.extend_from_slice(&crc.sum().to_be_bytes());
+    out.extend_from_slice(&message);
+}
+
+/// murmur2 hash of the Java client, which keys are assigned the partitions by.
+fn murmur2(data: &[u8]) -> u32 {
+    const M: u32 = 0x5bd1e995;
+    let mut h = 0x9747b28c ^ data.len() as u32;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> 24;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M) ^ k;
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, &b) in tail.iter().enumerate() {
+            h ^= (b as u32) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^ (h >> 15)
+}
+
+/// Reader of the responses, failing on the truncated ones.
+struct Decoder<'a>(&'a [u8]);
+
+impl<'a> Decoder<'a> {
+
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(Error::new(ErrorKind::InvalidData, "truncated response"));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn i16(&mut self) -> io::Result<i16> {
+        let b = self.take(2)?;
+        Ok(i16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        let b = self.take(4)?;
+        Ok(i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i64(&mut self) -> io::Result<i64> {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(i64::from_be_bytes(b))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.i16()?;
+        if len < 0 {
+            return Ok(String::new());
+        }
+        String::from_utf8(self.take(len as usize)?.to_vec())
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "malformed string in the response"))
+    }
+}
+
+fn put_i16(out: &mut Vec<u8>, v: i16) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_i32(out: &mut Vec<u8>, v: i32) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    put_i16(out, s.len() as i16);
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// Appends the bytes prefixed by their size, -1 for null.
+fn put_bytes(out: &mut Vec<u8>, bytes: Option<&[u8]>) {
+    match bytes {
+        Some(bytes) => {
+            put_i32(out, bytes.len() as i32);
+            out.extend_from_slice(bytes);
+        },
+        None => put_i32(out, -1)
+    }
+}
@@ -3,20 +3,32 @@ extern crate nix;
 extern crate libc;
 extern crate num_cpus;
 extern crate chrono;
-extern crate kafka;
+extern crate openssl;
 
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
 extern crate serde_json;
 extern crate toml;
 #[macro_use]
 extern crate nom;
 extern crate glob;
+extern crate flate2;
+extern crate zstd;
+extern crate snap;
 
+#[macro_use]
+pub mod log;
 pub mod collector;
 pub mod syscall;
 pub mod aggregator;
+pub mod spool;
+pub mod parquet;
 pub mod config;
+pub mod serializer;
 pub mod state;
+pub mod enricher;
 mod error;
 mod value;
+mod http;
+mod kafka;
@@ -0,0 +1,69 @@
+//! Reports the errors of the aggregators and enrichers on the standard error. Errors on the
+//! event path repeat for every event while a destination is failing, so each call site of
+//! `log_error!` writes at most one message per interval, and its next message counts the
+//! messages suppressed in between.
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// interval in milliseconds between two messages of the same call site
+pub const INTERVAL_MS: u64 = 10000;
+
+/// Writes the message to the standard error, unless the call site already wrote one
+/// within the interval, e.g.
+///
+/// ```ignore
+/// log_error!("unable to write the event to {}: {}", path.display(), e);
+/// ```
+macro_rules! log_error {
+    ($($arg:tt)*) => {{
+        static LIMIT: $crate::log::RateLimit = $crate::log::RateLimit::new($crate::log::INTERVAL_MS);
+        LIMIT.log(format_args!($($arg)*));
+    }};
+}
+
+pub struct RateLimit {
+    interval_ms: u64,
+    /// time in milliseconds since the epoch from which the next message is written
+    next_ms: AtomicU64,
+    /// number of the messages suppressed since the last one written
+    suppressed: AtomicU64
+}
+
+impl RateLimit {
+
+    pub const fn new(interval_ms: u64) -> RateLimit {
+        RateLimit {
+            interval_ms: interval_ms,
+            next_ms: AtomicU64::new(0),
+            suppressed: AtomicU64::new(0)
+        }
+    }
+
+    /// Returns the number of the messages suppressed since the last one if the message
+    /// is to be written, or `None` if it's suppressed.
+    pub fn check(&self) -> Option<u64> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+        let next = self.next_ms.load(Ordering::SeqCst);
+        // ~ of the threads racing for the message, only the one swapping the deadline writes it
+        if now < next || self.next_ms.compare_exchange(next, now + self.interval_ms,
+                                                       Ordering::SeqCst, Ordering::SeqCst).is_err() {
+            self.suppressed.fetch_add(1, Ordering::SeqCst);
+            return None;
+        }
+        Some(self.suppressed.swap(0, Ordering::SeqCst))
+    }
+
+    pub fn log(&self, args: fmt::Arguments) {
+        if let Some(suppressed) = self.check() {
+            let stderr = io::stderr();
+            let mut err = stderr.lock();
+            let _ = if suppressed > 0 {
+                writeln!(err, "{} ({} similar messages suppressed)", args, suppressed)
+            } else {
+                writeln!(err, "{}", args)
+            };
+        }
+    }
+}
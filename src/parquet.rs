@@ -0,0 +1,721 @@
+//! Writer of the syscall events as Parquet files with one row per event. The header fields of
+//! the event are stored in typed columns, while the parameters are stored as the JSON column.
+//!
+//! The rows are buffered until the row group is full, and each column of the row group is
+//! written as the single `PLAIN` encoded data page, optionally compressed by `gzip` or `zstd`.
+//! The integer columns carry the min/max statistics, so the readers can skip the row groups
+//! outside of the queried time range. The file metadata is appended after each row group, so
+//! the readers, which only look at the last footer, can read the rows written up to the last
+//! complete row group even if the writer never finishes. `readable_len` finds that footer
+//! in the file left by a crashed writer.
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use chrono::{DateTime, UTC};
+use flate2;
+use flate2::write::GzEncoder;
+use serde_json::{self, Value as Json};
+use zstd;
+use syscall::{SyscallInfo, Direction, Category};
+use syscall::syscall_table::SyscallTable;
+
+const MAGIC: &'static [u8] = b"PAR1";
+
+// ~ physical types
+const INT32: i32 = 1;
+const INT64: i32 = 2;
+const BYTE_ARRAY: i32 = 6;
+
+// ~ encodings
+const PLAIN: i32 = 0;
+const RLE: i32 = 3;
+
+// ~ thrift compact protocol types
+const T_BOOL_TRUE: u8 = 1;
+const T_BOOL_FALSE: u8 = 2;
+const T_BYTE: u8 = 3;
+const T_I16: u8 = 4;
+const T_I32: u8 = 5;
+const T_I64: u8 = 6;
+const T_DOUBLE: u8 = 7;
+const T_BINARY: u8 = 8;
+const T_LIST: u8 = 9;
+const T_SET: u8 = 10;
+const T_MAP: u8 = 11;
+const T_STRUCT: u8 = 12;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Logical {
+    None,
+    String,
+    Json,
+    TimestampNanos
+}
+
+/// names, physical types, whether the column is nullable and the logical types of the columns
+const COLUMNS: &'static [(&'static str, i32, bool, Logical)] = &[
+    ("ts", INT64, false, Logical::TimestampNanos),
+    ("tid", INT64, false, Logical::None),
+    ("pid", INT64, true, Logical::None),
+    ("cpu", INT32, false, Logical::None),
+    ("dir", BYTE_ARRAY, false, Logical::String),
+    ("comm", BYTE_ARRAY, true, Logical::String),
+    ("syscall", BYTE_ARRAY, false, Logical::String),
+    ("category", BYTE_ARRAY, false, Logical::String),
+    ("result", INT64, true, Logical::None),
+    ("resolved_path", BYTE_ARRAY, true, Logical::String),
+    ("container_id", BYTE_ARRAY, true, Logical::String),
+    ("params", BYTE_ARRAY, false, Logical::Json)
+];
+
+/// Row of the syscall event.
+pub struct Row {
+    /// timestamp in nanoseconds from epoch
+    pub ts: i64,
+    pub tid: i64,
+    pub pid: Option<i64>,
+    pub cpu: i32,
+    /// direction of the event, either `enter` or `exit`
+    pub dir: &'static str,
+    pub comm: Option<String>,
+    pub syscall: String,
+    pub category: &'static str,
+    /// return value of the syscall, taken from the `res` parameter, or the `fd` parameter
+    /// of the syscalls returning the file descriptor, on exit
+    pub result: Option<i64>,
+    pub resolved_path: Option<String>,
+    pub container_id: Option<String>,
+    /// parameters of the syscall as the JSON object
+    pub params: String
+}
+
+impl Row {
+
+    pub fn from_info(info: &SyscallInfo) -> Row {
+        let params = serde_json::to_value(&info.params).unwrap_or(Json::Null);
+        Row {
+            ts: info.ts.timestamp() * 1000000000 + info.ts.timestamp_subsec_nanos() as i64,
+            tid: info.tid as i64,
+            pid: info.pid.map(|pid| pid as i64),
+            cpu: info.cpu as i32,
+            dir: if info.dir == Direction::Enter { "enter" } else { "exit" },
+            comm: info.comm.clone(),
+            syscall: info.name.clone(),
+            category: info.category.name(),
+            result: if info.dir == Direction::Exit { result(&params) } else { None },
+            resolved_path: info.resolved_path.clone(),
+            container_id: info.container.as_ref().map(|c| c.id.clone()),
+            params: params.to_string()
+        }
+    }
+
+    /// Builds the row from the event in the JSON form, as written by the JSON serializer.
+    /// The category, which isn't part of the JSON form, is looked up in the syscall table.
+    /// Returns `None` if the mandatory fields are missing.
+    pub fn from_json(event: &Json, table: &SyscallTable) -> Option<Row> {
+        let ts = match event.get("ts").and_then(|ts| ts.as_str()).and_then(|ts| ts.parse::<DateTime<UTC>>().ok()) {
+            Some(ts) => ts.timestamp() * 1000000000 + ts.timestamp_subsec_nanos() as i64,
+            None => return None
+        };
+        let syscall = match event.get("name").and_then(|n| n.as_str()) {
+            Some(name) => name.to_string(),
+            None => return None
+        };
+        let exit = event.get("dir").and_then(|d| d.as_str()) == Some("Exit");
+        let params = event.get("params").cloned().unwrap_or(Json::Null);
+        let string = |name: &str| event.get(name).and_then(|v| v.as_str()).map(|v| v.to_string());
+        Some(Row {
+            ts: ts,
+            tid: event.get("tid").and_then(|t| t.as_i64())?,
+            pid: event.get("pid").and_then(|p| p.as_i64()),
+            cpu: event.get("cpu").and_then(|c| c.as_i64()).unwrap_or(0) as i32,
+            dir: if exit { "exit" } else { "enter" },
+            comm: string("comm"),
+            category: table.iter()
+                .find(|meta| meta.name == syscall)
+                .map_or(Category::Unknown, |meta| meta.category)
+                .name(),
+            syscall: syscall,
+            result: if exit { result(&params) } else { None },
+            resolved_path: string("resolved_path"),
+            container_id: event.get("container").and_then(|c| c.get("id")).and_then(|id| id.as_str())
+                .map(|id| id.to_string()),
+            params: params.to_string()
+        })
+    }
+}
+
+fn result(params: &Json) -> Option<i64> {
+    params.get("res").or(params.get("fd")).and_then(|v| v.as_i64())
+}
+
+/// Values of the column in the pending row group.
+struct Column {
+    /// PLAIN encoded values, without the nulls
+    values: Vec<u8>,
+    /// whether the value of each row is present
+    defined: Vec<bool>,
+    /// bounds of the integer values
+    min: Option<i64>,
+    max: Option<i64>
+}
+
+impl Column {
+
+    fn new() -> Column {
+        Column { values: Vec::new(), defined: Vec::new(), min: None, max: None }
+    }
+
+    fn int(&mut self, v: Option<i64>, physical: i32) {
+        self.defined.push(v.is_some());
+        if let Some(v) = v {
+            if physical == INT32 {
+                self.values.extend_from_slice(&le(v as u64, 4));
+            } else {
+                self.values.extend_from_slice(&le(v as u64, 8));
+            }
+            self.min = Some(self.min.map_or(v, |min| ::std::cmp::min(min, v)));
+            self.max = Some(self.max.map_or(v, |max| ::std::cmp::max(max, v)));
+        }
+    }
+
+    fn bytes(&mut self, v: Option<&str>) {
+        self.defined.push(v.is_some());
+        if let Some(v) = v {
+            self.values.extend_from_slice(&le(v.len() as u64, 4));
+            self.values.extend_from_slice(v.as_bytes());
+        }
+    }
+}
+
+fn le(v: u64, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (v >> (i * 8)) as u8).collect()
+}
+
+/// Metadata of the column chunk written to the file.
+struct ChunkMeta {
+    offset: i64,
+    num_values: i64,
+    uncompressed_size: i64,
+    compressed_size: i64,
+    null_count: i64,
+    min: Option<i64>,
+    max: Option<i64>
+}
+
+struct RowGroupMeta {
+    chunks: Vec<ChunkMeta>,
+    num_rows: i64,
+    total_byte_size: i64
+}
+
+pub struct ParquetWriter<W: Write> {
+    out: W,
+    /// compression codec of the pages, either `none`, `gzip` or `zstd`
+    compression: String,
+    row_group_size: usize,
+    columns: Vec<Column>,
+    /// rows in the pending row group
+    rows: usize,
+    /// bytes written to the file so far
+    offset: u64,
+    row_groups: Vec<RowGroupMeta>
+}
+
+impl<W: Write> ParquetWriter<W> {
+
+    pub fn new(mut out: W, compression: &str, row_group_size: usize) -> io::Result<ParquetWriter<W>> {
+        out.write_all(MAGIC)?;
+        Ok(ParquetWriter {
+            out: out,
+            compression: compression.to_string(),
+            row_group_size: row_group_size,
+            columns: COLUMNS.iter().map(|_| Column::new()).collect(),
+            rows: 0,
+            offset: MAGIC.len() as u64,
+            row_groups: Vec::new()
+        })
+    }
+
+    /// Returns the number of bytes written to the file, and the size of the values
+    /// buffered in the pending row group.
+    pub fn bytes(&self) -> (u64, u64) {
+        (self.offset, self.columns.iter().map(|c| c.values.len() as u64).sum())
+    }
+
+    pub fn write(&mut self, row: &Row) -> io::Result<()> {
+        {
+            let c = &mut self.columns;
+            c[0].int(Some(row.ts), INT64);
+            c[1].int(Some(row.tid), INT64);
+            c[2].int(row.pid, INT64);
+            c[3].int(Some(row.cpu as i64), INT32);
+            c[4].bytes(Some(row.dir));
+            c[5].bytes(row.comm.as_deref());
+            c[6].bytes(Some(row.syscall.as_str()));
+            c[7].bytes(Some(row.category));
+            c[8].int(row.result, INT64);
+            c[9].bytes(row.resolved_path.as_deref());
+            c[10].bytes(row.container_id.as_deref());
+            c[11].bytes(Some(row.params.as_str()));
+        }
+        self.rows += 1;
+        if self.rows >= self.row_group_size {
+            self.flush_row_group()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the pending rows as the row group.
+    pub fn flush_row_group(&mut self) -> io::Result<()> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        let mut chunks = Vec::with_capacity(COLUMNS.len());
+        let mut total_byte_size = 0;
+        for (i, &(_, _, optional, _)) in COLUMNS.iter().enumerate() {
+            let column = ::std::mem::replace(&mut self.columns[i], Column::new());
+            let mut page = Vec::new();
+            if optional {
+                let levels = definition_levels(&column.defined);
+                page.extend_from_slice(&le(levels.len() as u64, 4));
+                page.extend_from_slice(&levels);
+            }
+            page.extend_from_slice(&column.values);
+            let body = self.compress(&page)?;
+
+            let mut header = Thrift::new();
+            header.i32(1, 0);
+            header.i32(2, page.len() as i32);
+            header.i32(3, body.len() as i32);
+            header.begin(5);
+            header.i32(1, self.rows as i32);
+            header.i32(2, PLAIN);
+            header.i32(3, RLE);
+            header.i32(4, RLE);
+            header.end();
+            let header = header.finish();
+
+            self.out.write_all(&header)?;
+            self.out.write_all(&body)?;
+            chunks.push(ChunkMeta {
+                offset: self.offset as i64,
+                num_values: self.rows as i64,
+                uncompressed_size: (header.len() + page.len()) as i64,
+                compressed_size: (header.len() + body.len()) as i64,
+                null_count: column.defined.iter().filter(|d| !**d).count() as i64,
+                min: column.min,
+                max: column.max
+            });
+            self.offset += (header.len() + body.len()) as u64;
+            total_byte_size += (header.len() + page.len()) as i64;
+        }
+        self.row_groups.push(RowGroupMeta {
+            chunks: chunks,
+            num_rows: self.rows as i64,
+            total_byte_size: total_byte_size
+        });
+        self.rows = 0;
+        self.write_footer()
+    }
+
+    fn compress(&self, page: &[u8]) -> io::Result<Vec<u8>> {
+        match self.compression.as_str() {
+            "gzip" => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::Default);
+                encoder.write_all(page)?;
+                encoder.finish()
+            },
+            "zstd" => zstd::stream::encode_all(page, 0),
+            _ => Ok(page.to_vec())
+        }
+    }
+
+    fn codec(&self) -> i32 {
+        match self.compression.as_str() {
+            "gzip" => 2,
+            "zstd" => 6,
+            _ => 0
+        }
+    }
+
+    /// Writes the pending rows, or the file metadata of the file without rows, and returns
+    /// the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.rows > 0 {
+            self.flush_row_group()?;
+        } else if self.row_groups.is_empty() {
+            self.write_footer()?;
+        }
+        Ok(self.out)
+    }
+
+    /// Appends the file metadata describing the row groups written so far, followed by its
+    /// length and the magic number, and flushes the file.
+    fn write_footer(&mut self) -> io::Result<()> {
+        let meta = self.metadata();
+        self.out.write_all(&meta)?;
+        self.out.write_all(&le(meta.len() as u64, 4))?;
+        self.out.write_all(MAGIC)?;
+        self.out.flush()?;
+        self.offset += (meta.len() + 4 + MAGIC.len()) as u64;
+        Ok(())
+    }
+
+    fn metadata(&self) -> Vec<u8> {
+        let mut meta = Thrift::new();
+        meta.i32(1, 1);
+        meta.list(2, T_STRUCT, COLUMNS.len() + 1);
+        meta.begin_element();
+        meta.binary(4, b"schema");
+        meta.i32(5, COLUMNS.len() as i32);
+        meta.end();
+        for &(name, physical, optional, logical) in COLUMNS {
+            meta.begin_element();
+            meta.i32(1, physical);
+            meta.i32(3, if optional { 1 } else { 0 });
+            meta.binary(4, name.as_bytes());
+            // ~ converted types of the older readers, UTF8 and JSON
+            match logical {
+                Logical::String => meta.i32(6, 0),
+                Logical::Json => meta.i32(6, 19),
+                _ => {}
+            }
+            match logical {
+                Logical::String => {
+                    meta.begin(10);
+                    meta.begin(1);
+                    meta.end();
+                    meta.end();
+                },
+                Logical::Json => {
+                    meta.begin(10);
+                    meta.begin(12);
+                    meta.end();
+                    meta.end();
+                },
+                Logical::TimestampNanos => {
+                    meta.begin(10);
+                    meta.begin(8);
+                    meta.bool(1, true);
+                    meta.begin(2);
+                    meta.begin(3);
+                    meta.end();
+                    meta.end();
+                    meta.end();
+                    meta.end();
+                },
+                Logical::None => {}
+            }
+            meta.end();
+        }
+        meta.i64(3, self.row_groups.iter().map(|g| g.num_rows).sum());
+        meta.list(4, T_STRUCT, self.row_groups.len());
+        for group in &self.row_groups {
+            meta.begin_element();
+            meta.list(1, T_STRUCT, group.chunks.len());
+            for (chunk, &(name, physical, _, _)) in group.chunks.iter().zip(COLUMNS.iter()) {
+                meta.begin_element();
+                meta.i64(2, chunk.offset);
+                meta.begin(3);
+                meta.i32(1, physical);
+                meta.list(2, T_I32, 2);
+                meta.i32_element(PLAIN);
+                meta.i32_element(RLE);
+                meta.list(3, T_BINARY, 1);
+                meta.binary_element(name.as_bytes());
+                meta.i32(4, self.codec());
+                meta.i64(5, chunk.num_values);
+                meta.i64(6, chunk.uncompressed_size);
+                meta.i64(7, chunk.compressed_size);
+                meta.i64(9, chunk.offset);
+                if let (Some(min), Some(max)) = (chunk.min, chunk.max) {
+                    let len = if physical == INT32 { 4 } else { 8 };
+                    meta.begin(12);
+                    meta.i64(3, chunk.null_count);
+                    meta.binary(5, &le(max as u64, len));
+                    meta.binary(6, &le(min as u64, len));
+                    meta.end();
+                }
+                meta.end();
+                meta.end();
+            }
+            meta.i64(2, group.total_byte_size);
+            meta.i64(3, group.num_rows);
+            meta.end();
+        }
+        meta.binary(6, format!("cubostratusc version {}", env!("CARGO_PKG_VERSION")).as_bytes());
+        meta.finish()
+    }
+}
+
+/// Encodes the definition levels of the nullable column with the RLE/bit-packing hybrid
+/// encoding, as the bit-packed runs of one bit wide levels.
+fn definition_levels(defined: &[bool]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let groups = defined.len().div_ceil(8);
+    let mut header = (groups as u64) << 1 | 1;
+    while header >= 0x80 {
+        buf.push(header as u8 | 0x80);
+        header >>= 7;
+    }
+    buf.push(header as u8);
+    for group in defined.chunks(8) {
+        let mut byte = 0u8;
+        for (i, &d) in group.iter().enumerate() {
+            if d {
+                byte |= 1 << i;
+            }
+        }
+        buf.push(byte);
+    }
+    buf
+}
+
+/// size of the windows the file is scanned backwards in
+const SCAN_WINDOW: u64 = 65536;
+
+/// Returns the length of the readable part of the file left by the writer that didn't
+/// finish, i.e. the end of its last footer, or `None` if it doesn't hold any footer. The
+/// footer candidates are validated by decoding their metadata.
+pub fn readable_len<R: Read + Seek>(r: &mut R) -> io::Result<Option<u64>> {
+    let len = r.seek(SeekFrom::End(0))?;
+    let mut end = len;
+    while end >= (2 * MAGIC.len() + 4) as u64 {
+        // ~ the windows overlap so the magic number spanning two windows is found
+        let start = end.saturating_sub(SCAN_WINDOW);
+        let mut window = vec![0u8; (end - start) as usize];
+        r.seek(SeekFrom::Start(start))?;
+        r.read_exact(&mut window)?;
+        for i in (0..window.len().saturating_sub(MAGIC.len() - 1)).rev() {
+            if &window[i..i + MAGIC.len()] != MAGIC {
+                continue;
+            }
+            let magic_at = start + i as u64;
+            if magic_at < (MAGIC.len() + 4) as u64 {
+                continue;
+            }
+            let mut size = [0u8; 4];
+            r.seek(SeekFrom::Start(magic_at - 4))?;
+            r.read_exact(&mut size)?;
+            let size = size.iter().rev().fold(0u64, |acc, b| acc << 8 | *b as u64);
+            if size + 4 + (MAGIC.len() as u64) > magic_at {
+                continue;
+            }
+            let mut meta = vec![0u8; size as usize];
+            r.seek(SeekFrom::Start(magic_at - 4 - size))?;
+            r.read_exact(&mut meta)?;
+            let mut reader = ThriftReader { buf: &meta, pos: 0, depth: 0 };
+            if reader.skip(T_STRUCT).is_some() && reader.pos == meta.len() {
+                return Ok(Some(magic_at + MAGIC.len() as u64));
+            }
+        }
+        if start == 0 {
+            break;
+        }
+        end = start + MAGIC.len() as u64 - 1;
+    }
+    Ok(None)
+}
+
+/// nesting limit of the decoded structures and lists, deeper than the file metadata goes
+const MAX_THRIFT_DEPTH: usize = 16;
+
+/// Decoder of the thrift compact protocol, which only skips the values to validate them.
+struct ThriftReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    /// nesting level of the value being skipped
+    depth: usize
+}
+
+impl<'a> ThriftReader<'a> {
+
+    fn byte(&mut self) -> Option<u8> {
+        let b = self.buf.get(self.pos).cloned();
+        self.pos += 1;
+        b
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut v = 0u64;
+        for shift in 0..10 {
+            let b = self.byte()?;
+            v |= ((b & 0x7f) as u64) << (shift * 7);
+            if b & 0x80 == 0 {
+                return Some(v);
+            }
+        }
+        None
+    }
+
+    fn advance(&mut self, n: u64) -> Option<()> {
+        if n > (self.buf.len() - self.pos.min(self.buf.len())) as u64 {
+            return None;
+        }
+        self.pos += n as usize;
+        Some(())
+    }
+
+    /// Skips the value of the compact protocol type.
+    fn skip(&mut self, kind: u8) -> Option<()> {
+        if self.depth >= MAX_THRIFT_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        let skipped = self.skip_value(kind);
+        self.depth -= 1;
+        skipped
+    }
+
+    fn skip_value(&mut self, kind: u8) -> Option<()> {
+        match kind {
+            T_BOOL_TRUE | T_BOOL_FALSE => Some(()),
+            T_BYTE => self.advance(1),
+            T_I16 | T_I32 | T_I64 => self.varint().map(|_| ()),
+            T_DOUBLE => self.advance(8),
+            T_BINARY => {
+                let len = self.varint()?;
+                self.advance(len)
+            },
+            T_LIST | T_SET => {
+                let header = self.byte()?;
+                let len = match header >> 4 {
+                    15 => self.varint()?,
+                    len => len as u64
+                };
+                // ~ the elements of the boolean lists are single bytes
+                let element = match header & 0x0f {
+                    T_BOOL_TRUE | T_BOOL_FALSE => T_BYTE,
+                    element => element
+                };
+                for _ in 0..len {
+                    self.skip(element)?;
+                }
+                Some(())
+            },
+            T_MAP => {
+                let len = self.varint()?;
+                if len == 0 {
+                    return Some(());
+                }
+                let kinds = self.byte()?;
+                for _ in 0..len {
+                    self.skip(kinds >> 4)?;
+                    self.skip(kinds & 0x0f)?;
+                }
+                Some(())
+            },
+            T_STRUCT => {
+                loop {
+                    let header = self.byte()?;
+                    if header == 0 {
+                        return Some(());
+                    }
+                    if header >> 4 == 0 {
+                        // ~ the long form carries the field id
+                        self.varint()?;
+                    }
+                    self.skip(header & 0x0f)?;
+                }
+            },
+            _ => None
+        }
+    }
+}
+
+/// Encoder of the thrift structures in the compact protocol.
+struct Thrift {
+    buf: Vec<u8>,
+    /// ids of the last fields written to the nested structures
+    last: Vec<i16>
+}
+
+impl Thrift {
+
+    fn new() -> Thrift {
+        Thrift { buf: Vec::new(), last: vec![0] }
+    }
+
+    fn varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.buf.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        self.buf.push(v as u8);
+    }
+
+    fn zigzag(&mut self, v: i64) {
+        self.varint(((v << 1) ^ (v >> 63)) as u64);
+    }
+
+    fn field(&mut self, id: i16, kind: u8) {
+        let last = self.last.last().cloned().unwrap_or(0);
+        if id > last && id - last <= 15 {
+            self.buf.push(((id - last) as u8) << 4 | kind);
+        } else {
+            self.buf.push(kind);
+            self.zigzag(id as i64);
+        }
+        if let Some(last) = self.last.last_mut() {
+            *last = id;
+        }
+    }
+
+    fn i32(&mut self, id: i16, v: i32) {
+        self.field(id, T_I32);
+        self.zigzag(v as i64);
+    }
+
+    fn i64(&mut self, id: i16, v: i64) {
+        self.field(id, T_I64);
+        self.zigzag(v);
+    }
+
+    fn bool(&mut self, id: i16, v: bool) {
+        self.field(id, if v { T_BOOL_TRUE } else { T_BOOL_TRUE + 1 });
+    }
+
+    fn binary(&mut self, id: i16, v: &[u8]) {
+        self.field(id, T_BINARY);
+        self.binary_element(v);
+    }
+
+    fn list(&mut self, id: i16, kind: u8, len: usize) {
+        self.field(id, T_LIST);
+        if len < 15 {
+            self.buf.push((len as u8) << 4 | kind);
+        } else {
+            self.buf.push(0xf0 | kind);
+            self.varint(len as u64);
+        }
+    }
+
+    fn i32_element(&mut self, v: i32) {
+        self.zigzag(v as i64);
+    }
+
+    fn binary_element(&mut self, v: &[u8]) {
+        self.varint(v.len() as u64);
+        self.buf.extend_from_slice(v);
+    }
+
+    /// Starts the structure field.
+    fn begin(&mut self, id: i16) {
+        self.field(id, T_STRUCT);
+        self.last.push(0);
+    }
+
+    /// Starts the structure element of the list.
+    fn begin_element(&mut self) {
+        self.last.push(0);
+    }
+
+    fn end(&mut self) {
+        self.buf.push(0);
+        self.last.pop();
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.buf.push(0);
+        self.buf
+    }
+}
@@ -0,0 +1,435 @@
+//! Avro encoding of the syscall events. The schema is generated from the syscall table, where
+//! the parameters of each syscall, on entering and on exiting it, make up their own record,
+//! e.g. `OpenExitParams`, and the `params` field is the union of these records. Parameters
+//! that aren't decoded by the collector are typed as `null`.
+//!
+//! The events are encoded as the bare Avro datums, optionally framed in the Confluent wire
+//! format, i.e. prefixed by the zero magic byte and the id of the schema in the schema
+//! registry. The `ObjectContainer` renders the events as the blocks of the Avro object
+//! container file, whose header embeds the schema.
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use flate2;
+use flate2::write::DeflateEncoder;
+use serde_json;
+use zstd;
+use value::Value;
+use syscall::{SyscallInfo, Direction, ParamType};
+use syscall::syscall_table::SyscallTable;
+use state::users::Account;
+use super::Serializer;
+
+const NAMESPACE: &'static str = "cubostratus";
+/// magic byte of the Confluent wire format
+const CONFLUENT_MAGIC: u8 = 0;
+const OBJECT_MAGIC: &'static [u8] = b"Obj\x01";
+
+/// Type of the parameter values in the schema.
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Long,
+    String,
+    Null
+}
+
+fn kind(param: &ParamType) -> Kind {
+    match *param {
+        ParamType::Int8 | ParamType::Int16 | ParamType::Int32 | ParamType::Int64 |
+        ParamType::UInt8 | ParamType::UInt16 | ParamType::UInt32 | ParamType::UInt64 |
+        ParamType::ErrNo | ParamType::Fd | ParamType::Pid |
+        ParamType::Flags8 | ParamType::Flags16 | ParamType::Flags32 |
+        ParamType::Uid | ParamType::Gid | ParamType::SyscallId => Kind::Long,
+        ParamType::FsPath | ParamType::CharBuffer | ParamType::ByteBuffer |
+        ParamType::SockAddr | ParamType::SockTuple => Kind::String,
+        _ => Kind::Null
+    }
+}
+
+/// Record of the syscall parameters.
+struct ParamsRecord {
+    name: String,
+    fields: Vec<(&'static str, Kind)>
+}
+
+pub struct AvroSerializer {
+    /// schema of the events in the JSON form
+    schema: String,
+    records: Vec<ParamsRecord>,
+    /// indices of the parameter records by the syscall name, on entering and on exiting it
+    by_syscall: HashMap<&'static str, [Option<usize>; 2]>,
+    /// id of the schema in the schema registry, if the events are framed in the
+    /// Confluent wire format
+    schema_id: Option<u32>
+}
+
+impl Serializer for AvroSerializer {
+
+    fn serialize(&self, info: &SyscallInfo) -> Vec<u8> {
+        let mut buf = Vec::new();
+        if let Some(id) = self.schema_id {
+            buf.push(CONFLUENT_MAGIC);
+            buf.extend_from_slice(&[(id >> 24) as u8, (id >> 16) as u8, (id >> 8) as u8, id as u8]);
+        }
+        self.encode(&mut buf, info);
+        buf
+    }
+
+    fn content_type(&self) -> &'static str {
+        "avro/binary"
+    }
+}
+
+impl AvroSerializer {
+
+    pub fn new(schema_id: Option<u32>) -> AvroSerializer {
+        let table = SyscallTable::default();
+        let mut records = Vec::new();
+        let mut by_syscall = HashMap::<&'static str, [Option<usize>; 2]>::new();
+        for (id, meta) in table.iter().enumerate() {
+            // ~ even ids are the enter events
+            let dir = id % 2;
+            let entry = by_syscall.entry(meta.name).or_insert([None, None]);
+            if entry[dir].is_some() {
+                continue;
+            }
+            entry[dir] = Some(records.len());
+            let mut name = meta.name[..1].to_uppercase();
+            name.push_str(&meta.name[1..]);
+            name.push_str(if dir == 0 { "EnterParams" } else { "ExitParams" });
+            records.push(ParamsRecord {
+                name: name,
+                fields: meta.params.iter().map(|p| (p.name, kind(&p.kind))).collect()
+            });
+        }
+        AvroSerializer {
+            schema: schema(&records),
+            records: records,
+            by_syscall: by_syscall,
+            schema_id: schema_id
+        }
+    }
+
+    /// Returns the schema of the events in the JSON form.
+    pub fn schema(&self) -> &str {
+        &self.schema
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>, info: &SyscallInfo) {
+        write_long(buf, info.ts.timestamp() * 1000000000 + info.ts.timestamp_subsec_nanos() as i64);
+        write_long(buf, info.tid as i64);
+        write_long(buf, info.cpu as i64);
+        write_long(buf, if info.dir == Direction::Enter { 0 } else { 1 });
+        write_opt_string(buf, info.comm.as_ref());
+        write_opt_long(buf, info.pid);
+        write_opt_long(buf, info.vtid);
+        write_opt_long(buf, info.vpid);
+        write_opt_account(buf, info.user.as_ref());
+        write_opt_account(buf, info.group.as_ref());
+        if !info.ancestry.is_empty() {
+            write_long(buf, info.ancestry.len() as i64);
+            for ancestor in &info.ancestry {
+                write_long(buf, ancestor.pid as i64);
+                write_string(buf, &ancestor.comm);
+            }
+        }
+        write_long(buf, 0);
+        write_string(buf, &info.name);
+        self.encode_params(buf, info);
+        write_opt_string(buf, info.resolved_path.as_ref());
+        match info.pod {
+            Some(ref pod) => {
+                write_long(buf, 1);
+                write_string(buf, &pod.name);
+                write_string(buf, &pod.namespace);
+                write_string(buf, &pod.uid);
+                write_labels(buf, &pod.labels);
+            },
+            None => write_long(buf, 0)
+        }
+        match info.container {
+            Some(ref container) => {
+                write_long(buf, 1);
+                write_string(buf, &container.id);
+                write_string(buf, &container.name);
+                write_string(buf, &container.image);
+                write_opt_string(buf, container.image_digest.as_ref());
+                write_labels(buf, &container.labels);
+            },
+            None => write_long(buf, 0)
+        }
+    }
+
+    /// Encodes the parameters as the branch of the `params` union. The first branch is
+    /// `null`, taken by the syscalls missing from the table.
+    fn encode_params(&self, buf: &mut Vec<u8>, info: &SyscallInfo) {
+        let dir = if info.dir == Direction::Enter { 0 } else { 1 };
+        let index = match self.by_syscall.get(info.name.as_str()).and_then(|r| r[dir]) {
+            Some(index) => index,
+            None => {
+                write_long(buf, 0);
+                return;
+            }
+        };
+        write_long(buf, index as i64 + 1);
+        for &(name, kind) in &self.records[index].fields {
+            let value = info.params.get(name);
+            match (kind, value.and_then(long), value) {
+                (Kind::Null, _, _) => {},
+                (Kind::Long, Some(v), _) => {
+                    write_long(buf, 1);
+                    write_long(buf, v);
+                },
+                (Kind::String, _, Some(Value::String(s))) => {
+                    write_long(buf, 1);
+                    write_string(buf, s);
+                },
+                // ~ the socket addresses are rendered as `sip:sport->dip:dport`
+                (Kind::String, _, Some(Value::Sock(sock))) => {
+                    write_long(buf, 1);
+                    write_string(buf, &sock.to_string());
+                },
+                _ => write_long(buf, 0)
+            }
+        }
+    }
+}
+
+/// Generates the schema of the events.
+fn schema(records: &[ParamsRecord]) -> String {
+    let optional = |kind: &str| json!(["null", kind]);
+    let mut params = vec![json!("null")];
+    for record in records {
+        let fields = record.fields.iter()
+            .map(|&(name, kind)| match kind {
+                Kind::Long => json!({ "name": name, "type": optional("long"), "default": null }),
+                Kind::String => json!({ "name": name, "type": optional("string"), "default": null }),
+                Kind::Null => json!({ "name": name, "type": "null", "default": null })
+            })
+            .collect::<Vec<_>>();
+        params.push(json!({ "type": "record", "name": record.name, "fields": fields }));
+    }
+    let account = |name: &str| json!({
+        "type": "record",
+        "name": name,
+        "fields": [
+            { "name": "id", "type": "long" },
+            { "name": "name", "type": ["null", "string"], "default": null }
+        ]
+    });
+    let labels = json!({ "type": "map", "values": "string" });
+    let schema = json!({
+        "type": "record",
+        "name": "SyscallInfo",
+        "namespace": NAMESPACE,
+        "fields": [
+            { "name": "ts", "type": "long", "doc": "timestamp in nanoseconds from epoch" },
+            { "name": "tid", "type": "long" },
+            { "name": "cpu", "type": "int" },
+            { "name": "dir", "type": { "type": "enum", "name": "Direction", "symbols": ["ENTER", "EXIT"] } },
+            { "name": "comm", "type": ["null", "string"], "default": null },
+            { "name": "pid", "type": ["null", "long"], "default": null },
+            { "name": "vtid", "type": ["null", "long"], "default": null },
+            { "name": "vpid", "type": ["null", "long"], "default": null },
+            { "name": "user", "type": ["null", account("User")], "default": null },
+            { "name": "group", "type": ["null", account("Group")], "default": null },
+            { "name": "ancestry", "type": { "type": "array", "items": {
+                "type": "record",
+                "name": "Ancestor",
+                "fields": [
+                    { "name": "pid", "type": "long" },
+                    { "name": "comm", "type": "string" }
+                ]
+            } } },
+            { "name": "name", "type": "string" },
+            { "name": "params", "type": params, "default": null },
+            { "name": "resolved_path", "type": ["null", "string"], "default": null },
+            { "name": "pod", "type": ["null", {
+                "type": "record",
+                "name": "Pod",
+                "fields": [
+                    { "name": "name", "type": "string" },
+                    { "name": "namespace", "type": "string" },
+                    { "name": "uid", "type": "string" },
+                    { "name": "labels", "type": labels.clone() }
+                ]
+            }], "default": null },
+            { "name": "container", "type": ["null", {
+                "type": "record",
+                "name": "Container",
+                "fields": [
+                    { "name": "id", "type": "string" },
+                    { "name": "name", "type": "string" },
+                    { "name": "image", "type": "string" },
+                    { "name": "image_digest", "type": ["null", "string"], "default": null },
+                    { "name": "labels", "type": labels }
+                ]
+            }], "default": null }
+        ]
+    });
+    serde_json::to_string(&schema).unwrap()
+}
+
+/// Returns the integer parameter value as the Avro long. Unsigned 64 bit values beyond
+/// the range of the long wrap around.
+fn long(value: &Value) -> Option<i64> {
+    match *value {
+        Value::Int8(v) => Some(v as i64),
+        Value::Int16(v) => Some(v as i64),
+        Value::Int32(v) => Some(v as i64),
+        Value::Int64(v) => Some(v),
+        Value::UInt8(v) => Some(v as i64),
+        Value::UInt16(v) => Some(v as i64),
+        Value::UInt32(v) => Some(v as i64),
+        Value::UInt64(v) => Some(v as i64),
+        _ => None
+    }
+}
+
+fn write_long(buf: &mut Vec<u8>, v: i64) {
+    // ~ zigzag encoding
+    let mut v = ((v << 1) ^ (v >> 63)) as u64;
+    while v >= 0x80 {
+        buf.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    write_long(buf, bytes.len() as i64);
+    buf.extend_from_slice(bytes);
+}
+
+fn write_string(buf: &mut Vec<u8>, s: &str) {
+    write_bytes(buf, s.as_bytes());
+}
+
+fn write_opt_string(buf: &mut Vec<u8>, s: Option<&String>) {
+    match s {
+        Some(s) => {
+            write_long(buf, 1);
+            write_string(buf, s);
+        },
+        None => write_long(buf, 0)
+    }
+}
+
+fn write_opt_long(buf: &mut Vec<u8>, v: Option<u64>) {
+    match v {
+        Some(v) => {
+            write_long(buf, 1);
+            write_long(buf, v as i64);
+        },
+        None => write_long(buf, 0)
+    }
+}
+
+fn write_opt_account(buf: &mut Vec<u8>, account: Option<&Account>) {
+    match account {
+        Some(account) => {
+            write_long(buf, 1);
+            write_long(buf, account.id as i64);
+            write_opt_string(buf, account.name.as_ref());
+        },
+        None => write_long(buf, 0)
+    }
+}
+
+fn write_labels(buf: &mut Vec<u8>, labels: &BTreeMap<String, String>) {
+    if !labels.is_empty() {
+        write_long(buf, labels.len() as i64);
+        for (k, v) in labels {
+            write_string(buf, k);
+            write_string(buf, v);
+        }
+    }
+    write_long(buf, 0);
+}
+
+/// Renders the encoded events as the Avro object container file. The events are collected
+/// in the block, which is compressed by the codec, either `null`, `deflate` or `zstandard`,
+/// and terminated by the sync marker of the file.
+pub struct ObjectContainer {
+    schema: String,
+    codec: String,
+    sync: [u8; 16],
+    /// encoded events of the pending block
+    block: Vec<u8>,
+    count: u64
+}
+
+impl ObjectContainer {
+
+    pub fn new(schema: &str, codec: &str) -> ObjectContainer {
+        ObjectContainer {
+            schema: schema.to_string(),
+            codec: codec.to_string(),
+            sync: sync_marker(),
+            block: Vec::new(),
+            count: 0
+        }
+    }
+
+    /// Renders the file header embedding the schema.
+    pub fn header(&self) -> Vec<u8> {
+        let mut buf = OBJECT_MAGIC.to_vec();
+        write_long(&mut buf, 2);
+        write_string(&mut buf, "avro.schema");
+        write_string(&mut buf, &self.schema);
+        write_string(&mut buf, "avro.codec");
+        write_string(&mut buf, &self.codec);
+        write_long(&mut buf, 0);
+        buf.extend_from_slice(&self.sync);
+        buf
+    }
+
+    pub fn append(&mut self, event: &[u8]) {
+        self.block.extend_from_slice(event);
+        self.count += 1;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns the size of the pending block before the compression.
+    pub fn block_bytes(&self) -> usize {
+        self.block.len()
+    }
+
+    /// Renders the pending block and starts the new one.
+    pub fn take_block(&mut self) -> io::Result<Vec<u8>> {
+        let data = match self.codec.as_str() {
+            "deflate" => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::Default);
+                encoder.write_all(&self.block)?;
+                encoder.finish()?
+            },
+            "zstandard" => zstd::stream::encode_all(&self.block[..], 0)?,
+            _ => self.block.clone()
+        };
+        let mut buf = Vec::with_capacity(data.len() + 32);
+        write_long(&mut buf, self.count as i64);
+        write_bytes(&mut buf, &data);
+        buf.extend_from_slice(&self.sync);
+        self.block.clear();
+        self.count = 0;
+        Ok(buf)
+    }
+}
+
+/// Generates the random sync marker, falling back to the current time.
+fn sync_marker() -> [u8; 16] {
+    let mut sync = [0u8; 16];
+    let random = File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut sync));
+    if random.is_err() {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let seed = now.as_secs() ^ (now.subsec_nanos() as u64) << 32;
+        for (i, b) in sync.iter_mut().enumerate() {
+            *b = (seed >> (i % 8 * 8)) as u8 ^ i as u8;
+        }
+    }
+    sync
+}
@@ -0,0 +1,81 @@
+//! CBOR (RFC 7049) encoding of the syscall events. The event is encoded from its JSON data
+//! model, so the CBOR document has the same structure as the JSON one.
+use serde_json::{self, Value};
+use syscall::SyscallInfo;
+use super::Serializer;
+
+const MAJOR_UINT: u8 = 0;
+const MAJOR_NEGINT: u8 = 1;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+
+pub struct CborSerializer;
+
+impl Serializer for CborSerializer {
+
+    fn serialize(&self, info: &SyscallInfo) -> Vec<u8> {
+        let mut buf = Vec::new();
+        encode(&mut buf, &serde_json::to_value(info).unwrap());
+        buf
+    }
+
+    fn content_type(&self) -> &'static str {
+        "application/cbor"
+    }
+}
+
+/// Writes the initial byte of the data item with the argument, e.g. the integer or the
+/// length, in the shortest form.
+fn write_head(buf: &mut Vec<u8>, major: u8, arg: u64) {
+    let (info, len) = match arg {
+        0...23 => (arg as u8, 0),
+        24...0xff => (24, 1),
+        0x100...0xffff => (25, 2),
+        0x10000...0xffffffff => (26, 4),
+        _ => (27, 8)
+    };
+    buf.push(major << 5 | info);
+    for i in (0..len).rev() {
+        buf.push((arg >> (i * 8)) as u8);
+    }
+}
+
+fn encode(buf: &mut Vec<u8>, value: &Value) {
+    match *value {
+        Value::Null => buf.push(0xf6),
+        Value::Bool(b) => buf.push(if b { 0xf5 } else { 0xf4 }),
+        Value::Number(ref n) => {
+            if let Some(v) = n.as_u64() {
+                write_head(buf, MAJOR_UINT, v);
+            } else if let Some(v) = n.as_i64() {
+                // ~ negative integers are encoded as -1 - n
+                write_head(buf, MAJOR_NEGINT, !(v as u64));
+            } else {
+                buf.push(0xfb);
+                let bits = n.as_f64().unwrap_or(0.0).to_bits();
+                for i in (0..8).rev() {
+                    buf.push((bits >> (i * 8)) as u8);
+                }
+            }
+        },
+        Value::String(ref s) => {
+            write_head(buf, MAJOR_TEXT, s.len() as u64);
+            buf.extend_from_slice(s.as_bytes());
+        },
+        Value::Array(ref items) => {
+            write_head(buf, MAJOR_ARRAY, items.len() as u64);
+            for item in items {
+                encode(buf, item);
+            }
+        },
+        Value::Object(ref map) => {
+            write_head(buf, MAJOR_MAP, map.len() as u64);
+            for (k, v) in map.iter() {
+                write_head(buf, MAJOR_TEXT, k.len() as u64);
+                buf.extend_from_slice(k.as_bytes());
+                encode(buf, v);
+            }
+        }
+    }
+}
@@ -0,0 +1,18 @@
+//! JSON encoding of the syscall events.
+use serde_json;
+use syscall::SyscallInfo;
+use super::Serializer;
+
+pub struct JsonSerializer;
+
+impl Serializer for JsonSerializer {
+
+    fn serialize(&self, info: &SyscallInfo) -> Vec<u8> {
+        // ~ the events only have string keyed maps, which can always be encoded
+        serde_json::to_vec(info).unwrap()
+    }
+
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+}
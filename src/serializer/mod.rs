@@ -0,0 +1,83 @@
+//! Encoders of the syscall events. JSON is the default and the only format of the text based
+//! aggregators, while the binary formats trade readability for the size of the events on
+//! high volume destinations such as Kafka topics.
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use syscall::SyscallInfo;
+
+pub mod json;
+pub mod msgpack;
+pub mod cbor;
+pub mod protobuf;
+pub mod avro;
+
+pub use self::json::JsonSerializer;
+pub use self::msgpack::MsgpackSerializer;
+pub use self::cbor::CborSerializer;
+pub use self::protobuf::ProtobufSerializer;
+pub use self::avro::AvroSerializer;
+
+/// names of the serialization formats selectable in the configuration
+pub const FORMATS: &'static [&'static str] = &["json", "msgpack", "cbor", "protobuf", "avro"];
+
+pub trait Serializer {
+
+    /// Encodes the syscall event.
+    fn serialize(&self, info: &SyscallInfo) -> Vec<u8>;
+
+    /// Returns the media type of the encoded events.
+    fn content_type(&self) -> &'static str;
+}
+
+/// Returns the serializer of the format with the given name.
+pub fn from_name(name: &str) -> Option<Box<Serializer + Send>> {
+    match name {
+        "json" => Some(Box::new(JsonSerializer)),
+        "msgpack" => Some(Box::new(MsgpackSerializer)),
+        "cbor" => Some(Box::new(CborSerializer)),
+        "protobuf" => Some(Box::new(ProtobufSerializer)),
+        "avro" => Some(Box::new(AvroSerializer::new(None))),
+        _ => None
+    }
+}
+
+/// names of the formats and the event encoded in them
+type Bodies = Vec<(&'static str, Arc<[u8]>)>;
+
+/// Encodings of the event shared by the aggregators. Each format is encoded the first time an
+/// aggregator asks for it, so the event is encoded at most once per format, and not at all
+/// when no aggregator needs it. The clones share the encodings.
+#[derive(Clone, Default)]
+pub struct Encoded {
+    bodies: Rc<RefCell<Bodies>>
+}
+
+impl Encoded {
+
+    pub fn new() -> Encoded {
+        Encoded::default()
+    }
+
+    /// Creates the encodings of the event whose JSON body is already known.
+    pub fn with_json(json: Vec<u8>) -> Encoded {
+        let encoded = Encoded::new();
+        encoded.bodies.borrow_mut().push(("json", Arc::from(json)));
+        encoded
+    }
+
+    /// Returns the event encoded by the serializer, identified by the name of its format.
+    pub fn get(&self, format: &'static str, serializer: &Serializer, info: &SyscallInfo) -> Arc<[u8]> {
+        if let Some((_, body)) = self.bodies.borrow().iter().find(|&&(f, _)| f == format) {
+            return body.clone();
+        }
+        let body: Arc<[u8]> = Arc::from(serializer.serialize(info));
+        self.bodies.borrow_mut().push((format, body.clone()));
+        body
+    }
+
+    /// Returns the event encoded as JSON.
+    pub fn json(&self, info: &SyscallInfo) -> Arc<[u8]> {
+        self.get("json", &JsonSerializer, info)
+    }
+}
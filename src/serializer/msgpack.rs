@@ -0,0 +1,107 @@
+//! MessagePack encoding of the syscall events. The event is encoded from its JSON data model,
+//! so the MessagePack document has the same structure as the JSON one.
+use serde_json::{self, Value};
+use syscall::SyscallInfo;
+use super::Serializer;
+
+pub struct MsgpackSerializer;
+
+impl Serializer for MsgpackSerializer {
+
+    fn serialize(&self, info: &SyscallInfo) -> Vec<u8> {
+        let mut buf = Vec::new();
+        encode(&mut buf, &serde_json::to_value(info).unwrap());
+        buf
+    }
+
+    fn content_type(&self) -> &'static str {
+        "application/msgpack"
+    }
+}
+
+fn write_be(buf: &mut Vec<u8>, v: u64, len: usize) {
+    for i in (0..len).rev() {
+        buf.push((v >> (i * 8)) as u8);
+    }
+}
+
+/// Writes the marker and the length of the string, array or map, picking the shortest of
+/// the fixed, 8 (strings only), 16 and 32 bit length variants.
+fn write_len(buf: &mut Vec<u8>, len: usize, fix: u8, fix_max: usize, markers: [Option<u8>; 3]) {
+    if len <= fix_max {
+        buf.push(fix | len as u8);
+    } else if let (Some(marker), true) = (markers[0], len <= 0xff) {
+        buf.push(marker);
+        write_be(buf, len as u64, 1);
+    } else if len <= 0xffff {
+        buf.push(markers[1].unwrap());
+        write_be(buf, len as u64, 2);
+    } else {
+        buf.push(markers[2].unwrap());
+        write_be(buf, len as u64, 4);
+    }
+}
+
+fn encode_str(buf: &mut Vec<u8>, s: &str) {
+    write_len(buf, s.len(), 0xa0, 31, [Some(0xd9), Some(0xda), Some(0xdb)]);
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn encode_int(buf: &mut Vec<u8>, v: i64) {
+    if v >= 0 {
+        let v = v as u64;
+        match v {
+            0...0x7f => buf.push(v as u8),
+            0x80...0xff => { buf.push(0xcc); write_be(buf, v, 1); },
+            0x100...0xffff => { buf.push(0xcd); write_be(buf, v, 2); },
+            0x10000...0xffffffff => { buf.push(0xce); write_be(buf, v, 4); },
+            _ => { buf.push(0xcf); write_be(buf, v, 8); }
+        }
+    } else if v >= -32 {
+        buf.push(v as u8);
+    } else if v >= -0x80 {
+        buf.push(0xd0);
+        write_be(buf, v as u64, 1);
+    } else if v >= -0x8000 {
+        buf.push(0xd1);
+        write_be(buf, v as u64, 2);
+    } else if v >= -0x80000000 {
+        buf.push(0xd2);
+        write_be(buf, v as u64, 4);
+    } else {
+        buf.push(0xd3);
+        write_be(buf, v as u64, 8);
+    }
+}
+
+fn encode(buf: &mut Vec<u8>, value: &Value) {
+    match *value {
+        Value::Null => buf.push(0xc0),
+        Value::Bool(b) => buf.push(if b { 0xc3 } else { 0xc2 }),
+        Value::Number(ref n) => {
+            if let Some(v) = n.as_i64() {
+                encode_int(buf, v);
+            } else if let Some(v) = n.as_u64() {
+                buf.push(0xcf);
+                write_be(buf, v, 8);
+            } else {
+                buf.push(0xcb);
+                write_be(buf, n.as_f64().unwrap_or(0.0).to_bits(), 8);
+            }
+        },
+        Value::String(ref s) => encode_str(buf, s),
+        Value::Array(ref items) => {
+            write_len(buf, items.len(), 0x90, 15, [None, Some(0xdc), Some(0xdd)]);
+            for item in items {
+                encode(buf, item);
+            }
+        },
+        Value::Object(ref map) => {
+            write_len(buf, map.len(), 0x80, 15, [None, Some(0xde), Some(0xdf)]);
+            for (k, v) in map.iter() {
+                encode_str(buf, k);
+                encode(buf, v);
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! Protocol Buffers encoding of the syscall events, following the `SyscallInfo` message of
+//! the schema published in `proto/syscall.proto`. Fields holding the zero value are omitted,
+//! as proto3 encoders do.
+use std::collections::BTreeMap;
+use value::{Sock, Value};
+use syscall::{SyscallInfo, Direction};
+use state::users::Account;
+use super::Serializer;
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_LEN: u8 = 2;
+
+pub struct ProtobufSerializer;
+
+impl Serializer for ProtobufSerializer {
+
+    fn serialize(&self, info: &SyscallInfo) -> Vec<u8> {
+        let mut msg = Message::new();
+        let ts = info.ts.timestamp() as u64 * 1000000000 + info.ts.timestamp_subsec_nanos() as u64;
+        msg.uint(1, ts);
+        msg.uint(2, info.tid);
+        msg.uint(3, info.cpu as u64);
+        msg.uint(4, if info.dir == Direction::Enter { 0 } else { 1 });
+        msg.string(5, info.comm.as_ref().map_or("", |c| c.as_str()));
+        msg.uint(6, info.pid.unwrap_or(0));
+        msg.uint(7, info.vtid.unwrap_or(0));
+        msg.uint(8, info.vpid.unwrap_or(0));
+        if let Some(ref user) = info.user {
+            msg.message(9, &account(user));
+        }
+        if let Some(ref group) = info.group {
+            msg.message(10, &account(group));
+        }
+        for ancestor in &info.ancestry {
+            let mut m = Message::new();
+            m.uint(1, ancestor.pid);
+            m.string(2, &ancestor.comm);
+            msg.message(11, &m);
+        }
+        msg.string(12, &info.name);
+        for (name, value) in &info.params {
+            let mut entry = Message::new();
+            entry.string(1, name);
+            entry.message(2, &param(value));
+            msg.message(13, &entry);
+        }
+        msg.string(14, info.resolved_path.as_ref().map_or("", |p| p.as_str()));
+        if let Some(ref pod) = info.pod {
+            let mut m = Message::new();
+            m.string(1, &pod.name);
+            m.string(2, &pod.namespace);
+            m.string(3, &pod.uid);
+            m.labels(4, &pod.labels);
+            msg.message(15, &m);
+        }
+        if let Some(ref container) = info.container {
+            let mut m = Message::new();
+            m.string(1, &container.id);
+            m.string(2, &container.name);
+            m.string(3, &container.image);
+            m.string(4, container.image_digest.as_ref().map_or("", |d| d.as_str()));
+            m.labels(5, &container.labels);
+            msg.message(16, &m);
+        }
+        msg.buf
+    }
+
+    fn content_type(&self) -> &'static str {
+        "application/x-protobuf"
+    }
+}
+
+fn account(account: &Account) -> Message {
+    let mut m = Message::new();
+    m.uint(1, account.id as u64);
+    m.string(2, account.name.as_ref().map_or("", |n| n.as_str()));
+    m
+}
+
+/// Encodes the `ParamValue` message. The members of the `oneof` are written even if they
+/// hold the zero value, so the decoder can tell which one is set.
+fn param(value: &Value) -> Message {
+    let mut m = Message::new();
+    match *value {
+        Value::String(ref s) => {
+            m.key(1, WIRE_LEN);
+            m.bytes(s.as_bytes());
+        },
+        Value::Int8(v) => m.sint_field(2, v as i64),
+        Value::Int16(v) => m.sint_field(2, v as i64),
+        Value::Int32(v) => m.sint_field(2, v as i64),
+        Value::Int64(v) => m.sint_field(2, v),
+        Value::UInt8(v) => m.uint_field(3, v as u64),
+        Value::UInt16(v) => m.uint_field(3, v as u64),
+        Value::UInt32(v) => m.uint_field(3, v as u64),
+        Value::UInt64(v) => m.uint_field(3, v),
+        Value::Sock(ref sock) => m.message(4, &self::sock(sock)),
+        Value::None => {}
+    }
+    m
+}
+
+fn sock(sock: &Sock) -> Message {
+    let mut m = Message::new();
+    m.string(1, sock.family);
+    m.string(2, sock.ip.as_ref().map_or("", |ip| ip.as_str()));
+    m.uint(3, sock.port.unwrap_or(0) as u64);
+    m.string(4, sock.sip.as_ref().map_or("", |ip| ip.as_str()));
+    m.uint(5, sock.sport.unwrap_or(0) as u64);
+    m.string(6, sock.dip.as_ref().map_or("", |ip| ip.as_str()));
+    m.uint(7, sock.dport.unwrap_or(0) as u64);
+    m.string(8, sock.path.as_ref().map_or("", |path| path.as_str()));
+    m
+}
+
+struct Message {
+    buf: Vec<u8>
+}
+
+impl Message {
+
+    fn new() -> Message {
+        Message { buf: Vec::new() }
+    }
+
+    fn varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.buf.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        self.buf.push(v as u8);
+    }
+
+    fn key(&mut self, field: u32, wire: u8) {
+        self.varint((field as u64) << 3 | wire as u64);
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.varint(bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn uint_field(&mut self, field: u32, v: u64) {
+        self.key(field, WIRE_VARINT);
+        self.varint(v);
+    }
+
+    fn sint_field(&mut self, field: u32, v: i64) {
+        self.key(field, WIRE_VARINT);
+        // ~ zigzag encoding
+        self.varint(((v << 1) ^ (v >> 63)) as u64);
+    }
+
+    fn uint(&mut self, field: u32, v: u64) {
+        if v != 0 {
+            self.uint_field(field, v);
+        }
+    }
+
+    fn string(&mut self, field: u32, s: &str) {
+        if !s.is_empty() {
+            self.key(field, WIRE_LEN);
+            self.bytes(s.as_bytes());
+        }
+    }
+
+    fn message(&mut self, field: u32, m: &Message) {
+        self.key(field, WIRE_LEN);
+        self.bytes(&m.buf);
+    }
+
+    /// Encodes the `map<string, string>` field as the repeated entry messages.
+    fn labels(&mut self, field: u32, labels: &BTreeMap<String, String>) {
+        for (k, v) in labels {
+            let mut entry = Message::new();
+            entry.string(1, k);
+            entry.string(2, v);
+            self.message(field, &entry);
+        }
+    }
+}
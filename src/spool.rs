@@ -0,0 +1,293 @@
+//! Durable on-disk buffer for the messages that couldn't be delivered to the brokers. The
+//! messages are appended to segment files in the spool directory, named after the sequence
+//! number of the segment, e.g. `00000000000000000042.spool`. Each message is framed as
+//!
+//! ```text
+//! key length (u32 BE) | value length (u32 BE) | key | value
+//! ```
+//!
+//! The messages are replayed in the order they were appended and the segments are removed
+//! once all of their messages are delivered. The position of the next message to replay is
+//! kept in the `offset` file, as the sequence number of the oldest segment and the offset
+//! in it (both u64 BE), so the messages delivered before a restart aren't replayed again.
+//! When the spool exceeds its size cap, or the segments get older than the age cap, the
+//! oldest segments are dropped.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use config::SpoolConfig;
+
+const SEGMENT_EXT: &'static str = "spool";
+/// name of the file keeping the replay position
+const OFFSET_FILE: &'static str = "offset";
+/// size of the frame header holding the key and value lengths
+const HEADER_LEN: u64 = 8;
+
+struct Segment {
+    seq: u64,
+    path: PathBuf,
+    bytes: u64
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct SpoolStats {
+    /// number of segment files in the spool
+    pub segments: usize,
+    /// size of the segment files in bytes
+    pub bytes: u64,
+    /// messages appended to the spool
+    pub appended: u64,
+    /// messages replayed from the spool
+    pub replayed: u64,
+    /// segments dropped due to the size or age caps
+    pub dropped_segments: u64
+}
+
+pub struct Spool {
+    dir: PathBuf,
+    segment_bytes: u64,
+    max_bytes: u64,
+    max_age: Duration,
+    /// segments from the oldest to the newest
+    segments: VecDeque<Segment>,
+    /// writer of the newest segment
+    writer: Option<BufWriter<File>>,
+    /// file keeping the replay position
+    offset_file: File,
+    /// offset of the next message to replay in the oldest segment
+    read_offset: u64,
+    /// offset past the messages returned by the last `peek`
+    peek_offset: u64,
+    stats: SpoolStats
+}
+
+fn u32_be(buf: &[u8]) -> u32 {
+    (buf[0] as u32) << 24 | (buf[1] as u32) << 16 | (buf[2] as u32) << 8 | buf[3] as u32
+}
+
+fn write_u32_be<W: Write>(w: &mut W, v: u32) -> io::Result<()> {
+    w.write_all(&[(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8])
+}
+
+fn u64_be(buf: &[u8]) -> u64 {
+    (u32_be(&buf[..4]) as u64) << 32 | u32_be(&buf[4..]) as u64
+}
+
+impl Spool {
+
+    /// Opens the spool in the configured directory. Segments left by previous runs are kept
+    /// and replayed first, from the position their replay got to.
+    pub fn open(config: &SpoolConfig) -> io::Result<Spool> {
+        let dir = PathBuf::from(&config.dir);
+        fs::create_dir_all(&dir)?;
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(SEGMENT_EXT) {
+                continue;
+            }
+            let seq = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse::<u64>().ok());
+            if let Some(seq) = seq {
+                let bytes = fs::metadata(&path)?.len();
+                segments.push(Segment { seq: seq, path: path, bytes: bytes });
+            }
+        }
+        segments.sort_by_key(|s| s.seq);
+        let mut offset_file = OpenOptions::new().read(true).write(true).create(true).truncate(false)
+            .open(dir.join(OFFSET_FILE))?;
+        let mut position = [0u8; 16];
+        let read_offset = match offset_file.read_exact(&mut position) {
+            Ok(()) => {
+                let seq = u64_be(&position[..8]);
+                // ~ the replayed segments left behind, e.g. when their removal failed
+                while segments.first().is_some_and(|s| s.seq < seq) {
+                    let _ = fs::remove_file(&segments.remove(0).path);
+                }
+                match segments.first() {
+                    Some(segment) if segment.seq == seq => ::std::cmp::min(u64_be(&position[8..]), segment.bytes),
+                    _ => 0
+                }
+            },
+            Err(_) => 0
+        };
+        let mut spool = Spool {
+            dir: dir,
+            segment_bytes: config.segment_bytes,
+            max_bytes: config.max_bytes,
+            max_age: Duration::from_secs(config.max_age),
+            segments: segments.into_iter().collect(),
+            writer: None,
+            offset_file: offset_file,
+            read_offset: read_offset,
+            peek_offset: read_offset,
+            stats: SpoolStats::default()
+        };
+        // ~ the position of the segments dropped or removed before the restart isn't kept
+        spool.persist()?;
+        spool.update_stats();
+        Ok(spool)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.iter().map(|s| s.bytes).sum::<u64>() <= self.read_offset
+    }
+
+    /// Appends the keys and values of the messages to the newest segment,
+    /// rolling a new segment once the newest one is full.
+    pub fn append<B: AsRef<[u8]>>(&mut self, messages: &[(String, B)]) -> io::Result<()> {
+        for (key, value) in messages {
+            let value = value.as_ref();
+            let full = self.segments.back().is_none_or(|s| s.bytes >= self.segment_bytes);
+            if self.writer.is_none() || full {
+                self.roll()?;
+            }
+            let len = HEADER_LEN + key.len() as u64 + value.len() as u64;
+            {
+                let writer = self.writer.as_mut().unwrap();
+                write_u32_be(writer, key.len() as u32)?;
+                write_u32_be(writer, value.len() as u32)?;
+                writer.write_all(key.as_bytes())?;
+                writer.write_all(value)?;
+            }
+            if let Some(segment) = self.segments.back_mut() {
+                segment.bytes += len;
+            }
+            self.stats.appended += 1;
+        }
+        if let Some(ref mut writer) = self.writer {
+            writer.flush()?;
+        }
+        self.enforce_caps();
+        self.update_stats();
+        Ok(())
+    }
+
+    /// Opens the new segment for writing.
+    fn roll(&mut self) -> io::Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        let seq = self.segments.back().map_or(0, |s| s.seq + 1);
+        let path = self.dir.join(format!("{:020}.{}", seq, SEGMENT_EXT));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        self.segments.push_back(Segment { seq: seq, path: path, bytes: 0 });
+        self.writer = Some(BufWriter::new(file));
+        Ok(())
+    }
+
+    /// Drops the oldest segments while the spool exceeds the size cap, or
+    /// while their last message is older than the age cap.
+    fn enforce_caps(&mut self) {
+        let now = SystemTime::now();
+        while self.segments.len() > 1 {
+            let too_big = self.segments.iter().map(|s| s.bytes).sum::<u64>() > self.max_bytes;
+            let too_old = fs::metadata(&self.segments[0].path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok())
+                .is_some_and(|age| age > self.max_age);
+            if !too_big && !too_old {
+                break;
+            }
+            self.remove_oldest();
+            self.stats.dropped_segments += 1;
+        }
+    }
+
+    fn remove_oldest(&mut self) {
+        if let Some(segment) = self.segments.pop_front() {
+            let _ = fs::remove_file(&segment.path);
+        }
+        if self.segments.is_empty() {
+            self.writer = None;
+        }
+        self.read_offset = 0;
+        self.peek_offset = 0;
+    }
+
+    /// Reads up to `max` of the oldest messages without removing them. The messages are
+    /// removed from the spool by `commit` once they're delivered. Truncated messages, e.g.
+    /// written right before a crash, end the segment.
+    pub fn peek(&mut self, max: usize) -> io::Result<Vec<(String, Vec<u8>)>> {
+        if let Some(ref mut writer) = self.writer {
+            writer.flush()?;
+        }
+        let mut messages = Vec::new();
+        while let Some((path, bytes)) = self.segments.front().map(|s| (s.path.clone(), s.bytes)) {
+            if self.read_offset >= bytes {
+                // ~ the newest segment is kept while it's being written
+                if self.segments.len() == 1 {
+                    break;
+                }
+                self.remove_oldest();
+                continue;
+            }
+            let mut reader = BufReader::new(File::open(&path)?);
+            reader.seek(SeekFrom::Start(self.read_offset))?;
+            let mut offset = self.read_offset;
+            let mut header = [0u8; HEADER_LEN as usize];
+            while messages.len() < max && offset + HEADER_LEN <= bytes {
+                reader.read_exact(&mut header)?;
+                let key_len = u32_be(&header[..4]) as u64;
+                let value_len = u32_be(&header[4..]) as u64;
+                if offset + HEADER_LEN + key_len + value_len > bytes {
+                    break;
+                }
+                let mut key = vec![0u8; key_len as usize];
+                let mut value = vec![0u8; value_len as usize];
+                reader.read_exact(&mut key)?;
+                reader.read_exact(&mut value)?;
+                messages.push((String::from_utf8_lossy(&key).into_owned(), value));
+                offset += HEADER_LEN + key_len + value_len;
+            }
+            self.peek_offset = offset;
+            if messages.len() < max && offset < bytes {
+                // ~ truncated message at the end of the segment
+                self.peek_offset = bytes;
+            }
+            break;
+        }
+        Ok(messages)
+    }
+
+    /// Removes the messages returned by the last `peek` from the spool, and syncs the
+    /// replay position to the disk.
+    pub fn commit(&mut self, count: usize) -> io::Result<()> {
+        self.read_offset = self.peek_offset;
+        self.stats.replayed += count as u64;
+        // ~ the exhausted newest segment is removed as well, so the
+        // messages appended afterwards start a fresh segment
+        if self.segments.front().is_some_and(|s| self.read_offset >= s.bytes) {
+            self.remove_oldest();
+        }
+        self.update_stats();
+        self.persist()
+    }
+
+    /// Writes the replay position to the offset file and syncs it.
+    fn persist(&mut self) -> io::Result<()> {
+        // ~ the sequence numbers start over once the spool is empty, and so does the position
+        let seq = self.segments.front().map_or(0, |s| s.seq);
+        let offset = if self.segments.is_empty() { 0 } else { self.read_offset };
+        let mut position = Vec::with_capacity(16);
+        for v in &[seq, offset] {
+            write_u32_be(&mut position, (v >> 32) as u32)?;
+            write_u32_be(&mut position, *v as u32)?;
+        }
+        self.offset_file.seek(SeekFrom::Start(0))?;
+        self.offset_file.write_all(&position)?;
+        self.offset_file.sync_data()
+    }
+
+    fn update_stats(&mut self) {
+        self.stats.segments = self.segments.len();
+        self.stats.bytes = self.segments.iter().map(|s| s.bytes).sum::<u64>() - self.read_offset;
+    }
+
+    pub fn stats(&self) -> SpoolStats {
+        self.stats.clone()
+    }
+}
@@ -0,0 +1,74 @@
+//! Decodes the capability sets found in the `/proc/[pid]/status` file. Each set is rendered
+//! by the kernel as a hexadecimal bitmask where the bit number corresponds to the capability
+//! number, e.g. `0000000000003000` has the `CAP_NET_ADMIN` (12) and `CAP_NET_RAW` (13) bits set.
+
+/// capability names indexed by the capability number
+const CAP_NAMES: [&'static str; 41] = [
+    "CAP_CHOWN",
+    "CAP_DAC_OVERRIDE",
+    "CAP_DAC_READ_SEARCH",
+    "CAP_FOWNER",
+    "CAP_FSETID",
+    "CAP_KILL",
+    "CAP_SETGID",
+    "CAP_SETUID",
+    "CAP_SETPCAP",
+    "CAP_LINUX_IMMUTABLE",
+    "CAP_NET_BIND_SERVICE",
+    "CAP_NET_BROADCAST",
+    "CAP_NET_ADMIN",
+    "CAP_NET_RAW",
+    "CAP_IPC_LOCK",
+    "CAP_IPC_OWNER",
+    "CAP_SYS_MODULE",
+    "CAP_SYS_RAWIO",
+    "CAP_SYS_CHROOT",
+    "CAP_SYS_PTRACE",
+    "CAP_SYS_PACCT",
+    "CAP_SYS_ADMIN",
+    "CAP_SYS_BOOT",
+    "CAP_SYS_NICE",
+    "CAP_SYS_RESOURCE",
+    "CAP_SYS_TIME",
+    "CAP_SYS_TTY_CONFIG",
+    "CAP_MKNOD",
+    "CAP_LEASE",
+    "CAP_AUDIT_WRITE",
+    "CAP_AUDIT_CONTROL",
+    "CAP_SETFCAP",
+    "CAP_MAC_OVERRIDE",
+    "CAP_MAC_ADMIN",
+    "CAP_SYSLOG",
+    "CAP_WAKE_ALARM",
+    "CAP_BLOCK_SUSPEND",
+    "CAP_AUDIT_READ",
+    "CAP_PERFMON",
+    "CAP_BPF",
+    "CAP_CHECKPOINT_RESTORE"
+];
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Capabilities {
+    /// capabilities preserved across `execve`
+    pub inheritable: Vec<String>,
+    /// capabilities the thread may assume
+    pub permitted: Vec<String>,
+    /// capabilities used by the kernel to perform permission checks
+    pub effective: Vec<String>,
+    /// capabilities limiting the ones that can be gained during `execve`
+    pub bounding: Vec<String>,
+    /// capabilities preserved across `execve` of unprivileged programs
+    pub ambient: Vec<String>
+}
+
+/// Translates the capability bitmask to the list of capability names. Capabilities unknown
+/// to this table, i.e. introduced by newer kernels, are rendered by their number.
+pub fn decode(mask: u64) -> Vec<String> {
+    (0..64)
+        .filter(|bit| mask & (1u64 << bit) != 0)
+        .map(|bit| match CAP_NAMES.get(bit as usize) {
+            Some(name) => name.to_string(),
+            None => format!("CAP_{}", bit)
+        })
+        .collect()
+}
@@ -2,18 +2,20 @@
 //! file describes control groups to which the process belongs. For each cgroup hierarchy there is
 //! an entry of the form `hierarchy-id:subsystems:cgroup-path`, for example:
 //!
-//!     7:cpu,cpuacct:/user.slice
+//! ```text
+//! 7:cpu,cpuacct:/user.slice
+//! ```
 //!
 //! where 7 is an unique hierarchy identifier, `cpu` and `cpuacct` are the cgroup subsystems bound
 //! to the hierarchy, and finally, `/user.slice` is the cgroup pathname. There is a special `name=systemd`
 //! cgroup with no bounded subsystem and is used by `systemd` to track services and user sessions.
 
-use std::path::PathBuf;
 use std::str::{self, FromStr};
 use nom::{alpha, IResult};
-use std::io::{Error, Result, ErrorKind, Read};
+use std::io::Read;
 use std::fs::File;
 use super::parsers::{consume_until_line_ending, parse_u8};
+use error::{Error, Result};
 
 #[derive(Serialize, Debug)]
 pub struct CGroup {
@@ -25,12 +27,44 @@ pub struct CGroup {
     pub path: String
 }
 
+const CONTAINER_ID_LEN: usize = 64;
+
+impl CGroup {
+    /// Extracts the container identifier from the cgroup pathname. Container runtimes place
+    /// containers in cgroups whose last path component is, or embeds, the 64 characters long
+    /// hexadecimal container id, for example:
+    ///
+    /// ```text
+    /// /docker/3f4a...
+    /// /kubepods/burstable/pod1b2c.../3f4a...
+    /// /kubepods.slice/kubepods-pod1b2c.slice/docker-3f4a....scope
+    /// /system.slice/cri-containerd-3f4a....scope
+    /// ```
+    ///
+    /// Returns `None` if the cgroup doesn't belong to a container.
+    pub fn container_id(&self) -> Option<String> {
+        let name = self.path.trim_end().rsplit('/').next().unwrap_or("");
+        let name = name.trim_end_matches(".scope");
+        let id = name.rsplit('-').next().unwrap_or(name);
+        if id.len() == CONTAINER_ID_LEN && id.chars().all(|c| c.is_ascii_hexdigit()) {
+            Some(id.to_string())
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the container identifier of the first cgroup that belongs to a container.
+pub fn container_id(cgroups: &[CGroup]) -> Option<String> {
+    cgroups.iter().filter_map(|c| c.container_id()).next()
+}
+
 // parses cgroup subsystems
-named!(controller, recognize!(chain!(
-                                alpha ~
-                                opt!(alt!(tag!("_") | tag!("="))) ~
-                                opt!(alpha),
-                                || {})));
+named!(controller, recognize!(do_parse!(
+                                alpha >>
+                                opt!(alt!(tag!("_") | tag!("="))) >>
+                                opt!(alpha) >>
+                                ())));
 
 named!(parse_controllers<Vec<String> >,
        many0!(do_parse!(c: map_res!(
@@ -61,15 +95,13 @@ fn parse_cgroups(buf: &[u8]) -> IResult<&[u8], Vec<CGroup>> {
 
 pub fn cgroups(pid: u64, root: String) -> Result<Vec<CGroup>> {
     let mut buf = String::new();
-    let mut f = try!(File::open(format!("{}/{}/cgroup", root, pid)));
-    f.read_to_string(&mut buf);
+    File::open(format!("{}/{}/cgroup", root, pid))
+            .and_then(|mut f| f.read_to_string(&mut buf))
+            .map_err(|e| Error::from_proc_io(pid, e))?;
     match parse_cgroups(buf.as_bytes()) {
-        IResult::Done(i, o) => {
+        IResult::Done(_, o) => {
             Ok(o)
         },
-        IResult::Error(e) => {
-            Err(Error::new(ErrorKind::InvalidInput, "unable to parse input"))
-        },
-        _ => Err(Error::new(ErrorKind::InvalidInput, "unable to parse input")),
+        _ => Err(Error::ProcParseError(pid, "malformed cgroup file".to_string())),
     }
-}
\ No newline at end of file
+}
@@ -1,3 +1,10 @@
 pub mod cgroups;
 pub mod thread;
+pub mod namespaces;
+pub mod capabilities;
+pub mod process;
+pub mod users;
+pub mod resync;
+pub mod table;
+pub mod paths;
 mod parsers;
\ No newline at end of file
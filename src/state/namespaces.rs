@@ -0,0 +1,84 @@
+//! Reads the namespace membership of processes from the `/proc` pseudo file system. Every
+//! process has an entry in the `/proc/[pid]/ns` directory for each namespace type. The entries
+//! are symbolic links whose targets identify the namespace by its inode number, for example:
+//!
+//! ```text
+//! net -> net:[4026531993]
+//! ```
+//!
+//! Two processes are in the same namespace if the inode numbers of their links are equal.
+
+use std::fs;
+use libc;
+use nom::IResult;
+use super::parsers::parse_u64;
+use error::{Error, Result};
+
+/// `clone` flags that create new namespaces for the child, as reported by the driver
+pub const PPM_CL_CLONE_NEWIPC: u32 = 1 << 3;
+pub const PPM_CL_CLONE_NEWNET: u32 = 1 << 4;
+pub const PPM_CL_CLONE_NEWNS: u32 = 1 << 5;
+pub const PPM_CL_CLONE_NEWPID: u32 = 1 << 6;
+pub const PPM_CL_CLONE_NEWUTS: u32 = 1 << 7;
+pub const PPM_CL_CLONE_NEWUSER: u32 = 1 << 20;
+pub const PPM_CL_CLONE_NEWCGROUP: u32 = 1 << 28;
+
+pub const PPM_CL_CLONE_NEWANY: u32 = PPM_CL_CLONE_NEWIPC | PPM_CL_CLONE_NEWNET |
+                                     PPM_CL_CLONE_NEWNS | PPM_CL_CLONE_NEWPID |
+                                     PPM_CL_CLONE_NEWUTS | PPM_CL_CLONE_NEWUSER |
+                                     PPM_CL_CLONE_NEWCGROUP;
+
+/// native syscall numbers of the syscalls which move the caller to other namespaces, on the
+/// architecture the collector is built for. The driver reports them as generic syscall events.
+pub const SYS_SETNS: u16 = libc::SYS_setns as u16;
+pub const SYS_UNSHARE: u16 = libc::SYS_unshare as u16;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Namespaces {
+    /// pid namespace inode
+    pub pid: Option<u64>,
+    /// network namespace inode
+    pub net: Option<u64>,
+    /// mount namespace inode
+    pub mnt: Option<u64>,
+    /// UTS (hostname) namespace inode
+    pub uts: Option<u64>,
+    /// System V IPC namespace inode
+    pub ipc: Option<u64>,
+    /// user namespace inode
+    pub user: Option<u64>,
+    /// cgroup namespace inode
+    pub cgroup: Option<u64>
+}
+
+// parses the target of the namespace link, e.g. `net:[4026531993]`
+named!(parse_ns_inode<u64>, do_parse!(take_until_and_consume!(":[") >>
+                                      inode: parse_u64 >>
+                                      char!(']') >>
+                                      (inode)));
+
+fn ns_inode(pid: u64, root: &str, ns: &str) -> Option<u64> {
+    fs::read_link(format!("{}/{}/ns/{}", root, pid, ns))
+        .ok()
+        .and_then(|target| target.to_str().map(|t| t.to_string()))
+        .and_then(|target| match parse_ns_inode(target.as_bytes()) {
+            IResult::Done(_, inode) => Some(inode),
+            _ => None
+        })
+}
+
+/// Reads the namespaces of the process. Namespace types not supported by the running kernel,
+/// or links that can't be read due to insufficient privileges, are left empty.
+pub fn namespaces(pid: u64, root: String) -> Result<Namespaces> {
+    // ~ fail if the process is gone
+    fs::metadata(format!("{}/{}/ns", root, pid)).map_err(|e| Error::from_proc_io(pid, e))?;
+    Ok(Namespaces {
+        pid: ns_inode(pid, &root, "pid"),
+        net: ns_inode(pid, &root, "net"),
+        mnt: ns_inode(pid, &root, "mnt"),
+        uts: ns_inode(pid, &root, "uts"),
+        ipc: ns_inode(pid, &root, "ipc"),
+        user: ns_inode(pid, &root, "user"),
+        cgroup: ns_inode(pid, &root, "cgroup")
+    })
+}
@@ -1,6 +1,5 @@
 //! Contains useful [nom](https://github.com/Geal/nom) parsers.
 
-use std::path::PathBuf;
 use nom::not_line_ending;
 use std::str::{self, FromStr};
 use std::borrow::ToOwned;
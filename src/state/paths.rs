@@ -0,0 +1,30 @@
+//! Lexical resolution of the file system paths passed to syscalls. Relative paths are
+//! resolved against the working directory of the process, or the directory referred by
+//! the file descriptor of the `*at` syscalls. Symbolic links aren't followed, so the
+//! resolved path is the one the process asked for, rather than the file it ended up with.
+
+/// special file descriptor of the `*at` syscalls referring to the working directory
+pub const AT_FDCWD: i64 = -100;
+
+/// Removes the `.` and empty components and folds the `..` components of the absolute path.
+/// The `..` components of the root directory refer to the root directory itself.
+pub fn normalize(path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {},
+            ".." => { components.pop(); },
+            _ => components.push(component)
+        }
+    }
+    format!("/{}", components.join("/"))
+}
+
+/// Resolves the path against the base directory. Absolute paths are only normalized.
+pub fn resolve(base: &str, path: &str) -> String {
+    if path.starts_with('/') {
+        normalize(path)
+    } else {
+        normalize(&format!("{}/{}", base, path))
+    }
+}
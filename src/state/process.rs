@@ -0,0 +1,159 @@
+//! Reads the process attributes which aren't part of the `status` file from the `/proc`
+//! pseudo file system: the executable, command line, working directory, audit login session
+//! and the start time of the process, as well as selected environment variables.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Result, Error, ErrorKind};
+use chrono::{DateTime, UTC, TimeZone};
+use libc;
+
+/// suffix appended by the kernel to the link target of deleted executables
+const DELETED_SUFFIX: &'static str = " (deleted)";
+/// value of `loginuid` and `sessionid` for processes outside of a login session
+const AUDIT_UNSET: u32 = 4294967295;
+/// 0-based indices of the `stat` file fields, counted from the `state` field
+const STAT_PGRP_IDX: usize = 2;
+const STAT_SESSION_IDX: usize = 3;
+const STAT_START_TIME_IDX: usize = 19;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Executable {
+    /// absolute path of the executable
+    pub path: String,
+    /// whether the executable was removed from the filesystem after the process started
+    pub deleted: bool
+}
+
+fn read_file(path: String) -> Result<String> {
+    let mut buf = String::new();
+    File::open(path)?.read_to_string(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_link(path: String) -> Option<String> {
+    fs::read_link(path).ok().and_then(|p| p.to_str().map(|p| p.to_string()))
+}
+
+/// Resolves the executable of the process. Kernel threads don't have one.
+pub fn exe(pid: u64, root: &str) -> Option<Executable> {
+    read_link(format!("{}/{}/exe", root, pid)).map(|path| {
+        if path.ends_with(DELETED_SUFFIX) {
+            Executable {
+                path: path[..path.len() - DELETED_SUFFIX.len()].to_string(),
+                deleted: true
+            }
+        } else {
+            Executable {
+                path: path,
+                deleted: false
+            }
+        }
+    })
+}
+
+/// Reads the command line arguments. The arguments are separated by NUL bytes.
+pub fn cmdline(pid: u64, root: &str) -> Vec<String> {
+    let mut buf = Vec::new();
+    match File::open(format!("{}/{}/cmdline", root, pid)).and_then(|mut f| f.read_to_end(&mut buf)) {
+        Ok(_) => buf.split(|b| *b == 0)
+                    .filter(|arg| !arg.is_empty())
+                    .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                    .collect(),
+        Err(_) => Vec::new()
+    }
+}
+
+/// Resolves the current working directory of the process.
+pub fn cwd(pid: u64, root: &str) -> Option<String> {
+    read_link(format!("{}/{}/cwd", root, pid))
+}
+
+/// Resolves the file the descriptor of the process refers to. Descriptors that don't
+/// refer to files have pseudo paths, such as `socket:[42]`.
+pub fn fd_path(pid: u64, root: &str, fd: i64) -> Option<String> {
+    read_link(format!("{}/{}/fd/{}", root, pid, fd))
+}
+
+fn audit_id(pid: u64, root: &str, name: &str) -> Option<u32> {
+    read_file(format!("{}/{}/{}", root, pid, name)).ok()
+        .and_then(|id| id.trim().parse::<u32>().ok())
+        .and_then(|id| if id == AUDIT_UNSET { None } else { Some(id) })
+}
+
+/// Reads the audit user id the process was logged in as.
+pub fn loginuid(pid: u64, root: &str) -> Option<u32> {
+    audit_id(pid, root, "loginuid")
+}
+
+/// Reads the audit session id of the process.
+pub fn sessionid(pid: u64, root: &str) -> Option<u32> {
+    audit_id(pid, root, "sessionid")
+}
+
+/// Reads the boot time of the system expressed in seconds since the epoch.
+pub fn boot_time(root: &str) -> Result<i64> {
+    let stat = read_file(format!("{}/stat", root))?;
+    stat.lines()
+        .find(|line| line.starts_with("btime "))
+        .and_then(|line| line["btime ".len()..].trim().parse::<i64>().ok())
+        .ok_or(Error::new(ErrorKind::InvalidData, "unable to find boot time"))
+}
+
+/// Reads the numeric field from the `stat` file. The fields are read after the closing
+/// parenthesis of the `comm` field since the command name can contain spaces.
+fn stat_field(pid: u64, root: &str, idx: usize) -> Option<u64> {
+    let stat = match read_file(format!("{}/{}/stat", root, pid)) {
+        Ok(stat) => stat,
+        Err(_) => return None
+    };
+    stat.rfind(')')
+        .and_then(|i| stat[i + 1..].split_whitespace().nth(idx))
+        .and_then(|field| field.parse::<u64>().ok())
+}
+
+/// Reads the process group id of the process.
+pub fn pgid(pid: u64, root: &str) -> Option<u64> {
+    stat_field(pid, root, STAT_PGRP_IDX)
+}
+
+/// Reads the session id of the process, i.e. the pid of the session leader.
+pub fn sid(pid: u64, root: &str) -> Option<u64> {
+    stat_field(pid, root, STAT_SESSION_IDX)
+}
+
+/// Computes the start time of the process from the `starttime` field of the `stat` file,
+/// which is expressed in clock ticks since boot.
+pub fn start_time(pid: u64, root: &str, boot_time: i64) -> Option<DateTime<UTC>> {
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as u64;
+    if ticks == 0 {
+        return None;
+    }
+    stat_field(pid, root, STAT_START_TIME_IDX)
+        .map(|start| {
+            let secs = boot_time + (start / ticks) as i64;
+            let nanos = ((start % ticks) * (1000000000 / ticks)) as u32;
+            UTC.timestamp(secs, nanos)
+        })
+}
+
+/// Reads the environment variables of the process whose names are in the `names` list.
+pub fn environ(pid: u64, root: &str, names: &[String]) -> BTreeMap<String, String> {
+    let mut vars = BTreeMap::new();
+    if names.is_empty() {
+        return vars;
+    }
+    let mut buf = Vec::new();
+    if File::open(format!("{}/{}/environ", root, pid)).and_then(|mut f| f.read_to_end(&mut buf)).is_err() {
+        return vars;
+    }
+    for var in buf.split(|b| *b == 0) {
+        let var = String::from_utf8_lossy(var);
+        if let Some(i) = var.find('=') {
+            if names.iter().any(|name| *name == var[..i]) {
+                vars.insert(var[..i].to_string(), var[i + 1..].to_string());
+            }
+        }
+    }
+    vars
+}
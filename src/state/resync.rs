@@ -0,0 +1,41 @@
+//! Periodically rescans the `/proc` file system in the background. The thread registry drifts
+//! from the live state of the system when events are dropped in the ring buffer, so the fresh
+//! registries built by the rescans are handed over to be reconciled with the registry that's
+//! maintained from the stream of events.
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+use std::sync::mpsc::{sync_channel, Receiver};
+use config::StateConfig;
+use super::thread::ThreadRegistry;
+
+pub struct Resync {
+    rx: Receiver<ThreadRegistry>
+}
+
+impl Resync {
+
+    /// Starts the thread that rescans `/proc` every `resync_interval` seconds.
+    pub fn start(config: StateConfig) -> io::Result<Resync> {
+        // ~ a single pending registry, the rescans block until it's taken
+        let (tx, rx) = sync_channel(1);
+        let interval = Duration::from_secs(config.resync_interval);
+        thread::Builder::new().name("state-resync".to_string()).spawn(move || {
+            loop {
+                thread::sleep(interval);
+                let mut fresh = ThreadRegistry::with_config(config.clone());
+                fresh.collect();
+                if tx.send(fresh).is_err() {
+                    break;
+                }
+            }
+        })?;
+        Ok(Resync { rx: rx })
+    }
+
+    /// Returns the registry built by the last rescan if it wasn't taken yet.
+    pub fn poll(&self) -> Option<ThreadRegistry> {
+        self.rx.try_recv().ok()
+    }
+}
@@ -0,0 +1,222 @@
+//! Bounded table for the state kept about the entities of the system, such as threads and
+//! processes. The table holds at most `capacity` entries. Entries of exited entities are kept
+//! for a grace period, so events processed after the exit can still be decorated, and then
+//! expire. When the table is full, exited entries are evicted first, followed by the least
+//! recently used ones.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::collections::hash_map;
+use std::fmt;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+use config::TableConfig;
+
+struct Entry<V> {
+    value: V,
+    /// access tick of the last lookup
+    tick: u64,
+    /// time the entity exited at
+    exited_at: Option<Instant>
+}
+
+/// Counters of the entries removed by the table policies.
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct TableStats {
+    /// number of entries in the table
+    pub len: usize,
+    /// entries removed once the grace period of the exited entity elapsed
+    pub expired: u64,
+    /// entries removed due to the capacity limit
+    pub evicted: u64
+}
+
+pub struct Table<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    /// keys indexed by access tick, from the least to the most recently used
+    lru: BTreeMap<u64, K>,
+    /// exited keys in the order they exited
+    exited: VecDeque<(Instant, K)>,
+    tick: u64,
+    capacity: usize,
+    exited_ttl: Duration,
+    stats: TableStats
+}
+
+impl<K: Hash + Eq + Clone, V> Table<K, V> {
+
+    pub fn new(config: &TableConfig) -> Table<K, V> {
+        Table {
+            entries: HashMap::new(),
+            lru: BTreeMap::new(),
+            exited: VecDeque::new(),
+            tick: 0,
+            capacity: config.capacity,
+            exited_ttl: Duration::from_secs(config.exited_ttl),
+            stats: TableStats::default()
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    pub fn get(&self, k: &K) -> Option<&V> {
+        self.entries.get(k).map(|e| &e.value)
+    }
+
+    pub fn get_mut(&mut self, k: &K) -> Option<&mut V> {
+        self.entries.get_mut(k).map(|e| &mut e.value)
+    }
+
+    pub fn contains_key(&self, k: &K) -> bool {
+        self.entries.contains_key(k)
+    }
+
+    /// Marks the entry as the most recently used one.
+    pub fn touch(&mut self, k: &K) {
+        let tick = self.next_tick();
+        if let Some(e) = self.entries.get_mut(k) {
+            self.lru.remove(&e.tick);
+            e.tick = tick;
+            self.lru.insert(tick, k.clone());
+        }
+    }
+
+    /// Inserts the entry, replacing the one with the same key. If the table is full, the entry
+    /// that exited first, or else the least recently used one, is evicted and returned.
+    pub fn insert(&mut self, k: K, v: V) -> Option<(K, V)> {
+        let tick = self.next_tick();
+        if let Some(old) = self.entries.remove(&k) {
+            self.lru.remove(&old.tick);
+        }
+        let evicted = if self.capacity > 0 && self.entries.len() >= self.capacity {
+            self.evict_victim()
+        } else {
+            None
+        };
+        self.lru.insert(tick, k.clone());
+        self.entries.insert(k, Entry { value: v, tick: tick, exited_at: None });
+        evicted
+    }
+
+    fn evict_victim(&mut self) -> Option<(K, V)> {
+        let mut victim = None;
+        while let Some((exited_at, k)) = self.exited.pop_front() {
+            if self.is_exited_at(&k, exited_at) {
+                victim = Some(k);
+                break;
+            }
+        }
+        if victim.is_none() {
+            victim = self.lru.values().next().cloned();
+        }
+        victim.and_then(|k| self.evict(&k).map(|v| (k, v)))
+    }
+
+    /// Removes the entry due to capacity pressure, e.g. when the entry it depends
+    /// on was evicted from another table.
+    pub fn evict(&mut self, k: &K) -> Option<V> {
+        self.remove(k).inspect(|_| self.stats.evicted += 1)
+    }
+
+    fn is_exited_at(&self, k: &K, exited_at: Instant) -> bool {
+        self.entries.get(k).is_some_and(|e| e.exited_at == Some(exited_at))
+    }
+
+    pub fn remove(&mut self, k: &K) -> Option<V> {
+        self.entries.remove(k).map(|e| {
+            self.lru.remove(&e.tick);
+            e.value
+        })
+    }
+
+    /// Marks the entity as exited. The entry expires once the grace period elapses.
+    pub fn mark_exited(&mut self, k: &K) {
+        let now = Instant::now();
+        if let Some(e) = self.entries.get_mut(k) {
+            if e.exited_at.is_none() {
+                e.exited_at = Some(now);
+                self.exited.push_back((now, k.clone()));
+            }
+        }
+    }
+
+    /// Removes and returns the entries whose grace period elapsed.
+    pub fn expire(&mut self) -> Vec<(K, V)> {
+        let mut expired = Vec::new();
+        let now = Instant::now();
+        while let Some(&(exited_at, _)) = self.exited.front() {
+            if now.duration_since(exited_at) < self.exited_ttl {
+                break;
+            }
+            if let Some((exited_at, k)) = self.exited.pop_front() {
+                // ~ the entry could have been replaced after it exited
+                if self.is_exited_at(&k, exited_at) {
+                    if let Some(v) = self.remove(&k) {
+                        self.stats.expired += 1;
+                        expired.push((k, v));
+                    }
+                }
+            }
+        }
+        expired
+    }
+
+    /// Removes all the entries, keeping the counters.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.lru.clear();
+        self.exited.clear();
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.entries.keys() }
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.entries.values() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> TableStats {
+        TableStats { len: self.entries.len(), ..self.stats.clone() }
+    }
+}
+
+impl<K: Hash + Eq + fmt::Debug, V: fmt::Debug> fmt::Debug for Table<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.entries.iter().map(|(k, e)| (k, &e.value))).finish()
+    }
+}
+
+pub struct Keys<'a, K: 'a, V: 'a> {
+    inner: hash_map::Keys<'a, K, Entry<V>>
+}
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.inner.next()
+    }
+}
+
+pub struct Values<'a, K: 'a, V: 'a> {
+    inner: hash_map::Values<'a, K, Entry<V>>
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<&'a V> {
+        self.inner.next().map(|e| &e.value)
+    }
+}
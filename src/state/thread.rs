@@ -1,16 +1,28 @@
 //! Parses the `status` files from the `/proc` pseudo file system and collects information about
-//! currently running processes.
+//! currently running processes and their threads. Threads are enumerated from the
+//! `/proc/[pid]/task/[tid]` directories, while the state shared by the threads of the same
+//! thread group is kept once per process.
 
-use super::parsers::{parse_line, consume_until_line_ending, parse_u32_octal, parse_u64, parse_u32};
 use super::cgroups::{CGroup, cgroups};
-use nom::{IResult, line_ending, space};
-use std::collections::HashMap;
-use std::io::{Error, Result, ErrorKind, Read};
-use std::fs::File;
+use super::capabilities::{self, Capabilities};
+use super::process::{self, Executable};
+use super::users::{Account, UserResolver};
+use super::table::{Table, TableStats};
+use super::paths::{self, AT_FDCWD};
+use super::namespaces::{self, Namespaces, namespaces, PPM_CL_CLONE_NEWANY};
+use nom::{IResult, line_ending, not_line_ending};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::io::{ErrorKind, Read};
+use std::fs::{self, File};
+use std::str;
 use glob::glob;
-use std::path::Path;
+use chrono::{DateTime, UTC};
+use config::{StateConfig, TableConfig};
+use error::{Error, Result};
+use syscall::SyscallInfo;
+use value::Value;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, PartialEq)]
 pub enum ThreadState {
     Running,
     Sleeping,
@@ -18,32 +30,286 @@ pub enum ThreadState {
     Stopped,
     TraceStopped,
     Dead,
-    Zombie
+    Zombie,
+    Idle
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub enum SeccompMode {
+    Disabled,
+    Strict,
+    Filter
 }
 
 pub struct ThreadRegistry {
-    pub threads: HashMap<u64, ThreadInfo>,
-    proc_root: String
+    /// threads indexed by thread id
+    pub threads: Table<u64, ThreadInfo>,
+    /// processes indexed by process id
+    pub processes: Table<u64, ProcessInfo>,
+    /// threads that entered `setns` or `unshare` and whose namespaces
+    /// have to be reloaded once the syscall returns
+    pending_namespaces: HashSet<u64>,
+    /// resolved paths of the `openat` and `fchdir` calls awaiting their exit events, bounded
+    /// like the thread table. The paths whose exit event was dropped expire after the grace
+    /// period of the exited threads.
+    pending_paths: Table<u64, String>,
+    /// ids of the child processes indexed by the parent process id. The processes are linked
+    /// and unlinked along with their entries in the process table, so it holds as many
+    /// children as the table holds processes.
+    children: HashMap<u64, BTreeSet<u64>>,
+    /// limits of the open file table of each process
+    fd_table: TableConfig,
+    /// open files evicted from the tables of the processes
+    fd_evictions: u64,
+    /// maximum number of processes in the ancestry chain
+    ancestry_depth: usize,
+    /// corrections made by reconciling the registry with the rescans of `/proc`
+    corrections: Corrections,
+    /// corrections made by the last rescan
+    last_corrections: Corrections,
+    /// number of the rescans reconciled
+    resyncs: u64,
+    /// names of the environment variables captured from processes
+    environ: Vec<String>,
+    /// boot time of the system in seconds since the epoch
+    boot_time: Option<i64>,
+    /// mount point of the host `/proc` file system
+    proc_root: String,
+    /// location of the host `/etc` directory
+    etc_root: String,
+    /// resolves the user and group names of threads
+    users: UserResolver
+}
+
+/// Outcome of enumerating the processes and threads from the `/proc` file system. Processes
+/// and threads that exit while being scanned are skipped, while the ones that can't be read
+/// for other reasons are reported as failures.
+#[derive(Serialize, Debug, Default)]
+pub struct ScanReport {
+    /// number of processes and threads successfully collected
+    pub scanned: usize,
+    /// number of processes and threads that vanished during the scan
+    pub skipped: usize,
+    /// processes and threads that couldn't be collected
+    pub failed: Vec<ScanFailure>
+}
+
+#[derive(Serialize, Debug)]
+pub struct ScanFailure {
+    /// process or thread id, if known
+    pub pid: Option<u64>,
+    /// reason of the failure
+    pub reason: String
+}
+
+impl ScanReport {
+
+    fn fail(&mut self, pid: Option<u64>, reason: String) {
+        self.failed.push(ScanFailure { pid: pid, reason: reason });
+    }
+
+    fn record<T>(&mut self, result: Result<T>) -> Option<T> {
+        match result {
+            Ok(v) => Some(v),
+            Err(Error::ProcessVanished(_)) => {
+                self.skipped += 1;
+                None
+            },
+            Err(e) => {
+                let pid = match e {
+                    Error::ProcPermissionDenied(pid) |
+                    Error::ProcIoError(pid, _) |
+                    Error::ProcParseError(pid, _) => Some(pid),
+                    _ => None
+                };
+                self.fail(pid, e.to_string());
+                None
+            }
+        }
+    }
+}
+
+/// Counts of the differences between the registry and `/proc` fixed by the rescans.
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct Corrections {
+    /// processes found in `/proc` that were missing from the registry
+    pub added_processes: u64,
+    /// threads found in `/proc` that were missing from the registry
+    pub added_threads: u64,
+    /// processes that exited without the registry noticing
+    pub evicted_processes: u64,
+    /// threads that exited without the registry noticing
+    pub evicted_threads: u64
+}
+
+impl Corrections {
+
+    fn add(&mut self, other: &Corrections) {
+        self.added_processes += other.added_processes;
+        self.added_threads += other.added_threads;
+        self.evicted_processes += other.evicted_processes;
+        self.evicted_threads += other.evicted_threads;
+    }
+}
+
+/// Point in time view of the registry. The snapshot is emitted as the header record
+/// followed by one record per process holding the process and its threads, so the size
+/// of the records doesn't grow with the number of processes.
+pub struct Snapshot<'a> {
+    pub header: SnapshotHeader<'a>,
+    pub processes: Vec<ProcessRecord<'a>>
+}
+
+/// Record of the snapshot, tagged with its kind in the `record` field.
+#[derive(Serialize)]
+#[serde(tag = "record")]
+pub enum SnapshotRecord<'a> {
+    #[serde(rename = "header")]
+    Header(&'a SnapshotHeader<'a>),
+    #[serde(rename = "process")]
+    Process(&'a ProcessRecord<'a>)
+}
+
+#[derive(Serialize)]
+pub struct SnapshotHeader<'a> {
+    /// time the snapshot was taken at, shared by all records of the snapshot
+    pub ts: DateTime<UTC>,
+    /// number of the process records following the header
+    pub processes: usize,
+    /// number of threads in the process records
+    pub threads: usize,
+    /// number of resyncs reconciled since the registry was created
+    pub resyncs: u64,
+    /// corrections made by the last resync
+    pub last_corrections: &'a Corrections,
+    /// corrections made since the registry was created
+    pub corrections: &'a Corrections,
+    /// occupancy and eviction counters of the process table
+    pub process_table: TableStats,
+    /// occupancy and eviction counters of the thread table
+    pub thread_table: TableStats,
+    /// occupancy and eviction counters of the open file tables of all processes
+    pub fd_table: TableStats,
+    /// occupancy, expiration and eviction counters of the paths awaiting the exit events
+    pub pending_path_table: TableStats
+}
+
+#[derive(Serialize)]
+pub struct ProcessRecord<'a> {
+    /// time the snapshot was taken at
+    pub ts: DateTime<UTC>,
+    /// process id
+    pub pid: u64,
+    /// the process, absent when only its threads are known
+    pub process: Option<&'a ProcessInfo>,
+    /// threads of the process
+    pub threads: Vec<&'a ThreadInfo>
+}
+
+impl<'a> Snapshot<'a> {
+
+    /// Returns the header record followed by the process records.
+    pub fn records(&self) -> Vec<SnapshotRecord<'_>> {
+        let mut records = vec![SnapshotRecord::Header(&self.header)];
+        records.extend(self.processes.iter().map(SnapshotRecord::Process));
+        records
+    }
+}
+
+/// A process in the ancestry chain of a thread.
+#[derive(Serialize, Debug, Clone)]
+pub struct Ancestor {
+    /// process id
+    pub pid: u64,
+    /// command name of the process
+    pub comm: String
 }
 
 #[derive(Serialize, Debug)]
 pub struct ThreadInfo {
-    /// filename of the executable
+    /// command name of the thread
     pub comm: String,
-    /// current state of the process
+    /// current state of the thread
     pub state: ThreadState,
     /// process id (i.e., thread group id)
     pub pid: u64,
     /// thread id
     pub tid: u64,
-    /// process id of the parent process
-    pub ppid: u64,
+    /// thread id in the innermost pid namespace of the process
+    pub vtid: u64,
     /// real user id
     pub uid: u32,
+    /// effective user id
+    pub euid: u32,
+    /// saved set user id
+    pub suid: u32,
+    /// filesystem user id
+    pub fsuid: u32,
     /// real group id
     pub gid: u32,
+    /// effective group id
+    pub egid: u32,
+    /// saved set group id
+    pub sgid: u32,
+    /// filesystem group id
+    pub fsgid: u32,
+    /// capability sets of the thread
+    pub capabilities: Option<Capabilities>,
+    /// seccomp operating mode of the thread
+    pub seccomp: Option<SeccompMode>,
+    /// whether the thread is prevented from gaining privileges through `execve`
+    pub no_new_privs: bool,
     /// cgroups bounded to this thread
-    pub cgroups: Option<Vec<CGroup>>
+    pub cgroups: Option<Vec<CGroup>>,
+    /// namespaces the thread is member of
+    pub namespaces: Option<Namespaces>,
+    /// real user of the thread as seen from its mount namespace
+    pub user: Option<Account>,
+    /// real group of the thread as seen from its mount namespace
+    pub group: Option<Account>
+}
+
+/// Holds the state shared by all threads of the thread group.
+#[derive(Serialize, Debug)]
+pub struct ProcessInfo {
+    /// process id (i.e., thread group id)
+    pub pid: u64,
+    /// process id in the innermost pid namespace of the process
+    pub vpid: u64,
+    /// process id of the parent process
+    pub ppid: u64,
+    /// process group id
+    pub pgid: Option<u64>,
+    /// session id, i.e. process id of the session leader
+    pub sid: Option<u64>,
+    /// command name of the process, i.e. of its main thread, kept after the main thread exits
+    pub comm: String,
+    /// ids of the threads in the thread group
+    pub tids: BTreeSet<u64>,
+    /// number of threads in the thread group
+    pub num_threads: u32,
+    /// resident set size in kB
+    pub vm_rss: Option<u64>,
+    /// virtual memory size in kB
+    pub vm_size: Option<u64>,
+    /// executable of the process
+    pub exe: Option<Executable>,
+    /// command line arguments of the process
+    pub cmdline: Vec<String>,
+    /// current working directory of the process
+    pub cwd: Option<String>,
+    /// audit user id the process was logged in as
+    pub loginuid: Option<u32>,
+    /// audit session id of the process
+    pub sessionid: Option<u32>,
+    /// time the process was started at
+    pub start_time: Option<DateTime<UTC>>,
+    /// environment variables of the process selected by the `environ` filter
+    pub environ: BTreeMap<String, String>,
+    /// paths of the files open by the process indexed by file descriptor, the least
+    /// recently used ones are evicted beyond the `max_fds` limit
+    #[serde(skip_serializing)]
+    pub fds: Table<i64, String>
 }
 
 named!(parse_thread_state<ThreadState>,
@@ -53,89 +319,889 @@ named!(parse_thread_state<ThreadState>,
           | tag!("T (stopped)") => { |_| ThreadState::Stopped }
           | tag!("t (tracing stop)") => { |_| ThreadState::TraceStopped }
           | tag!("X (dead)") => { |_| ThreadState::Dead }
-          | tag!("Z (zombie)") => { |_| ThreadState::Zombie }));
-
-
-named!(parse_command<String>, delimited!(tag!("Name:\t"), parse_line, line_ending));
-named!(parse_umask<u32>, delimited!(tag!("Umask:\t"), parse_u32_octal, line_ending));
-named!(parse_state<ThreadState>, delimited!(tag!("State:\t"), parse_thread_state, line_ending));
-named!(parse_pid<u64>, delimited!(tag!("Tgid:\t"), parse_u64, line_ending));
-named!(parse_tid<u64>, delimited!(tag!("Pid:\t"), parse_u64, line_ending));
-named!(parse_ppid<u64>, delimited!(tag!("PPid:\t"), parse_u64, line_ending));
-
-named!(parse_uid<u32>, chain!(tag!("Uid:\t") ~ uid: parse_u32 ~ consume_until_line_ending,
-                              || {(uid)}));
-named!(parse_gid<u32>, chain!(tag!("Gid:\t") ~ gid: parse_u32 ~ consume_until_line_ending,
-                              || {(gid)}));
-
-named!(thread_info<ThreadInfo>,
-       do_parse!(
-            comm: parse_command >>
-            opt!(parse_umask) >>
-            state: parse_state >>
-            pid: parse_pid >>
-            consume_until_line_ending >>
-            tid: parse_tid >>
-            ppid: parse_ppid >>
-            consume_until_line_ending >>
-            uid: parse_uid >>
-            gid: parse_gid >>
-            (ThreadInfo {
-                 comm: comm,
-                 state: state,
-                 pid: pid,
-                 tid: tid,
-                 ppid: ppid,
-                 uid: uid,
-                 gid: gid,
-                 cgroups: None,
-            })
-       ));
+          | tag!("Z (zombie)") => { |_| ThreadState::Zombie }
+          | tag!("I (idle)") => { |_| ThreadState::Idle }));
+
+// parses a single `Key:\tvalue` entry of the status file
+named!(parse_status_entry<(&'a str, &'a str)>,
+       do_parse!(key: map_res!(take_until_and_consume!(":"), str::from_utf8) >>
+                 value: map_res!(not_line_ending, str::from_utf8) >>
+                 line_ending >>
+                 ((key, value.trim()))));
+
+named!(parse_status_entries<Vec<(&'a str, &'a str)> >, many0!(parse_status_entry));
 
-fn parse_thread(buf: &[u8]) -> IResult<&[u8], ThreadInfo> {
-    map!(buf, thread_info, |t| {t})
+/// result of parsing the status file entries, failing with the reason
+type ParseResult<T> = ::std::result::Result<T, String>;
+
+fn parse_error(key: &str) -> String {
+    format!("unable to parse {} in status file", key)
 }
 
-pub fn parse_thread_info(pid: u64, root: String) -> Result<ThreadInfo> {
+fn required<'a>(entries: &HashMap<&str, &'a str>, key: &str) -> ParseResult<&'a str> {
+    entries.get(key).cloned().ok_or(parse_error(key))
+}
+
+fn parse_id(entries: &HashMap<&str, &str>, key: &str) -> ParseResult<u64> {
+    required(entries, key)?.parse::<u64>().map_err(|_| parse_error(key))
+}
+
+/// Parses the whitespace separated list of ids, e.g. the real, effective, saved set
+/// and filesystem uids of the `Uid` entry.
+fn parse_ids(entries: &HashMap<&str, &str>, key: &str) -> ParseResult<Vec<u64>> {
+    let ids = required(entries, key)?.split_whitespace()
+        .map(|id| id.parse::<u64>())
+        .collect::<::std::result::Result<Vec<_>, _>>();
+    match ids {
+        Ok(ref ids) if !ids.is_empty() => Ok(ids.clone()),
+        _ => Err(parse_error(key))
+    }
+}
+
+fn parse_capabilities(entries: &HashMap<&str, &str>, key: &str) -> Vec<String> {
+    entries.get(key)
+        .and_then(|mask| u64::from_str_radix(mask, 16).ok())
+        .map(capabilities::decode)
+        .unwrap_or_default()
+}
+
+/// Parses the memory size entry such as `VmRSS:    1416 kB`.
+fn parse_kb(entries: &HashMap<&str, &str>, key: &str) -> Option<u64> {
+    entries.get(key)
+        .and_then(|v| v.split_whitespace().next())
+        .and_then(|v| v.parse::<u64>().ok())
+}
+
+/// Builds the thread and the process it belongs to from the entries of the status file. The
+/// entries can appear in any order, and the ones that aren't recognized are skipped. Entries
+/// that are only available on recent kernels are optional.
+fn status_info(entries: &HashMap<&str, &str>) -> ParseResult<(ThreadInfo, ProcessInfo)> {
+    let state = match parse_thread_state(required(entries, "State")?.as_bytes()) {
+        IResult::Done(_, state) => state,
+        _ => return Err(parse_error("State"))
+    };
+    let pid = parse_id(entries, "Tgid")?;
+    let tid = parse_id(entries, "Pid")?;
+    let uids = parse_ids(entries, "Uid")?;
+    let gids = parse_ids(entries, "Gid")?;
+    // ~ the ids are ordered from the outermost to the innermost pid namespace,
+    // and only present on kernels that support nested pid namespaces (4.1+)
+    let vpid = parse_ids(entries, "NStgid").ok().and_then(|ids| ids.last().cloned());
+    let vtid = parse_ids(entries, "NSpid").ok().and_then(|ids| ids.last().cloned());
+    let caps = if entries.contains_key("CapEff") {
+        Some(Capabilities {
+            inheritable: parse_capabilities(entries, "CapInh"),
+            permitted: parse_capabilities(entries, "CapPrm"),
+            effective: parse_capabilities(entries, "CapEff"),
+            bounding: parse_capabilities(entries, "CapBnd"),
+            ambient: parse_capabilities(entries, "CapAmb")
+        })
+    } else {
+        None
+    };
+    let seccomp = match entries.get("Seccomp") {
+        Some(&"0") => Some(SeccompMode::Disabled),
+        Some(&"1") => Some(SeccompMode::Strict),
+        Some(&"2") => Some(SeccompMode::Filter),
+        _ => None
+    };
+    let id = |ids: &Vec<u64>, i: usize| ids.get(i).or(ids.first()).cloned().unwrap_or(0) as u32;
+
+    let thread = ThreadInfo {
+        comm: required(entries, "Name")?.to_string(),
+        state: state,
+        pid: pid,
+        tid: tid,
+        vtid: vtid.unwrap_or(tid),
+        uid: id(&uids, 0),
+        euid: id(&uids, 1),
+        suid: id(&uids, 2),
+        fsuid: id(&uids, 3),
+        gid: id(&gids, 0),
+        egid: id(&gids, 1),
+        sgid: id(&gids, 2),
+        fsgid: id(&gids, 3),
+        capabilities: caps,
+        seccomp: seccomp,
+        no_new_privs: entries.get("NoNewPrivs") == Some(&"1"),
+        cgroups: None,
+        namespaces: None,
+        user: None,
+        group: None
+    };
+    let process = ProcessInfo {
+        pid: pid,
+        vpid: vpid.unwrap_or(pid),
+        ppid: parse_id(entries, "PPid")?,
+        pgid: None,
+        sid: None,
+        comm: thread.comm.clone(),
+        tids: BTreeSet::new(),
+        num_threads: entries.get("Threads").and_then(|t| t.parse::<u32>().ok()).unwrap_or(1),
+        vm_rss: parse_kb(entries, "VmRSS"),
+        vm_size: parse_kb(entries, "VmSize"),
+        exe: None,
+        cmdline: Vec::new(),
+        cwd: None,
+        loginuid: None,
+        sessionid: None,
+        start_time: None,
+        environ: BTreeMap::new(),
+        // ~ bounded once the process is added to the registry
+        fds: Table::new(&TableConfig { capacity: 0, exited_ttl: 0 })
+    };
+    Ok((thread, process))
+}
+
+fn parse_status(pid: u64, root: &str) -> Result<(ThreadInfo, ProcessInfo)> {
     let mut buf = String::new();
-    let mut f = try!(File::open(format!("{}/{}/status", root, pid)));
-    f.read_to_string(&mut buf);
-    match parse_thread(buf.as_bytes()) {
-        IResult::Done(i, o) => {
-            Ok(o)
-        },
-        IResult::Error(e) => {
-            Err(Error::new(ErrorKind::InvalidInput, "unable to parse status file"))
+    File::open(format!("{}/{}/status", root, pid))
+            .and_then(|mut f| f.read_to_string(&mut buf))
+            .map_err(|e| Error::from_proc_io(pid, e))?;
+    match parse_status_entries(buf.as_bytes()) {
+        IResult::Done(_, entries) => {
+            status_info(&entries.into_iter().collect()).map_err(|e| Error::ProcParseError(pid, e))
         },
-        _ => Err(Error::new(ErrorKind::InvalidInput, "unable to parse status file")),
+        _ => Err(Error::ProcParseError(pid, "malformed status file".to_string())),
     }
 }
 
+pub fn parse_thread_info(pid: u64, root: String) -> Result<ThreadInfo> {
+    parse_status(pid, &root).map(|(thread, _)| thread)
+}
+
+pub fn parse_process_info(pid: u64, root: String) -> Result<ProcessInfo> {
+    parse_status(pid, &root).map(|(_, process)| process)
+}
+
 impl ThreadRegistry {
 
     pub fn new() -> ThreadRegistry {
+        ThreadRegistry::with_config(StateConfig::default())
+    }
+
+    pub fn with_config(config: StateConfig) -> ThreadRegistry {
         ThreadRegistry {
-            threads: HashMap::new(),
-            proc_root: "/proc".to_string()
+            threads: Table::new(&config.threads),
+            processes: Table::new(&config.processes),
+            pending_namespaces: HashSet::new(),
+            pending_paths: Table::new(&config.threads),
+            children: HashMap::new(),
+            fd_table: TableConfig { capacity: config.max_fds, exited_ttl: 0 },
+            fd_evictions: 0,
+            ancestry_depth: config.ancestry_depth,
+            corrections: Corrections::default(),
+            last_corrections: Corrections::default(),
+            resyncs: 0,
+            environ: config.environ,
+            boot_time: process::boot_time(&config.proc_root).ok(),
+            users: UserResolver::new(&config.proc_root, &config.etc_root, &config.processes),
+            proc_root: config.proc_root,
+            etc_root: config.etc_root
+        }
+    }
+
+    /// Creates the registry reading the host state from the given roots, e.g. when the
+    /// host file systems are mounted at `/host/proc` and `/host/etc`.
+    pub fn with_roots(proc_root: &str, etc_root: &str) -> ThreadRegistry {
+        ThreadRegistry::with_config(StateConfig {
+            proc_root: proc_root.to_string(),
+            etc_root: etc_root.to_string(),
+            ..StateConfig::default()
+        })
+    }
+
+    /// Returns the mount point of the host `/proc` file system.
+    pub fn proc_root(&self) -> &str {
+        &self.proc_root
+    }
+
+    /// Returns the location of the host `/etc` directory.
+    pub fn etc_root(&self) -> &str {
+        &self.etc_root
+    }
+
+    /// Enumerates the processes and, for every process, the threads found
+    /// in the `/proc/[pid]/task` directory. Processes come and go while the
+    /// directories are walked, so the entries that can't be read are skipped
+    /// and accounted in the returned report rather than aborting the scan.
+    pub fn collect(&mut self) -> ScanReport {
+        let mut report = ScanReport::default();
+        for pid in self.scan_dir(&self.proc_root.clone(), &mut report) {
+            let mut pi = match report.record(parse_process_info(pid, self.proc_root.clone())) {
+                Some(pi) => pi,
+                None => continue
+            };
+            self.load_process(&mut pi);
+            self.insert_process(pi);
+            if self.collect_tasks(pid, &mut report) {
+                report.scanned += 1;
+            } else {
+                // ~ all threads exited, so did the process
+                self.remove_process(pid);
+                report.skipped += 1;
+            }
         }
+        report
     }
 
-    pub fn collect(&mut self) {
-        for e in glob(&format!("{}/*[0-9]*", self.proc_root.clone()))
-            .expect("error") {
+    /// Collects the threads of the process. Returns `false` if none of
+    /// the threads could be collected.
+    fn collect_tasks(&mut self, pid: u64, report: &mut ScanReport) -> bool {
+        let task_root = self.task_root(pid);
+        let mut collected = false;
+        for tid in self.scan_dir(&task_root, report) {
+            let mut ti = match report.record(parse_thread_info(tid, task_root.clone())) {
+                Some(ti) => ti,
+                None => continue
+            };
+            ti.cgroups = report.record(cgroups(tid, task_root.clone()));
+            ti.namespaces = namespaces(tid, task_root.clone()).ok();
+            self.insert_thread(ti);
+            collected = true;
+        }
+        collected
+    }
+
+    /// Lists the numeric entries of the directory.
+    fn scan_dir(&self, dir: &str, report: &mut ScanReport) -> Vec<u64> {
+        let paths = match glob(&format!("{}/*[0-9]*", dir)) {
+            Ok(paths) => paths,
+            Err(e) => {
+                report.fail(None, e.to_string());
+                return Vec::new();
+            }
+        };
+        let mut ids = Vec::new();
+        for e in paths {
             match e {
                 Ok(path) => {
-                    let pid = path.file_name().unwrap()
-                                .to_str()
-                                .unwrap().parse::<u64>().unwrap();
-                    let mut ti = parse_thread_info(pid, self.proc_root.clone()).unwrap();
-                    ti.cgroups = Some(cgroups(pid, self.proc_root.clone()).unwrap());
-                    self.threads.insert(pid, ti);
+                    if let Some(id) = path.file_name().and_then(|n| n.to_str()).and_then(|n| n.parse::<u64>().ok()) {
+                        ids.push(id);
+                    }
                 },
                 Err(e) => {
+                    // ~ unreadable entry, typically one that vanished while being listed
+                    match e.error().kind() {
+                        ErrorKind::NotFound => report.skipped += 1,
+                        _ => report.fail(None, e.to_string())
+                    }
+                }
+            }
+        }
+        ids
+    }
+
+    /// Reconciles the registry with the result of a fresh scan of `/proc`. Processes and
+    /// threads missed due to dropped events are added, while the ones that are neither in
+    /// the scan nor in `/proc` anymore are evicted. Threads collected after the scan started
+    /// are kept as they're still alive. Returns the corrections made.
+    pub fn reconcile(&mut self, mut fresh: ThreadRegistry) -> Corrections {
+        let mut corrections = Corrections::default();
+
+        let stale = self.threads.keys()
+            .filter(|tid| !fresh.threads.contains_key(tid) && !self.is_alive(**tid))
+            .cloned()
+            .collect::<Vec<_>>();
+        for tid in stale {
+            let pid = self.threads.get(&tid).map(|ti| ti.pid).unwrap_or(0);
+            let known = self.processes.contains_key(&pid);
+            self.remove_thread(tid);
+            corrections.evicted_threads += 1;
+            if known && !self.processes.contains_key(&pid) {
+                corrections.evicted_processes += 1;
+            }
+        }
+        let stale = self.processes.keys()
+            .filter(|pid| !fresh.processes.contains_key(pid) && !self.is_alive(**pid))
+            .cloned()
+            .collect::<Vec<_>>();
+        for pid in stale {
+            let tids = self.processes.get(&pid).map(|pi| pi.tids.clone()).unwrap_or_default();
+            for tid in tids {
+                self.threads.remove(&tid);
+                self.forget_thread(tid);
+                corrections.evicted_threads += 1;
+            }
+            self.remove_process(pid);
+            corrections.evicted_processes += 1;
+        }
+
+        let missing = fresh.processes.keys()
+            .filter(|pid| !self.processes.contains_key(pid))
+            .cloned()
+            .collect::<Vec<_>>();
+        for pid in missing {
+            if let Some(mut pi) = fresh.processes.remove(&pid) {
+                // ~ the threads are linked below
+                pi.tids.clear();
+                self.insert_process(pi);
+                corrections.added_processes += 1;
+            }
+        }
+        let missing = fresh.threads.keys()
+            .filter(|tid| !self.threads.contains_key(tid))
+            .cloned()
+            .collect::<Vec<_>>();
+        for tid in missing {
+            if let Some(ti) = fresh.threads.remove(&tid) {
+                self.link_thread(ti);
+                corrections.added_threads += 1;
+            }
+        }
+
+        self.corrections.add(&corrections);
+        self.last_corrections = corrections.clone();
+        self.resyncs += 1;
+        corrections
+    }
+
+    fn is_alive(&self, tid: u64) -> bool {
+        fs::metadata(format!("{}/{}", self.proc_root, tid)).is_ok()
+    }
+
+    /// Returns the corrections made since the registry was created.
+    pub fn corrections(&self) -> &Corrections {
+        &self.corrections
+    }
+
+    /// Returns the corrections made by the last rescan.
+    pub fn last_corrections(&self) -> &Corrections {
+        &self.last_corrections
+    }
+
+    /// Takes the snapshot of the processes and threads in the registry. The threads are
+    /// grouped by their process, ordered by the process id.
+    pub fn snapshot(&self) -> Snapshot<'_> {
+        let ts = UTC::now();
+        let mut threads: BTreeMap<u64, Vec<&ThreadInfo>> = BTreeMap::new();
+        for ti in self.threads.values() {
+            threads.entry(ti.pid).or_default().push(ti);
+        }
+        for pid in self.processes.keys() {
+            threads.entry(*pid).or_default();
+        }
+        let processes = threads.into_iter()
+            .map(|(pid, mut threads)| {
+                threads.sort_by_key(|ti| ti.tid);
+                ProcessRecord { ts: ts, pid: pid, process: self.processes.get(&pid), threads: threads }
+            })
+            .collect::<Vec<_>>();
+        Snapshot {
+            header: SnapshotHeader {
+                ts: ts,
+                processes: processes.len(),
+                threads: self.threads.len(),
+                resyncs: self.resyncs,
+                last_corrections: &self.last_corrections,
+                corrections: &self.corrections,
+                process_table: self.processes.stats(),
+                thread_table: self.threads.stats(),
+                fd_table: TableStats {
+                    len: self.processes.values().map(|pi| pi.fds.len()).sum(),
+                    expired: 0,
+                    evicted: self.fd_evictions
+                },
+                pending_path_table: self.pending_paths.stats()
+            },
+            processes: processes
+        }
+    }
+
+    /// Returns the directory where the threads of the process are found.
+    fn task_root(&self, pid: u64) -> String {
+        format!("{}/{}/task", self.proc_root, pid)
+    }
+
+    /// Adds the process to the registry and links it to its parent. If the process
+    /// table is full, the evicted process is dropped along with its threads.
+    fn insert_process(&mut self, mut pi: ProcessInfo) {
+        pi.fds = Table::new(&self.fd_table);
+        self.children.entry(pi.ppid).or_default().insert(pi.pid);
+        if let Some((_, evicted)) = self.processes.insert(pi.pid, pi) {
+            for tid in evicted.tids.iter() {
+                self.threads.evict(tid);
+                self.forget_thread(*tid);
+            }
+            self.unlink_process(&evicted);
+        }
+    }
+
+    /// Removes the process from the registry.
+    fn remove_process(&mut self, pid: u64) {
+        if let Some(pi) = self.processes.remove(&pid) {
+            self.unlink_process(&pi);
+        }
+    }
+
+    /// Unlinks the removed process from the process tree. The orphaned children are
+    /// re-parented to the init process of the pid namespace, just like the kernel does.
+    fn unlink_process(&mut self, pi: &ProcessInfo) {
+        let (pid, ppid) = (pi.pid, pi.ppid);
+        if let Some(siblings) = self.children.get_mut(&ppid) {
+            siblings.remove(&pid);
+            if siblings.is_empty() {
+                self.children.remove(&ppid);
+            }
+        }
+        let reaper = self.container_init(ppid).map(|pi| pi.pid).unwrap_or(1);
+        if let Some(orphans) = self.children.remove(&pid) {
+            for orphan in orphans.iter() {
+                if let Some(pi) = self.processes.get_mut(orphan) {
+                    pi.ppid = reaper;
+                }
+            }
+            self.children.entry(reaper).or_default().extend(orphans);
+        }
+    }
+
+    /// Removes the exited thread, along with its process if it was the last thread.
+    fn remove_thread(&mut self, tid: u64) {
+        if let Some(ti) = self.threads.remove(&tid) {
+            if self.release_thread(&ti) {
+                self.remove_process(ti.pid);
+            }
+        }
+    }
+
+    /// Unlinks the removed thread from its process. Returns `true`
+    /// if it was the last thread of the process.
+    fn release_thread(&mut self, ti: &ThreadInfo) -> bool {
+        self.forget_thread(ti.tid);
+        match self.processes.get_mut(&ti.pid) {
+            Some(pi) => {
+                pi.tids.remove(&ti.tid);
+                pi.tids.is_empty()
+            },
+            None => false
+        }
+    }
+
+    /// Drops the syscalls of the removed thread awaiting their exit events.
+    fn forget_thread(&mut self, tid: u64) {
+        self.pending_namespaces.remove(&tid);
+        self.pending_paths.remove(&tid);
+    }
+
+    /// Removes the threads and processes whose grace period after the exit elapsed.
+    /// Processes are marked as exited once their last thread is gone.
+    fn expire(&mut self) {
+        self.pending_paths.expire();
+        for (_, ti) in self.threads.expire() {
+            if self.release_thread(&ti) {
+                self.processes.mark_exited(&ti.pid);
+            }
+        }
+        for (_, pi) in self.processes.expire() {
+            self.unlink_process(&pi);
+        }
+    }
+
+    /// Returns the ancestors of the process, starting with its parent.
+    pub fn ancestors(&self, pid: u64) -> Vec<&ProcessInfo> {
+        let mut ancestors = Vec::new();
+        let mut ppid = match self.processes.get(&pid) {
+            Some(pi) => pi.ppid,
+            None => return ancestors
+        };
+        // ~ the ancestry ends at the idle task (pid 0), while the length
+        // guard protects against cycles caused by pid reuse
+        while let Some(pi) = self.processes.get(&ppid) {
+            if ancestors.len() >= self.processes.len() {
+                break;
+            }
+            ancestors.push(pi);
+            ppid = pi.ppid;
+        }
+        ancestors
+    }
+
+    /// Returns all the processes descending from the process, in breadth-first order.
+    pub fn descendants(&self, pid: u64) -> Vec<&ProcessInfo> {
+        let mut descendants = Vec::new();
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(pid);
+        while let Some(pid) = queue.pop_front() {
+            if let Some(children) = self.children.get(&pid) {
+                for child in children.iter() {
+                    if !visited.insert(*child) {
+                        continue;
+                    }
+                    if let Some(pi) = self.processes.get(child) {
+                        descendants.push(pi);
+                        queue.push_back(*child);
+                    }
+                }
+            }
+        }
+        descendants
+    }
+
+    /// Returns the leader of the session the process belongs to.
+    pub fn session_leader(&self, pid: u64) -> Option<&ProcessInfo> {
+        self.processes.get(&pid)
+            .and_then(|pi| pi.sid)
+            .and_then(|sid| self.processes.get(&sid))
+    }
+
+    /// Returns the nearest init process of a container, i.e. the process itself or its
+    /// closest ancestor that is the first process of a nested pid namespace.
+    pub fn container_init(&self, pid: u64) -> Option<&ProcessInfo> {
+        let is_init = |pi: &ProcessInfo| pi.vpid == 1 && pi.pid != 1;
+        match self.processes.get(&pid) {
+            Some(pi) if is_init(pi) => Some(pi),
+            Some(_) => self.ancestors(pid).into_iter().find(|pi| is_init(pi)),
+            None => None
+        }
+    }
+
+    /// Returns the command names and process ids of the process and its ancestors, up to
+    /// the configured ancestry depth.
+    pub fn ancestry(&self, pid: u64) -> Vec<Ancestor> {
+        let process = match self.processes.get(&pid) {
+            Some(pi) if self.ancestry_depth > 0 => pi,
+            _ => return Vec::new()
+        };
+        let mut ancestry = vec![Ancestor { pid: pid, comm: process.comm.clone() }];
+        for pi in self.ancestors(pid).into_iter().take(self.ancestry_depth - 1) {
+            ancestry.push(Ancestor { pid: pi.pid, comm: pi.comm.clone() });
+        }
+        ancestry
+    }
+
+    fn insert_thread(&mut self, mut ti: ThreadInfo) {
+        self.resolve_accounts(&mut ti);
+        self.link_thread(ti);
+    }
+
+    /// Adds the thread to the registry and links it to its process. If the thread
+    /// table is full, the evicted thread is dropped, along with its process if it
+    /// was the last thread.
+    fn link_thread(&mut self, ti: ThreadInfo) {
+        if let Some(pi) = self.processes.get_mut(&ti.pid) {
+            pi.tids.insert(ti.tid);
+        }
+        if let Some((_, evicted)) = self.threads.insert(ti.tid, ti) {
+            if self.release_thread(&evicted) {
+                self.remove_process(evicted.pid);
+            }
+        }
+    }
+
+    /// Returns the thread with the given id along with the process it belongs to. Threads
+    /// that aren't in the registry yet, i.e. spawned after the initial collection, are looked
+    /// up in the `/proc` file system and added to the registry, as well as their process if
+    /// it's also unknown. Returns `None` if the thread doesn't exist.
+    pub fn get_or_collect(&mut self, tid: u64) -> Option<(&ThreadInfo, &ProcessInfo)> {
+        if !self.threads.contains_key(&tid) {
+            // ~ the /proc/[tid] entries of non-leader threads aren't
+            // listed but they can be accessed directly
+            let (mut ti, mut pi) = match parse_status(tid, &self.proc_root) {
+                Ok(status) => status,
+                Err(_) => return None
+            };
+            if !self.processes.contains_key(&ti.pid) {
+                if ti.pid != tid {
+                    match parse_process_info(ti.pid, self.proc_root.clone()) {
+                        Ok(leader) => pi = leader,
+                        Err(_) => return None
+                    }
+                }
+                let pid = pi.pid;
+                self.load_process(&mut pi);
+                self.insert_process(pi);
+                // ~ pick up the siblings so the process is only removed
+                // once all of its threads exit
+                self.collect_tasks(pid, &mut ScanReport::default());
+            }
+            ti.cgroups = cgroups(tid, self.proc_root.clone()).ok();
+            ti.namespaces = namespaces(tid, self.proc_root.clone()).ok();
+            self.insert_thread(ti);
+        }
+        let pid = match self.threads.get(&tid) {
+            Some(ti) => ti.pid,
+            None => return None
+        };
+        self.threads.touch(&tid);
+        self.processes.touch(&pid);
+        match self.threads.get(&tid) {
+            Some(ti) => self.processes.get(&ti.pid).map(|pi| (ti, pi)),
+            None => None
+        }
+    }
+
+    fn resolve_accounts(&mut self, ti: &mut ThreadInfo) {
+        let mnt = ti.namespaces.as_ref().and_then(|ns| ns.mnt);
+        let (user, group) = self.users.resolve(ti.tid, mnt, ti.uid, ti.gid);
+        ti.user = Some(user);
+        ti.group = Some(group);
+    }
+
+    /// Reads the process attributes which aren't part of the status file.
+    fn load_process(&self, pi: &mut ProcessInfo) {
+        let root = &self.proc_root;
+        pi.exe = process::exe(pi.pid, root);
+        pi.cmdline = process::cmdline(pi.pid, root);
+        pi.cwd = process::cwd(pi.pid, root);
+        pi.pgid = process::pgid(pi.pid, root);
+        pi.sid = process::sid(pi.pid, root);
+        pi.loginuid = process::loginuid(pi.pid, root);
+        pi.sessionid = process::sessionid(pi.pid, root);
+        pi.start_time = self.boot_time.and_then(|bt| process::start_time(pi.pid, root, bt));
+        pi.environ = process::environ(pi.pid, root, &self.environ);
+    }
+
+    /// Updates the state of the registry from the syscall event. Threads moving to other
+    /// namespaces via `setns` or `unshare`, and children cloned into new namespaces get their
+    /// namespaces, as well as their pid namespace relative ids, reloaded. Threads that
+    /// successfully executed a new program get their command and process attributes reloaded.
+    /// Exited threads are removed once the grace period configured for the table elapses.
+    /// The working directories and the open files of processes are tracked to resolve the
+    /// file system paths of the event.
+    pub fn update(&mut self, info: &mut SyscallInfo) {
+        self.expire();
+        if self.pending_namespaces.remove(&info.tid) {
+            self.reload_namespaces(info.tid);
+        }
+        // ~ resolve before the working directory or the file descriptors change
+        info.resolved_path = self.resolve_path(info);
+        let tid = info.tid;
+        let fd = match info.params.get("fd") {
+            Some(&Value::Int64(fd)) => Some(fd),
+            _ => None
+        };
+        let succeeded = match info.params.get("res") {
+            Some(&Value::Int64(res)) => res >= 0,
+            _ => false
+        };
+        match info.name.as_str() {
+            "open" | "creat" => {
+                if let (Some(fd), Some(path)) = (fd, info.resolved_path.clone()) {
+                    if fd >= 0 {
+                        self.set_fd(tid, fd, path);
+                    }
+                }
+            },
+            "openat" => {
+                // ~ the exit event only carries the file descriptor
+                if let (Some(fd), false) = (fd, info.params.contains_key("dirfd")) {
+                    if let Some(path) = self.pending_paths.remove(&tid) {
+                        if fd >= 0 {
+                            self.set_fd(tid, fd, path);
+                        }
+                    }
+                }
+            },
+            "close" => {
+                if let (Some(fd), Some(pi)) = (fd, self.process_mut(tid)) {
+                    pi.fds.remove(&fd);
+                }
+            },
+            "chdir" => {
+                if let (true, Some(path)) = (succeeded, info.resolved_path.clone()) {
+                    self.set_cwd(tid, path);
+                }
+            },
+            // ~ the exit event only carries the result
+            "fchdir" if fd.is_none() => {
+                if let (Some(path), true) = (self.pending_paths.remove(&tid), succeeded) {
+                    self.set_cwd(tid, path);
+                }
+            },
+            "syscall" => {
+                match info.params.get("native_id") {
+                    Some(&Value::UInt16(id)) if id == namespaces::SYS_SETNS ||
+                                                id == namespaces::SYS_UNSHARE => {
+                        self.pending_namespaces.insert(info.tid);
+                    },
+                    _ => {}
+                }
+            },
+            "procexit" => {
+                self.pending_namespaces.remove(&info.tid);
+                self.threads.mark_exited(&info.tid);
+            },
+            "execve" => {
+                if let Some(&Value::Int64(0)) = info.params.get("res") {
+                    self.reload_process(info.tid);
+                }
+            },
+            "clone" => {
+                let flags = match info.params.get("flags") {
+                    Some(&Value::UInt32(flags)) => flags,
+                    _ => 0
+                };
+                if flags & PPM_CL_CLONE_NEWANY == 0 {
+                    return;
+                }
+                // ~ the parent gets the child's tid, while the child gets zero
+                match info.params.get("res") {
+                    Some(&Value::Int64(res)) if res > 0 => self.reload_namespaces(res as u64),
+                    Some(&Value::Int64(0)) => self.reload_namespaces(info.tid),
+                    _ => {}
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn process_mut(&mut self, tid: u64) -> Option<&mut ProcessInfo> {
+        match self.threads.get(&tid).map(|ti| ti.pid) {
+            Some(pid) => self.processes.get_mut(&pid),
+            None => None
+        }
+    }
+
+    fn set_fd(&mut self, tid: u64, fd: i64, path: String) {
+        let evicted = match self.process_mut(tid) {
+            Some(pi) => pi.fds.insert(fd, path).is_some(),
+            None => false
+        };
+        if evicted {
+            self.fd_evictions += 1;
+        }
+    }
+
+    /// Keeps the path resolved on the syscall entry until the exit event arrives.
+    fn set_pending_path(&mut self, tid: u64, path: String) {
+        self.pending_paths.insert(tid, path);
+        // ~ the grace period starts right away, so the path expires if the exit event is dropped
+        self.pending_paths.mark_exited(&tid);
+    }
+
+    fn set_cwd(&mut self, tid: u64, cwd: String) {
+        if let Some(pi) = self.process_mut(tid) {
+            pi.cwd = Some(cwd);
+        }
+    }
+
+    /// Resolves the file system path the syscall operates on to the absolute path. Returns
+    /// `None` if the event doesn't carry a path or the path can't be resolved.
+    fn resolve_path(&mut self, info: &SyscallInfo) -> Option<String> {
+        let tid = info.tid;
+        let param = |name: &str| match info.params.get(name) {
+            Some(Value::String(path)) => Some(path.clone()),
+            _ => None
+        };
+        let fd = |name: &str| match info.params.get(name) {
+            Some(&Value::Int64(fd)) => Some(fd),
+            _ => None
+        };
+        match info.name.as_str() {
+            "open" | "creat" => {
+                param("name").and_then(|path| self.resolve_at(tid, AT_FDCWD, &path))
+            },
+            "stat" | "lstat" | "stat64" | "lstat64" | "chdir" => {
+                param("path").and_then(|path| self.resolve_at(tid, AT_FDCWD, &path))
+            },
+            "openat" => {
+                match (fd("dirfd"), param("name")) {
+                    (Some(dirfd), Some(path)) => {
+                        let resolved = self.resolve_at(tid, dirfd, &path);
+                        if let Some(ref resolved) = resolved {
+                            self.set_pending_path(tid, resolved.clone());
+                        }
+                        resolved
+                    },
+                    _ => self.pending_paths.get(&tid).cloned()
+                }
+            },
+            "fchdir" => {
+                match fd("fd") {
+                    Some(fd) => {
+                        let resolved = self.fd_path(tid, fd);
+                        if let Some(ref resolved) = resolved {
+                            self.set_pending_path(tid, resolved.clone());
+                        }
+                        resolved
+                    },
+                    None => self.pending_paths.get(&tid).cloned()
+                }
+            },
+            _ => None
+        }
+    }
+
+    /// Resolves the path relative to the directory referred by `dirfd`, or the
+    /// working directory of the process if `dirfd` is `AT_FDCWD`.
+    fn resolve_at(&mut self, tid: u64, dirfd: i64, path: &str) -> Option<String> {
+        if path.starts_with('/') {
+            return Some(paths::normalize(path));
+        }
+        let base = if dirfd == AT_FDCWD {
+            self.cwd(tid)
+        } else {
+            self.fd_path(tid, dirfd)
+        };
+        base.map(|base| paths::resolve(&base, path))
+    }
+
+    /// Returns the working directory of the thread's process. Falls back to
+    /// `/proc` if the process is unknown or its working directory isn't tracked.
+    fn cwd(&mut self, tid: u64) -> Option<String> {
+        match self.process_mut(tid).and_then(|pi| pi.cwd.clone()) {
+            Some(cwd) => Some(cwd),
+            None => process::cwd(tid, &self.proc_root)
+        }
+    }
+
+    /// Returns the path of the file open by the thread's process. Descriptors opened before
+    /// the process was tracked, or by syscalls that aren't tracked, such as `dup`, are
+    /// looked up in `/proc`.
+    fn fd_path(&mut self, tid: u64, fd: i64) -> Option<String> {
+        if let Some(pi) = self.process_mut(tid) {
+            if let Some(path) = pi.fds.get(&fd).cloned() {
+                pi.fds.touch(&fd);
+                return Some(path);
+            }
+        }
+        let path = process::fd_path(tid, &self.proc_root, fd);
+        match path {
+            Some(ref path) if path.starts_with('/') => {
+                self.set_fd(tid, fd, path.clone());
+            },
+            _ => {}
+        }
+        path
+    }
+
+    fn reload_process(&mut self, tid: u64) {
+        let pid = match self.threads.get_mut(&tid) {
+            Some(ti) => {
+                if let Ok(fresh) = parse_thread_info(tid, self.proc_root.clone()) {
+                    ti.comm = fresh.comm;
+                }
+                ti.pid
+            },
+            None => return
+        };
+        let comm = self.threads.get(&tid).map(|ti| ti.comm.clone());
+        if let Some(mut pi) = self.processes.remove(&pid) {
+            // ~ the thread executing the program becomes the main thread
+            if let Some(comm) = comm {
+                pi.comm = comm;
+            }
+            self.load_process(&mut pi);
+            // ~ the descriptors closed on exec are unknown, so they're looked up again
+            pi.fds.clear();
+            self.processes.insert(pid, pi);
+        }
+    }
 
+    fn reload_namespaces(&mut self, tid: u64) {
+        let proc_root = self.proc_root.clone();
+        if let Some(mut ti) = self.threads.remove(&tid) {
+            ti.namespaces = namespaces(tid, proc_root.clone()).ok();
+            if let Ok((fresh, fresh_process)) = parse_status(tid, &proc_root) {
+                ti.vtid = fresh.vtid;
+                if let Some(pi) = self.processes.get_mut(&ti.pid) {
+                    pi.vpid = fresh_process.vpid;
                 }
             }
+            // ~ the thread may now see the databases of another root file system
+            self.resolve_accounts(&mut ti);
+            self.threads.insert(tid, ti);
         }
     }
 }
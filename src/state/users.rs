@@ -0,0 +1,168 @@
+//! Resolves user and group names from the `passwd` and `group` databases. Processes running
+//! in containers see the databases of their own root file system, so the databases are read
+//! through the `/proc/[pid]/root` link of a process in the container and cached per mount
+//! namespace. Processes in the host mount namespace use the databases of the host.
+//!
+//! A mount namespace lives as long as the processes in it, so the cache holds at most as many
+//! namespaces as the process table holds processes, and the least recently used ones are
+//! evicted first, e.g. the namespaces of containers that are gone.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::sync::Arc;
+use std::time::SystemTime;
+use config::TableConfig;
+use super::namespaces::namespaces;
+use super::table::Table;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Account {
+    /// numeric user or group id
+    pub id: u32,
+    /// user or group name, if present in the database
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>
+}
+
+/// Users and groups read from the databases of a single root file system.
+struct Accounts {
+    users: HashMap<u32, String>,
+    groups: HashMap<u32, String>,
+    /// directory the databases were read from
+    etc_dir: String,
+    /// modification times of the `passwd` and `group` files when they were read
+    mtimes: (Option<SystemTime>, Option<SystemTime>)
+}
+
+pub struct UserResolver {
+    proc_root: String,
+    etc_root: String,
+    /// mount namespace inode of the host
+    host_mnt: Option<u64>,
+    /// accounts indexed by mount namespace inode
+    accounts: Table<u64, Arc<Accounts>>,
+    /// accounts of the host
+    host: Option<Arc<Accounts>>
+}
+
+/// Parses the `name:password:id:...` entries shared by the `passwd` and `group` databases.
+fn parse_db(content: &str) -> HashMap<u32, String> {
+    let mut entries = HashMap::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split(':');
+        // ~ the first entry wins if the id is shared by several names
+        if let (Some(name), Some(id)) = (fields.next(), fields.nth(1).and_then(|id| id.parse::<u32>().ok())) {
+            entries.entry(id).or_insert(name.to_string());
+        }
+    }
+    entries
+}
+
+fn mtime(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn read_file(path: &str) -> io::Result<String> {
+    let mut buf = String::new();
+    File::open(path)?.read_to_string(&mut buf)?;
+    Ok(buf)
+}
+
+impl Accounts {
+
+    fn read(etc_dir: String) -> io::Result<Accounts> {
+        let passwd = format!("{}/passwd", etc_dir);
+        let group = format!("{}/group", etc_dir);
+        // ~ take the modification times first so concurrent changes trigger a reload
+        let mtimes = (mtime(&passwd), mtime(&group));
+        // ~ a missing group database is tolerated, e.g. in minimal images
+        let groups = read_file(&group).map(|c| parse_db(&c)).unwrap_or_default();
+        Ok(Accounts {
+            users: parse_db(&read_file(&passwd)?),
+            groups: groups,
+            etc_dir: etc_dir,
+            mtimes: mtimes
+        })
+    }
+
+    fn is_stale(&self) -> bool {
+        self.mtimes != (mtime(&format!("{}/passwd", self.etc_dir)),
+                        mtime(&format!("{}/group", self.etc_dir)))
+    }
+}
+
+impl UserResolver {
+
+    /// Creates the resolver caching the databases of as many mount namespaces as
+    /// the process table holds processes.
+    pub fn new(proc_root: &str, etc_root: &str, processes: &TableConfig) -> UserResolver {
+        UserResolver {
+            proc_root: proc_root.to_string(),
+            etc_root: etc_root.to_string(),
+            host_mnt: namespaces(1, proc_root.to_string()).ok().and_then(|ns| ns.mnt),
+            accounts: Table::new(&TableConfig { capacity: processes.capacity, exited_ttl: 0 }),
+            host: None
+        }
+    }
+
+    /// Resolves the user and group of the process. The `mnt` is the mount namespace
+    /// inode of the process, or `None` if it's unknown, in which case the host
+    /// databases are used.
+    pub fn resolve(&mut self, pid: u64, mnt: Option<u64>, uid: u32, gid: u32) -> (Account, Account) {
+        let accounts = self.accounts(pid, mnt);
+        let name = |db: Option<&HashMap<u32, String>>, id: u32| db.and_then(|db| db.get(&id).cloned());
+        (Account {
+            id: uid,
+            name: name(accounts.as_ref().map(|a| &a.users), uid)
+        },
+        Account {
+            id: gid,
+            name: name(accounts.as_ref().map(|a| &a.groups), gid)
+        })
+    }
+
+    fn accounts(&mut self, pid: u64, mnt: Option<u64>) -> Option<Arc<Accounts>> {
+        match mnt {
+            Some(mnt) if Some(mnt) != self.host_mnt => {
+                if let Some(accounts) = self.accounts.get(&mnt).cloned() {
+                    if !accounts.is_stale() {
+                        self.accounts.touch(&mnt);
+                        return Some(accounts);
+                    }
+                }
+                // ~ the root link is only readable while the process is alive,
+                // so failures aren't cached and the next process retries
+                let etc_dir = format!("{}/{}/root/etc", self.proc_root, pid);
+                match Accounts::read(etc_dir) {
+                    Ok(accounts) => {
+                        // ~ once the process exits its root link vanishes and the
+                        // databases are considered stale, so they get read again
+                        // through the root link of the next process
+                        let accounts = Arc::new(accounts);
+                        self.accounts.insert(mnt, accounts.clone());
+                        Some(accounts)
+                    },
+                    Err(_) => {
+                        self.accounts.remove(&mnt);
+                        None
+                    }
+                }
+            },
+            _ => {
+                let fresh = match self.host {
+                    Some(ref host) => !host.is_stale(),
+                    None => false
+                };
+                if !fresh {
+                    self.host = Accounts::read(self.etc_root.clone()).ok().map(Arc::new);
+                }
+                self.host.clone()
+            }
+        }
+    }
+}
@@ -2,11 +2,15 @@ pub mod syscall_table;
 
 use libc;
 use std::ffi::CStr;
-use std::{ptr, slice};
-use std::mem::size_of;
+use std::slice;
 use std::collections::HashMap;
+use std::sync::Arc;
 use chrono::{DateTime, UTC};
-use value::Value;
+use value::{Sock, Value};
+use enricher::kubernetes::PodInfo;
+use enricher::docker::ContainerInfo;
+use state::users::Account;
+use state::thread::Ancestor;
 
 #[repr(C, packed)]
 pub struct Syscall {
@@ -46,14 +50,53 @@ pub struct SyscallParam {
 pub struct SyscallInfo {
     /// timestamp expressed as UTC date/time structure
     pub ts: DateTime<UTC>,
+    /// the thread id that generated the syscall
+    pub tid: u64,
+    /// the cpu the syscall was captured on
+    pub cpu: usize,
+    /// whether the event was emitted on entering or exiting the syscall
+    pub dir: Direction,
+    /// command name of the thread
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comm: Option<String>,
+    /// process id of the thread
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u64>,
+    /// thread id as seen from the pid namespace of the process
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vtid: Option<u64>,
+    /// process id as seen from the pid namespace of the process
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vpid: Option<u64>,
+    /// real user of the thread
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<Account>,
+    /// real group of the thread
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<Account>,
+    /// the process and its ancestors, starting with the process
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ancestry: Vec<Ancestor>,
     /// name of the system call
     pub name: String,
+    /// category of the system call
+    #[serde(skip_serializing)]
+    pub category: Category,
     /// syscall's parameter map
-    pub params: HashMap<String, Value>
-
+    pub params: HashMap<String, Value>,
+    /// absolute path of the file the syscall operates on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved_path: Option<String>,
+    /// metadata of the kubernetes pod the thread is running in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pod: Option<Arc<PodInfo>>,
+    /// metadata of the container the thread is running in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<Arc<ContainerInfo>>
 }
 
 /// determines the syscall category
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Category {
     Unknown,
     Other,
@@ -78,6 +121,42 @@ pub enum Category {
 
 }
 
+/// all syscall categories
+pub const CATEGORIES: &'static [Category] = &[
+    Category::Unknown, Category::Other, Category::File, Category::Net, Category::IPC,
+    Category::Memory, Category::Process, Category::Sleep, Category::System, Category::Signal,
+    Category::User, Category::Time, Category::Processing, Category::IOBase, Category::IORead,
+    Category::IOWrite, Category::IOOther, Category::Wait, Category::Scheduler, Category::Internal
+];
+
+impl Category {
+    /// Returns the name of the category used in the configuration, e.g. `io_read`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Category::Unknown => "unknown",
+            Category::Other => "other",
+            Category::File => "file",
+            Category::Net => "net",
+            Category::IPC => "ipc",
+            Category::Memory => "memory",
+            Category::Process => "process",
+            Category::Sleep => "sleep",
+            Category::System => "system",
+            Category::Signal => "signal",
+            Category::User => "user",
+            Category::Time => "time",
+            Category::Processing => "processing",
+            Category::IOBase => "io_base",
+            Category::IORead => "io_read",
+            Category::IOWrite => "io_write",
+            Category::IOOther => "io_other",
+            Category::Wait => "wait",
+            Category::Scheduler => "scheduler",
+            Category::Internal => "internal"
+        }
+    }
+}
+
 pub enum Flags {
     None,
     CreatesFd,
@@ -146,6 +225,8 @@ pub enum ParamFormat {
     Dir
 }
 
+/// direction of the event, i.e. whether it's emitted on entering or exiting the syscall
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
 pub enum Direction {
     Enter,
     Exit
@@ -160,16 +241,19 @@ impl SyscallMeta {
     /// the `SyscallParam::parse` method. The return value of the previous method is put into
     /// the hash map and indexed by parameter name.
     ///
-    pub fn build_params(&self, syscall: *mut Syscall) -> HashMap<String, Value> {
+    /// # Safety
+    ///
+    /// The `syscall` has to point to the event in the ring buffer described by this metadata.
+    pub unsafe fn build_params(&self, syscall: *mut Syscall) -> HashMap<String, Value> {
         let mut params = HashMap::<String, Value>::default();
         unsafe {
             let lens = syscall.offset(1) as *const u16;
-            let mut buf = lens.offset(self.nparams as isize) as *const u8;
+            let mut buf = lens.add(self.nparams) as *const u8;
             for (i, len) in slice::from_raw_parts(lens, self.nparams)
                         .iter()
                         .enumerate() {
-                let ref param = self.params[i];
-                params.insert(param.name.to_string(), param.parse(buf));
+                let param = &self.params[i];
+                params.insert(param.name.to_string(), param.parse(buf, *len));
                 buf = buf.offset(*len as isize);
             }
         }
@@ -180,7 +264,12 @@ impl SyscallMeta {
 impl SyscallParam {
     /// Transforms the raw buffer which contains the parameter value to a native
     /// data type suitable for serialization.
-    pub fn parse(&self, buf: *const u8) -> Value {
+    ///
+    /// # Safety
+    ///
+    /// The `buf` has to point to the value of the parameter within the syscall event,
+    /// which is `len` bytes long.
+    pub unsafe fn parse(&self, buf: *const u8, len: u16) -> Value {
         match self.kind {
             ParamType::Int8 => {
                 unsafe { Value::Int8(*(buf as *const i8)) }
@@ -195,7 +284,7 @@ impl SyscallParam {
                 unsafe { Value::Int64(*(buf as *const i64)) }
             },
             ParamType::UInt8 => {
-                unsafe { Value::UInt8(*(buf as *const u8)) }
+                unsafe { Value::UInt8(*buf) }
             },
             ParamType::UInt16 => {
                 unsafe { Value::UInt16(*(buf as *const u16)) }
@@ -218,6 +307,15 @@ impl SyscallParam {
             ParamType::Pid => {
                 unsafe { Value::Int64(*(buf as *const i64)) }
             },
+            ParamType::Flags8 => {
+                unsafe { Value::UInt8(*buf) }
+            },
+            ParamType::Flags16 => {
+                unsafe { Value::UInt16(*(buf as *const u16)) }
+            },
+            ParamType::Flags32 => {
+                unsafe { Value::UInt32(*(buf as *const u32)) }
+            },
             ParamType::Uid | ParamType::Gid  => {
                 unsafe { Value::UInt32(*(buf as *const u32)) }
             },
@@ -229,7 +327,15 @@ impl SyscallParam {
             },
             ParamType::ByteBuffer => {
                 Value::String(self.to_string(buf))
-            }
+            },
+            ParamType::SockAddr => {
+                let buf = unsafe { slice::from_raw_parts(buf, len as usize) };
+                Sock::from_addr(buf).map_or(Value::None, Value::Sock)
+            },
+            ParamType::SockTuple => {
+                let buf = unsafe { slice::from_raw_parts(buf, len as usize) };
+                Sock::from_tuple(buf).map_or(Value::None, Value::Sock)
+            },
             _ => Value::None
         }
     }
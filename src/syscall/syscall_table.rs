@@ -1,3 +1,4 @@
+use std::slice;
 use super::{SyscallMeta, SyscallParam, Category, Flags, ParamType, ParamFormat};
 
 pub enum Syscalls {
@@ -156,6 +157,53 @@ impl Default for SyscallTable {
                 SyscallMeta{ name: "recvmmsg", category: Category::IORead, flags: vec![], nparams: 0, params: vec![] },
                 SyscallMeta{ name: "recvmmsg", category: Category::IORead, flags: vec![], nparams: 0, params: vec![] },
                 SyscallMeta{ name: "accept", category: Category::Net, flags: vec![Flags::ModifiesState, Flags::CreatesFd], nparams: 1, params: vec![SyscallParam { name: "flags", kind: ParamType::Int32, fmt: ParamFormat::Hex }]},
+                SyscallMeta{ name: "accept", category: Category::Net, flags: vec![Flags::ModifiesState, Flags::CreatesFd], nparams: 3, params: vec![SyscallParam { name: "fd", kind: ParamType::Fd, fmt: ParamFormat::Dec }, SyscallParam { name: "tuple", kind: ParamType::SockTuple, fmt: ParamFormat::Na }, SyscallParam { name: "queuepct", kind: ParamType::UInt8, fmt: ParamFormat::Dec }]},
+                SyscallMeta{ name: "creat", category: Category::File, flags: vec![Flags::CreatesFd, Flags::ModifiesState], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "creat", category: Category::File, flags: vec![Flags::CreatesFd, Flags::ModifiesState], nparams: 3, params: vec![SyscallParam { name: "fd", kind: ParamType::Fd, fmt: ParamFormat::Dec }, SyscallParam { name: "name", kind: ParamType::FsPath, fmt: ParamFormat::Na }, SyscallParam { name: "mode", kind: ParamType::UInt32, fmt: ParamFormat::Hex }]},
+                SyscallMeta{ name: "pipe", category: Category::IPC, flags: vec![Flags::CreatesFd, Flags::ModifiesState], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "pipe", category: Category::IPC, flags: vec![Flags::CreatesFd, Flags::ModifiesState], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "eventfd", category: Category::IPC, flags: vec![Flags::CreatesFd, Flags::ModifiesState], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "eventfd", category: Category::IPC, flags: vec![Flags::CreatesFd, Flags::ModifiesState], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "futex", category: Category::IPC, flags: vec![Flags::None], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "futex", category: Category::IPC, flags: vec![Flags::None], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "stat", category: Category::File, flags: vec![Flags::None], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "stat", category: Category::File, flags: vec![Flags::None], nparams: 2, params: vec![SyscallParam { name: "res", kind: ParamType::ErrNo, fmt: ParamFormat::Dec }, SyscallParam { name: "path", kind: ParamType::FsPath, fmt: ParamFormat::Na }]},
+                SyscallMeta{ name: "lstat", category: Category::File, flags: vec![Flags::None], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "lstat", category: Category::File, flags: vec![Flags::None], nparams: 2, params: vec![SyscallParam { name: "res", kind: ParamType::ErrNo, fmt: ParamFormat::Dec }, SyscallParam { name: "path", kind: ParamType::FsPath, fmt: ParamFormat::Na }]},
+                SyscallMeta{ name: "fstat", category: Category::File, flags: vec![Flags::UsesFd], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "fstat", category: Category::File, flags: vec![Flags::UsesFd], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "stat64", category: Category::File, flags: vec![Flags::None], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "stat64", category: Category::File, flags: vec![Flags::None], nparams: 2, params: vec![SyscallParam { name: "res", kind: ParamType::ErrNo, fmt: ParamFormat::Dec }, SyscallParam { name: "path", kind: ParamType::FsPath, fmt: ParamFormat::Na }]},
+                SyscallMeta{ name: "lstat64", category: Category::File, flags: vec![Flags::None], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "lstat64", category: Category::File, flags: vec![Flags::None], nparams: 2, params: vec![SyscallParam { name: "res", kind: ParamType::ErrNo, fmt: ParamFormat::Dec }, SyscallParam { name: "path", kind: ParamType::FsPath, fmt: ParamFormat::Na }]},
+                SyscallMeta{ name: "fstat64", category: Category::File, flags: vec![Flags::UsesFd], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "fstat64", category: Category::File, flags: vec![Flags::UsesFd], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "epoll_wait", category: Category::Wait, flags: vec![Flags::Waits], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "epoll_wait", category: Category::Wait, flags: vec![Flags::Waits], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "poll", category: Category::Wait, flags: vec![Flags::Waits], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "poll", category: Category::Wait, flags: vec![Flags::Waits], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "select", category: Category::Wait, flags: vec![Flags::Waits], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "select", category: Category::Wait, flags: vec![Flags::Waits], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "select", category: Category::Wait, flags: vec![Flags::Waits], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "select", category: Category::Wait, flags: vec![Flags::Waits], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "lseek", category: Category::File, flags: vec![Flags::UsesFd], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "lseek", category: Category::File, flags: vec![Flags::UsesFd], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "llseek", category: Category::File, flags: vec![Flags::UsesFd], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "llseek", category: Category::File, flags: vec![Flags::UsesFd], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "ioctl", category: Category::IOOther, flags: vec![Flags::UsesFd], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "ioctl", category: Category::IOOther, flags: vec![Flags::UsesFd], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "getcwd", category: Category::File, flags: vec![Flags::None], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "getcwd", category: Category::File, flags: vec![Flags::None], nparams: 2, params: vec![SyscallParam { name: "res", kind: ParamType::ErrNo, fmt: ParamFormat::Dec }, SyscallParam { name: "path", kind: ParamType::CharBuffer, fmt: ParamFormat::Na }]},
+                SyscallMeta{ name: "chdir", category: Category::File, flags: vec![Flags::ModifiesState], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "chdir", category: Category::File, flags: vec![Flags::ModifiesState], nparams: 2, params: vec![SyscallParam { name: "res", kind: ParamType::ErrNo, fmt: ParamFormat::Dec }, SyscallParam { name: "path", kind: ParamType::CharBuffer, fmt: ParamFormat::Na }]},
+                SyscallMeta{ name: "fchdir", category: Category::File, flags: vec![Flags::UsesFd, Flags::ModifiesState], nparams: 1, params: vec![SyscallParam { name: "fd", kind: ParamType::Fd, fmt: ParamFormat::Dec }]},
+                SyscallMeta{ name: "fchdir", category: Category::File, flags: vec![Flags::UsesFd, Flags::ModifiesState], nparams: 1, params: vec![SyscallParam { name: "res", kind: ParamType::ErrNo, fmt: ParamFormat::Dec }]},
+                SyscallMeta{ name: "mkdir", category: Category::File, flags: vec![Flags::None], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "mkdir", category: Category::File, flags: vec![Flags::None], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "rmdir", category: Category::File, flags: vec![Flags::None], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "rmdir", category: Category::File, flags: vec![Flags::None], nparams: 0, params: vec![]},
+                SyscallMeta{ name: "openat", category: Category::File, flags: vec![Flags::CreatesFd, Flags::ModifiesState], nparams: 4, params: vec![SyscallParam { name: "dirfd", kind: ParamType::Fd, fmt: ParamFormat::Dec }, SyscallParam { name: "name", kind: ParamType::CharBuffer, fmt: ParamFormat::Na }, SyscallParam { name: "flags", kind: ParamType::Flags32, fmt: ParamFormat::Hex }, SyscallParam { name: "mode", kind: ParamType::UInt32, fmt: ParamFormat::Hex }]},
+                SyscallMeta{ name: "openat", category: Category::File, flags: vec![Flags::CreatesFd, Flags::ModifiesState], nparams: 1, params: vec![SyscallParam { name: "fd", kind: ParamType::Fd, fmt: ParamFormat::Dec }]},
 
 
 
@@ -171,4 +219,9 @@ impl SyscallTable {
     pub fn get_syscall_meta(&self, id: usize) -> Option<&SyscallMeta> {
         self.syscall_metas.get(id)
     }
+
+    /// Iterates over the syscall metadata in the order of the syscall ids.
+    pub fn iter(&self) -> slice::Iter<'_, SyscallMeta> {
+        self.syscall_metas.iter()
+    }
 }
@@ -1,3 +1,6 @@
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
 /// Container for primitive stack allocated `(i32, u32, bool, etc)` as well as heap
 /// allocated `(String)` data types. This enum is used by `SyscallParam::parse` method
 /// to store the content of the system call parameter's payload.
@@ -16,5 +19,155 @@ pub enum Value {
     UInt16(u16),
     UInt32(u32),
     UInt64(u64),
+    Sock(Sock),
     None
 }
+
+/// Socket address, or the pair of the source and destination addresses of the socket.
+/// The IP addresses are rendered in their textual form, so they can be indexed as IPs.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Sock {
+    /// address family, either `inet`, `inet6` or `unix`
+    pub family: &'static str,
+    /// IP address and port of the single address
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// source IP address and port of the pair
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sport: Option<u16>,
+    /// destination IP address and port of the pair
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dport: Option<u16>,
+    /// path of the unix domain socket
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>
+}
+
+// ~ address families of the sysdig driver, see `ppm_events_public.h`
+const PPM_AF_UNIX: u8 = 1;
+const PPM_AF_INET: u8 = 2;
+const PPM_AF_INET6: u8 = 10;
+
+impl Sock {
+
+    fn new(family: &'static str) -> Sock {
+        Sock { family: family, ip: None, port: None, sip: None, sport: None, dip: None, dport: None, path: None }
+    }
+
+    /// Decodes the `PT_SOCKADDR` parameter, i.e. the family followed by the IP address and
+    /// the port, or by the path of the unix socket.
+    pub fn from_addr(buf: &[u8]) -> Option<Sock> {
+        match *buf.first()? {
+            PPM_AF_INET if buf.len() >= 7 => {
+                let mut sock = Sock::new("inet");
+                sock.ip = Some(ipv4(&buf[1..5]));
+                sock.port = Some(port(&buf[5..7]));
+                Some(sock)
+            },
+            PPM_AF_INET6 if buf.len() >= 19 => {
+                let mut sock = Sock::new("inet6");
+                sock.ip = Some(ipv6(&buf[1..17]));
+                sock.port = Some(port(&buf[17..19]));
+                Some(sock)
+            },
+            PPM_AF_UNIX => {
+                let mut sock = Sock::new("unix");
+                sock.path = Some(path(&buf[1..]));
+                Some(sock)
+            },
+            _ => None
+        }
+    }
+
+    /// Decodes the `PT_SOCKTUPLE` parameter, i.e. the family followed by the source and
+    /// the destination IP addresses and ports, or by the kernel addresses of the unix
+    /// sockets and the path.
+    pub fn from_tuple(buf: &[u8]) -> Option<Sock> {
+        match *buf.first()? {
+            PPM_AF_INET if buf.len() >= 13 => {
+                let mut sock = Sock::new("inet");
+                sock.sip = Some(ipv4(&buf[1..5]));
+                sock.sport = Some(port(&buf[5..7]));
+                sock.dip = Some(ipv4(&buf[7..11]));
+                sock.dport = Some(port(&buf[11..13]));
+                Some(sock)
+            },
+            PPM_AF_INET6 if buf.len() >= 37 => {
+                let mut sock = Sock::new("inet6");
+                sock.sip = Some(ipv6(&buf[1..17]));
+                sock.sport = Some(port(&buf[17..19]));
+                sock.dip = Some(ipv6(&buf[19..35]));
+                sock.dport = Some(port(&buf[35..37]));
+                Some(sock)
+            },
+            PPM_AF_UNIX if buf.len() >= 17 => {
+                let mut sock = Sock::new("unix");
+                sock.path = Some(path(&buf[17..]));
+                Some(sock)
+            },
+            _ => None
+        }
+    }
+}
+
+fn ipv4(buf: &[u8]) -> String {
+    Ipv4Addr::new(buf[0], buf[1], buf[2], buf[3]).to_string()
+}
+
+fn ipv6(buf: &[u8]) -> String {
+    let mut octets = [0u8; 16];
+    octets.copy_from_slice(buf);
+    Ipv6Addr::from(octets).to_string()
+}
+
+/// Decodes the port, stored in the host byte order by the driver.
+fn port(buf: &[u8]) -> u16 {
+    u16::from_ne_bytes([buf[0], buf[1]])
+}
+
+fn path(buf: &[u8]) -> String {
+    let end = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..end]).into_owned()
+}
+
+impl fmt::Display for Sock {
+    /// Renders the address as `ip:port`, the pair as `sip:sport->dip:dport`, or the path.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let endpoint = |ip: &Option<String>, port: Option<u16>| match (ip, port) {
+            (Some(ip), Some(port)) if self.family == "inet6" => format!("[{}]:{}", ip, port),
+            (Some(ip), Some(port)) => format!("{}:{}", ip, port),
+            _ => String::new()
+        };
+        if let Some(ref path) = self.path {
+            write!(f, "{}", path)
+        } else if self.ip.is_some() {
+            write!(f, "{}", endpoint(&self.ip, self.port))
+        } else {
+            write!(f, "{}->{}", endpoint(&self.sip, self.sport), endpoint(&self.dip, self.dport))
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::String(ref s) => write!(f, "{}", s),
+            Value::Int8(v) => write!(f, "{}", v),
+            Value::Int16(v) => write!(f, "{}", v),
+            Value::Int32(v) => write!(f, "{}", v),
+            Value::Int64(v) => write!(f, "{}", v),
+            Value::UInt8(v) => write!(f, "{}", v),
+            Value::UInt16(v) => write!(f, "{}", v),
+            Value::UInt32(v) => write!(f, "{}", v),
+            Value::UInt64(v) => write!(f, "{}", v),
+            Value::Sock(ref sock) => write!(f, "{}", sock),
+            Value::None => write!(f, "<NA>")
+        }
+    }
+}
@@ -0,0 +1,68 @@
+extern crate cubostratusc;
+
+use cubostratusc::config;
+
+fn elasticsearch(settings: &str) -> Result<(), String> {
+    let content = format!("[elasticsearch]\nurl = \"http://localhost:9200\"\n{}", settings);
+    config::parse_config(&content).map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn kafka(settings: &str) -> Result<(), String> {
+    let content = format!("[kafka]\nhosts = [\"localhost:9092\"]\ntopic = \"events\"\nack_timeout = 1\n{}", settings);
+    config::parse_config(&content).map(|_| ()).map_err(|e| e.to_string())
+}
+
+#[test]
+fn rejects_empty_kafka_queue() {
+    assert_eq!(kafka("queue_size = 1"), Ok(()));
+    assert!(kafka("queue_size = 0").is_err());
+}
+
+#[test]
+fn rejects_zero_kafka_timeout() {
+    assert_eq!(kafka("timeout = 5"), Ok(()));
+    assert!(kafka("timeout = 0").is_err());
+}
+
+#[test]
+fn validates_kafka_sasl_settings() {
+    let sasl = |mechanism: &str, username: &str| {
+        kafka(&format!("[kafka.sasl]\nmechanism = \"{}\"\nusername = \"{}\"\npassword = \"secret\"\n",
+                       mechanism, username))
+    };
+    assert_eq!(sasl("PLAIN", "user"), Ok(()));
+    assert_eq!(sasl("SCRAM-SHA-256", "user"), Ok(()));
+    assert_eq!(sasl("SCRAM-SHA-512", "user"), Ok(()));
+    assert!(sasl("GSSAPI", "user").unwrap_err().contains("sasl mechanism"));
+    assert!(sasl("PLAIN", "").unwrap_err().contains("username"));
+    assert!(kafka("[kafka.sasl]\nmechanism = \"PLAIN\"\nusername = \"user\"\n").is_err());
+}
+
+#[test]
+fn accepts_default_elasticsearch_index() {
+    assert_eq!(elasticsearch(""), Ok(()));
+    assert_eq!(elasticsearch("index = \"events-%Y.%m.%d-%H\""), Ok(()));
+}
+
+#[test]
+fn rejects_invalid_elasticsearch_index() {
+    // ~ unknown specifier, uppercase, forbidden character and the empty name
+    for index in &["events-%Q", "events-%", "Events-%Y", "events,%Y", "_events", "%Y/%m", ""] {
+        assert!(elasticsearch(&format!("index = {:?}", index)).is_err(), "accepted `{}`", index);
+    }
+    // ~ the month name renders uppercase letters
+    assert!(elasticsearch("index = \"events-%B\"").is_err());
+}
+
+#[test]
+fn rejects_elasticsearch_index_without_prefix() {
+    // ~ the template would match every index
+    assert!(elasticsearch("index = \"%Y-events\"").is_err());
+    assert!(elasticsearch("index = \"%%events-%Y\"").is_err());
+}
+
+#[test]
+fn rejects_empty_elasticsearch_batches_and_queue() {
+    assert!(elasticsearch("batch_size = 0").is_err());
+    assert!(elasticsearch("queue_size = 0").is_err());
+}
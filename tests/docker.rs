@@ -0,0 +1,107 @@
+extern crate chrono;
+extern crate cubostratusc;
+extern crate flate2;
+extern crate httparse;
+extern crate openssl;
+extern crate snap;
+
+mod support;
+
+use std::thread;
+use std::time::Duration;
+use cubostratusc::config;
+use cubostratusc::enricher::Enricher;
+use cubostratusc::enricher::docker::DockerEnricher;
+use support::docker::{Engine, IMAGE_DIGEST};
+
+const NGINX: &'static str = "3f4a9c1e2b7d6a5f8e0c1b2a3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60";
+const REDIS: &'static str = "8c7b6a5f4e3d2c1b0a9f8e7d6c5b4a3f2e1d0c9b8a7f6e5d4c3b2a1f0e9d8c7b";
+/// container of the other runtime, unknown to the engine
+const PODMAN: &'static str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+fn docker_enricher(engine: &Engine) -> DockerEnricher {
+    let content = format!("[stdout]\n[docker]\nsocket = {:?}\n", engine.socket());
+    let mut enricher = DockerEnricher::new(config::parse_config(&content).unwrap().docker.unwrap());
+    enricher.start().unwrap();
+    enricher
+}
+
+/// Enriches the event of the thread running in the container, and returns the container name.
+fn enrich(enricher: &mut DockerEnricher, id: &str) -> Option<String> {
+    let thread = support::thread_info(1, &format!("/system.slice/docker-{}.scope", id));
+    let mut info = support::syscall_info(1, "open");
+    enricher.enrich(&thread, &mut info);
+    info.container.map(|container| container.name.clone())
+}
+
+fn inspections(engine: &Engine, id: &str) -> usize {
+    engine.requests().iter().filter(|path| **path == format!("/containers/{}/json", id)).count()
+}
+
+#[test]
+fn enriches_events_with_the_running_containers() {
+    let engine = Engine::start(&support::temp_dir("docker-running"));
+    engine.add(NGINX, "web", "nginx:1.25", &[("com.example.team", "edge")]);
+    let mut enricher = docker_enricher(&engine);
+
+    let thread = support::thread_info(1, &format!("/docker/{}", NGINX));
+    let mut info = support::syscall_info(1, "open");
+    enricher.enrich(&thread, &mut info);
+    let container = info.container.unwrap();
+    assert_eq!(container.id, NGINX);
+    assert_eq!(container.name, "web");
+    assert_eq!(container.image, "nginx:1.25");
+    assert_eq!(container.image_digest.as_deref(), Some(IMAGE_DIGEST));
+    assert_eq!(container.labels.get("com.example.team").map(|l| l.as_str()), Some("edge"));
+    assert_eq!(&engine.requests()[..3], &["/containers/json".to_string(), format!("/containers/{}/json", NGINX),
+                                          format!("/images/sha256:{}/json", NGINX)]);
+
+    // ~ threads outside of containers
+    let mut info = support::syscall_info(2, "open");
+    enricher.enrich(&support::thread_info(2, "/user.slice"), &mut info);
+    assert!(info.container.is_none());
+}
+
+#[test]
+fn inspects_the_unknown_containers_once() {
+    let engine = Engine::start(&support::temp_dir("docker-lookup"));
+    let mut enricher = docker_enricher(&engine);
+    engine.add(REDIS, "cache", "redis:7", &[]);
+
+    // ~ the container started before the event stream was followed
+    assert_eq!(enrich(&mut enricher, REDIS), None);
+    assert!(support::wait_until(Duration::from_secs(5), || enrich(&mut enricher, REDIS).is_some()));
+    assert_eq!(enrich(&mut enricher, REDIS).as_deref(), Some("cache"));
+    assert_eq!(inspections(&engine, REDIS), 1);
+
+    // ~ the misses aren't queried again within the retry window
+    assert_eq!(enrich(&mut enricher, PODMAN), None);
+    assert!(support::wait_until(Duration::from_secs(5), || inspections(&engine, PODMAN) == 1));
+    for _ in 0..10 {
+        assert_eq!(enrich(&mut enricher, PODMAN), None);
+    }
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(inspections(&engine, PODMAN), 1);
+}
+
+#[test]
+fn follows_the_container_events() {
+    let engine = Engine::start(&support::temp_dir("docker-events"));
+    let mut enricher = docker_enricher(&engine);
+    assert!(support::wait_until(Duration::from_secs(5), || engine.followed()));
+
+    // ~ the started container is inspected before any event of its threads
+    engine.add(NGINX, "web", "nginx:1.25", &[]);
+    engine.emit("start", NGINX);
+    assert!(support::wait_until(Duration::from_secs(5), || inspections(&engine, NGINX) == 1));
+    assert!(support::wait_until(Duration::from_secs(5), || enrich(&mut enricher, NGINX).is_some()));
+    assert_eq!(enrich(&mut enricher, NGINX).as_deref(), Some("web"));
+
+    engine.add(NGINX, "web-blue", "nginx:1.25", &[]);
+    engine.emit("rename", NGINX);
+    assert!(support::wait_until(Duration::from_secs(5), || enrich(&mut enricher, NGINX).as_deref() == Some("web-blue")));
+
+    engine.remove(NGINX);
+    engine.emit("destroy", NGINX);
+    assert!(support::wait_until(Duration::from_secs(5), || enrich(&mut enricher, NGINX).is_none()));
+}
@@ -0,0 +1,111 @@
+extern crate chrono;
+extern crate cubostratusc;
+extern crate flate2;
+extern crate httparse;
+extern crate openssl;
+extern crate serde_json;
+extern crate snap;
+
+mod support;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use serde_json::Value;
+use cubostratusc::aggregator::{Aggregator, ElasticsearchAggregator};
+use cubostratusc::config;
+use cubostratusc::serializer::Encoded;
+use support::http::{Request, Server};
+
+/// Indexes the events and waits for the requests to complete, by dropping the aggregator.
+/// Returns the requests and the name of the index the events were indexed to.
+fn index(server: &Server, extra: &str, events: &[&str]) -> (Vec<Request>, String) {
+    let content = format!("[elasticsearch]\nurl = \"http://localhost:{}/\"\nindex = \"events-%Y.%m.%d\"\n\
+                           timeout = 1\nretry_backoff_ms = 10\n{}", server.port(), extra);
+    let mut aggregator = ElasticsearchAggregator::new(config::parse_config(&content).unwrap().elasticsearch.unwrap());
+    aggregator.start().unwrap();
+    let info = support::syscall_info(1, "open");
+    for event in events {
+        aggregator.do_aggregate(&info, Encoded::with_json(event.as_bytes().to_vec()));
+    }
+    drop(aggregator);
+    (server.requests(), info.ts.format("events-%Y.%m.%d").to_string())
+}
+
+/// Builds the bulk response with an item of each status.
+fn bulk_response(statuses: &[u16]) -> Vec<u8> {
+    let items = statuses.iter()
+        .map(|status| format!("{{\"index\":{{\"status\":{},\"error\":{}}}}}", status,
+                              if *status < 300 { "null" } else { "{\"type\":\"failure\"}" }))
+        .collect::<Vec<_>>();
+    let errors = statuses.iter().any(|status| *status >= 300);
+    format!("{{\"took\":1,\"errors\":{},\"items\":[{}]}}", errors, items.join(",")).into_bytes()
+}
+
+/// Splits the bulk body into the actions and the documents.
+fn bulk_lines(request: &Request) -> Vec<Value> {
+    assert_eq!(request.body.last(), Some(&b'\n'));
+    String::from_utf8(request.body.clone()).unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn installs_template_and_posts_bulk_actions() {
+    let server = Server::start(|request| if request.method == "PUT" {
+        (200, b"{\"acknowledged\":true}".to_vec())
+    } else {
+        (200, bulk_response(&[201, 201]))
+    });
+    let (requests, index) = index(&server, "template = \"cubostratus\"\n", &["{\"n\":1}", "{\"n\":2}"]);
+
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].method, "PUT");
+    assert_eq!(requests[0].path, "/_index_template/cubostratus");
+    assert_eq!(requests[0].header("content-type"), Some("application/json"));
+    let template: Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(template["index_patterns"], serde_json::from_str::<Value>("[\"events-*\"]").unwrap());
+    assert_eq!(template["template"]["mappings"]["properties"]["ts"]["type"], Value::String("date".to_string()));
+
+    assert_eq!(requests[1].method, "POST");
+    assert_eq!(requests[1].path, "/_bulk");
+    assert_eq!(requests[1].header("content-type"), Some("application/x-ndjson"));
+    let action = serde_json::from_str::<Value>(&format!("{{\"index\":{{\"_index\":\"{}\"}}}}", index)).unwrap();
+    assert_eq!(bulk_lines(&requests[1]),
+               vec![action.clone(), serde_json::from_str("{\"n\":1}").unwrap(),
+                    action, serde_json::from_str("{\"n\":2}").unwrap()]);
+}
+
+#[test]
+fn resends_only_the_items_rejected_by_back_pressure() {
+    let count = Arc::new(AtomicUsize::new(0));
+    let server = Server::start(move |_| if count.fetch_add(1, Ordering::SeqCst) == 0 {
+        (200, bulk_response(&[201, 429, 400]))
+    } else {
+        (200, bulk_response(&[201]))
+    });
+    let (requests, _) = index(&server, "max_retries = 3\n", &["{\"n\":1}", "{\"n\":2}", "{\"n\":3}"]);
+
+    assert_eq!(requests.len(), 2);
+    assert_eq!(bulk_lines(&requests[0]).len(), 6);
+    let lines = bulk_lines(&requests[1]);
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[1], serde_json::from_str::<Value>("{\"n\":2}").unwrap());
+}
+
+#[test]
+fn drops_the_items_rejected_for_good() {
+    let server = Server::start(|_| (200, bulk_response(&[400, 409])));
+    let (requests, _) = index(&server, "max_retries = 3\n", &["{\"n\":1}", "{\"n\":2}"]);
+
+    assert_eq!(requests.len(), 1);
+}
+
+#[test]
+fn fails_to_start_when_the_template_is_rejected() {
+    let server = Server::start(|_| (400, b"{\"error\":\"bad mapping\"}".to_vec()));
+    let content = format!("[elasticsearch]\nurl = \"http://localhost:{}\"\ntemplate = \"cubostratus\"\ntimeout = 1\n",
+                          server.port());
+    let mut aggregator = ElasticsearchAggregator::new(config::parse_config(&content).unwrap().elasticsearch.unwrap());
+    assert!(aggregator.start().is_err());
+}
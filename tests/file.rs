@@ -0,0 +1,208 @@
+extern crate chrono;
+extern crate cubostratusc;
+extern crate flate2;
+extern crate httparse;
+extern crate openssl;
+extern crate snap;
+extern crate zstd;
+
+mod support;
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use flate2::read::GzDecoder;
+use cubostratusc::aggregator::{Aggregator, FileAggregator};
+use cubostratusc::config;
+use cubostratusc::serializer::Encoded;
+
+fn file_aggregator(dir: &Path, max_files: usize) -> FileAggregator {
+    configured_aggregator(dir, &format!("compression = \"none\"\nmax_files = {}\n", max_files))
+}
+
+/// Creates the aggregator with the additional settings, given as the TOML lines of the section.
+fn configured_aggregator(dir: &Path, settings: &str) -> FileAggregator {
+    let content = format!("[file]\ndir = {:?}\n{}", dir.to_str().unwrap(), settings);
+    FileAggregator::new(config::parse_config(&content).unwrap().file.unwrap())
+}
+
+/// Aggregates the events whose JSON bodies are their numbers, padded to 16 bytes with the newline.
+fn aggregate(aggregator: &mut FileAggregator, events: ::std::ops::Range<u64>) {
+    for n in events {
+        let event = format!("{:015}", n).into_bytes();
+        aggregator.do_aggregate(&support::syscall_info(1, "open"), Encoded::with_json(event));
+    }
+}
+
+/// Reads the rotated file, decompressing it according to its extension.
+fn read(path: &Path) -> String {
+    let file = File::open(path).unwrap();
+    let content = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("gz") => {
+            let mut content = Vec::new();
+            GzDecoder::new(file).unwrap().read_to_end(&mut content).unwrap();
+            content
+        },
+        Some("zst") => zstd::stream::decode_all(file).unwrap(),
+        _ => fs::read(path).unwrap()
+    };
+    String::from_utf8(content).unwrap()
+}
+
+/// Returns the events of the rotated files, in the order of the files.
+fn rotated_events(dir: &Path) -> Vec<u64> {
+    names(dir).iter()
+        .filter(|name| !name.starts_with('.'))
+        .flat_map(|name| read(&dir.join(name)).lines().map(|line| line.parse().unwrap()).collect::<Vec<u64>>())
+        .collect()
+}
+
+fn names(dir: &Path) -> Vec<String> {
+    let mut names = fs::read_dir(dir).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+#[test]
+fn continues_the_sequence_of_the_previous_run() {
+    let dir = support::temp_dir("file-seq");
+    fs::write(dir.join("events-20170301T120000-000041.ndjson"), b"{}\n").unwrap();
+    fs::write(dir.join(".events.ndjson"), b"{}\n").unwrap();
+    let mut aggregator = file_aggregator(&dir, 0);
+    aggregator.start().unwrap();
+    let names = names(&dir);
+    assert_eq!(names.len(), 2);
+    assert!(names.iter().any(|n| n.ends_with("-000042.ndjson")), "{:?}", names);
+}
+
+#[test]
+fn removes_the_oldest_files_by_sequence() {
+    let dir = support::temp_dir("file-retention");
+    // ~ the second file was rotated after the clock was set back
+    fs::write(dir.join("events-20990101T000000-000001.ndjson"), b"{}\n").unwrap();
+    fs::write(dir.join("events-20170101T000000-000002.ndjson"), b"{}\n").unwrap();
+    fs::write(dir.join(".events.ndjson"), b"{}\n").unwrap();
+    let mut aggregator = file_aggregator(&dir, 2);
+    aggregator.start().unwrap();
+    let names = names(&dir);
+    assert_eq!(names.len(), 2);
+    assert!(names.contains(&"events-20170101T000000-000002.ndjson".to_string()), "{:?}", names);
+    assert!(names.iter().any(|n| n.ends_with("-000003.ndjson")), "{:?}", names);
+}
+
+#[test]
+fn rotates_the_files_by_size() {
+    let dir = support::temp_dir("file-size");
+    let mut aggregator = configured_aggregator(&dir, "compression = \"none\"\nmax_bytes = 64\n");
+    aggregator.start().unwrap();
+
+    // ~ the file is rotated by the event reaching the limit
+    aggregate(&mut aggregator, 0..10);
+    let names = names(&dir);
+    assert_eq!(names.len(), 3, "{:?}", names);
+    assert!(names[0] == ".events.ndjson" && names[1].ends_with("-000000.ndjson") && names[2].ends_with("-000001.ndjson"),
+            "{:?}", names);
+    assert_eq!(rotated_events(&dir), (0..8).collect::<Vec<_>>());
+
+    drop(aggregator);
+    assert_eq!(fs::read(dir.join(".events.ndjson")).unwrap(), b"000000000000008\n000000000000009\n");
+}
+
+#[test]
+fn rotates_the_files_by_age() {
+    let dir = support::temp_dir("file-age");
+    let mut aggregator = configured_aggregator(&dir, "compression = \"none\"\nmax_age = 1\n");
+    aggregator.start().unwrap();
+
+    // ~ the age is counted from the first event of the file
+    aggregator.poll();
+    assert!(names(&dir).is_empty());
+    aggregate(&mut aggregator, 0..2);
+    aggregator.poll();
+    assert_eq!(names(&dir), vec![".events.ndjson"]);
+    assert!(support::wait_until(Duration::from_secs(5), || {
+        aggregator.poll();
+        !dir.join(".events.ndjson").exists()
+    }));
+    assert_eq!(rotated_events(&dir), vec![0, 1]);
+
+    aggregate(&mut aggregator, 2..3);
+    assert!(support::wait_until(Duration::from_secs(5), || {
+        aggregator.poll();
+        !dir.join(".events.ndjson").exists()
+    }));
+    assert_eq!(rotated_events(&dir), vec![0, 1, 2]);
+}
+
+#[test]
+fn compresses_the_rotated_files() {
+    for &(compression, ext) in [("gzip", ".ndjson.gz"), ("zstd", ".ndjson.zst")].iter() {
+        let dir = support::temp_dir(&format!("file-{}", compression));
+        let settings = format!("compression = {:?}\nmax_bytes = 16000\n", compression);
+        let mut aggregator = configured_aggregator(&dir, &settings);
+        aggregator.start().unwrap();
+
+        aggregate(&mut aggregator, 0..5000);
+        // ~ waits for the worker to compress the rotated files
+        drop(aggregator);
+        let names = names(&dir);
+        assert_eq!(names.len(), 5, "{:?}", names);
+        assert!(names.iter().all(|name| name.ends_with(ext)), "{:?}", names);
+        assert_eq!(rotated_events(&dir), (0..5000).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn never_reveals_partial_files() {
+    let dir = support::temp_dir("file-partial");
+    let mut aggregator = configured_aggregator(&dir, "compression = \"gzip\"\nmax_bytes = 1600000\n");
+    aggregator.start().unwrap();
+
+    // ~ every visible file decompresses to whole events, while the files are compressed
+    let done = Arc::new(AtomicBool::new(false));
+    let watcher = {
+        let (dir, done) = (dir.clone(), done.clone());
+        thread::spawn(move || {
+            let mut checked = 0;
+            loop {
+                let last = done.load(Ordering::SeqCst);
+                for name in names(&dir).iter().filter(|name| !name.starts_with('.')) {
+                    assert!(name.ends_with(".ndjson.gz"), "{}", name);
+                    assert_eq!(read(&dir.join(name)).len(), 1600000, "{}", name);
+                    checked += 1;
+                }
+                if last {
+                    return checked;
+                }
+            }
+        })
+    };
+    aggregate(&mut aggregator, 0..500000);
+    drop(aggregator);
+    done.store(true, Ordering::SeqCst);
+    assert!(watcher.join().unwrap() >= 5);
+    assert_eq!(rotated_events(&dir), (0..500000).collect::<Vec<_>>());
+}
+
+#[test]
+fn compresses_the_files_left_hidden_by_the_previous_run() {
+    let dir = support::temp_dir("file-resume");
+    fs::write(dir.join(".events-20170301T120000-000001.ndjson.gz.tmp"), b"partial").unwrap();
+    fs::write(dir.join(".events-20170301T120000-000001.ndjson"), b"000000000000000\n").unwrap();
+    fs::write(dir.join(".events.ndjson"), b"000000000000001\n").unwrap();
+    let mut aggregator = configured_aggregator(&dir, "compression = \"gzip\"\n");
+    aggregator.start().unwrap();
+
+    drop(aggregator);
+    let names = names(&dir);
+    assert_eq!(names.len(), 2, "{:?}", names);
+    assert!(names[0].ends_with("-000001.ndjson.gz"), "{:?}", names);
+    assert!(names[1].ends_with("-000002.ndjson.gz"), "{:?}", names);
+    assert_eq!(rotated_events(&dir), vec![0, 1]);
+}
@@ -0,0 +1,91 @@
+extern crate chrono;
+extern crate cubostratusc;
+extern crate flate2;
+extern crate httparse;
+extern crate openssl;
+extern crate snap;
+
+mod support;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use cubostratusc::aggregator::StdoutAggregator;
+use cubostratusc::aggregator::format::Formatter;
+use cubostratusc::config;
+use cubostratusc::enricher::docker::ContainerInfo;
+use cubostratusc::enricher::kubernetes::PodInfo;
+use cubostratusc::state::users::Account;
+use cubostratusc::syscall::{ParamFormat, ParamType, SyscallInfo, SyscallParam};
+
+fn insert_u32(info: &mut SyscallInfo, name: &'static str, kind: ParamType, value: u32) {
+    let param = SyscallParam { name: name, kind: kind, fmt: ParamFormat::Dec };
+    let value = [value];
+    info.params.insert(name.to_string(), unsafe { param.parse(value.as_ptr() as *const u8, 4) });
+}
+
+#[test]
+fn renders_args_in_the_order_of_the_syscall_table() {
+    let mut info = support::syscall_info(1, "open");
+    insert_u32(&mut info, "mode", ParamType::UInt32, 420);
+    insert_u32(&mut info, "flags", ParamType::UInt32, 1);
+    insert_u32(&mut info, "fd", ParamType::UInt32, 3);
+    insert_u32(&mut info, "extra", ParamType::UInt32, 7);
+    let line = Formatter::new("%evt.args").unwrap().format(&info);
+    assert_eq!(line, "fd=3 flags=1 mode=420 extra=7");
+}
+
+#[test]
+fn renders_the_literal_percent_and_the_trailing_dot() {
+    let mut info = support::syscall_info(7, "open");
+    info.comm = Some("cat".to_string());
+    let line = Formatter::new("100%% %proc.name. %evt.type%%").unwrap().format(&info);
+    assert_eq!(line, "100% cat. open%");
+}
+
+#[test]
+fn rejects_unknown_fields() {
+    for format in &["%evt.unknown", "%proc.name %", "%", "%evt.arg.", "% proc.name"] {
+        match Formatter::new(format) {
+            Err(e) => assert!(e.to_string().contains("unknown format field"), "{}", e),
+            Ok(_) => panic!("accepted `{}`", format)
+        }
+    }
+    let content = "[stdout]\nformat = \"text\"\ntemplate = \"%proc.name %evt.bogus\"\n";
+    let mut aggregator = StdoutAggregator::new(config::parse_config(content).unwrap().stdout.unwrap());
+    assert!(aggregator.start().is_err());
+}
+
+#[test]
+fn renders_absent_attributes_as_na() {
+    let info = support::syscall_info(7, "open");
+    let line = Formatter::new("%proc.name|%proc.vpid|%thread.vtid|%user.name|%group.name|%container.id|\
+                               %container.name|%k8s.pod.name|%fd.name|%evt.arg.fd|%evt.args").unwrap().format(&info);
+    assert_eq!(line, "<NA>|<NA>|<NA>|<NA>|<NA>|<NA>|<NA>|<NA>|<NA>|<NA>|");
+}
+
+#[test]
+fn renders_the_enriched_attributes() {
+    let mut info = support::syscall_info(7, "open");
+    info.vpid = Some(1);
+    info.vtid = Some(2);
+    info.user = Some(Account { id: 1000, name: Some("alice".to_string()) });
+    // ~ ids missing from the database are rendered as numbers
+    info.group = Some(Account { id: 1001, name: None });
+    info.container = Some(Arc::new(ContainerInfo {
+        id: "3f4a".to_string(),
+        name: "web".to_string(),
+        image: "nginx".to_string(),
+        image_digest: None,
+        labels: BTreeMap::new()
+    }));
+    info.pod = Some(Arc::new(PodInfo {
+        name: "web-1".to_string(),
+        namespace: "default".to_string(),
+        uid: "a1b2".to_string(),
+        labels: BTreeMap::new()
+    }));
+    info.resolved_path = Some("/etc/passwd".to_string());
+    let line = Formatter::new("%proc.pid %proc.vpid %thread.tid %thread.vtid %user.name %group.name %container.id \
+                               %container.name %k8s.pod.name %fd.name").unwrap().format(&info);
+    assert_eq!(line, "7 1 7 2 alice 1001 3f4a web web-1 /etc/passwd");
+}
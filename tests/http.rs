@@ -0,0 +1,115 @@
+extern crate chrono;
+extern crate cubostratusc;
+extern crate flate2;
+extern crate httparse;
+extern crate openssl;
+extern crate serde_json;
+extern crate snap;
+
+mod support;
+
+use std::io::Read;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+use flate2::read::GzDecoder;
+use serde_json::Value;
+use cubostratusc::aggregator::{Aggregator, HttpAggregator};
+use cubostratusc::config;
+use cubostratusc::serializer::Encoded;
+use support::http::{Request, Server};
+
+/// Sends the events and waits for the requests to complete, by dropping the aggregator.
+fn post(server: &Server, extra: &str, events: &[&str]) -> Vec<Request> {
+    let content = format!("[http]\nurl = \"http://localhost:{}/intake?source=test\"\ntimeout = 1\n\
+                           retry_backoff_ms = 10\n{}", server.port(), extra);
+    let mut aggregator = HttpAggregator::new(config::parse_config(&content).unwrap().http.unwrap());
+    aggregator.start().unwrap();
+    let info = support::syscall_info(1, "open");
+    for event in events {
+        aggregator.do_aggregate(&info, Encoded::with_json(event.as_bytes().to_vec()));
+    }
+    drop(aggregator);
+    server.requests()
+}
+
+/// Answers the requests with the statuses in turn, and with the last one afterwards.
+fn statuses(statuses: &'static [u16]) -> impl Fn(&Request) -> (u16, Vec<u8>) {
+    let count = Arc::new(AtomicUsize::new(0));
+    move |_| {
+        let i = count.fetch_add(1, Ordering::SeqCst);
+        (statuses[i.min(statuses.len() - 1)], Vec::new())
+    }
+}
+
+#[test]
+fn posts_newline_delimited_batches_with_custom_headers() {
+    let server = Server::start(|_| (200, Vec::new()));
+    let requests = post(&server, "batch_size = 2\nconcurrency = 1\n[http.headers]\nAuthorization = \"Bearer token\"\n",
+                        &["{\"n\":1}", "{\"n\":2}", "{\"n\":3}"]);
+
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].method, "POST");
+    assert_eq!(requests[0].path, "/intake?source=test");
+    assert_eq!(requests[0].header("host"), Some(format!("localhost:{}", server.port()).as_str()));
+    assert_eq!(requests[0].header("content-type"), Some("application/x-ndjson"));
+    assert_eq!(requests[0].header("authorization"), Some("Bearer token"));
+    assert_eq!(requests[0].header("content-encoding"), None);
+    assert_eq!(requests[0].body, b"{\"n\":1}\n{\"n\":2}\n");
+    assert_eq!(requests[1].body, b"{\"n\":3}\n");
+}
+
+#[test]
+fn posts_gzip_compressed_json_arrays() {
+    let server = Server::start(|_| (200, Vec::new()));
+    let requests = post(&server, "format = \"json\"\ngzip = true\n", &["{\"n\":1}", "{\"n\":2}"]);
+
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].header("content-type"), Some("application/json"));
+    assert_eq!(requests[0].header("content-encoding"), Some("gzip"));
+    let mut body = String::new();
+    GzDecoder::new(&requests[0].body[..]).unwrap().read_to_string(&mut body).unwrap();
+    let events: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(events, serde_json::from_str::<Value>("[{\"n\":1},{\"n\":2}]").unwrap());
+}
+
+#[test]
+fn retries_server_errors() {
+    let server = Server::start(statuses(&[503, 500, 200]));
+    let requests = post(&server, "max_retries = 3\n", &["{\"n\":1}"]);
+
+    assert_eq!(requests.len(), 3);
+    assert!(requests.iter().all(|r| r.body == b"{\"n\":1}\n"));
+}
+
+#[test]
+fn gives_up_after_max_retries() {
+    let server = Server::start(statuses(&[429]));
+    let requests = post(&server, "max_retries = 2\n", &["{\"n\":1}"]);
+
+    assert_eq!(requests.len(), 3);
+}
+
+#[test]
+fn drops_rejected_batches_without_retrying() {
+    let server = Server::start(statuses(&[400, 200]));
+    let requests = post(&server, "max_retries = 3\n", &["{\"n\":1}"]);
+
+    assert_eq!(requests.len(), 1);
+}
+
+#[test]
+fn retries_timed_out_requests() {
+    let count = Arc::new(AtomicUsize::new(0));
+    let server = Server::start(move |_| {
+        if count.fetch_add(1, Ordering::SeqCst) == 0 {
+            thread::sleep(Duration::from_secs(2));
+        }
+        (200, Vec::new())
+    });
+    let requests = post(&server, "max_retries = 1\n", &["{\"n\":1}"]);
+
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].body, b"{\"n\":1}\n");
+}
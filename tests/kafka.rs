@@ -0,0 +1,472 @@
+extern crate chrono;
+extern crate cubostratusc;
+extern crate flate2;
+extern crate httparse;
+extern crate openssl;
+extern crate snap;
+
+mod support;
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use cubostratusc::aggregator::Aggregator;
+use cubostratusc::aggregator::kafka::KafkaAggregator;
+use cubostratusc::config::{self, KafkaConfig};
+use cubostratusc::enricher::docker::ContainerInfo;
+use cubostratusc::serializer::Encoded;
+use cubostratusc::spool::SpoolStats;
+use support::broker::{Broker, Message, Produce};
+use support::pki::{self, Identity};
+
+/// Parses the kafka section producing to the `events` topic of the host.
+fn kafka_config(host: &str, extra: &str) -> KafkaConfig {
+    let content = format!("[kafka]\nhosts = [\"{}\"]\ntopic = \"events\"\nack_timeout = 1\n{}", host, extra);
+    config::parse_config(&content).unwrap().kafka.unwrap()
+}
+
+/// Starts the aggregator producing to the broker, with the additional settings, once its
+/// worker asked the broker for the metadata. The worker connects in the background with the
+/// spool, and the events it gets before it's connected are spooled.
+fn started(broker: &Broker, settings: &str) -> KafkaAggregator {
+    let mut aggregator = KafkaAggregator::new(kafka_config(&broker.address(), settings));
+    aggregator.start().unwrap();
+    assert!(support::wait_until(Duration::from_secs(5), || broker.metadata_requests() > 0));
+    aggregator
+}
+
+/// Waits until the broker took the number of messages, and returns them.
+fn delivered(broker: &Broker, count: usize) -> Vec<Message> {
+    assert!(support::wait_until(Duration::from_secs(10), || broker.messages().len() >= count));
+    broker.messages()
+}
+
+/// Waits until the broker received the number of produce requests, and returns them.
+fn received(broker: &Broker, count: usize) -> Vec<Produce> {
+    assert!(support::wait_until(Duration::from_secs(10), || broker.requests().len() >= count));
+    broker.requests()
+}
+
+/// Waits until the spool stats of the aggregator satisfy the condition.
+fn spooled<F: Fn(&SpoolStats) -> bool>(aggregator: &KafkaAggregator, condition: F) -> bool {
+    support::wait_until(Duration::from_secs(10), || aggregator.spool_stats().is_some_and(|stats| condition(&stats)))
+}
+
+/// Aggregates the event of the thread, whose JSON body is the value.
+fn aggregate(aggregator: &mut KafkaAggregator, tid: u64, value: &str) {
+    aggregator.do_aggregate(&support::syscall_info(tid, "open"), Encoded::with_json(value.as_bytes().to_vec()));
+}
+
+fn values(messages: &[Message]) -> Vec<String> {
+    messages.iter().map(|m| String::from_utf8(m.value.clone()).unwrap()).collect()
+}
+
+/// Writes the CA bundle and the client identity, and renders the TLS section using them.
+fn tls_section(dir: &Path, ca: &Identity, client: Option<&Identity>, verify_hostname: bool) -> String {
+    let (ca_file, _) = ca.write(dir, "ca");
+    let mut section = format!("[kafka.tls]\nca_file = {:?}\nverify_hostname = {}\n",
+                              ca_file.to_str().unwrap(), verify_hostname);
+    if let Some(client) = client {
+        let (cert_file, key_file) = client.write(dir, "client");
+        section.push_str(&format!("cert_file = {:?}\nkey_file = {:?}\n",
+                                  cert_file.to_str().unwrap(), key_file.to_str().unwrap()));
+    }
+    section
+}
+
+#[test]
+fn produces_over_tls_with_client_certificate() {
+    let dir = support::temp_dir("kafka-tls");
+    let ca = pki::ca("test ca");
+    let client = pki::issue(&ca, "client", &[]);
+    let broker = Broker::start_tls("localhost", pki::acceptor(&pki::issue(&ca, "broker", &["localhost"]), Some(&ca)));
+
+    let tls = tls_section(&dir, &ca, Some(&client), true);
+    let mut aggregator = KafkaAggregator::new(kafka_config(&broker.address(), &tls));
+    aggregator.start().unwrap();
+    aggregate(&mut aggregator, 1, "hello");
+    aggregator.flush();
+
+    let messages = delivered(&broker, 1);
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].topic, "events");
+    assert_eq!(messages[0].value, b"hello");
+}
+
+#[test]
+fn rejects_broker_certificate_issued_for_another_host() {
+    let dir = support::temp_dir("kafka-tls-host");
+    let ca = pki::ca("test ca");
+    let broker = Broker::start_tls("localhost", pki::acceptor(&pki::issue(&ca, "broker", &["kafka.example.com"]), None));
+
+    let tls = tls_section(&dir, &ca, None, true);
+    let mut aggregator = KafkaAggregator::new(kafka_config(&broker.address(), &tls));
+    assert!(aggregator.start().is_err());
+    assert!(broker.messages().is_empty());
+}
+
+#[test]
+fn verifies_advertised_leader_against_its_own_address() {
+    let dir = support::temp_dir("kafka-tls-leader");
+    let ca = pki::ca("test ca");
+    // ~ the bootstrap host matches the certificate, the leader advertised in the metadata doesn't
+    let broker = Broker::start_tls("127.0.0.1", pki::acceptor(&pki::issue(&ca, "broker", &["localhost"]), None));
+
+    let tls = tls_section(&dir, &ca, None, true);
+    let mut aggregator = KafkaAggregator::new(kafka_config(&format!("localhost:{}", broker.port()), &tls));
+    aggregator.start().unwrap();
+    aggregate(&mut aggregator, 1, "hello");
+    aggregator.flush();
+    assert!(support::wait_until(Duration::from_secs(5), || aggregator.stats() == vec![("kafka.dropped", 1)]));
+    assert!(broker.messages().is_empty());
+}
+
+#[test]
+fn accepts_other_host_without_hostname_verification() {
+    let dir = support::temp_dir("kafka-tls-noverify");
+    let ca = pki::ca("test ca");
+    let broker = Broker::start_tls("localhost", pki::acceptor(&pki::issue(&ca, "broker", &["kafka.example.com"]), None));
+
+    let tls = tls_section(&dir, &ca, None, false);
+    let mut aggregator = KafkaAggregator::new(kafka_config(&broker.address(), &tls));
+    aggregator.start().unwrap();
+    aggregate(&mut aggregator, 1, "hello");
+    aggregator.flush();
+    assert_eq!(delivered(&broker, 1).len(), 1);
+}
+
+#[test]
+fn rejects_untrusted_broker_without_hostname_verification() {
+    let dir = support::temp_dir("kafka-tls-untrusted");
+    let ca = pki::ca("test ca");
+    let other_ca = pki::ca("other ca");
+    let broker = Broker::start_tls("localhost", pki::acceptor(&pki::issue(&other_ca, "broker", &["localhost"]), None));
+
+    let tls = tls_section(&dir, &ca, None, false);
+    let mut aggregator = KafkaAggregator::new(kafka_config(&broker.address(), &tls));
+    assert!(aggregator.start().is_err());
+}
+
+#[test]
+fn fails_without_client_certificate_when_broker_requires_it() {
+    let dir = support::temp_dir("kafka-tls-client");
+    let ca = pki::ca("test ca");
+    let broker = Broker::start_tls("localhost", pki::acceptor(&pki::issue(&ca, "broker", &["localhost"]), Some(&ca)));
+
+    let tls = tls_section(&dir, &ca, None, true);
+    let mut aggregator = KafkaAggregator::new(kafka_config(&broker.address(), &tls));
+    assert!(aggregator.start().is_err());
+    assert!(broker.handshake_failures() > 0);
+}
+
+fn sasl_section(mechanism: &str, password: &str) -> String {
+    format!("[kafka.sasl]\nmechanism = \"{}\"\nusername = \"user,=1\"\npassword = \"{}\"\n", mechanism, password)
+}
+
+#[test]
+fn authenticates_with_sasl_plain_and_scram() {
+    for mechanism in &["PLAIN", "SCRAM-SHA-256", "SCRAM-SHA-512"] {
+        let broker = Broker::start("127.0.0.1");
+        broker.require_sasl(mechanism, "user,=1", "secret");
+
+        let mut aggregator = KafkaAggregator::new(kafka_config(&broker.address(), &sasl_section(mechanism, "secret")));
+        aggregator.start().unwrap();
+        aggregate(&mut aggregator, 1, "hello");
+        aggregator.flush();
+
+        assert_eq!(values(&delivered(&broker, 1)), vec!["hello"], "{}", mechanism);
+        // ~ the connection the metadata is loaded over, and the one to the leader
+        assert_eq!(broker.authentications(), 2, "{}", mechanism);
+        assert_eq!(broker.authentication_failures(), 0, "{}", mechanism);
+    }
+}
+
+#[test]
+fn authenticates_with_sasl_over_tls() {
+    let dir = support::temp_dir("kafka-tls-sasl");
+    let ca = pki::ca("test ca");
+    let broker = Broker::start_tls("localhost", pki::acceptor(&pki::issue(&ca, "broker", &["localhost"]), None));
+    broker.require_sasl("SCRAM-SHA-512", "user,=1", "secret");
+
+    let settings = format!("{}{}", tls_section(&dir, &ca, None, true), sasl_section("SCRAM-SHA-512", "secret"));
+    let mut aggregator = KafkaAggregator::new(kafka_config(&broker.address(), &settings));
+    aggregator.start().unwrap();
+    aggregate(&mut aggregator, 1, "hello");
+    aggregator.flush();
+
+    assert_eq!(values(&delivered(&broker, 1)), vec!["hello"]);
+    assert_eq!(broker.authentications(), 2);
+}
+
+#[test]
+fn fails_with_wrong_sasl_password() {
+    for mechanism in &["PLAIN", "SCRAM-SHA-256"] {
+        let broker = Broker::start("127.0.0.1");
+        broker.require_sasl(mechanism, "user,=1", "secret");
+
+        let mut aggregator = KafkaAggregator::new(kafka_config(&broker.address(), &sasl_section(mechanism, "guess")));
+        let e = aggregator.start().err().unwrap();
+        assert!(e.to_string().contains("SASL authentication"), "{}", e);
+        assert_eq!(broker.authentication_failures(), 1, "{}", mechanism);
+        assert_eq!(broker.metadata_requests(), 0, "{}", mechanism);
+    }
+}
+
+#[test]
+fn fails_with_sasl_mechanism_the_broker_does_not_enable() {
+    let broker = Broker::start("127.0.0.1");
+    broker.require_sasl("SCRAM-SHA-512", "user,=1", "secret");
+
+    let mut aggregator = KafkaAggregator::new(kafka_config(&broker.address(), &sasl_section("PLAIN", "secret")));
+    let e = aggregator.start().err().unwrap();
+    assert!(e.to_string().contains("SCRAM-SHA-512"), "{}", e);
+    assert_eq!(broker.metadata_requests(), 0);
+}
+
+#[test]
+fn spools_while_broker_is_down_and_replays_in_order() {
+    let dir = support::temp_dir("kafka-spool");
+    let mut broker = Broker::start("127.0.0.1");
+    let spool = format!("batch_size = 1\n[kafka.spool]\ndir = {:?}\n", dir.to_str().unwrap());
+    let mut aggregator = started(&broker, &spool);
+
+    aggregate(&mut aggregator, 1, "1");
+    assert_eq!(delivered(&broker, 1).len(), 1);
+
+    broker.stop();
+    aggregate(&mut aggregator, 2, "2");
+    aggregate(&mut aggregator, 3, "3");
+    assert!(spooled(&aggregator, |stats| stats.appended == 2));
+    broker.restart();
+    // ~ appended behind the spooled messages until the spool is drained
+    aggregate(&mut aggregator, 4, "4");
+
+    assert_eq!(values(&delivered(&broker, 4)), vec!["1", "2", "3", "4"]);
+    assert!(spooled(&aggregator, |stats| stats.replayed == 3 && stats.bytes == 0));
+    assert_eq!(aggregator.spool_stats().unwrap().appended, 3);
+    assert!(aggregator.stats().contains(&("kafka.spool.replayed", 3)));
+}
+
+#[test]
+fn resumes_the_replay_after_a_restart() {
+    let dir = support::temp_dir("kafka-spool-restart");
+    let broker = Broker::start("127.0.0.1");
+    let spool = format!("batch_size = 2\n[kafka.spool]\ndir = {:?}\n", dir.to_str().unwrap());
+    broker.limit(Some(0));
+    let mut aggregator = started(&broker, &spool);
+    for tid in 1..7 {
+        aggregate(&mut aggregator, tid, &tid.to_string());
+    }
+    assert!(spooled(&aggregator, |stats| stats.appended == 6));
+
+    // ~ the replay stops after the first batch
+    broker.limit(Some(2));
+    assert!(spooled(&aggregator, |stats| stats.replayed == 2));
+    drop(aggregator);
+    assert_eq!(values(&broker.messages()), vec!["1", "2"]);
+
+    broker.limit(None);
+    let aggregator = started(&broker, &spool);
+    assert_eq!(values(&delivered(&broker, 6)), vec!["1", "2", "3", "4", "5", "6"]);
+    assert!(spooled(&aggregator, |stats| stats.replayed == 4 && stats.bytes == 0));
+    assert_eq!(broker.messages().len(), 6);
+}
+
+#[test]
+fn emits_snapshot_records_in_order() {
+    let broker = Broker::start("127.0.0.1");
+    let mut aggregator = started(&broker, "snapshot_topic = \"snapshots\"\nbatch_size = 2\n");
+    let records = (0..5).map(|i| format!("{{\"record\":{}}}", i)).collect::<Vec<_>>();
+    aggregator.snapshot(&records);
+
+    let messages = delivered(&broker, 5);
+    assert_eq!(messages.iter().map(|m| String::from_utf8(m.value.clone()).unwrap()).collect::<Vec<_>>(), records);
+    assert!(messages.iter().all(|m| m.topic == "snapshots" && m.key == messages[0].key));
+}
+
+#[test]
+fn batches_the_events_by_count() {
+    let broker = Broker::start("127.0.0.1");
+    let mut aggregator = started(&broker, "batch_size = 3\nlinger_ms = 60000\n");
+    for tid in 1..8 {
+        aggregate(&mut aggregator, tid, &tid.to_string());
+    }
+    let batches = received(&broker, 2).iter().map(|r| values(&r.messages)).collect::<Vec<_>>();
+    assert_eq!(batches, vec![vec!["1", "2", "3"], vec!["4", "5", "6"]]);
+
+    aggregator.flush();
+    let requests = received(&broker, 3);
+    assert_eq!(requests.len(), 3);
+    assert_eq!(values(&requests[2].messages), vec!["7"]);
+}
+
+#[test]
+fn batches_the_events_by_bytes() {
+    let broker = Broker::start("127.0.0.1");
+    let mut aggregator = started(&broker, "batch_bytes = 10\nlinger_ms = 60000\n");
+    for (tid, value) in ["aaaa", "bbbb", "cccc", "dd", "eeeeeeeeee", "f"].iter().enumerate() {
+        aggregate(&mut aggregator, tid as u64, value);
+    }
+    // ~ the batch is sent once its messages reach the size, the keys are empty
+    let batches = received(&broker, 2).iter().map(|r| values(&r.messages)).collect::<Vec<_>>();
+    assert_eq!(batches, vec![vec!["aaaa", "bbbb", "cccc"], vec!["dd", "eeeeeeeeee"]]);
+}
+
+#[test]
+fn sends_the_batch_once_it_lingers() {
+    let broker = Broker::start("127.0.0.1");
+    let mut aggregator = started(&broker, "linger_ms = 200\n");
+    let started_at = Instant::now();
+    aggregate(&mut aggregator, 1, "1");
+    aggregate(&mut aggregator, 2, "2");
+    aggregator.poll();
+    assert!(broker.requests().is_empty());
+
+    assert!(support::wait_until(Duration::from_secs(5), || {
+        aggregator.poll();
+        !broker.requests().is_empty()
+    }));
+    assert!(started_at.elapsed() >= Duration::from_millis(200));
+    let requests = broker.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(values(&requests[0].messages), vec!["1", "2"]);
+}
+
+#[test]
+fn compresses_the_message_sets() {
+    for &(compression, codec) in [("none", 0), ("gzip", 1), ("snappy", 2)].iter() {
+        let broker = Broker::start("127.0.0.1");
+        let settings = format!("batch_size = 3\ncompression = {:?}\n", compression);
+        let mut aggregator = started(&broker, &settings);
+        let value = "x".repeat(512);
+        for tid in 1..4 {
+            aggregate(&mut aggregator, tid, &format!("{}{}", value, tid));
+        }
+        let messages = delivered(&broker, 3);
+        assert_eq!(values(&messages), (1..4).map(|tid| format!("{}{}", value, tid)).collect::<Vec<_>>());
+        assert!(messages.iter().all(|m| m.codec == codec), "{}", compression);
+    }
+}
+
+#[test]
+fn sends_the_required_acks() {
+    for &(required_acks, acks) in [("none", 0), ("one", 1), ("all", -1)].iter() {
+        let broker = Broker::start("127.0.0.1");
+        let settings = format!("batch_size = 1\nrequired_acks = {:?}\n", required_acks);
+        let mut aggregator = started(&broker, &settings);
+        aggregate(&mut aggregator, 1, "1");
+        // ~ the brokers don't answer the requests requiring no acknowledgement
+        let requests = received(&broker, 1);
+        assert_eq!(requests[0].required_acks, acks, "{}", required_acks);
+        assert_eq!(requests[0].timeout, 1000);
+        assert_eq!(aggregator.stats(), vec![("kafka.dropped", 0)]);
+    }
+}
+
+/// Produces the events of the threads to the broker with four partitions, keyed by the attribute.
+fn keyed(partition_key: &str, infos: &[cubostratusc::syscall::SyscallInfo]) -> Vec<Message> {
+    let broker = Broker::start("127.0.0.1");
+    broker.set_partitions(4);
+    let settings = format!("batch_size = 100\npartition_key = {:?}\n", partition_key);
+    let mut aggregator = started(&broker, &settings);
+    for info in infos {
+        aggregator.do_aggregate(info, Encoded::with_json(info.tid.to_string().into_bytes()));
+    }
+    // ~ the worker sends the queued batches before it finishes
+    drop(aggregator);
+    broker.messages()
+}
+
+#[test]
+fn keys_the_messages_by_the_partition_key() {
+    let infos = (1..9).chain(1..9).map(|tid| {
+        let mut info = support::syscall_info(tid, "open");
+        if tid > 1 {
+            info.container = Some(Arc::new(ContainerInfo {
+                id: format!("{:064x}", tid),
+                name: format!("web-{}", tid),
+                image: "nginx".to_string(),
+                image_digest: None,
+                labels: Default::default()
+            }));
+        }
+        info
+    }).collect::<Vec<_>>();
+
+    // ~ the messages without the key are spread over the partitions in turn
+    let messages = keyed("none", &infos);
+    assert!(messages.iter().all(|m| m.key.is_empty()));
+    for partition in 0..4 {
+        assert_eq!(messages.iter().filter(|m| m.partition == partition).count(), 4);
+    }
+
+    let messages = keyed("host", &infos);
+    let hostname = fs::read_to_string("/proc/sys/kernel/hostname").unwrap();
+    assert_eq!(messages.len(), 16);
+    assert!(messages.iter().all(|m| m.key == hostname.trim().as_bytes() && m.partition == messages[0].partition));
+
+    // ~ the value of each message is the thread id, which is the pid, and names the container
+    for &partition_key in ["pid", "container"].iter() {
+        let messages = keyed(partition_key, &infos);
+        assert_eq!(messages.len(), 16);
+        let mut partitions = HashMap::new();
+        for m in &messages {
+            let tid = String::from_utf8(m.value.clone()).unwrap().parse::<u64>().unwrap();
+            let key = match partition_key {
+                "pid" => tid.to_string(),
+                _ if tid > 1 => format!("{:064x}", tid),
+                _ => String::new()
+            };
+            assert_eq!(m.key, key.as_bytes());
+            // ~ the messages of the key keep to one partition, the ones without it are spread
+            if key.is_empty() {
+                continue;
+            }
+            assert_eq!(*partitions.entry(m.key.clone()).or_insert(m.partition), m.partition, "{}", partition_key);
+        }
+        assert!(partitions.values().collect::<HashSet<_>>().len() > 1, "{}", partition_key);
+    }
+}
+
+/// error code of the partition whose leader is on another broker
+const NOT_LEADER_FOR_PARTITION: i16 = 6;
+
+#[test]
+fn spools_only_the_messages_of_the_rejected_partitions() {
+    let dir = support::temp_dir("kafka-spool-rejected");
+    let broker = Broker::start("127.0.0.1");
+    broker.set_partitions(2);
+    broker.reject(1, Some(NOT_LEADER_FOR_PARTITION));
+    let settings = format!("batch_size = 4\n[kafka.spool]\ndir = {:?}\n", dir.to_str().unwrap());
+    let mut aggregator = started(&broker, &settings);
+
+    // ~ the messages go to the partitions in turn, the ones of the first are delivered
+    for tid in 1..5 {
+        aggregate(&mut aggregator, tid, &tid.to_string());
+    }
+    assert_eq!(values(&delivered(&broker, 2)), vec!["1", "3"]);
+    assert!(spooled(&aggregator, |stats| stats.appended == 2));
+
+    // ~ the replay spools the rejected ones again, until the producer takes the first partition
+    aggregate(&mut aggregator, 5, "5");
+    aggregator.flush();
+    let messages = delivered(&broker, 5);
+    assert_eq!(values(&messages), vec!["1", "3", "2", "5", "4"]);
+    assert!(messages.iter().all(|m| m.partition == 0));
+    assert!(spooled(&aggregator, |stats| (stats.appended, stats.replayed, stats.bytes) == (4, 3, 0)));
+}
+
+#[test]
+fn drops_only_the_messages_of_the_rejected_partitions() {
+    let broker = Broker::start("127.0.0.1");
+    broker.set_partitions(2);
+    broker.reject(1, Some(NOT_LEADER_FOR_PARTITION));
+    let mut aggregator = started(&broker, "batch_size = 4\n");
+    for tid in 1..5 {
+        aggregate(&mut aggregator, tid, &tid.to_string());
+    }
+    assert!(support::wait_until(Duration::from_secs(5), || aggregator.stats() == vec![("kafka.dropped", 2)]));
+    assert_eq!(values(&broker.messages()), vec!["1", "3"]);
+}
@@ -0,0 +1,109 @@
+extern crate chrono;
+extern crate cubostratusc;
+extern crate flate2;
+extern crate httparse;
+extern crate openssl;
+extern crate snap;
+
+mod support;
+
+use std::fs::File;
+use std::io::Write;
+use std::thread;
+use std::time::{Duration, Instant};
+use cubostratusc::config::{self, KubernetesConfig};
+use cubostratusc::enricher::Enricher;
+use cubostratusc::enricher::kubernetes::KubernetesEnricher;
+use support::http::Server;
+use support::pki;
+
+const CONTAINER_ID: &'static str = "3f4a9c1e2b7d6a5f8e0c1b2a3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60";
+
+fn pod_list() -> Vec<u8> {
+    format!("{{\"items\": [{{\"metadata\": {{\"name\": \"nginx-5d8f\", \"namespace\": \"web\", \"uid\": \"42\", \
+             \"labels\": {{\"app\": \"nginx\"}}}}, \"status\": {{\"containerStatuses\": \
+             [{{\"containerID\": \"docker://{}\"}}]}}}}]}}", CONTAINER_ID).into_bytes()
+}
+
+fn kubernetes_config(extra: &str) -> KubernetesConfig {
+    let content = format!("[stdout]\n[kubernetes]\ntimeout = 1\n{}", extra);
+    config::parse_config(&content).unwrap().kubernetes.unwrap()
+}
+
+/// Enriches the event of the thread running in the container.
+fn enrich(enricher: &mut KubernetesEnricher) -> Option<String> {
+    let thread = support::thread_info(1, &format!("/kubepods/besteffort/pod42/{}", CONTAINER_ID));
+    let mut info = support::syscall_info(1, "open");
+    enricher.enrich(&thread, &mut info);
+    info.pod.map(|pod| format!("{}/{} app={}", pod.namespace, pod.name, pod.labels["app"]))
+}
+
+#[test]
+fn enriches_events_with_pods_listed_by_kubelet() {
+    let dir = support::temp_dir("kubernetes");
+    let token = dir.join("token");
+    File::create(&token).unwrap().write_all(b"secret\n").unwrap();
+    let server = Server::start(|_| (200, pod_list()));
+
+    let mut enricher = KubernetesEnricher::new(kubernetes_config(
+        &format!("url = \"http://localhost:{}/pods\"\ntoken_file = {:?}\n", server.port(), token.to_str().unwrap())));
+    enricher.start().unwrap();
+    assert_eq!(enrich(&mut enricher), Some("web/nginx-5d8f app=nginx".to_string()));
+
+    let requests = server.requests();
+    assert_eq!(requests[0].method, "GET");
+    assert_eq!(requests[0].path, "/pods");
+    assert_eq!(requests[0].header("host"), Some(format!("localhost:{}", server.port()).as_str()));
+    assert_eq!(requests[0].header("authorization"), Some("Bearer secret"));
+}
+
+#[test]
+fn reads_chunked_pod_list() {
+    let server = Server::start(|_| (200, pod_list()));
+    server.set_chunked(true);
+    let mut enricher = KubernetesEnricher::new(kubernetes_config(
+        &format!("url = \"http://localhost:{}/pods\"\n", server.port())));
+    enricher.start().unwrap();
+    assert_eq!(enrich(&mut enricher), Some("web/nginx-5d8f app=nginx".to_string()));
+}
+
+#[test]
+fn fetches_pods_over_tls() {
+    let dir = support::temp_dir("kubernetes-tls");
+    let ca = pki::ca("test ca");
+    let (ca_file, _) = ca.write(&dir, "ca");
+    let server = Server::start_tls(pki::acceptor(&pki::issue(&ca, "kubelet", &["localhost"]), None),
+                                   |_| (200, pod_list()));
+
+    let mut enricher = KubernetesEnricher::new(kubernetes_config(
+        &format!("url = \"https://localhost:{}/pods\"\nca_file = {:?}\n", server.port(), ca_file.to_str().unwrap())));
+    enricher.start().unwrap();
+    assert_eq!(enrich(&mut enricher), Some("web/nginx-5d8f app=nginx".to_string()));
+}
+
+#[test]
+fn rejects_kubelet_certificate_of_untrusted_ca() {
+    let dir = support::temp_dir("kubernetes-untrusted");
+    let ca = pki::ca("test ca");
+    let (ca_file, _) = ca.write(&dir, "ca");
+    let server = Server::start_tls(pki::acceptor(&pki::issue(&pki::ca("other ca"), "kubelet", &["localhost"]), None),
+                                   |_| (200, pod_list()));
+
+    let mut enricher = KubernetesEnricher::new(kubernetes_config(
+        &format!("url = \"https://localhost:{}/pods\"\nca_file = {:?}\n", server.port(), ca_file.to_str().unwrap())));
+    assert!(enricher.start().is_err());
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn gives_up_on_unresponsive_kubelet() {
+    let server = Server::start(|_| {
+        thread::sleep(Duration::from_secs(3));
+        (200, pod_list())
+    });
+    let mut enricher = KubernetesEnricher::new(kubernetes_config(
+        &format!("url = \"http://127.0.0.1:{}/pods\"\n", server.port())));
+    let started = Instant::now();
+    assert!(enricher.start().is_err());
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
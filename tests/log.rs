@@ -0,0 +1,16 @@
+extern crate cubostratusc;
+
+use std::thread;
+use std::time::Duration;
+use cubostratusc::log::RateLimit;
+
+#[test]
+fn counts_the_messages_suppressed_within_the_interval() {
+    let limit = RateLimit::new(100);
+    assert_eq!(limit.check(), Some(0));
+    assert_eq!(limit.check(), None);
+    assert_eq!(limit.check(), None);
+    thread::sleep(Duration::from_millis(150));
+    assert_eq!(limit.check(), Some(2));
+    assert_eq!(limit.check(), None);
+}
@@ -0,0 +1,144 @@
+extern crate chrono;
+extern crate cubostratusc;
+extern crate flate2;
+extern crate httparse;
+extern crate openssl;
+extern crate snap;
+
+mod support;
+
+use cubostratusc::config::StateConfig;
+use cubostratusc::state::namespaces::{self, PPM_CL_CLONE_NEWNET, PPM_CL_CLONE_NEWPID};
+use cubostratusc::state::thread::ThreadRegistry;
+use cubostratusc::syscall::{ParamFormat, ParamType, SyscallInfo, SyscallParam};
+use support::procfs::{self, ProcFs};
+
+const HOST_NET: u64 = 4026531993;
+const CONTAINER_NET: u64 = 4026532310;
+
+/// Links the namespace of the main thread, both in its `/proc/[pid]` and `task` entries.
+fn link_ns(procfs: &ProcFs, pid: u64, ns: &str, inode: u64) {
+    let target = format!("{}:[{}]", ns, inode);
+    procfs.link(&format!("{}/ns/{}", pid, ns), &target)
+        .link(&format!("{}/task/{}/ns/{}", pid, pid, ns), &target);
+}
+
+/// Moves the process to a nested pid namespace where it has the given id.
+fn set_vpid(procfs: &ProcFs, pid: u64, ppid: u64, comm: &str, vpid: u64) {
+    let status = format!("{}NStgid:\t{}\t{}\nNSpid:\t{}\t{}\n", procfs::status(pid, pid, ppid, comm),
+                         pid, vpid, pid, vpid);
+    procfs.write(&format!("{}/status", pid), status.as_bytes());
+}
+
+/// Lays out the runtime 500 and its child 600, both in the host namespaces.
+fn registry(name: &str) -> (ProcFs, ThreadRegistry) {
+    let procfs = ProcFs::new(name);
+    procfs.process(1, 0, "systemd").process(500, 1, "runc").process(600, 500, "runc:[1:CHILD]");
+    for pid in [500, 600].iter() {
+        link_ns(&procfs, *pid, "net", HOST_NET);
+    }
+    let mut registry = ThreadRegistry::with_config(StateConfig {
+        proc_root: procfs.root().to_string(),
+        etc_root: procfs.path("etc").to_str().unwrap().to_string(),
+        ..StateConfig::default()
+    });
+    registry.collect();
+    (procfs, registry)
+}
+
+fn insert<T>(info: &mut SyscallInfo, name: &'static str, kind: ParamType, value: T) {
+    let param = SyscallParam { name: name, kind: kind, fmt: ParamFormat::Dec };
+    let value = [value];
+    let len = std::mem::size_of::<T>() as u16;
+    info.params.insert(name.to_string(), unsafe { param.parse(value.as_ptr() as *const u8, len) });
+}
+
+fn clone_event(tid: u64, res: i64, flags: u32) -> SyscallInfo {
+    let mut info = support::syscall_info(tid, "clone");
+    insert(&mut info, "res", ParamType::ErrNo, res);
+    insert(&mut info, "flags", ParamType::Flags32, flags);
+    info
+}
+
+fn net(registry: &ThreadRegistry, tid: u64) -> Option<u64> {
+    registry.threads.get(&tid).and_then(|ti| ti.namespaces.as_ref()).and_then(|ns| ns.net)
+}
+
+#[test]
+fn reads_the_namespace_inodes_of_the_links() {
+    let procfs = ProcFs::new("ns-links");
+    procfs.link("10/ns/net", "net:[4026531993]")
+        .link("10/ns/mnt", "mnt:[4026531840]")
+        .link("10/ns/pid", "pid:[4026531836]")
+        .link("10/ns/cgroup", "cgroup:[abc]");
+
+    let ns = namespaces::namespaces(10, procfs.root().to_string()).unwrap();
+    assert_eq!((ns.net, ns.mnt, ns.pid), (Some(4026531993), Some(4026531840), Some(4026531836)));
+    // ~ missing and malformed links
+    assert_eq!((ns.user, ns.uts, ns.ipc, ns.cgroup), (None, None, None, None));
+    // ~ the process is gone
+    assert!(namespaces::namespaces(11, procfs.root().to_string()).is_err());
+}
+
+#[test]
+fn reloads_children_cloned_into_new_namespaces() {
+    let (procfs, mut registry) = registry("ns-clone");
+    assert_eq!(net(&registry, 600), Some(HOST_NET));
+    assert_eq!(registry.threads.get(&600).map(|ti| ti.vtid), Some(600));
+    link_ns(&procfs, 600, "net", CONTAINER_NET);
+    set_vpid(&procfs, 600, 500, "runc:[1:CHILD]", 1);
+
+    // ~ plain forks keep the namespaces of the parent
+    registry.update(&mut clone_event(500, 600, 0));
+    assert_eq!(net(&registry, 600), Some(HOST_NET));
+
+    // ~ the parent gets the tid of the child
+    registry.update(&mut clone_event(500, 600, PPM_CL_CLONE_NEWNET | PPM_CL_CLONE_NEWPID));
+    assert_eq!(net(&registry, 600), Some(CONTAINER_NET));
+    assert_eq!(registry.threads.get(&600).map(|ti| ti.vtid), Some(1));
+    assert_eq!(registry.processes.get(&600).map(|pi| pi.vpid), Some(1));
+    assert_eq!(registry.get_or_collect(600).map(|(ti, pi)| (ti.vtid, pi.vpid)), Some((1, 1)));
+}
+
+#[test]
+fn reloads_the_child_on_its_own_clone_exit() {
+    let (procfs, mut registry) = registry("ns-clone-child");
+    link_ns(&procfs, 600, "net", CONTAINER_NET);
+
+    // ~ while the child gets zero
+    registry.update(&mut clone_event(600, 0, PPM_CL_CLONE_NEWNET));
+    assert_eq!(net(&registry, 600), Some(CONTAINER_NET));
+    assert_eq!(net(&registry, 500), Some(HOST_NET));
+}
+
+#[test]
+fn reloads_threads_on_the_exit_of_setns_and_unshare() {
+    for (i, id) in [namespaces::SYS_SETNS, namespaces::SYS_UNSHARE].iter().enumerate() {
+        let (procfs, mut registry) = registry(&format!("ns-setns-{}", i));
+        let mut enter = support::syscall_info(500, "syscall");
+        insert(&mut enter, "native_id", ParamType::UInt16, *id);
+        registry.update(&mut enter);
+        link_ns(&procfs, 500, "net", CONTAINER_NET);
+        set_vpid(&procfs, 500, 1, "runc", 3);
+        assert_eq!(net(&registry, 500), Some(HOST_NET));
+
+        // ~ the next event of the thread is the exit of the syscall
+        registry.update(&mut support::syscall_info(500, "syscall"));
+        assert_eq!(net(&registry, 500), Some(CONTAINER_NET));
+        assert_eq!(registry.threads.get(&500).map(|ti| ti.vtid), Some(3));
+        assert_eq!(registry.processes.get(&500).map(|pi| pi.vpid), Some(3));
+        assert_eq!(net(&registry, 600), Some(HOST_NET));
+    }
+}
+
+#[test]
+fn ignores_other_native_syscalls() {
+    let (procfs, mut registry) = registry("ns-other");
+    let mut enter = support::syscall_info(500, "syscall");
+    insert(&mut enter, "native_id", ParamType::UInt16, 0u16);
+    registry.update(&mut enter);
+    link_ns(&procfs, 500, "net", CONTAINER_NET);
+
+    registry.update(&mut support::syscall_info(500, "syscall"));
+    assert_eq!(net(&registry, 500), Some(HOST_NET));
+}
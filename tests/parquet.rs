@@ -0,0 +1,300 @@
+extern crate chrono;
+extern crate cubostratusc;
+extern crate flate2;
+extern crate httparse;
+extern crate openssl;
+extern crate parquet;
+extern crate serde_json;
+extern crate snap;
+
+mod support;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{TimeZone, UTC};
+use cubostratusc::aggregator::{Aggregator, ParquetAggregator};
+use cubostratusc::config::{self, ParquetConfig};
+use cubostratusc::enricher::docker::ContainerInfo;
+use cubostratusc::parquet::Row;
+use cubostratusc::serializer::{Encoded, JsonSerializer, Serializer};
+use cubostratusc::syscall::{Direction, ParamFormat, ParamType, SyscallInfo, SyscallParam};
+use cubostratusc::syscall::syscall_table::SyscallTable;
+use parquet::basic::{LogicalType, TimeUnit};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::file::statistics::Statistics;
+use parquet::record::{Field, Row as ParquetRow, RowAccessor};
+
+fn parquet_config(dir: &Path, compression: &str) -> ParquetConfig {
+    configured(dir, &format!("row_group_size = 2\ncompression = {:?}\n", compression))
+}
+
+/// Parses the configuration with the additional settings, given as the TOML lines of the section.
+fn configured(dir: &Path, settings: &str) -> ParquetConfig {
+    let content = format!("[parquet]\ndir = {:?}\n{}", dir.to_str().unwrap(), settings);
+    config::parse_config(&content).unwrap().parquet.unwrap()
+}
+
+fn row(tid: u64, name: &str) -> Row {
+    Row::from_info(&support::syscall_info(tid, name))
+}
+
+/// Returns the paths of the files in the directory, sorted by name.
+fn files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+/// Reads the tid and syscall columns of the rows with the reference reader.
+fn read_rows(path: &Path) -> Vec<(i64, String)> {
+    let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+    reader.get_row_iter(None).unwrap()
+        .map(|row| {
+            let row = row.unwrap();
+            (row.get_long(1).unwrap(), row.get_string(6).unwrap().clone())
+        })
+        .collect()
+}
+
+#[test]
+fn writes_readable_files_on_drop() {
+    for compression in &["none", "gzip", "zstd"] {
+        let dir = support::temp_dir("parquet");
+        {
+            let mut aggregator = ParquetAggregator::new(parquet_config(&dir, compression));
+            aggregator.start().unwrap();
+            for (tid, name) in [(1, "open"), (2, "read"), (3, "close")].iter() {
+                aggregator.write(&row(*tid, name)).unwrap();
+            }
+        }
+        let files = files(&dir);
+        assert_eq!(files.len(), 1);
+        assert_eq!(read_rows(&files[0]), vec![(1, "open".to_string()), (2, "read".to_string()),
+                                              (3, "close".to_string())]);
+    }
+}
+
+#[test]
+fn recovers_row_groups_of_crashed_run() {
+    let dir = support::temp_dir("parquet-recover");
+    let mut aggregator = ParquetAggregator::new(parquet_config(&dir, "none"));
+    aggregator.start().unwrap();
+    for tid in 1..6 {
+        aggregator.write(&row(tid, "open")).unwrap();
+    }
+    // ~ the process dies without finishing the file, in the middle of the next row group
+    mem::forget(aggregator);
+    let active = dir.join(".events.parquet");
+    OpenOptions::new().append(true).open(&active).unwrap().write_all(b"\x15\x00PAR1\x15\x04").unwrap();
+
+    let mut aggregator = ParquetAggregator::new(parquet_config(&dir, "none"));
+    aggregator.start().unwrap();
+    let files = files(&dir);
+    assert_eq!(files.len(), 1);
+    assert!(!active.exists());
+    let tids = read_rows(&files[0]).into_iter().map(|(tid, _)| tid).collect::<Vec<_>>();
+    assert_eq!(tids, vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn removes_leftover_without_complete_row_group() {
+    let dir = support::temp_dir("parquet-empty");
+    let mut aggregator = ParquetAggregator::new(parquet_config(&dir, "none"));
+    aggregator.start().unwrap();
+    aggregator.write(&row(1, "open")).unwrap();
+    mem::forget(aggregator);
+
+    let mut aggregator = ParquetAggregator::new(parquet_config(&dir, "none"));
+    aggregator.start().unwrap();
+    assert!(files(&dir).is_empty());
+}
+
+/// Builds the row `i` of the generated rows, each nullable column missing in some of them.
+fn generated(i: i64) -> Row {
+    Row {
+        ts: 1488362400000000000 + i * 1000,
+        tid: i,
+        pid: if i % 3 == 0 { None } else { Some(i / 2) },
+        cpu: (i % 8) as i32,
+        dir: if i % 2 == 1 { "exit" } else { "enter" },
+        comm: if i % 5 == 0 { None } else { Some(format!("worker-{}", i % 7)) },
+        syscall: ["open", "read", "close"][(i % 3) as usize].to_string(),
+        category: "file",
+        result: if i % 2 == 1 { Some(i - 5000) } else { None },
+        resolved_path: if i % 4 == 0 { Some(format!("/var/lib/app/{}.db", i)) } else { None },
+        container_id: if i % 6 == 0 { Some(format!("{:064x}", i)) } else { None },
+        params: format!("{{\"fd\":{},\"name\":\"/tmp/\\\"{}\\\"\"}}", i, i)
+    }
+}
+
+type Columns = (i64, i64, Option<i64>, i32, String, Option<String>, String, String, Option<i64>,
+                Option<String>, Option<String>, String);
+
+fn columns(row: &Row) -> Columns {
+    (row.ts, row.tid, row.pid, row.cpu, row.dir.to_string(), row.comm.clone(), row.syscall.clone(),
+     row.category.to_string(), row.result, row.resolved_path.clone(), row.container_id.clone(), row.params.clone())
+}
+
+/// Reads all the columns of the rows with the reference reader.
+fn read_columns(path: &Path) -> Vec<Columns> {
+    let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+    reader.get_row_iter(None).unwrap().map(|row| {
+        let row: ParquetRow = row.unwrap();
+        let fields = row.get_column_iter().map(|(_, field)| field.clone()).collect::<Vec<_>>();
+        let long = |i: usize| match fields[i] {
+            Field::Null => None,
+            Field::Long(v) => Some(v),
+            ref field => panic!("unexpected {:?}", field)
+        };
+        let string = |i: usize| match fields[i] {
+            Field::Null => None,
+            Field::Str(ref v) => Some(v.clone()),
+            ref field => panic!("unexpected {:?}", field)
+        };
+        (long(0).unwrap(), long(1).unwrap(), long(2), row.get_int(3).unwrap(), string(4).unwrap(), string(5),
+         string(6).unwrap(), string(7).unwrap(), long(8), string(9), string(10), string(11).unwrap())
+    }).collect()
+}
+
+#[test]
+fn writes_the_columns_of_many_row_groups() {
+    let rows = (0..10000).map(generated).collect::<Vec<_>>();
+    for compression in &["none", "gzip", "zstd"] {
+        let dir = support::temp_dir(&format!("parquet-columns-{}", compression));
+        {
+            let settings = format!("row_group_size = 1000\ncompression = {:?}\n", compression);
+            let mut aggregator = ParquetAggregator::new(configured(&dir, &settings));
+            aggregator.start().unwrap();
+            for row in &rows {
+                aggregator.write(row).unwrap();
+            }
+        }
+        let files = files(&dir);
+        assert_eq!(files.len(), 1);
+        assert_eq!(read_columns(&files[0]), rows.iter().map(columns).collect::<Vec<_>>(), "{}", compression);
+
+        let reader = SerializedFileReader::new(File::open(&files[0]).unwrap()).unwrap();
+        let metadata = reader.metadata();
+        assert_eq!(metadata.num_row_groups(), 10);
+        let schema = metadata.file_metadata().schema_descr();
+        match schema.column(0).logical_type_ref() {
+            Some(LogicalType::Timestamp(ts)) => assert!(ts.is_adjusted_to_u_t_c && matches!(ts.unit, TimeUnit::NANOS)),
+            logical => panic!("unexpected {:?}", logical)
+        }
+        assert_eq!(schema.column(11).logical_type_ref(), Some(&LogicalType::Json));
+        assert!(schema.column(2).self_type().is_optional() && !schema.column(1).self_type().is_optional());
+        // ~ the bounds of the time range of the row groups, and the nulls of the nullable columns
+        for (i, row_group) in metadata.row_groups().iter().enumerate() {
+            let first = rows[i * 1000].ts;
+            match row_group.column(0).statistics() {
+                Some(Statistics::Int64(stats)) => assert_eq!((stats.min_opt(), stats.max_opt()), (Some(&first), Some(&(first + 999000)))),
+                stats => panic!("unexpected {:?}", stats)
+            }
+            assert_eq!(row_group.column(2).statistics().and_then(|stats| stats.null_count_opt()), Some(333 + (i % 3 == 0) as u64));
+        }
+    }
+}
+
+#[test]
+fn rotates_the_files_by_size() {
+    let dir = support::temp_dir("parquet-size");
+    let mut aggregator = ParquetAggregator::new(configured(&dir, "row_group_size = 100\nmax_bytes = 16384\n"));
+    aggregator.start().unwrap();
+    for i in 0..2000 {
+        aggregator.write(&generated(i)).unwrap();
+    }
+    drop(aggregator);
+
+    let files = files(&dir);
+    assert!(files.len() > 2, "{:?}", files);
+    assert!(files.iter().all(|file| !file.file_name().unwrap().to_str().unwrap().starts_with('.')));
+    let tids = files.iter().flat_map(|file| read_rows(file)).map(|(tid, _)| tid).collect::<Vec<_>>();
+    assert_eq!(tids, (0..2000).collect::<Vec<_>>());
+}
+
+#[test]
+fn rotates_the_files_by_age() {
+    let dir = support::temp_dir("parquet-age");
+    let mut aggregator = ParquetAggregator::new(configured(&dir, "max_age = 1\n"));
+    aggregator.start().unwrap();
+    aggregator.do_aggregate(&support::syscall_info(1, "open"), Encoded::new());
+    aggregator.do_aggregate(&support::syscall_info(2, "read"), Encoded::new());
+    assert_eq!(files(&dir), vec![dir.join(".events.parquet")]);
+
+    assert!(support::wait_until(Duration::from_secs(5), || {
+        aggregator.poll();
+        !dir.join(".events.parquet").exists()
+    }));
+    let files = files(&dir);
+    assert_eq!(files.len(), 1);
+    assert_eq!(read_rows(&files[0]), vec![(1, "open".to_string()), (2, "read".to_string())]);
+}
+
+fn insert<T>(info: &mut SyscallInfo, name: &'static str, kind: ParamType, value: T) {
+    let param = SyscallParam { name: name, kind: kind, fmt: ParamFormat::Dec };
+    let value = [value];
+    let len = std::mem::size_of::<T>() as u16;
+    info.params.insert(name.to_string(), unsafe { param.parse(value.as_ptr() as *const u8, len) });
+}
+
+const CONTAINER: &'static str = "3f4a9c1e2b7d6a5f8e0c1b2a3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60";
+
+/// Builds the exit of the `read` syscall in the container.
+fn read_exit() -> SyscallInfo {
+    let mut info = support::syscall_info(42, "read");
+    info.ts = UTC.ymd(2017, 3, 1).and_hms_nano(10, 32, 1, 123456789);
+    info.cpu = 3;
+    info.comm = Some("cat".to_string());
+    info.resolved_path = Some("/etc/hosts".to_string());
+    info.container = Some(Arc::new(ContainerInfo {
+        id: CONTAINER.to_string(),
+        name: "web".to_string(),
+        image: "nginx".to_string(),
+        image_digest: None,
+        labels: Default::default()
+    }));
+    insert(&mut info, "res", ParamType::ErrNo, -13i64);
+    info
+}
+
+#[test]
+fn builds_the_rows_of_the_events() {
+    let row = Row::from_info(&read_exit());
+    assert_eq!(columns(&row), (1488364321123456789, 42, Some(42), 3, "exit".to_string(), Some("cat".to_string()),
+                               "read".to_string(), "file".to_string(), Some(-13), Some("/etc/hosts".to_string()),
+                               Some(CONTAINER.to_string()), "{\"res\":-13}".to_string()));
+
+    // ~ the result is only known on exit
+    let mut info = read_exit();
+    info.dir = Direction::Enter;
+    assert_eq!(Row::from_info(&info).result, None);
+}
+
+#[test]
+fn builds_the_rows_of_the_json_events() {
+    let table = SyscallTable::default();
+    let parse = |info: &SyscallInfo| serde_json::from_slice::<serde_json::Value>(&JsonSerializer.serialize(info)).unwrap();
+    let info = read_exit();
+    let row = Row::from_json(&parse(&info), &table).unwrap();
+    // ~ the category is looked up in the syscall table
+    let mut expected = columns(&Row::from_info(&info));
+    expected.7 = "io_read".to_string();
+    assert_eq!(columns(&row), expected);
+
+    let mut info = support::syscall_info(7, "frobnicate");
+    info.dir = Direction::Enter;
+    info.pid = None;
+    let row = Row::from_json(&parse(&info), &table).unwrap();
+    assert_eq!((row.dir, row.category, row.pid, row.result, row.params.as_str()), ("enter", "unknown", None, None, "{}"));
+
+    // ~ the mandatory fields
+    for field in ["ts", "tid", "name"].iter() {
+        let mut event = parse(&read_exit());
+        event.as_object_mut().unwrap().remove(*field);
+        assert!(Row::from_json(&event, &table).is_none(), "{}", field);
+    }
+}
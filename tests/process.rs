@@ -0,0 +1,86 @@
+extern crate chrono;
+extern crate cubostratusc;
+extern crate flate2;
+extern crate httparse;
+extern crate libc;
+extern crate openssl;
+extern crate snap;
+
+mod support;
+
+use chrono::{TimeZone, UTC};
+use cubostratusc::state::process;
+use support::procfs::{self, ProcFs};
+
+#[test]
+fn strips_the_deleted_suffix_of_the_executable() {
+    let procfs = ProcFs::new("process-exe");
+    procfs.link("10/exe", "/usr/bin/python3.11 (deleted)").link("11/exe", "/usr/bin/bash");
+
+    let exe = process::exe(10, procfs.root()).unwrap();
+    assert_eq!(exe.path, "/usr/bin/python3.11");
+    assert!(exe.deleted);
+    let exe = process::exe(11, procfs.root()).unwrap();
+    assert_eq!(exe.path, "/usr/bin/bash");
+    assert!(!exe.deleted);
+    // ~ kernel threads
+    assert!(process::exe(12, procfs.root()).is_none());
+}
+
+#[test]
+fn splits_the_command_line_at_the_nul_bytes() {
+    let procfs = ProcFs::new("process-cmdline");
+    procfs.write("10/cmdline", b"nginx: worker process\0-c\0/etc/nginx/nginx.conf\0")
+        .write("11/cmdline", b"");
+
+    assert_eq!(process::cmdline(10, procfs.root()), vec!["nginx: worker process", "-c", "/etc/nginx/nginx.conf"]);
+    assert!(process::cmdline(11, procfs.root()).is_empty());
+    assert!(process::cmdline(12, procfs.root()).is_empty());
+}
+
+#[test]
+fn reads_the_stat_fields_after_the_command_name() {
+    let procfs = ProcFs::new("process-stat");
+    // ~ the command name may contain spaces and parentheses
+    procfs.stat(10, "tmux: server) (1", 1, 20, 30, 250);
+
+    assert_eq!(process::pgid(10, procfs.root()), Some(20));
+    assert_eq!(process::sid(10, procfs.root()), Some(30));
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as i64;
+    let expected = UTC.timestamp(procfs::BOOT_TIME + 250 / ticks, ((250 % ticks) * (1000000000 / ticks)) as u32);
+    let boot_time = process::boot_time(procfs.root()).unwrap();
+    assert_eq!(boot_time, procfs::BOOT_TIME);
+    assert_eq!(process::start_time(10, procfs.root(), boot_time), Some(expected));
+    assert_eq!(process::pgid(11, procfs.root()), None);
+}
+
+#[test]
+fn reads_the_audit_session() {
+    let procfs = ProcFs::new("process-audit");
+    procfs.write("10/loginuid", b"1000").write("10/sessionid", b"3\n")
+        .write("11/loginuid", b"4294967295").write("11/sessionid", b"4294967295");
+
+    assert_eq!(process::loginuid(10, procfs.root()), Some(1000));
+    assert_eq!(process::sessionid(10, procfs.root()), Some(3));
+    // ~ processes outside of a login session
+    assert_eq!(process::loginuid(11, procfs.root()), None);
+    assert_eq!(process::sessionid(11, procfs.root()), None);
+    assert_eq!(process::loginuid(12, procfs.root()), None);
+}
+
+#[test]
+fn captures_only_the_allowed_environment_variables() {
+    let procfs = ProcFs::new("process-environ");
+    procfs.write("10/environ", b"PATH=/usr/bin\0AWS_SECRET_ACCESS_KEY=secret\0POD_NAME=web-1\0EMPTY=\0\
+                                 NO_VALUE\0OPTS=a=b\0");
+    let names = ["POD_NAME", "EMPTY", "OPTS", "NO_VALUE", "MISSING"].iter()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+
+    let environ = process::environ(10, procfs.root(), &names);
+    assert_eq!(environ.into_iter().collect::<Vec<_>>(),
+               vec![("EMPTY".to_string(), "".to_string()), ("OPTS".to_string(), "a=b".to_string()),
+                    ("POD_NAME".to_string(), "web-1".to_string())]);
+    // ~ nothing is read unless some variables are allowed
+    assert!(process::environ(10, procfs.root(), &[]).is_empty());
+}
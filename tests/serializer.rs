@@ -0,0 +1,452 @@
+extern crate apache_avro;
+extern crate chrono;
+extern crate ciborium;
+extern crate cubostratusc;
+extern crate flate2;
+extern crate httparse;
+extern crate openssl;
+#[macro_use]
+extern crate prost;
+extern crate rmpv;
+extern crate serde_json;
+extern crate snap;
+
+mod support;
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CString;
+use std::sync::Arc;
+use apache_avro::Schema;
+use apache_avro::types::Value as Avro;
+use cubostratusc::enricher::docker::ContainerInfo;
+use cubostratusc::enricher::kubernetes::PodInfo;
+use cubostratusc::serializer::{Encoded, Serializer, MsgpackSerializer, CborSerializer, ProtobufSerializer,
+                               AvroSerializer};
+use cubostratusc::serializer::avro::ObjectContainer;
+use cubostratusc::state::thread::Ancestor;
+use cubostratusc::state::users::Account;
+use cubostratusc::syscall::{ParamFormat, ParamType, SyscallInfo, SyscallParam};
+use serde_json::Value;
+
+/// Serializer counting the events it encodes.
+struct Counting(Cell<usize>);
+
+impl Serializer for Counting {
+
+    fn serialize(&self, info: &SyscallInfo) -> Vec<u8> {
+        self.0.set(self.0.get() + 1);
+        info.name.clone().into_bytes()
+    }
+
+    fn content_type(&self) -> &'static str {
+        "text/plain"
+    }
+}
+
+fn insert<T: Copy>(info: &mut SyscallInfo, name: &'static str, kind: ParamType, value: T) {
+    let param = SyscallParam { name: name, kind: kind, fmt: ParamFormat::Na };
+    let value = [value];
+    let len = std::mem::size_of::<T>() as u16;
+    info.params.insert(name.to_string(), unsafe { param.parse(value.as_ptr() as *const u8, len) });
+}
+
+/// Builds the exit event of `open` with every attribute set, and with the strings, maps
+/// and integers long enough to need the wider length and integer variants.
+fn event() -> SyscallInfo {
+    let mut info = support::syscall_info(70000, "open");
+    info.cpu = 3;
+    info.comm = Some("cat".to_string());
+    info.vtid = Some(1);
+    info.user = Some(Account { id: 1000, name: Some("alice".to_string()) });
+    info.group = Some(Account { id: 1000, name: None });
+    info.ancestry = vec![Ancestor { pid: 70000, comm: "cat".to_string() },
+                         Ancestor { pid: 1, comm: "init".to_string() }];
+    insert(&mut info, "fd", ParamType::Fd, -2i64);
+    insert(&mut info, "flags", ParamType::Flags32, 0x8001u32);
+    insert(&mut info, "mode", ParamType::UInt32, 0o644u32);
+    insert(&mut info, "offset", ParamType::Int64, -100000i64);
+    insert(&mut info, "size", ParamType::UInt64, u64::MAX);
+    let path = CString::new("/etc/passwd").unwrap();
+    let param = SyscallParam { name: "name", kind: ParamType::FsPath, fmt: ParamFormat::Na };
+    info.params.insert("name".to_string(), unsafe { param.parse(path.as_ptr() as *const u8, 12) });
+    let mut tuple = vec![2u8, 10, 0, 0, 1];
+    tuple.extend_from_slice(&40000u16.to_ne_bytes());
+    tuple.extend_from_slice(&[192, 168, 1, 2]);
+    tuple.extend_from_slice(&443u16.to_ne_bytes());
+    let param = SyscallParam { name: "tuple", kind: ParamType::SockTuple, fmt: ParamFormat::Na };
+    info.params.insert("tuple".to_string(), unsafe { param.parse(tuple.as_ptr(), tuple.len() as u16) });
+    info.resolved_path = Some(format!("/srv/{}", "a".repeat(70000)));
+    info.pod = Some(Arc::new(PodInfo {
+        name: "web-0".to_string(),
+        namespace: "default".to_string(),
+        uid: "6f1c".to_string(),
+        labels: vec![("app".to_string(), "web".to_string())].into_iter().collect()
+    }));
+    info.container = Some(Arc::new(ContainerInfo {
+        id: "c0ffee".to_string(),
+        name: "web".to_string(),
+        image: "nginx:1.11".to_string(),
+        image_digest: Some("sha256:0123".to_string()),
+        labels: (0..20).map(|i| (format!("label{:02}", i), i.to_string())).collect()
+    }));
+    info
+}
+
+fn ts_nanos(info: &SyscallInfo) -> i64 {
+    info.ts.timestamp() * 1000000000 + info.ts.timestamp_subsec_nanos() as i64
+}
+
+fn from_msgpack(value: rmpv::Value) -> Value {
+    match value {
+        rmpv::Value::Nil => Value::Null,
+        rmpv::Value::Boolean(b) => Value::Bool(b),
+        rmpv::Value::Integer(i) => i.as_u64().map_or_else(|| Value::from(i.as_i64().unwrap()), Value::from),
+        rmpv::Value::F64(f) => Value::from(f),
+        rmpv::Value::String(s) => Value::String(s.into_str().unwrap()),
+        rmpv::Value::Array(items) => Value::Array(items.into_iter().map(from_msgpack).collect()),
+        rmpv::Value::Map(entries) => Value::Object(entries.into_iter()
+            .map(|(k, v)| (k.as_str().unwrap().to_string(), from_msgpack(v)))
+            .collect()),
+        other => panic!("unexpected MessagePack value {}", other)
+    }
+}
+
+fn from_cbor(value: ciborium::value::Value) -> Value {
+    use ciborium::value::Value as Cbor;
+    match value {
+        Cbor::Null => Value::Null,
+        Cbor::Bool(b) => Value::Bool(b),
+        Cbor::Integer(i) => {
+            let i = i128::from(i);
+            if i >= 0 { Value::from(i as u64) } else { Value::from(i as i64) }
+        },
+        Cbor::Float(f) => Value::from(f),
+        Cbor::Text(s) => Value::String(s),
+        Cbor::Array(items) => Value::Array(items.into_iter().map(from_cbor).collect()),
+        Cbor::Map(entries) => Value::Object(entries.into_iter()
+            .map(|(k, v)| (k.into_text().unwrap(), from_cbor(v)))
+            .collect()),
+        other => panic!("unexpected CBOR value {:?}", other)
+    }
+}
+
+#[test]
+fn encodes_each_format_once_across_the_clones() {
+    let info = support::syscall_info(1, "open");
+    let counting = Counting(Cell::new(0));
+    let encoded = Encoded::new();
+    let clone = encoded.clone();
+    assert_eq!(&encoded.get("counting", &counting, &info)[..], b"open");
+    assert_eq!(&clone.get("counting", &counting, &info)[..], b"open");
+    assert_eq!(counting.0.get(), 1);
+
+    let json = clone.json(&info);
+    assert!(json.starts_with(b"{"));
+    assert!(Arc::ptr_eq(&json, &encoded.json(&info)));
+}
+
+#[test]
+fn keeps_the_known_json_body() {
+    let encoded = Encoded::with_json(b"{}".to_vec());
+    assert_eq!(&encoded.json(&support::syscall_info(1, "open"))[..], b"{}");
+}
+
+#[test]
+fn msgpack_decodes_to_the_json_document() {
+    let info = event();
+    let bytes = MsgpackSerializer.serialize(&info);
+    let mut reader = &bytes[..];
+    let decoded = rmpv::decode::read_value(&mut reader).unwrap();
+    assert!(reader.is_empty());
+    assert_eq!(from_msgpack(decoded), serde_json::to_value(&info).unwrap());
+}
+
+#[test]
+fn cbor_decodes_to_the_json_document() {
+    let info = event();
+    let bytes = CborSerializer.serialize(&info);
+    let decoded = ciborium::de::from_reader::<ciborium::value::Value, _>(&bytes[..]).unwrap();
+    assert_eq!(from_cbor(decoded), serde_json::to_value(&info).unwrap());
+}
+
+/// Messages of `proto/syscall.proto`.
+mod proto {
+    use std::collections::HashMap;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Enumeration)]
+    #[repr(i32)]
+    pub enum Direction {
+        Enter = 0,
+        Exit = 1
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct Account {
+        #[prost(uint32, tag = "1")]
+        pub id: u32,
+        #[prost(string, tag = "2")]
+        pub name: String
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct Ancestor {
+        #[prost(uint64, tag = "1")]
+        pub pid: u64,
+        #[prost(string, tag = "2")]
+        pub comm: String
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct Sock {
+        #[prost(string, tag = "1")]
+        pub family: String,
+        #[prost(string, tag = "2")]
+        pub ip: String,
+        #[prost(uint32, tag = "3")]
+        pub port: u32,
+        #[prost(string, tag = "4")]
+        pub sip: String,
+        #[prost(uint32, tag = "5")]
+        pub sport: u32,
+        #[prost(string, tag = "6")]
+        pub dip: String,
+        #[prost(uint32, tag = "7")]
+        pub dport: u32,
+        #[prost(string, tag = "8")]
+        pub path: String
+    }
+
+    #[derive(Clone, PartialEq, Oneof)]
+    pub enum Param {
+        #[prost(string, tag = "1")]
+        String(String),
+        #[prost(sint64, tag = "2")]
+        Int(i64),
+        #[prost(uint64, tag = "3")]
+        Uint(u64),
+        #[prost(message, tag = "4")]
+        Sock(Sock)
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct ParamValue {
+        #[prost(oneof = "Param", tags = "1, 2, 3, 4")]
+        pub value: Option<Param>
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct Pod {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub namespace: String,
+        #[prost(string, tag = "3")]
+        pub uid: String,
+        #[prost(map = "string, string", tag = "4")]
+        pub labels: HashMap<String, String>
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct Container {
+        #[prost(string, tag = "1")]
+        pub id: String,
+        #[prost(string, tag = "2")]
+        pub name: String,
+        #[prost(string, tag = "3")]
+        pub image: String,
+        #[prost(string, tag = "4")]
+        pub image_digest: String,
+        #[prost(map = "string, string", tag = "5")]
+        pub labels: HashMap<String, String>
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct SyscallInfo {
+        #[prost(uint64, tag = "1")]
+        pub ts: u64,
+        #[prost(uint64, tag = "2")]
+        pub tid: u64,
+        #[prost(uint32, tag = "3")]
+        pub cpu: u32,
+        #[prost(enumeration = "Direction", tag = "4")]
+        pub dir: i32,
+        #[prost(string, tag = "5")]
+        pub comm: String,
+        #[prost(uint64, tag = "6")]
+        pub pid: u64,
+        #[prost(uint64, tag = "7")]
+        pub vtid: u64,
+        #[prost(uint64, tag = "8")]
+        pub vpid: u64,
+        #[prost(message, optional, tag = "9")]
+        pub user: Option<Account>,
+        #[prost(message, optional, tag = "10")]
+        pub group: Option<Account>,
+        #[prost(message, repeated, tag = "11")]
+        pub ancestry: Vec<Ancestor>,
+        #[prost(string, tag = "12")]
+        pub name: String,
+        #[prost(map = "string, message", tag = "13")]
+        pub params: HashMap<String, ParamValue>,
+        #[prost(string, tag = "14")]
+        pub resolved_path: String,
+        #[prost(message, optional, tag = "15")]
+        pub pod: Option<Pod>,
+        #[prost(message, optional, tag = "16")]
+        pub container: Option<Container>
+    }
+}
+
+#[test]
+fn protobuf_decodes_as_the_published_schema() {
+    use prost::Message;
+    use proto::Param;
+
+    let info = event();
+    let decoded = proto::SyscallInfo::decode(&ProtobufSerializer.serialize(&info)[..]).unwrap();
+    assert_eq!(decoded.ts as i64, ts_nanos(&info));
+    assert_eq!(decoded.tid, 70000);
+    assert_eq!(decoded.cpu, 3);
+    assert_eq!(decoded.dir, proto::Direction::Exit as i32);
+    assert_eq!(decoded.comm, "cat");
+    assert_eq!((decoded.pid, decoded.vtid, decoded.vpid), (70000, 1, 0));
+    assert_eq!(decoded.user, Some(proto::Account { id: 1000, name: "alice".to_string() }));
+    assert_eq!(decoded.group, Some(proto::Account { id: 1000, name: String::new() }));
+    assert_eq!(decoded.ancestry.iter().map(|a| (a.pid, a.comm.as_str())).collect::<Vec<_>>(),
+               vec![(70000, "cat"), (1, "init")]);
+    assert_eq!(decoded.name, "open");
+
+    let params = decoded.params.into_iter()
+        .map(|(name, value)| (name, value.value.unwrap()))
+        .collect::<HashMap<_, _>>();
+    assert_eq!(params.len(), 7);
+    assert_eq!(params["fd"], Param::Int(-2));
+    assert_eq!(params["offset"], Param::Int(-100000));
+    assert_eq!(params["flags"], Param::Uint(0x8001));
+    assert_eq!(params["mode"], Param::Uint(0o644));
+    assert_eq!(params["size"], Param::Uint(u64::MAX));
+    assert_eq!(params["name"], Param::String("/etc/passwd".to_string()));
+    match params["tuple"] {
+        Param::Sock(ref sock) => {
+            assert_eq!(sock.family, "inet");
+            assert_eq!((sock.sip.as_str(), sock.sport), ("10.0.0.1", 40000));
+            assert_eq!((sock.dip.as_str(), sock.dport), ("192.168.1.2", 443));
+        },
+        ref other => panic!("unexpected tuple {:?}", other)
+    }
+
+    assert_eq!(Some(&decoded.resolved_path), info.resolved_path.as_ref());
+    let pod = decoded.pod.unwrap();
+    assert_eq!((pod.name.as_str(), pod.namespace.as_str(), pod.uid.as_str()), ("web-0", "default", "6f1c"));
+    assert_eq!(pod.labels.get("app").map(|v| v.as_str()), Some("web"));
+    let container = decoded.container.unwrap();
+    assert_eq!(container.id, "c0ffee");
+    assert_eq!(container.image_digest, "sha256:0123");
+    assert_eq!(container.labels.into_iter().collect::<BTreeMap<_, _>>(),
+               info.container.as_ref().unwrap().labels);
+}
+
+/// Returns the field of the Avro record.
+fn field<'a>(record: &'a Avro, name: &str) -> &'a Avro {
+    match *record {
+        Avro::Record(ref fields) => &fields.iter().find(|&(n, _)| n == name).unwrap().1,
+        ref other => panic!("expected a record, got {:?}", other)
+    }
+}
+
+/// Returns the value of the union branch.
+fn branch(value: &Avro) -> &Avro {
+    match *value {
+        Avro::Union(_, ref value) => value,
+        ref other => panic!("expected a union, got {:?}", other)
+    }
+}
+
+fn assert_avro_event(record: &Avro, info: &SyscallInfo) {
+    assert_eq!(field(record, "ts"), &Avro::Long(ts_nanos(info)));
+    assert_eq!(field(record, "tid"), &Avro::Long(70000));
+    assert_eq!(field(record, "cpu"), &Avro::Int(3));
+    assert_eq!(field(record, "dir"), &Avro::Enum(1, "EXIT".to_string()));
+    assert_eq!(branch(field(record, "comm")), &Avro::String("cat".to_string()));
+    assert_eq!(branch(field(record, "vtid")), &Avro::Long(1));
+    assert_eq!(branch(field(record, "vpid")), &Avro::Null);
+    let user = branch(field(record, "user"));
+    assert_eq!(field(user, "id"), &Avro::Long(1000));
+    assert_eq!(branch(field(user, "name")), &Avro::String("alice".to_string()));
+    assert_eq!(branch(field(branch(field(record, "group")), "name")), &Avro::Null);
+    match *field(record, "ancestry") {
+        Avro::Array(ref ancestry) => {
+            assert_eq!(ancestry.len(), 2);
+            assert_eq!(field(&ancestry[1], "comm"), &Avro::String("init".to_string()));
+        },
+        ref other => panic!("unexpected ancestry {:?}", other)
+    }
+    assert_eq!(field(record, "name"), &Avro::String("open".to_string()));
+
+    // ~ only the parameters of the syscall table are part of the record
+    let params = branch(field(record, "params"));
+    assert_eq!(branch(field(params, "fd")), &Avro::Long(-2));
+    assert_eq!(branch(field(params, "name")), &Avro::String("/etc/passwd".to_string()));
+    assert_eq!(branch(field(params, "flags")), &Avro::Long(0x8001));
+    assert_eq!(branch(field(params, "mode")), &Avro::Long(0o644));
+
+    assert_eq!(branch(field(record, "resolved_path")), &Avro::String(info.resolved_path.clone().unwrap()));
+    let pod = branch(field(record, "pod"));
+    assert_eq!(field(pod, "uid"), &Avro::String("6f1c".to_string()));
+    let container = branch(field(record, "container"));
+    assert_eq!(branch(field(container, "image_digest")), &Avro::String("sha256:0123".to_string()));
+    match *field(container, "labels") {
+        Avro::Map(ref labels) => {
+            assert_eq!(labels.len(), 20);
+            assert_eq!(labels["label07"], Avro::String("7".to_string()));
+        },
+        ref other => panic!("unexpected labels {:?}", other)
+    }
+}
+
+#[test]
+fn avro_decodes_with_the_generated_schema() {
+    let info = event();
+    let serializer = AvroSerializer::new(None);
+    let schema = Schema::parse_str(serializer.schema()).unwrap();
+    let bytes = serializer.serialize(&info);
+    let mut reader = &bytes[..];
+    let record = apache_avro::from_avro_datum(&schema, &mut reader, None).unwrap();
+    assert!(reader.is_empty());
+    assert_avro_event(&record, &info);
+}
+
+#[test]
+fn avro_frames_the_events_in_the_confluent_wire_format() {
+    let info = event();
+    let serializer = AvroSerializer::new(Some(0x01020304));
+    let schema = Schema::parse_str(serializer.schema()).unwrap();
+    let bytes = serializer.serialize(&info);
+    assert_eq!(&bytes[..5], &[0, 1, 2, 3, 4]);
+    let record = apache_avro::from_avro_datum(&schema, &mut &bytes[5..], None).unwrap();
+    assert_avro_event(&record, &info);
+}
+
+#[test]
+fn avro_object_container_is_read_by_the_reference_reader() {
+    let info = event();
+    let serializer = AvroSerializer::new(None);
+    for codec in &["null", "deflate"] {
+        let mut container = ObjectContainer::new(serializer.schema(), codec);
+        let mut file = container.header();
+        container.append(&serializer.serialize(&info));
+        container.append(&serializer.serialize(&info));
+        file.extend_from_slice(&container.take_block().unwrap());
+        container.append(&serializer.serialize(&info));
+        file.extend_from_slice(&container.take_block().unwrap());
+
+        let records = apache_avro::Reader::new(&file[..]).unwrap()
+            .map(|record| record.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 3);
+        for record in &records {
+            assert_avro_event(record, &info);
+        }
+    }
+}
@@ -0,0 +1,241 @@
+extern crate chrono;
+extern crate cubostratusc;
+extern crate flate2;
+extern crate httparse;
+extern crate openssl;
+extern crate snap;
+
+mod support;
+
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+use cubostratusc::aggregator::Aggregator;
+use cubostratusc::aggregator::socket::SocketAggregator;
+use cubostratusc::config;
+use cubostratusc::serializer::Encoded;
+
+/// Starts the aggregator listening on the unix socket, with the additional settings.
+fn socket_aggregator(name: &str, settings: &str) -> (SocketAggregator, PathBuf) {
+    let path = support::temp_dir(name).join("events.sock");
+    let content = format!("[socket]\nunix_path = {:?}\nhandshake_timeout = 1\n{}", path.to_str().unwrap(), settings);
+    let mut aggregator = SocketAggregator::new(config::parse_config(&content).unwrap().socket.unwrap());
+    aggregator.start().unwrap();
+    (aggregator, path)
+}
+
+/// Aggregates the event whose JSON body is the thread id.
+fn aggregate(aggregator: &mut SocketAggregator, tid: u64) {
+    aggregator.do_aggregate(&support::syscall_info(tid, "open"), Encoded::with_json(tid.to_string().into_bytes()));
+}
+
+/// Client of the aggregator, reading without blocking so the test drives both ends.
+struct Client {
+    stream: UnixStream,
+    received: Vec<u8>,
+    closed: bool
+}
+
+impl Client {
+
+    fn connect(path: &PathBuf, handshake: &str) -> Client {
+        let mut stream = UnixStream::connect(path).unwrap();
+        stream.write_all(handshake.as_bytes()).unwrap();
+        stream.set_nonblocking(true).unwrap();
+        Client { stream: stream, received: Vec::new(), closed: false }
+    }
+
+    /// Polls the aggregator and reads until the received bytes satisfy the condition.
+    fn receive<F: Fn(&[u8], bool) -> bool>(&mut self, aggregator: &mut SocketAggregator, done: F) -> bool {
+        let mut buf = [0u8; 4096];
+        support::wait_until(Duration::from_secs(5), || {
+            aggregator.poll();
+            match self.stream.read(&mut buf) {
+                Ok(0) => self.closed = true,
+                Ok(n) => self.received.extend_from_slice(&buf[..n]),
+                Err(_) => {}
+            }
+            done(&self.received, self.closed)
+        })
+    }
+
+    /// Reads what's available, without polling the aggregator.
+    fn drain(&mut self) {
+        let mut buf = [0u8; 65536];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => {
+                    self.closed = true;
+                    return;
+                },
+                Ok(n) => self.received.extend_from_slice(&buf[..n]),
+                Err(_) => return
+            }
+        }
+    }
+
+    /// Takes the tids of the complete lines received.
+    fn tids(&mut self) -> Vec<u64> {
+        let end = self.received.iter().rposition(|&b| b == b'\n').map(|end| end + 1).unwrap_or(0);
+        let lines = String::from_utf8(self.received.drain(..end).collect()).unwrap();
+        lines.lines().map(|line| line.trim_start_matches('0').parse().unwrap()).collect()
+    }
+
+    /// Takes the received line, without its newline.
+    fn line(&mut self, aggregator: &mut SocketAggregator) -> String {
+        assert!(self.receive(aggregator, |received, _| received.contains(&b'\n')));
+        let end = self.received.iter().position(|&b| b == b'\n').unwrap();
+        let line = String::from_utf8(self.received[..end].to_vec()).unwrap();
+        self.received.drain(..end + 1);
+        line
+    }
+
+    /// Takes the received length prefixed frame.
+    fn frame(&mut self, aggregator: &mut SocketAggregator) -> Vec<u8> {
+        let len = |received: &[u8]| if received.len() < 4 {
+            None
+        } else {
+            Some(4 + ((received[0] as usize) << 24 | (received[1] as usize) << 16 |
+                      (received[2] as usize) << 8 | received[3] as usize))
+        };
+        assert!(self.receive(aggregator, |received, _| len(received).map(|n| received.len() >= n).unwrap_or(false)));
+        let n = len(&self.received).unwrap();
+        self.received.drain(..n).skip(4).collect()
+    }
+
+    /// Reads until the server closes the connection, and returns what's left.
+    fn rest(&mut self, aggregator: &mut SocketAggregator) -> String {
+        assert!(self.receive(aggregator, |_, closed| closed));
+        String::from_utf8(self.received.drain(..).collect()).unwrap()
+    }
+}
+
+#[test]
+fn streams_the_events_in_the_negotiated_framing() {
+    let (mut aggregator, path) = socket_aggregator("socket-framing", "");
+    let mut newline = Client::connect(&path, "format=json\n");
+    let mut length = Client::connect(&path, "format=json framing=length\n");
+    let mut text = Client::connect(&path, "  framing=newline   format=text \n");
+    assert_eq!(newline.line(&mut aggregator), "OK");
+    assert_eq!(length.line(&mut aggregator), "OK");
+    assert_eq!(text.line(&mut aggregator), "OK");
+
+    aggregate(&mut aggregator, 1);
+    aggregate(&mut aggregator, 22);
+    assert_eq!(newline.line(&mut aggregator), "1");
+    assert_eq!(newline.line(&mut aggregator), "22");
+    assert_eq!(length.frame(&mut aggregator), b"1");
+    assert_eq!(length.frame(&mut aggregator), b"22");
+    assert!(text.line(&mut aggregator).contains(" (1) < open"));
+    assert!(text.line(&mut aggregator).contains(" (22) < open"));
+}
+
+#[test]
+fn rejects_bad_handshakes() {
+    let (mut aggregator, path) = socket_aggregator("socket-handshake", "");
+    let cases = [
+        ("format=xml\n", "ERR unknown setting `format=xml`\n"),
+        ("format=json compress=gzip\n", "ERR unknown setting `compress=gzip`\n"),
+        ("format=msgpack\n", "ERR binary formats require the length framing\n"),
+        ("format=cbor framing=newline\n", "ERR binary formats require the length framing\n")
+    ];
+    for &(handshake, reply) in cases.iter() {
+        let mut client = Client::connect(&path, handshake);
+        assert_eq!(client.rest(&mut aggregator), reply);
+    }
+
+    // ~ the binary formats are fine with the length framing
+    let mut client = Client::connect(&path, "format=cbor framing=length\n");
+    assert_eq!(client.line(&mut aggregator), "OK");
+}
+
+#[test]
+fn disconnects_clients_not_completing_the_handshake() {
+    let (mut aggregator, path) = socket_aggregator("socket-handshake-timeout", "");
+    let mut silent = Client::connect(&path, "format=json");
+    let mut long = Client::connect(&path, &"x".repeat(300));
+
+    assert_eq!(long.rest(&mut aggregator), "");
+    assert_eq!(silent.rest(&mut aggregator), "");
+}
+
+#[test]
+fn rejects_the_clients_above_the_limit() {
+    let (mut aggregator, path) = socket_aggregator("socket-max-clients", "max_clients = 1\n");
+    let mut first = Client::connect(&path, "format=json\n");
+    assert_eq!(first.line(&mut aggregator), "OK");
+
+    let mut second = Client::connect(&path, "format=json\n");
+    assert_eq!(second.rest(&mut aggregator), "ERR too many clients\n");
+    aggregate(&mut aggregator, 1);
+    assert_eq!(first.line(&mut aggregator), "1");
+
+    // ~ the slot is freed once writing to the disconnected client fails
+    drop(first);
+    assert!(support::wait_until(Duration::from_secs(5), || {
+        aggregate(&mut aggregator, 2);
+        aggregator.poll();
+        let mut third = Client::connect(&path, "format=json\n");
+        third.line(&mut aggregator) == "OK"
+    }));
+}
+
+/// events streamed to the stalled client
+const STALLED_EVENTS: u64 = 8192;
+
+/// Streams the events of 1 KiB to the client that stopped reading and to the one keeping up,
+/// far more than the kernel and the aggregator buffer.
+fn stall(aggregator: &mut SocketAggregator, slow: &mut Client, fast: &mut Client) -> Vec<u64> {
+    let mut tids = Vec::new();
+    for tid in 1..STALLED_EVENTS + 1 {
+        let event = format!("{:0>1023}", tid).into_bytes();
+        aggregator.do_aggregate(&support::syscall_info(tid, "open"), Encoded::with_json(event));
+        aggregator.poll();
+        fast.drain();
+        tids.extend(fast.tids());
+    }
+    assert!(fast.receive(aggregator, |received, _| received.ends_with(format!("{}\n", STALLED_EVENTS).as_bytes())));
+    tids.extend(fast.tids());
+    assert!(!slow.closed);
+    tids
+}
+
+#[test]
+fn disconnects_the_slow_clients() {
+    let (mut aggregator, path) = socket_aggregator("socket-slow-disconnect", "buffer_bytes = 262144\n");
+    let mut slow = Client::connect(&path, "format=json\n");
+    let mut fast = Client::connect(&path, "format=json\n");
+    assert_eq!(slow.line(&mut aggregator), "OK");
+    assert_eq!(fast.line(&mut aggregator), "OK");
+
+    assert_eq!(stall(&mut aggregator, &mut slow, &mut fast), (1..STALLED_EVENTS + 1).collect::<Vec<_>>());
+    // ~ the slow client gets what was written before it was disconnected
+    assert!(slow.receive(&mut aggregator, |_, closed| closed));
+    let tids = slow.tids();
+    assert!(!tids.is_empty() && tids.len() < STALLED_EVENTS as usize, "{}", tids.len());
+    assert_eq!(tids, (1..tids.len() as u64 + 1).collect::<Vec<_>>());
+}
+
+#[test]
+fn drops_the_events_of_the_slow_clients() {
+    let (mut aggregator, path) = socket_aggregator("socket-slow-drop",
+                                                   "buffer_bytes = 262144\nslow_client = \"drop\"\n");
+    let mut slow = Client::connect(&path, "format=json\n");
+    let mut fast = Client::connect(&path, "format=json\n");
+    assert_eq!(slow.line(&mut aggregator), "OK");
+    assert_eq!(fast.line(&mut aggregator), "OK");
+
+    assert_eq!(stall(&mut aggregator, &mut slow, &mut fast), (1..STALLED_EVENTS + 1).collect::<Vec<_>>());
+    // ~ once the client reads again, it gets the buffered events and the ones after
+    let last = STALLED_EVENTS + 1;
+    assert!(slow.receive(&mut aggregator, |_, _| true));
+    aggregate(&mut aggregator, last);
+    assert!(slow.receive(&mut aggregator, |received, _| received.ends_with(format!("{}\n", last).as_bytes())));
+    assert!(!slow.closed);
+    let tids = slow.tids();
+    assert!(tids.len() < last as usize, "{}", tids.len());
+    assert_eq!(tids[0], 1);
+    assert_eq!(tids[tids.len() - 1], last);
+    assert!(tids.windows(2).all(|pair| pair[0] < pair[1]));
+}
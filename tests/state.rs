@@ -0,0 +1,321 @@
+extern crate chrono;
+extern crate cubostratusc;
+extern crate flate2;
+extern crate httparse;
+extern crate openssl;
+extern crate serde_json;
+extern crate snap;
+
+mod support;
+
+use std::ffi::CString;
+use std::fs;
+use std::process;
+use cubostratusc::config::{StateConfig, TableConfig};
+use cubostratusc::state::thread::ThreadRegistry;
+use cubostratusc::syscall::{ParamFormat, ParamType, SyscallInfo, SyscallParam};
+use support::procfs::{self, ProcFs};
+
+/// Builds the event of the syscall carrying the file descriptor and the path parameters.
+fn fd_event(name: &str, fd: (&'static str, i64), path: Option<(&'static str, &str)>) -> SyscallInfo {
+    let pid = process::id() as u64;
+    let mut info = support::syscall_info(pid, name);
+    let param = SyscallParam { name: fd.0, kind: ParamType::Fd, fmt: ParamFormat::Dec };
+    info.params.insert(fd.0.to_string(), unsafe { param.parse(fd.1.to_ne_bytes().as_ptr(), 8) });
+    if let Some((name, path)) = path {
+        let path = CString::new(path).unwrap();
+        let param = SyscallParam { name: name, kind: ParamType::FsPath, fmt: ParamFormat::Na };
+        let len = path.as_bytes_with_nul().len() as u16;
+        info.params.insert(name.to_string(), unsafe { param.parse(path.as_ptr() as *const u8, len) });
+    }
+    info
+}
+
+/// Lays out the process tree of a container, whose init process 200 runs in a nested pid
+/// namespace, started by the shim 100 on the host:
+///
+/// ```text
+/// 1 systemd -> 100 containerd-shim -> 200 init -> 300 bash -> 400 sleep
+/// ```
+///
+/// 300 leads the session of 400, and runs the second thread 301.
+fn container_tree(name: &str) -> ProcFs {
+    let procfs = ProcFs::new(name);
+    procfs.process(1, 0, "systemd")
+        .process(100, 1, "containerd-shim")
+        .process(200, 100, "init")
+        .process(300, 200, "bash")
+        .thread(300, 301, 200, "worker")
+        .process(400, 300, "sleep");
+    for &(pid, vpid, ppid, comm) in [(200, 1, 100, "init"), (300, 5, 200, "bash"), (400, 6, 300, "sleep")].iter() {
+        let status = format!("{}NStgid:\t{}\t{}\nNSpid:\t{}\t{}\n", procfs::status(pid, pid, ppid, comm),
+                             pid, vpid, pid, vpid);
+        procfs.write(&format!("{}/status", pid), status.as_bytes());
+    }
+    procfs.stat(300, "bash", 200, 300, 300, 100).stat(400, "sleep", 300, 400, 300, 200);
+    procfs
+}
+
+/// Creates the registry over the fixture, removing the exited threads and processes
+/// on the next event.
+fn fixture_registry(procfs: &ProcFs) -> ThreadRegistry {
+    let exited = TableConfig { exited_ttl: 0, ..TableConfig::default() };
+    let mut registry = ThreadRegistry::with_config(StateConfig {
+        threads: exited.clone(),
+        processes: exited,
+        proc_root: procfs.root().to_string(),
+        etc_root: procfs.path("etc").to_str().unwrap().to_string(),
+        ..StateConfig::default()
+    });
+    registry.collect();
+    registry
+}
+
+/// Feeds the exit of the thread, followed by an event expiring it.
+fn exit_thread(registry: &mut ThreadRegistry, tid: u64) {
+    registry.update(&mut support::syscall_info(tid, "procexit"));
+    registry.update(&mut support::syscall_info(1, "getpid"));
+}
+
+fn pids(processes: Vec<&cubostratusc::state::thread::ProcessInfo>) -> Vec<u64> {
+    processes.iter().map(|pi| pi.pid).collect()
+}
+
+#[test]
+fn walks_the_process_tree() {
+    let procfs = container_tree("state-tree");
+    let registry = fixture_registry(&procfs);
+
+    assert_eq!(pids(registry.ancestors(400)), vec![300, 200, 100, 1]);
+    assert_eq!(pids(registry.ancestors(1)), Vec::<u64>::new());
+    assert_eq!(registry.session_leader(400).map(|pi| pi.pid), Some(300));
+    assert_eq!(registry.session_leader(100).map(|pi| pi.pid), None);
+    assert_eq!(registry.container_init(400).map(|pi| pi.pid), Some(200));
+    assert_eq!(registry.container_init(200).map(|pi| pi.pid), Some(200));
+    assert_eq!(registry.container_init(100).map(|pi| pi.pid), None);
+    let ancestry = registry.ancestry(400).into_iter().map(|a| (a.pid, a.comm)).collect::<Vec<_>>();
+    assert_eq!(ancestry, vec![(400, "sleep".to_string()), (300, "bash".to_string()), (200, "init".to_string()),
+                              (100, "containerd-shim".to_string()), (1, "systemd".to_string())]);
+}
+
+#[test]
+fn limits_the_ancestry_to_the_configured_depth() {
+    let procfs = container_tree("state-depth");
+    let mut registry = ThreadRegistry::with_config(StateConfig {
+        ancestry_depth: 2,
+        proc_root: procfs.root().to_string(),
+        ..StateConfig::default()
+    });
+    registry.collect();
+
+    assert_eq!(registry.ancestry(400).iter().map(|a| a.pid).collect::<Vec<_>>(), vec![400, 300]);
+    assert!(registry.ancestry(999).is_empty());
+}
+
+#[test]
+fn reparents_orphans_to_the_nearest_init() {
+    let procfs = container_tree("state-reparent");
+    let mut registry = fixture_registry(&procfs);
+
+    // ~ the orphans within the container are reaped by its init process
+    exit_thread(&mut registry, 301);
+    exit_thread(&mut registry, 300);
+    assert!(registry.processes.get(&300).is_none());
+    assert_eq!(registry.processes.get(&400).map(|pi| pi.ppid), Some(200));
+    assert_eq!(pids(registry.ancestors(400)), vec![200, 100, 1]);
+    assert_eq!(pids(registry.descendants(200)), vec![400]);
+
+    // ~ the ones on the host by the init process of the host
+    exit_thread(&mut registry, 100);
+    assert_eq!(registry.processes.get(&200).map(|pi| pi.ppid), Some(1));
+    assert_eq!(pids(registry.ancestors(400)), vec![200, 1]);
+}
+
+#[test]
+fn keeps_the_command_of_a_process_whose_main_thread_exited() {
+    let procfs = container_tree("state-leader-exit");
+    let mut registry = fixture_registry(&procfs);
+
+    exit_thread(&mut registry, 300);
+    assert!(registry.threads.get(&300).is_none());
+    assert!(registry.processes.get(&300).is_some());
+    let ancestry = registry.ancestry(400);
+    assert_eq!(ancestry[1].pid, 300);
+    assert_eq!(ancestry[1].comm, "bash");
+}
+
+#[test]
+fn splits_snapshot_into_header_and_process_records() {
+    let procfs = container_tree("state-snapshot");
+    let mut registry = ThreadRegistry::with_roots(procfs.root(), procfs.path("etc").to_str().unwrap());
+    registry.collect();
+    let snapshot = registry.snapshot();
+    let records = snapshot.records().iter()
+        .map(|record| serde_json::to_value(record).unwrap())
+        .collect::<Vec<_>>();
+
+    let header = &records[0];
+    assert_eq!(header["record"].as_str(), Some("header"));
+    assert_eq!(header["processes"].as_u64(), Some(5));
+    assert_eq!(header["threads"].as_u64(), Some(6));
+    assert_eq!(header["resyncs"].as_u64(), Some(0));
+    assert!(header["corrections"]["added_processes"].is_u64());
+
+    let pids = records[1..].iter()
+        .map(|record| {
+            assert_eq!(record["record"].as_str(), Some("process"));
+            assert_eq!(record["ts"], header["ts"]);
+            assert_eq!(record["process"]["pid"], record["pid"]);
+            for thread in record["threads"].as_array().unwrap() {
+                assert_eq!(thread["pid"], record["pid"]);
+            }
+            record["pid"].as_u64().unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(pids, vec![1, 100, 200, 300, 400]);
+    let tids = records[4]["threads"].as_array().unwrap().iter()
+        .map(|thread| thread["tid"].as_u64().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(tids, vec![300, 301]);
+}
+
+#[test]
+fn counts_corrections_of_the_resyncs() {
+    let procfs = container_tree("state-corrections");
+    let etc_root = procfs.path("etc").to_str().unwrap().to_string();
+    let mut registry = ThreadRegistry::with_roots(procfs.root(), &etc_root);
+    let mut fresh = ThreadRegistry::with_roots(procfs.root(), &etc_root);
+    fresh.collect();
+    let corrections = registry.reconcile(fresh);
+
+    assert_eq!(corrections.added_processes, 5);
+    assert_eq!(corrections.added_threads, 6);
+    assert_eq!(registry.last_corrections().added_processes, 5);
+    assert_eq!(registry.corrections().added_threads, 6);
+    let snapshot = registry.snapshot();
+    assert_eq!(snapshot.header.resyncs, 1);
+    assert_eq!(snapshot.header.processes, snapshot.processes.len());
+
+    // ~ the exit of the process was missed
+    procfs.remove("400");
+    let mut fresh = ThreadRegistry::with_roots(procfs.root(), &etc_root);
+    fresh.collect();
+    let corrections = registry.reconcile(fresh);
+    assert_eq!(corrections.evicted_processes, 1);
+    assert_eq!(corrections.evicted_threads, 1);
+    assert_eq!(corrections.added_processes, 0);
+    assert_eq!(registry.corrections().evicted_processes, 1);
+    assert_eq!(registry.snapshot().header.resyncs, 2);
+    assert!(registry.processes.get(&400).is_none());
+}
+
+#[test]
+fn indexes_the_threads_of_the_task_directories() {
+    let procfs = ProcFs::new("state-tasks");
+    procfs.process(1, 0, "systemd")
+        .process(300, 1, "java")
+        .thread(300, 301, 1, "GC Thread#0")
+        .thread(300, 302, 1, "C2 Compiler");
+    let mut registry = ThreadRegistry::with_roots(procfs.root(), procfs.path("etc").to_str().unwrap());
+    let report = registry.collect();
+
+    assert_eq!(report.scanned, 2);
+    assert_eq!(registry.threads.len(), 4);
+    let tids = registry.processes.get(&300).unwrap().tids.iter().cloned().collect::<Vec<_>>();
+    assert_eq!(tids, vec![300, 301, 302]);
+    let (ti, pi) = registry.get_or_collect(302).unwrap();
+    assert_eq!((ti.tid, ti.pid, ti.comm.as_str()), (302, 300, "C2 Compiler"));
+    assert_eq!((pi.pid, pi.comm.as_str()), (300, "java"));
+}
+
+#[test]
+fn accounts_the_entries_that_vanished_or_failed_during_the_scan() {
+    let procfs = ProcFs::new("state-scan");
+    procfs.process(1, 0, "systemd")
+        // ~ exited before its status was read
+        .write("10/cmdline", b"")
+        // ~ unreadable status
+        .write("11/status/x", b"")
+        .write("12/status", b"not a status file\n")
+        // ~ all of its threads exited while the process was read
+        .write("13/status", procfs::status(13, 13, 1, "sh").as_bytes())
+        // ~ one of its threads exited
+        .process(14, 1, "nginx")
+        .write("14/task/15/cgroup", b"0::/\n")
+        .write("self/status", b"")
+        .write("sys/fs", b"");
+    let mut registry = ThreadRegistry::with_roots(procfs.root(), procfs.path("etc").to_str().unwrap());
+    let report = registry.collect();
+
+    assert_eq!(report.scanned, 2);
+    assert_eq!(report.skipped, 3);
+    let mut failed = report.failed.iter().map(|f| f.pid).collect::<Vec<_>>();
+    failed.sort();
+    assert_eq!(failed, vec![Some(11), Some(12)]);
+    assert!(report.failed.iter().all(|f| !f.reason.is_empty()));
+    let mut pids = registry.processes.keys().cloned().collect::<Vec<_>>();
+    pids.sort();
+    assert_eq!(pids, vec![1, 14]);
+    assert_eq!(registry.threads.len(), 2);
+}
+
+#[test]
+fn reads_processes_from_proc_root() {
+    let root = support::temp_dir("state-roots");
+    let (proc_root, etc_root) = (root.join("proc"), root.join("etc"));
+    fs::create_dir_all(&proc_root).unwrap();
+    fs::create_dir_all(&etc_root).unwrap();
+    let mut registry = ThreadRegistry::with_roots(proc_root.to_str().unwrap(), etc_root.to_str().unwrap());
+    registry.collect();
+
+    assert_eq!(registry.proc_root(), proc_root.to_str().unwrap());
+    assert_eq!(registry.snapshot().header.processes, 0);
+    assert!(registry.get_or_collect(process::id() as u64).is_none());
+}
+
+#[test]
+fn evicts_least_recently_used_open_files() {
+    let mut registry = ThreadRegistry::with_config(StateConfig { max_fds: 2, ..StateConfig::default() });
+    assert!(registry.get_or_collect(process::id() as u64).is_some());
+    for (fd, path) in [(100, "/tmp/a"), (101, "/tmp/b"), (102, "/tmp/c")].iter() {
+        registry.update(&mut fd_event("open", ("fd", *fd), Some(("name", path))));
+    }
+    let fd_table = registry.snapshot().header.fd_table;
+    assert_eq!(fd_table.len, 2);
+    assert_eq!(fd_table.evicted, 1);
+
+    registry.update(&mut fd_event("close", ("fd", 102), None));
+    assert_eq!(registry.snapshot().header.fd_table.len, 1);
+}
+
+#[test]
+fn expires_paths_whose_exit_event_was_dropped() {
+    let threads = TableConfig { exited_ttl: 0, ..TableConfig::default() };
+    let mut registry = ThreadRegistry::with_config(StateConfig { threads: threads, ..StateConfig::default() });
+    assert!(registry.get_or_collect(process::id() as u64).is_some());
+    let mut enter = fd_event("openat", ("dirfd", -100), Some(("name", "/tmp/a")));
+    registry.update(&mut enter);
+    assert_eq!(enter.resolved_path.as_deref(), Some("/tmp/a"));
+    assert_eq!(registry.snapshot().header.pending_path_table.len, 1);
+
+    registry.update(&mut fd_event("close", ("fd", 100), None));
+    let pending = registry.snapshot().header.pending_path_table;
+    assert_eq!(pending.len, 0);
+    assert_eq!(pending.expired, 1);
+}
+
+#[test]
+fn keeps_process_tree_within_table_capacity() {
+    let processes = TableConfig { capacity: 4, ..TableConfig::default() };
+    let mut registry = ThreadRegistry::with_config(StateConfig { processes: processes, ..StateConfig::default() });
+    registry.collect();
+
+    let header = registry.snapshot().header;
+    assert!(header.process_table.len <= 4);
+    assert!(header.process_table.evicted > 0);
+    let tree = registry.processes.keys()
+        .map(|pid| registry.descendants(*pid).len())
+        .max()
+        .unwrap_or(0);
+    assert!(tree < 4);
+}
@@ -0,0 +1,144 @@
+extern crate chrono;
+extern crate cubostratusc;
+extern crate flate2;
+extern crate httparse;
+extern crate openssl;
+extern crate snap;
+
+mod support;
+
+use cubostratusc::state::thread::{self, SeccompMode, ThreadState};
+use support::procfs::ProcFs;
+
+/// `status` file of a containerized thread, as rendered by a 5.x kernel.
+const STATUS: &'static str = "Name:\tnginx: worker\n\
+Umask:\t0022\n\
+State:\tS (sleeping)\n\
+Tgid:\t4242\n\
+Ngid:\t0\n\
+Pid:\t4243\n\
+PPid:\t4200\n\
+TracerPid:\t0\n\
+Uid:\t1000\t1001\t1002\t1003\n\
+Gid:\t2000\t2001\t2002\t2003\n\
+FDSize:\t64\n\
+Groups:\t2000 2001\n\
+NStgid:\t4242\t312\t7\n\
+NSpid:\t4243\t313\t8\n\
+NSpgid:\t4242\t312\t7\n\
+NSsid:\t4200\t300\t1\n\
+VmSize:\t  10240 kB\n\
+VmRSS:\t   2048 kB\n\
+Threads:\t3\n\
+SigQ:\t0/63338\n\
+CapInh:\t0000000000000000\n\
+CapPrm:\t00000000a80425fb\n\
+CapEff:\t0000000000003000\n\
+CapBnd:\t00000000a80425fb\n\
+CapAmb:\t0000000000000000\n\
+NoNewPrivs:\t1\n\
+Seccomp:\t2\n\
+Seccomp_filters:\t1\n\
+Speculation_Store_Bypass:\tthread force mitigated\n\
+Cpus_allowed_list:\t0-7\n\
+voluntary_ctxt_switches:\t150\n";
+
+/// Writes the status file of the thread 4243 and parses it as the thread and its process.
+fn parse(name: &str, status: &str) -> (thread::ThreadInfo, thread::ProcessInfo) {
+    let procfs = ProcFs::new(name);
+    procfs.write("4243/status", status.as_bytes());
+    (thread::parse_thread_info(4243, procfs.root().to_string()).unwrap(),
+     thread::parse_process_info(4243, procfs.root().to_string()).unwrap())
+}
+
+#[test]
+fn parses_the_ids_of_the_status_file() {
+    let (ti, pi) = parse("status-ids", STATUS);
+
+    assert_eq!(ti.comm, "nginx: worker");
+    assert_eq!(ti.state, ThreadState::Sleeping);
+    assert_eq!((ti.pid, ti.tid), (4242, 4243));
+    assert_eq!((ti.uid, ti.euid, ti.suid, ti.fsuid), (1000, 1001, 1002, 1003));
+    assert_eq!((ti.gid, ti.egid, ti.sgid, ti.fsgid), (2000, 2001, 2002, 2003));
+    assert_eq!((pi.pid, pi.ppid, pi.num_threads), (4242, 4200, 3));
+    assert_eq!((pi.vm_size, pi.vm_rss), (Some(10240), Some(2048)));
+}
+
+#[test]
+fn takes_the_ids_of_the_innermost_pid_namespace() {
+    let (ti, pi) = parse("status-nspid", STATUS);
+    assert_eq!(ti.vtid, 8);
+    assert_eq!(pi.vpid, 7);
+
+    // ~ kernels before 4.1 don't have the entries
+    let status = STATUS.lines()
+        .filter(|line| !line.starts_with("NS"))
+        .map(|line| format!("{}\n", line))
+        .collect::<String>();
+    let (ti, pi) = parse("status-no-nspid", &status);
+    assert_eq!(ti.vtid, 4243);
+    assert_eq!(pi.vpid, 4242);
+}
+
+#[test]
+fn decodes_the_capability_sets() {
+    let (ti, _) = parse("status-caps", STATUS);
+    let caps = ti.capabilities.unwrap();
+
+    assert_eq!(caps.effective, vec!["CAP_NET_ADMIN", "CAP_NET_RAW"]);
+    assert!(caps.inheritable.is_empty());
+    assert!(caps.ambient.is_empty());
+    assert_eq!(caps.permitted.len(), 14);
+    assert!(caps.bounding.contains(&"CAP_SYS_CHROOT".to_string()));
+    assert!(!caps.bounding.contains(&"CAP_SYS_ADMIN".to_string()));
+}
+
+#[test]
+fn reads_the_seccomp_mode_and_no_new_privs() {
+    let (ti, _) = parse("status-seccomp", STATUS);
+    assert_eq!(ti.seccomp, Some(SeccompMode::Filter));
+    assert!(ti.no_new_privs);
+
+    let status = STATUS.replace("Seccomp:\t2", "Seccomp:\t1").replace("NoNewPrivs:\t1", "NoNewPrivs:\t0");
+    let (ti, _) = parse("status-seccomp-strict", &status);
+    assert_eq!(ti.seccomp, Some(SeccompMode::Strict));
+    assert!(!ti.no_new_privs);
+
+    // ~ kernels built without seccomp nor capabilities lack the entries
+    let status = STATUS.lines()
+        .filter(|line| !line.starts_with("Seccomp") && !line.starts_with("NoNewPrivs") && !line.starts_with("Cap"))
+        .map(|line| format!("{}\n", line))
+        .collect::<String>();
+    let (ti, _) = parse("status-no-seccomp", &status);
+    assert_eq!(ti.seccomp, None);
+    assert!(!ti.no_new_privs);
+    assert!(ti.capabilities.is_none());
+}
+
+#[test]
+fn parses_the_entries_in_any_order() {
+    let mut lines = STATUS.lines().collect::<Vec<_>>();
+    lines.reverse();
+    let reversed = lines.iter().map(|line| format!("{}\n", line)).collect::<String>();
+    let (ti, pi) = parse("status-order", &reversed);
+
+    assert_eq!(ti.comm, "nginx: worker");
+    assert_eq!((ti.tid, ti.vtid, ti.euid, ti.fsgid), (4243, 8, 1001, 2003));
+    assert_eq!((pi.pid, pi.vpid, pi.ppid), (4242, 7, 4200));
+    assert_eq!(ti.capabilities.unwrap().effective, vec!["CAP_NET_ADMIN", "CAP_NET_RAW"]);
+}
+
+#[test]
+fn rejects_status_files_missing_the_required_entries() {
+    let procfs = ProcFs::new("status-missing");
+    let status = STATUS.lines()
+        .filter(|line| !line.starts_with("Uid"))
+        .map(|line| format!("{}\n", line))
+        .collect::<String>();
+    procfs.write("4243/status", status.as_bytes());
+    let err = thread::parse_thread_info(4243, procfs.root().to_string()).unwrap_err();
+    assert!(err.to_string().contains("Uid"), "{}", err);
+
+    procfs.write("4243/status", STATUS.replace("Tgid:\t4242", "Tgid:\tabc").as_bytes());
+    assert!(thread::parse_thread_info(4243, procfs.root().to_string()).is_err());
+}
@@ -0,0 +1,610 @@
+//! Kafka broker stand-in speaking version 0 of the metadata and produce APIs, which records
+//! the produce requests and their messages, decompressing the gzip and snappy message sets.
+//! It advertises itself as the leader of all the partitions of the topics, rejects the
+//! messages of the partitions it's told to, or the ones beyond the number of messages it's
+//! told to take, and can be stopped and started again on the same port. It can require the
+//! connections to authenticate with SASL `PLAIN` or `SCRAM`, verifying the client proof and
+//! signing the exchange like the brokers do.
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use flate2::read::GzDecoder;
+use openssl::base64;
+use openssl::hash::{self, MessageDigest};
+use openssl::pkcs5;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use openssl::ssl::SslAcceptor;
+use snap;
+
+const API_KEY_PRODUCE: i16 = 0;
+const API_KEY_METADATA: i16 = 3;
+const API_KEY_SASL_HANDSHAKE: i16 = 17;
+/// error code of the SASL mechanism the broker doesn't enable
+const UNSUPPORTED_SASL_MECHANISM: i16 = 33;
+/// iteration count of the salted SCRAM passwords
+const SCRAM_ITERATIONS: usize = 4096;
+/// header of the snappy framing of the Java client
+const SNAPPY_MAGIC: [u8; 8] = [0x82, b'S', b'N', b'A', b'P', b'P', b'Y', 0];
+/// error code of the messages beyond the limit
+const NOT_ENOUGH_REPLICAS: i16 = 19;
+/// topics listed in the metadata when the client asks for all of them
+const TOPICS: [&'static str; 2] = ["events", "snapshots"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    pub topic: String,
+    pub partition: i32,
+    /// compression codec of the message set the message came in, 0 when uncompressed,
+    /// 1 for gzip and 2 for snappy
+    pub codec: i8,
+    pub key: Vec<u8>,
+    pub value: Vec<u8>
+}
+
+/// Produce request, with the messages the broker took.
+#[derive(Clone, Debug)]
+pub struct Produce {
+    pub required_acks: i16,
+    pub timeout: i32,
+    pub messages: Vec<Message>
+}
+
+/// SASL mechanism the broker requires, and the credentials it accepts.
+#[derive(Clone)]
+struct Credentials {
+    mechanism: String,
+    username: String,
+    password: String
+}
+
+/// state shared with the connection threads
+struct Shared {
+    advertised: String,
+    port: u16,
+    acceptor: Option<SslAcceptor>,
+    partitions: AtomicUsize,
+    /// error codes the messages of the partitions are rejected with
+    rejected: Mutex<HashMap<i32, i16>>,
+    /// number of the messages taken before the later ones are rejected
+    limit: Mutex<Option<usize>>,
+    metadata_requests: AtomicUsize,
+    requests: Mutex<Vec<Produce>>,
+    handshake_failures: AtomicUsize,
+    sasl: Mutex<Option<Credentials>>,
+    authentications: AtomicUsize,
+    authentication_failures: AtomicUsize,
+    stop: AtomicBool,
+    connections: Mutex<Vec<JoinHandle<()>>>
+}
+
+pub struct Broker {
+    shared: Arc<Shared>,
+    listener: Option<JoinHandle<()>>
+}
+
+impl Broker {
+
+    /// Starts the plaintext broker advertising the host in the metadata.
+    pub fn start(advertised: &str) -> Broker {
+        Broker::bind(advertised, None)
+    }
+
+    /// Starts the broker accepting the TLS connections.
+    pub fn start_tls(advertised: &str, acceptor: SslAcceptor) -> Broker {
+        Broker::bind(advertised, Some(acceptor))
+    }
+
+    fn bind(advertised: &str, acceptor: Option<SslAcceptor>) -> Broker {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let shared = Arc::new(Shared {
+            advertised: advertised.to_string(),
+            port: listener.local_addr().unwrap().port(),
+            acceptor: acceptor,
+            partitions: AtomicUsize::new(1),
+            rejected: Mutex::new(HashMap::new()),
+            limit: Mutex::new(None),
+            metadata_requests: AtomicUsize::new(0),
+            requests: Mutex::new(Vec::new()),
+            handshake_failures: AtomicUsize::new(0),
+            sasl: Mutex::new(None),
+            authentications: AtomicUsize::new(0),
+            authentication_failures: AtomicUsize::new(0),
+            stop: AtomicBool::new(false),
+            connections: Mutex::new(Vec::new())
+        });
+        let mut broker = Broker { shared: shared, listener: None };
+        broker.listen(listener);
+        broker
+    }
+
+    pub fn port(&self) -> u16 {
+        self.shared.port
+    }
+
+    /// Returns the advertised `host:port` address of the broker.
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.shared.advertised, self.shared.port)
+    }
+
+    /// Returns the messages produced so far, in the order they were received.
+    pub fn messages(&self) -> Vec<Message> {
+        self.requests().into_iter().flat_map(|produce| produce.messages).collect()
+    }
+
+    /// Returns the produce requests received so far.
+    pub fn requests(&self) -> Vec<Produce> {
+        self.shared.requests.lock().unwrap().clone()
+    }
+
+    /// Sets the number of the partitions of each topic, advertised in the metadata.
+    pub fn set_partitions(&self, partitions: usize) {
+        self.shared.partitions.store(partitions, Ordering::SeqCst);
+    }
+
+    /// Rejects the messages produced to the partition with the error code, or takes
+    /// them again without it.
+    pub fn reject(&self, partition: i32, code: Option<i16>) {
+        let mut rejected = self.shared.rejected.lock().unwrap();
+        match code {
+            Some(code) => rejected.insert(partition, code),
+            None => rejected.remove(&partition)
+        };
+    }
+
+    /// Takes at most the number of messages in total, rejecting the message sets going
+    /// beyond it, or any number of messages without the limit.
+    pub fn limit(&self, messages: Option<usize>) {
+        *self.shared.limit.lock().unwrap() = messages;
+    }
+
+    /// Returns the number of the metadata requests answered, which clients send on connecting.
+    pub fn metadata_requests(&self) -> usize {
+        self.shared.metadata_requests.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of the TLS handshakes that failed.
+    pub fn handshake_failures(&self) -> usize {
+        self.shared.handshake_failures.load(Ordering::SeqCst)
+    }
+
+    /// Requires the connections to authenticate with the SASL mechanism and the credentials
+    /// before their first request.
+    pub fn require_sasl(&self, mechanism: &str, username: &str, password: &str) {
+        *self.shared.sasl.lock().unwrap() = Some(Credentials {
+            mechanism: mechanism.to_string(),
+            username: username.to_string(),
+            password: password.to_string()
+        });
+    }
+
+    /// Returns the number of the connections that authenticated with SASL.
+    pub fn authentications(&self) -> usize {
+        self.shared.authentications.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of the SASL authentications that failed.
+    pub fn authentication_failures(&self) -> usize {
+        self.shared.authentication_failures.load(Ordering::SeqCst)
+    }
+
+    /// Closes the listener and all the connections.
+    pub fn stop(&mut self) {
+        self.shared.stop.store(true, Ordering::SeqCst);
+        if let Some(listener) = self.listener.take() {
+            listener.join().unwrap();
+        }
+        let connections = ::std::mem::take(&mut *self.shared.connections.lock().unwrap());
+        for connection in connections {
+            let _ = connection.join();
+        }
+    }
+
+    /// Starts listening on the same port again, keeping the messages received so far.
+    pub fn restart(&mut self) {
+        self.stop();
+        self.shared.stop.store(false, Ordering::SeqCst);
+        let listener = TcpListener::bind(("127.0.0.1", self.shared.port)).unwrap();
+        self.listen(listener);
+    }
+
+    fn listen(&mut self, listener: TcpListener) {
+        listener.set_nonblocking(true).unwrap();
+        let shared = self.shared.clone();
+        self.listener = Some(thread::spawn(move || {
+            while !shared.stop.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let conn_shared = shared.clone();
+                        let connection = thread::spawn(move || serve(stream, &conn_shared));
+                        shared.connections.lock().unwrap().push(connection);
+                    },
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(5));
+                    },
+                    Err(_) => break
+                }
+            }
+        }));
+    }
+}
+
+impl Drop for Broker {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+trait Stream: Read + Write {}
+
+impl<T: Read + Write> Stream for T {}
+
+fn serve(stream: TcpStream, shared: &Shared) {
+    stream.set_nonblocking(false).unwrap();
+    // ~ the timeouts let the connection notice the broker is stopped
+    stream.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+    let mut stream: Box<Stream> = match shared.acceptor {
+        Some(ref acceptor) => {
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            match acceptor.accept(stream) {
+                Ok(tls) => {
+                    tls.get_ref().set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+                    Box::new(tls)
+                },
+                Err(_) => {
+                    shared.handshake_failures.fetch_add(1, Ordering::SeqCst);
+                    return;
+                }
+            }
+        },
+        None => Box::new(stream)
+    };
+    let credentials = shared.sasl.lock().unwrap().clone();
+    if let Some(credentials) = credentials {
+        // ~ the connection is closed when the authentication fails, like the brokers do
+        if authenticate(&mut stream, &credentials, shared) {
+            shared.authentications.fetch_add(1, Ordering::SeqCst);
+        } else {
+            shared.authentication_failures.fetch_add(1, Ordering::SeqCst);
+            return;
+        }
+    }
+    while let Some(request) = read_frame(&mut stream, shared) {
+        if let Some(response) = handle(&request, shared) {
+            if !write_frame(&mut stream, &response) {
+                return;
+            }
+        }
+    }
+}
+
+fn read_frame(stream: &mut Box<Stream>, shared: &Shared) -> Option<Vec<u8>> {
+    let mut size = [0u8; 4];
+    if !read_full(stream, &mut size, shared) {
+        return None;
+    }
+    let mut frame = vec![0u8; i32::from_be_bytes(size) as usize];
+    if !read_full(stream, &mut frame, shared) {
+        return None;
+    }
+    Some(frame)
+}
+
+fn write_frame(stream: &mut Box<Stream>, frame: &[u8]) -> bool {
+    let mut out = (frame.len() as i32).to_be_bytes().to_vec();
+    out.extend_from_slice(frame);
+    stream.write_all(&out).and_then(|_| stream.flush()).is_ok()
+}
+
+/// Answers the version 0 `SaslHandshake` request, then takes the SASL tokens framed by
+/// their size, and returns whether the client authenticated with the credentials.
+fn authenticate(stream: &mut Box<Stream>, credentials: &Credentials, shared: &Shared) -> bool {
+    let request = match read_frame(stream, shared) {
+        Some(request) => request,
+        None => return false
+    };
+    let mut r = Reader(&request);
+    let api_key = r.i16();
+    let _api_version = r.i16();
+    let correlation_id = r.i32();
+    let _client_id = r.string();
+    if api_key != API_KEY_SASL_HANDSHAKE {
+        return false;
+    }
+    let mechanism = r.string();
+    let mut response = Vec::new();
+    put_i32(&mut response, correlation_id);
+    put_i16(&mut response, if mechanism == credentials.mechanism { 0 } else { UNSUPPORTED_SASL_MECHANISM });
+    put_i32(&mut response, 1);
+    put_str(&mut response, &credentials.mechanism);
+    if !write_frame(stream, &response) || mechanism != credentials.mechanism {
+        return false;
+    }
+    let token = match read_frame(stream, shared) {
+        Some(token) => token,
+        None => return false
+    };
+    match mechanism.as_str() {
+        "PLAIN" => {
+            // ~ the authorization id, the username and the password separated by NUL bytes
+            let expected = format!("\0{}\0{}", credentials.username, credentials.password);
+            token == expected.as_bytes() && write_frame(stream, &[])
+        },
+        "SCRAM-SHA-256" => scram(stream, credentials, MessageDigest::sha256(), &token, shared),
+        "SCRAM-SHA-512" => scram(stream, credentials, MessageDigest::sha512(), &token, shared),
+        _ => false
+    }
+}
+
+/// Verifies the client proof of the SCRAM exchange, and answers with the server signature.
+fn scram(stream: &mut Box<Stream>, credentials: &Credentials, digest: MessageDigest, client_first: &[u8],
+         shared: &Shared) -> bool {
+    let client_first = String::from_utf8(client_first.to_vec()).unwrap();
+    let client_first = client_first.strip_prefix("n,,").unwrap();
+    // ~ the commas and equal signs of the username are escaped
+    if attribute(client_first, "n").replace("=2C", ",").replace("=3D", "=") != credentials.username {
+        return false;
+    }
+    let salt = b"stand-in salt";
+    let server_first = format!("r={}broker,s={},i={}", attribute(client_first, "r"), base64::encode_block(salt),
+                               SCRAM_ITERATIONS);
+    if !write_frame(stream, server_first.as_bytes()) {
+        return false;
+    }
+    let client_final = match read_frame(stream, shared) {
+        Some(client_final) => String::from_utf8(client_final).unwrap(),
+        None => return false
+    };
+    let (without_proof, proof) = client_final.split_at(client_final.rfind(",p=").unwrap());
+    let proof = base64::decode_block(&proof[3..]).unwrap();
+    if attribute(without_proof, "r") != format!("{}broker", attribute(client_first, "r")) {
+        return false;
+    }
+    let mut salted = vec![0u8; digest.size()];
+    pkcs5::pbkdf2_hmac(credentials.password.as_bytes(), salt, SCRAM_ITERATIONS, digest, &mut salted).unwrap();
+    let stored_key = hash::hash(digest, &hmac(digest, &salted, b"Client Key")).unwrap();
+    let auth_message = format!("{},{},{}", client_first, server_first, without_proof);
+    let signature = hmac(digest, &stored_key, auth_message.as_bytes());
+    let client_key = proof.iter().zip(signature).map(|(p, s)| p ^ s).collect::<Vec<_>>();
+    if hash::hash(digest, &client_key).unwrap()[..] != stored_key[..] {
+        return false;
+    }
+    let server_signature = hmac(digest, &hmac(digest, &salted, b"Server Key"), auth_message.as_bytes());
+    write_frame(stream, format!("v={}", base64::encode_block(&server_signature)).as_bytes())
+}
+
+/// Returns the value of the attribute of the SCRAM message.
+fn attribute<'a>(message: &'a str, name: &str) -> &'a str {
+    message.split(',')
+        .find_map(|attribute| attribute.strip_prefix(name).and_then(|a| a.strip_prefix('=')))
+        .unwrap()
+}
+
+fn hmac(digest: MessageDigest, key: &[u8], data: &[u8]) -> Vec<u8> {
+    let key = PKey::hmac(key).unwrap();
+    let mut signer = Signer::new(digest, &key).unwrap();
+    signer.update(data).unwrap();
+    signer.sign_to_vec().unwrap()
+}
+
+fn read_full(stream: &mut Box<Stream>, buf: &mut [u8], shared: &Shared) -> bool {
+    let mut n = 0;
+    while n < buf.len() {
+        if shared.stop.load(Ordering::SeqCst) {
+            return false;
+        }
+        match stream.read(&mut buf[n..]) {
+            Ok(0) => return false,
+            Ok(read) => n += read,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {},
+            Err(_) => return false
+        }
+    }
+    true
+}
+
+/// Answers the request, or returns `None` when no response is expected.
+fn handle(request: &[u8], shared: &Shared) -> Option<Vec<u8>> {
+    let mut r = Reader(request);
+    let api_key = r.i16();
+    let _api_version = r.i16();
+    let correlation_id = r.i32();
+    let _client_id = r.string();
+    let mut out = Vec::new();
+    put_i32(&mut out, correlation_id);
+    match api_key {
+        API_KEY_METADATA => {
+            let mut topics = (0..r.i32()).map(|_| r.string()).collect::<Vec<_>>();
+            if topics.is_empty() {
+                topics = TOPICS.iter().map(|t| t.to_string()).collect();
+            }
+            // ~ brokers
+            put_i32(&mut out, 1);
+            put_i32(&mut out, 0);
+            put_str(&mut out, &shared.advertised);
+            put_i32(&mut out, shared.port as i32);
+            // ~ topics with the partitions led by the broker
+            let partitions = shared.partitions.load(Ordering::SeqCst);
+            put_i32(&mut out, topics.len() as i32);
+            for topic in &topics {
+                put_i16(&mut out, 0);
+                put_str(&mut out, topic);
+                put_i32(&mut out, partitions as i32);
+                for partition in 0..partitions {
+                    put_i16(&mut out, 0);
+                    put_i32(&mut out, partition as i32);
+                    put_i32(&mut out, 0);
+                    put_i32(&mut out, 1);
+                    put_i32(&mut out, 0);
+                    put_i32(&mut out, 1);
+                    put_i32(&mut out, 0);
+                }
+            }
+            shared.metadata_requests.fetch_add(1, Ordering::SeqCst);
+            Some(out)
+        },
+        API_KEY_PRODUCE => {
+            let mut produce = Produce { required_acks: r.i16(), timeout: r.i32(), messages: Vec::new() };
+            let rejected = shared.rejected.lock().unwrap().clone();
+            let limit = *shared.limit.lock().unwrap();
+            let mut requests = shared.requests.lock().unwrap();
+            let mut offset = requests.iter().map(|p| p.messages.len()).sum::<usize>() as i64;
+            let mut acks = Vec::new();
+            for _ in 0..r.i32() {
+                let topic = r.string();
+                let mut partitions = Vec::new();
+                for _ in 0..r.i32() {
+                    let partition = r.i32();
+                    let size = r.i32() as usize;
+                    let set = r.take(size);
+                    let mut messages = Vec::new();
+                    read_message_set(set, &topic, partition, 0, &mut messages);
+                    let code = match rejected.get(&partition) {
+                        Some(&code) => code,
+                        None if limit.is_some_and(|limit| offset as usize + messages.len() > limit) =>
+                            NOT_ENOUGH_REPLICAS,
+                        None => 0
+                    };
+                    if code != 0 {
+                        partitions.push((partition, code, -1));
+                        continue;
+                    }
+                    partitions.push((partition, 0, offset));
+                    offset += messages.len() as i64;
+                    produce.messages.extend(messages);
+                }
+                acks.push((topic, partitions));
+            }
+            let required_acks = produce.required_acks;
+            requests.push(produce);
+            if required_acks == 0 {
+                return None;
+            }
+            put_i32(&mut out, acks.len() as i32);
+            for (topic, partitions) in acks {
+                put_str(&mut out, &topic);
+                put_i32(&mut out, partitions.len() as i32);
+                for (partition, code, offset) in partitions {
+                    put_i32(&mut out, partition);
+                    put_i16(&mut out, code);
+                    out.extend_from_slice(&offset.to_be_bytes());
+                }
+            }
+            Some(out)
+        },
+        _ => None
+    }
+}
+
+/// Reads the messages of the message set, and the ones of the compressed message sets
+/// wrapped in its messages.
+fn read_message_set(set: &[u8], topic: &str, partition: i32, codec: i8, messages: &mut Vec<Message>) {
+    let mut r = Reader(set);
+    while r.0.len() >= 12 {
+        let _offset = r.i64();
+        let size = r.i32() as usize;
+        if r.0.len() < size {
+            break;
+        }
+        let mut m = Reader(r.take(size));
+        let _crc = m.i32();
+        let magic = m.i8();
+        let attributes = m.i8();
+        if magic == 1 {
+            let _timestamp = m.i64();
+        }
+        let key = m.bytes();
+        let value = m.bytes();
+        match attributes & 0x07 {
+            0 => messages.push(Message { topic: topic.to_string(), partition: partition, codec: codec,
+                                         key: key, value: value }),
+            1 => {
+                let mut set = Vec::new();
+                GzDecoder::new(&value[..]).unwrap().read_to_end(&mut set).unwrap();
+                read_message_set(&set, topic, partition, 1, messages);
+            },
+            2 => read_message_set(&snappy(&value), topic, partition, 2, messages),
+            codec => panic!("unknown compression codec {}", codec)
+        }
+    }
+}
+
+/// Decompresses the snappy framing of the Java client, or the raw snappy block without it.
+fn snappy(value: &[u8]) -> Vec<u8> {
+    let mut decoder = snap::raw::Decoder::new();
+    if !value.starts_with(&SNAPPY_MAGIC) {
+        return decoder.decompress_vec(value).unwrap();
+    }
+    // ~ the header is followed by the version of the framing and the oldest compatible one
+    let mut r = Reader(&value[SNAPPY_MAGIC.len() + 8..]);
+    let mut set = Vec::new();
+    while !r.0.is_empty() {
+        let block = r.bytes();
+        set.extend(decoder.decompress_vec(&block).unwrap());
+    }
+    set
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+
+    fn take(&mut self, n: usize) -> &'a [u8] {
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        head
+    }
+
+    fn i8(&mut self) -> i8 {
+        self.take(1)[0] as i8
+    }
+
+    fn i16(&mut self) -> i16 {
+        let b = self.take(2);
+        i16::from_be_bytes([b[0], b[1]])
+    }
+
+    fn i32(&mut self) -> i32 {
+        let b = self.take(4);
+        i32::from_be_bytes([b[0], b[1], b[2], b[3]])
+    }
+
+    fn i64(&mut self) -> i64 {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(self.take(8));
+        i64::from_be_bytes(b)
+    }
+
+    fn string(&mut self) -> String {
+        let len = self.i16();
+        if len < 0 {
+            return String::new();
+        }
+        String::from_utf8(self.take(len as usize).to_vec()).unwrap()
+    }
+
+    fn bytes(&mut self) -> Vec<u8> {
+        let len = self.i32();
+        if len < 0 {
+            return Vec::new();
+        }
+        self.take(len as usize).to_vec()
+    }
+}
+
+fn put_i16(out: &mut Vec<u8>, v: i16) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_i32(out: &mut Vec<u8>, v: i32) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    put_i16(out, s.len() as i16);
+    out.extend_from_slice(s.as_bytes());
+}
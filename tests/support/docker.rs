@@ -0,0 +1,163 @@
+//! Docker Engine API stand-in served over a unix domain socket, which records the paths of
+//! the requests. It lists and inspects the containers added to it, and streams the container
+//! events it's asked to emit to the clients following the event stream.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use super::http::read_request;
+
+/// digest of the images, as listed in their `RepoDigests`
+pub const IMAGE_DIGEST: &'static str = "sha256:9b2c4d6e8f";
+
+/// state shared with the connection threads
+struct Shared {
+    /// inspected containers indexed by their identifiers
+    containers: Mutex<BTreeMap<String, String>>,
+    requests: Mutex<Vec<String>>,
+    /// clients following the event stream
+    followers: Mutex<Vec<Sender<String>>>,
+    stop: AtomicBool,
+    connections: Mutex<Vec<JoinHandle<()>>>
+}
+
+pub struct Engine {
+    shared: Arc<Shared>,
+    socket: String,
+    listener: Option<JoinHandle<()>>
+}
+
+impl Engine {
+
+    pub fn start(dir: &Path) -> Engine {
+        let socket = dir.join("docker.sock");
+        let _ = fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let shared = Arc::new(Shared {
+            containers: Mutex::new(BTreeMap::new()),
+            requests: Mutex::new(Vec::new()),
+            followers: Mutex::new(Vec::new()),
+            stop: AtomicBool::new(false),
+            connections: Mutex::new(Vec::new())
+        });
+        let listener_shared = shared.clone();
+        let listener = thread::spawn(move || {
+            let shared = listener_shared;
+            while !shared.stop.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let conn_shared = shared.clone();
+                        let connection = thread::spawn(move || serve(stream, &conn_shared));
+                        shared.connections.lock().unwrap().push(connection);
+                    },
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(5));
+                    },
+                    Err(_) => break
+                }
+            }
+        });
+        Engine { shared: shared, socket: socket.to_str().unwrap().to_string(), listener: Some(listener) }
+    }
+
+    pub fn socket(&self) -> &str {
+        &self.socket
+    }
+
+    /// Adds the running container, or replaces it, e.g. when it's renamed.
+    pub fn add(&self, id: &str, name: &str, image: &str, labels: &[(&str, &str)]) {
+        let labels = labels.iter().map(|&(k, v)| format!("{:?}: {:?}", k, v)).collect::<Vec<_>>().join(", ");
+        let container = format!("{{\"Id\": {:?}, \"Name\": \"/{}\", \"Image\": \"sha256:{}\", \
+                                 \"Config\": {{\"Image\": {:?}, \"Labels\": {{{}}}}}}}", id, name, id, image, labels);
+        self.shared.containers.lock().unwrap().insert(id.to_string(), container);
+    }
+
+    pub fn remove(&self, id: &str) {
+        self.shared.containers.lock().unwrap().remove(id);
+    }
+
+    /// Sends the container event to the clients following the event stream.
+    pub fn emit(&self, action: &str, id: &str) {
+        let event = format!("{{\"Type\": \"container\", \"Action\": {:?}, \"Actor\": {{\"ID\": {:?}}}}}\n", action, id);
+        self.shared.followers.lock().unwrap().retain(|follower| follower.send(event.clone()).is_ok());
+    }
+
+    /// Returns whether some client follows the event stream.
+    pub fn followed(&self) -> bool {
+        !self.shared.followers.lock().unwrap().is_empty()
+    }
+
+    /// Returns the paths of the requests received so far, in the order they were received.
+    pub fn requests(&self) -> Vec<String> {
+        self.shared.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::SeqCst);
+        if let Some(listener) = self.listener.take() {
+            let _ = listener.join();
+        }
+        let connections = ::std::mem::take(&mut *self.shared.connections.lock().unwrap());
+        for connection in connections {
+            let _ = connection.join();
+        }
+        let _ = fs::remove_file(&self.socket);
+    }
+}
+
+fn serve(mut stream: UnixStream, shared: &Shared) {
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let request = match read_request(&mut stream) {
+        Some(request) => request,
+        None => return
+    };
+    shared.requests.lock().unwrap().push(request.path.clone());
+    if request.path.starts_with("/events?") {
+        return follow(stream, shared);
+    }
+    let containers = shared.containers.lock().unwrap().clone();
+    let segments = request.path.trim_start_matches('/').split('/').collect::<Vec<_>>();
+    let body = match &segments[..] {
+        ["containers", "json"] => {
+            let ids = containers.keys().map(|id| format!("{{\"Id\": {:?}}}", id)).collect::<Vec<_>>();
+            Some(format!("[{}]", ids.join(", ")))
+        },
+        ["containers", id, "json"] => containers.get(*id).cloned(),
+        ["images", _, "json"] => Some(format!("{{\"RepoDigests\": [\"registry.local/app@{}\"]}}", IMAGE_DIGEST)),
+        _ => None
+    };
+    let (status, body) = match body {
+        Some(body) => (200, body),
+        None => (404, "{\"message\": \"No such container\"}".to_string())
+    };
+    let response = format!("HTTP/1.1 {} Status\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+                           status, body.len(), body);
+    let _ = stream.write_all(response.as_bytes());
+}
+
+/// Streams the emitted events in chunks until the engine is dropped.
+fn follow(mut stream: UnixStream, shared: &Shared) {
+    let (sender, receiver) = mpsc::channel::<String>();
+    if stream.write_all(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n").is_err() {
+        return;
+    }
+    shared.followers.lock().unwrap().push(sender);
+    while !shared.stop.load(Ordering::SeqCst) {
+        if let Ok(event) = receiver.recv_timeout(Duration::from_millis(10)) {
+            let chunk = format!("{:x}\r\n{}\r\n", event.len(), event);
+            if stream.write_all(chunk.as_bytes()).is_err() {
+                return;
+            }
+        }
+    }
+    let _ = stream.write_all(b"0\r\n\r\n");
+}
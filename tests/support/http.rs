@@ -0,0 +1,193 @@
+//! HTTP/1.1 server stand-in which records the requests and answers them with the responses
+//! of the handler. Each connection serves a single request, as the client closes it. The
+//! requests are parsed by `httparse`, so they're checked against a reference parser.
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use httparse;
+use openssl::ssl::SslAcceptor;
+
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// request headers with lowercased names
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>
+}
+
+impl Request {
+
+    /// Returns the value of the header with the given (lowercased) name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|&(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+/// Answers the request with the status code and the body.
+pub type Handler = Fn(&Request) -> (u16, Vec<u8>) + Send + Sync;
+
+struct Shared {
+    acceptor: Option<SslAcceptor>,
+    handler: Box<Handler>,
+    requests: Mutex<Vec<Request>>,
+    /// whether the response bodies are sent with the chunked transfer encoding
+    chunked: AtomicBool,
+    stop: AtomicBool,
+    connections: Mutex<Vec<JoinHandle<()>>>
+}
+
+pub struct Server {
+    shared: Arc<Shared>,
+    port: u16,
+    listener: Option<JoinHandle<()>>
+}
+
+impl Server {
+
+    pub fn start<F>(handler: F) -> Server
+            where F: Fn(&Request) -> (u16, Vec<u8>) + Send + Sync + 'static {
+        Server::bind(None, Box::new(handler))
+    }
+
+    /// Starts the server accepting the TLS connections.
+    pub fn start_tls<F>(acceptor: SslAcceptor, handler: F) -> Server
+            where F: Fn(&Request) -> (u16, Vec<u8>) + Send + Sync + 'static {
+        Server::bind(Some(acceptor), Box::new(handler))
+    }
+
+    fn bind(acceptor: Option<SslAcceptor>, handler: Box<Handler>) -> Server {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        listener.set_nonblocking(true).unwrap();
+        let shared = Arc::new(Shared {
+            acceptor: acceptor,
+            handler: handler,
+            requests: Mutex::new(Vec::new()),
+            chunked: AtomicBool::new(false),
+            stop: AtomicBool::new(false),
+            connections: Mutex::new(Vec::new())
+        });
+        let listener_shared = shared.clone();
+        let listener = thread::spawn(move || {
+            let shared = listener_shared;
+            while !shared.stop.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let conn_shared = shared.clone();
+                        let connection = thread::spawn(move || serve(stream, &conn_shared));
+                        shared.connections.lock().unwrap().push(connection);
+                    },
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(5));
+                    },
+                    Err(_) => break
+                }
+            }
+        });
+        Server { shared: shared, port: port, listener: Some(listener) }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Sends the response bodies in chunks rather than with the content length.
+    pub fn set_chunked(&self, chunked: bool) {
+        self.shared.chunked.store(chunked, Ordering::SeqCst);
+    }
+
+    /// Returns the requests received so far, in the order they were received.
+    pub fn requests(&self) -> Vec<Request> {
+        self.shared.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::SeqCst);
+        if let Some(listener) = self.listener.take() {
+            let _ = listener.join();
+        }
+        let connections = ::std::mem::take(&mut *self.shared.connections.lock().unwrap());
+        for connection in connections {
+            let _ = connection.join();
+        }
+    }
+}
+
+trait Stream: Read + Write {}
+
+impl<T: Read + Write> Stream for T {}
+
+fn serve(stream: TcpStream, shared: &Shared) {
+    stream.set_nonblocking(false).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut stream: Box<Stream> = match shared.acceptor {
+        Some(ref acceptor) => match acceptor.accept(stream) {
+            Ok(tls) => Box::new(tls),
+            Err(_) => return
+        },
+        None => Box::new(stream)
+    };
+    let request = match read_request(&mut stream) {
+        Some(request) => request,
+        None => return
+    };
+    shared.requests.lock().unwrap().push(request.clone());
+    let (status, body) = (shared.handler)(&request);
+    let mut response = format!("HTTP/1.1 {} Status\r\nConnection: close\r\n", status).into_bytes();
+    if shared.chunked.load(Ordering::SeqCst) {
+        response.extend_from_slice(b"Transfer-Encoding: chunked\r\n\r\n");
+        for chunk in body.chunks(7) {
+            response.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+            response.extend_from_slice(chunk);
+            response.extend_from_slice(b"\r\n");
+        }
+        response.extend_from_slice(b"0\r\n\r\n");
+    } else {
+        response.extend_from_slice(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes());
+        response.extend_from_slice(&body);
+    }
+    let _ = stream.write_all(&response).and_then(|_| stream.flush());
+}
+
+/// Reads the request head until `httparse` completes it, and then the body of the
+/// content length.
+pub fn read_request<S: Read>(stream: &mut S) -> Option<Request> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let n = stream.read(&mut chunk).ok()?;
+        if n == 0 {
+            return None;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        let mut headers = [httparse::EMPTY_HEADER; 32];
+        let mut parsed = httparse::Request::new(&mut headers);
+        if let httparse::Status::Complete(head_len) = parsed.parse(&buf).ok()? {
+            let headers = parsed.headers.iter()
+                .map(|h| Some((h.name.to_lowercase(), String::from_utf8(h.value.to_vec()).ok()?)))
+                .collect::<Option<Vec<_>>>()?;
+            let mut request = Request {
+                method: parsed.method?.to_string(),
+                path: parsed.path?.to_string(),
+                headers: headers,
+                body: buf[head_len..].to_vec()
+            };
+            let len = request.header("content-length").map_or(Some(0), |v| v.parse::<usize>().ok())?;
+            while request.body.len() < len {
+                let n = stream.read(&mut chunk).ok()?;
+                if n == 0 {
+                    return None;
+                }
+                request.body.extend_from_slice(&chunk[..n]);
+            }
+            request.body.truncate(len);
+            return Some(request);
+        }
+    }
+}
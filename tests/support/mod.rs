@@ -0,0 +1,94 @@
+//! Stand-ins of the services the aggregators and enrichers talk to, shared by the
+//! integration tests. Each test crate only uses some of them.
+#![allow(dead_code)]
+
+pub mod broker;
+pub mod docker;
+pub mod http;
+pub mod pki;
+pub mod procfs;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use chrono::UTC;
+use cubostratusc::state::cgroups::CGroup;
+use cubostratusc::state::thread::{ThreadInfo, ThreadState};
+use cubostratusc::syscall::{Category, Direction, SyscallInfo};
+
+static DIRS: AtomicUsize = AtomicUsize::new(0);
+
+/// Creates an empty directory under the temporary directory, unique to the test.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("cubostratusc-{}-{}-{}", name, process::id(),
+                                           DIRS.fetch_add(1, Ordering::SeqCst)));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Polls the condition until it holds or the timeout elapses.
+pub fn wait_until<F: FnMut() -> bool>(timeout: Duration, mut condition: F) -> bool {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    condition()
+}
+
+/// Builds the exit event of the syscall, generated by the thread.
+pub fn syscall_info(tid: u64, name: &str) -> SyscallInfo {
+    SyscallInfo {
+        ts: UTC::now(),
+        tid: tid,
+        cpu: 0,
+        dir: Direction::Exit,
+        comm: None,
+        pid: Some(tid),
+        vtid: None,
+        vpid: None,
+        user: None,
+        group: None,
+        ancestry: Vec::new(),
+        name: name.to_string(),
+        category: Category::File,
+        params: HashMap::new(),
+        resolved_path: None,
+        pod: None,
+        container: None
+    }
+}
+
+/// Builds the running thread, member of the cgroup.
+pub fn thread_info(tid: u64, cgroup_path: &str) -> ThreadInfo {
+    ThreadInfo {
+        comm: "test".to_string(),
+        state: ThreadState::Running,
+        pid: tid,
+        tid: tid,
+        vtid: tid,
+        uid: 0,
+        euid: 0,
+        suid: 0,
+        fsuid: 0,
+        gid: 0,
+        egid: 0,
+        sgid: 0,
+        fsgid: 0,
+        capabilities: None,
+        seccomp: None,
+        no_new_privs: false,
+        cgroups: Some(vec![CGroup { id: 1, controllers: vec!["cpu".to_string()], path: cgroup_path.to_string() }]),
+        namespaces: None,
+        user: None,
+        group: None
+    }
+}
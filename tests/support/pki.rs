@@ -0,0 +1,96 @@
+//! Issues the CA, broker and client certificates of the TLS tests.
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::ssl::{SslAcceptor, SslMethod, SslVerifyMode};
+use openssl::x509::extension::{BasicConstraints, SubjectAlternativeName};
+use openssl::x509::{X509, X509NameBuilder};
+
+pub struct Identity {
+    pub cert: X509,
+    pub key: PKey<Private>
+}
+
+impl Identity {
+
+    /// Writes the certificate and the key to `<name>.pem` and `<name>.key`.
+    pub fn write(&self, dir: &Path, name: &str) -> (PathBuf, PathBuf) {
+        let cert = dir.join(format!("{}.pem", name));
+        let key = dir.join(format!("{}.key", name));
+        File::create(&cert).unwrap().write_all(&self.cert.to_pem().unwrap()).unwrap();
+        File::create(&key).unwrap().write_all(&self.key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+        (cert, key)
+    }
+}
+
+/// Creates the self-signed CA certificate.
+pub fn ca(name: &str) -> Identity {
+    let key = key();
+    let cert = build(name, &[], &key, None, true);
+    Identity { cert: cert, key: key }
+}
+
+/// Issues the certificate for the DNS names or IP addresses, signed by the CA.
+pub fn issue(ca: &Identity, name: &str, alt_names: &[&str]) -> Identity {
+    let key = key();
+    let cert = build(name, alt_names, &key, Some(ca), false);
+    Identity { cert: cert, key: key }
+}
+
+/// Builds the TLS acceptor of the broker presenting the identity. When the client CA is
+/// given, the clients have to present the certificate signed by it.
+pub fn acceptor(identity: &Identity, client_ca: Option<&Identity>) -> SslAcceptor {
+    let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+    builder.set_private_key(&identity.key).unwrap();
+    builder.set_certificate(&identity.cert).unwrap();
+    if let Some(ca) = client_ca {
+        builder.cert_store_mut().add_cert(ca.cert.clone()).unwrap();
+        builder.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    }
+    builder.build()
+}
+
+fn key() -> PKey<Private> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+}
+
+fn build(name: &str, alt_names: &[&str], key: &PKey<Private>, issuer: Option<&Identity>, ca: bool) -> X509 {
+    let mut subject = X509NameBuilder::new().unwrap();
+    subject.append_entry_by_nid(Nid::COMMONNAME, name).unwrap();
+    let subject = subject.build();
+    let mut serial = BigNum::new().unwrap();
+    serial.rand(64, MsbOption::MAYBE_ZERO, false).unwrap();
+
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_serial_number(&serial.to_asn1_integer().unwrap()).unwrap();
+    builder.set_subject_name(&subject).unwrap();
+    builder.set_issuer_name(issuer.map_or(&subject, |i| i.cert.subject_name())).unwrap();
+    builder.set_pubkey(key).unwrap();
+    builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+    if ca {
+        builder.append_extension(BasicConstraints::new().critical().ca().build().unwrap()).unwrap();
+    }
+    if !alt_names.is_empty() {
+        let mut san = SubjectAlternativeName::new();
+        for alt_name in alt_names {
+            if alt_name.parse::<::std::net::IpAddr>().is_ok() {
+                san.ip(alt_name);
+            } else {
+                san.dns(alt_name);
+            }
+        }
+        let san = san.build(&builder.x509v3_context(issuer.map(|i| &*i.cert), None)).unwrap();
+        builder.append_extension(san).unwrap();
+    }
+    builder.sign(issuer.map_or(key, |i| &i.key), MessageDigest::sha256()).unwrap();
+    builder.build()
+}
@@ -0,0 +1,90 @@
+//! Fixture of the `/proc` file system, laid out under a temporary directory so the state
+//! tests don't depend on the processes running on the host.
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+/// boot time written to the `stat` file, in seconds since the epoch
+pub const BOOT_TIME: i64 = 1488362400;
+
+pub struct ProcFs {
+    root: PathBuf
+}
+
+/// Renders the `status` file of the thread with the minimal set of entries.
+pub fn status(pid: u64, tid: u64, ppid: u64, comm: &str) -> String {
+    format!("Name:\t{}\nState:\tS (sleeping)\nTgid:\t{}\nPid:\t{}\nPPid:\t{}\n\
+             Uid:\t1000\t1000\t1000\t1000\nGid:\t1000\t1000\t1000\t1000\nThreads:\t1\n",
+            comm, pid, tid, ppid)
+}
+
+impl ProcFs {
+
+    pub fn new(name: &str) -> ProcFs {
+        let root = super::temp_dir(name).join("proc");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("stat"), format!("cpu  1 2 3 4\nbtime {}\n", BOOT_TIME)).unwrap();
+        ProcFs { root: root }
+    }
+
+    pub fn root(&self) -> &str {
+        self.root.to_str().unwrap()
+    }
+
+    pub fn path(&self, path: &str) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// Adds the process with its main thread.
+    pub fn process(&self, pid: u64, ppid: u64, comm: &str) -> &ProcFs {
+        self.write(&format!("{}/status", pid), status(pid, pid, ppid, comm).as_bytes());
+        self.thread(pid, pid, ppid, comm)
+    }
+
+    /// Adds the thread to the `task` directory of its process. Unlike `/proc`, the fixture
+    /// can't hide the `/proc/[tid]` entries of non-leader threads from the listing, so
+    /// they're left out.
+    pub fn thread(&self, pid: u64, tid: u64, ppid: u64, comm: &str) -> &ProcFs {
+        let task = format!("{}/task/{}", pid, tid);
+        self.write(&format!("{}/status", task), status(pid, tid, ppid, comm).as_bytes());
+        self.write(&format!("{}/cgroup", task), b"0::/user.slice\n");
+        fs::create_dir_all(self.path(&format!("{}/ns", task))).unwrap();
+        self
+    }
+
+    /// Writes the `stat` file of the process, with the start time in clock ticks since boot.
+    pub fn stat(&self, pid: u64, comm: &str, ppid: u64, pgrp: u64, session: u64, start_time: u64) -> &ProcFs {
+        let stat = format!("{} ({}) S {} {} {} 0 -1 4194560 100 0 0 0 1 2 0 0 20 0 1 0 {} 8192 300 \
+                            18446744073709551615 1 1 0 0 0 0 0 0 0 0 0 0 17 0 0 0 0 0 0\n",
+                           pid, comm, ppid, pgrp, session, start_time);
+        self.write(&format!("{}/stat", pid), stat.as_bytes())
+    }
+
+    /// Writes the file, creating its parent directories.
+    pub fn write(&self, path: &str, content: &[u8]) -> &ProcFs {
+        let path = self.path(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        self
+    }
+
+    /// Creates the symbolic link, such as `exe` or `ns/net`, pointing to the target.
+    pub fn link(&self, path: &str, target: &str) -> &ProcFs {
+        let path = self.path(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let _ = fs::remove_file(&path);
+        symlink(Path::new(target), &path).unwrap();
+        self
+    }
+
+    /// Removes the file or directory, e.g. when the process exits.
+    pub fn remove(&self, path: &str) -> &ProcFs {
+        let path = self.path(path);
+        if path.is_dir() {
+            fs::remove_dir_all(&path).unwrap();
+        } else {
+            fs::remove_file(&path).unwrap();
+        }
+        self
+    }
+}
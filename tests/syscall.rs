@@ -0,0 +1,52 @@
+extern crate cubostratusc;
+extern crate serde_json;
+
+use cubostratusc::syscall::{ParamFormat, ParamType, SyscallParam};
+use serde_json::Value;
+
+fn parse(kind: ParamType, buf: &[u8]) -> Value {
+    let param = SyscallParam { name: "tuple", kind: kind, fmt: ParamFormat::Na };
+    let value = unsafe { param.parse(buf.as_ptr(), buf.len() as u16) };
+    serde_json::to_value(&value).unwrap()
+}
+
+#[test]
+fn decodes_ipv4_socket_tuple() {
+    let mut buf = vec![2u8, 10, 0, 0, 1];
+    buf.extend_from_slice(&40000u16.to_ne_bytes());
+    buf.extend_from_slice(&[192, 168, 1, 2]);
+    buf.extend_from_slice(&443u16.to_ne_bytes());
+    let tuple = parse(ParamType::SockTuple, &buf);
+    assert_eq!(tuple["family"].as_str(), Some("inet"));
+    assert_eq!(tuple["sip"].as_str(), Some("10.0.0.1"));
+    assert_eq!(tuple["sport"].as_u64(), Some(40000));
+    assert_eq!(tuple["dip"].as_str(), Some("192.168.1.2"));
+    assert_eq!(tuple["dport"].as_u64(), Some(443));
+}
+
+#[test]
+fn decodes_ipv6_socket_address() {
+    let mut buf = vec![10u8];
+    buf.extend_from_slice(&"::1".parse::<std::net::Ipv6Addr>().unwrap().octets());
+    buf.extend_from_slice(&8080u16.to_ne_bytes());
+    let addr = parse(ParamType::SockAddr, &buf);
+    assert_eq!(addr["family"].as_str(), Some("inet6"));
+    assert_eq!(addr["ip"].as_str(), Some("::1"));
+    assert_eq!(addr["port"].as_u64(), Some(8080));
+}
+
+#[test]
+fn decodes_unix_socket_tuple() {
+    let mut buf = vec![1u8];
+    buf.extend_from_slice(&[0xff; 16]);
+    buf.extend_from_slice(b"/run/docker.sock\0");
+    let tuple = parse(ParamType::SockTuple, &buf);
+    assert_eq!(tuple["family"].as_str(), Some("unix"));
+    assert_eq!(tuple["path"].as_str(), Some("/run/docker.sock"));
+}
+
+#[test]
+fn leaves_empty_and_truncated_addresses_undecoded() {
+    assert!(parse(ParamType::SockAddr, &[]).is_null());
+    assert!(parse(ParamType::SockTuple, &[2, 10, 0]).is_null());
+}
@@ -0,0 +1,217 @@
+extern crate chrono;
+extern crate cubostratusc;
+extern crate flate2;
+extern crate httparse;
+extern crate openssl;
+extern crate snap;
+
+mod support;
+
+use std::fs;
+use std::io::Read;
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+use chrono::{TimeZone, UTC};
+use cubostratusc::aggregator::Aggregator;
+use cubostratusc::aggregator::syslog::SyslogAggregator;
+use cubostratusc::config;
+use cubostratusc::serializer::Encoded;
+use cubostratusc::syscall::{Category, SyscallInfo};
+
+fn syslog_aggregator(transport: &str, address: &str) -> SyslogAggregator {
+    configured_aggregator(transport, address, "")
+}
+
+/// Starts the aggregator with the additional settings, given as the TOML lines of the section.
+fn configured_aggregator(transport: &str, address: &str, settings: &str) -> SyslogAggregator {
+    let content = format!("[syslog]\ntransport = \"{}\"\naddress = {:?}\ntimeout = 1\n{}",
+                          transport, address, settings);
+    let mut aggregator = SyslogAggregator::new(config::parse_config(&content).unwrap().syslog.unwrap());
+    aggregator.start().unwrap();
+    aggregator
+}
+
+fn receive(socket: &UnixDatagram) -> String {
+    let mut buf = [0u8; 4096];
+    let n = socket.recv(&mut buf).unwrap();
+    String::from_utf8(buf[..n].to_vec()).unwrap()
+}
+
+/// Binds the socket of the local daemon.
+fn daemon(name: &str) -> (UnixDatagram, PathBuf) {
+    let path = support::temp_dir(name).join("log");
+    let daemon = UnixDatagram::bind(&path).unwrap();
+    daemon.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    (daemon, path)
+}
+
+fn syscall_at(tid: u64, name: &str, category: Category) -> SyscallInfo {
+    let mut info = support::syscall_info(tid, name);
+    info.ts = UTC.ymd(2017, 3, 1).and_hms_nano(10, 32, 1, 123456789);
+    info.category = category;
+    info
+}
+
+/// Reads the octet counted frame.
+fn read_frame(stream: &mut TcpStream) -> String {
+    let mut len = String::new();
+    let mut byte = [0u8; 1];
+    loop {
+        stream.read_exact(&mut byte).unwrap();
+        if byte[0] == b' ' {
+            break;
+        }
+        len.push(byte[0] as char);
+    }
+    let mut message = vec![0u8; len.parse().unwrap()];
+    stream.read_exact(&mut message).unwrap();
+    String::from_utf8(message).unwrap()
+}
+
+#[test]
+fn recreates_unix_socket_after_daemon_restart() {
+    let dir = support::temp_dir("syslog-unix");
+    let path = dir.join("log");
+    let daemon = UnixDatagram::bind(&path).unwrap();
+    daemon.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut aggregator = syslog_aggregator("unix", path.to_str().unwrap());
+
+    aggregator.do_aggregate(&support::syscall_info(1, "open"), Encoded::new());
+    let message = receive(&daemon);
+    assert!(message.starts_with("<14>1 "));
+    assert!(message.contains(" open [syscall@32473 tid=\"1\""));
+
+    // ~ the restarted daemon binds a new socket at the same path
+    drop(daemon);
+    fs::remove_file(&path).unwrap();
+    aggregator.do_aggregate(&support::syscall_info(2, "open"), Encoded::new());
+    aggregator.do_aggregate(&support::syscall_info(3, "open"), Encoded::new());
+    assert!(aggregator.stats().contains(&("syslog.dropped", 2)));
+    let daemon = UnixDatagram::bind(&path).unwrap();
+    daemon.set_nonblocking(true).unwrap();
+
+    let mut buf = [0u8; 4096];
+    assert!(support::wait_until(Duration::from_secs(5), || {
+        aggregator.poll();
+        aggregator.do_aggregate(&support::syscall_info(4, "open"), Encoded::new());
+        daemon.recv(&mut buf).is_ok()
+    }));
+}
+
+#[test]
+fn reconnects_to_restarted_tcp_server() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let mut aggregator = syslog_aggregator("tcp", &address.to_string());
+
+    let (mut stream, _) = listener.accept().unwrap();
+    aggregator.do_aggregate(&support::syscall_info(1, "open"), Encoded::new());
+    assert!(read_frame(&mut stream).contains("tid=\"1\""));
+
+    drop(stream);
+    drop(listener);
+    // ~ the writes fail once the server reset the connection
+    assert!(support::wait_until(Duration::from_secs(5), || {
+        aggregator.do_aggregate(&support::syscall_info(2, "open"), Encoded::new());
+        aggregator.stats()[0].1 > 0
+    }));
+
+    let listener = TcpListener::bind(address).unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let mut stream = None;
+    assert!(support::wait_until(Duration::from_secs(5), || {
+        aggregator.poll();
+        if let Ok((accepted, _)) = listener.accept() {
+            stream = Some(accepted);
+        }
+        stream.is_some()
+    }));
+    let mut stream = stream.unwrap();
+    stream.set_nonblocking(false).unwrap();
+    aggregator.do_aggregate(&support::syscall_info(3, "open"), Encoded::new());
+    assert!(read_frame(&mut stream).contains("tid=\"3\""));
+}
+
+#[test]
+fn renders_the_rfc5424_message() {
+    let (daemon, path) = daemon("syslog-message");
+    let mut aggregator = configured_aggregator("unix", path.to_str().unwrap(), "hostname = \"node-1\"\n");
+    let mut info = syscall_at(2817, "open", Category::File);
+    info.comm = Some("cat".to_string());
+    info.resolved_path = Some("/etc/passwd".to_string());
+
+    aggregator.do_aggregate(&info, Encoded::new());
+    // ~ the time is truncated to microseconds
+    assert_eq!(receive(&daemon),
+               format!("<14>1 2017-03-01T10:32:01.123456Z node-1 cubostratusc {} open \
+                        [syscall@32473 tid=\"2817\" pid=\"2817\" comm=\"cat\" dir=\"<\" category=\"file\" \
+                        path=\"/etc/passwd\"] 10:32:01.123456789 0 cat (2817) < open ", process::id()));
+}
+
+#[test]
+fn sends_the_nilvalue_for_the_empty_header_fields() {
+    let (daemon, path) = daemon("syslog-nil");
+    let mut aggregator = configured_aggregator("unix", path.to_str().unwrap(),
+                                               "hostname = \"\"\napp_name = \"\"\n");
+
+    aggregator.do_aggregate(&syscall_at(1, "open", Category::File), Encoded::new());
+    assert!(receive(&daemon).starts_with(&format!("<14>1 2017-03-01T10:32:01.123456Z - - {} open [", process::id())));
+}
+
+#[test]
+fn escapes_the_structured_data_param_values() {
+    let (daemon, path) = daemon("syslog-escape");
+    let mut aggregator = syslog_aggregator("unix", path.to_str().unwrap());
+    let mut info = syscall_at(1, "open", Category::File);
+    info.comm = Some("a\"b".to_string());
+    info.resolved_path = Some("/tmp/x]\\y".to_string());
+
+    aggregator.do_aggregate(&info, Encoded::new());
+    let message = receive(&daemon);
+    assert!(message.contains(" comm=\"a\\\"b\" "), "{}", message);
+    assert!(message.contains(" path=\"/tmp/x\\]\\\\y\"] "), "{}", message);
+}
+
+#[test]
+fn picks_the_priority_by_the_category() {
+    let (daemon, path) = daemon("syslog-pri");
+    let mut aggregator = configured_aggregator("unix", path.to_str().unwrap(), "facility = \"local0\"\n\
+                                               [syslog.facilities]\nprocess = \"auth\"\n\
+                                               [syslog.severities]\nnet = \"notice\"\nprocess = \"warning\"\n");
+
+    for &(category, pri) in [(Category::File, 134), (Category::Net, 133), (Category::Process, 36)].iter() {
+        aggregator.do_aggregate(&syscall_at(1, "open", category), Encoded::new());
+        let message = receive(&daemon);
+        assert!(message.starts_with(&format!("<{}>1 ", pri)), "{}", message);
+    }
+}
+
+#[test]
+fn rejects_unknown_priority_names() {
+    for settings in ["facility = \"local8\"\n", "min_severity = \"verbose\"\n",
+                     "[syslog.severities]\nnetwork = \"info\"\n"].iter() {
+        let content = format!("[syslog]\naddress = \"/nonexistent\"\n{}", settings);
+        let mut aggregator = SyslogAggregator::new(config::parse_config(&content).unwrap().syslog.unwrap());
+        assert!(aggregator.start().is_err(), "{}", settings);
+    }
+}
+
+#[test]
+fn sends_only_the_events_at_least_as_severe_as_the_minimum() {
+    let (daemon, path) = daemon("syslog-min-severity");
+    let mut aggregator = configured_aggregator("unix", path.to_str().unwrap(), "min_severity = \"notice\"\n\
+                                               [syslog.severities]\nnet = \"warning\"\nprocess = \"notice\"\n");
+
+    aggregator.do_aggregate(&syscall_at(1, "open", Category::File), Encoded::new());
+    aggregator.do_aggregate(&syscall_at(2, "connect", Category::Net), Encoded::new());
+    aggregator.do_aggregate(&syscall_at(3, "execve", Category::Process), Encoded::new());
+    assert!(receive(&daemon).contains(" connect [syscall@32473 tid=\"2\""));
+    assert!(receive(&daemon).contains(" execve [syscall@32473 tid=\"3\""));
+    daemon.set_nonblocking(true).unwrap();
+    assert!(daemon.recv(&mut [0u8; 4096]).is_err());
+    // ~ the filtered events aren't accounted as dropped
+    assert!(aggregator.stats().contains(&("syslog.dropped", 0)));
+}
@@ -0,0 +1,89 @@
+extern crate chrono;
+extern crate cubostratusc;
+extern crate flate2;
+extern crate httparse;
+extern crate openssl;
+extern crate snap;
+
+mod support;
+
+use std::fs::{self, File};
+use cubostratusc::config::TableConfig;
+use cubostratusc::state::thread::ThreadRegistry;
+use cubostratusc::state::users::{Account, UserResolver};
+use support::procfs::ProcFs;
+
+const HOST_MNT: u64 = 4026531840;
+
+/// Lays out the host with its databases, and the container processes 10 and 11,
+/// each in its own mount namespace with its own databases.
+fn fixture(name: &str) -> (ProcFs, String) {
+    let procfs = ProcFs::new(name);
+    let etc_root = procfs.path("../etc").to_str().unwrap().to_string();
+    fs::create_dir_all(&etc_root).unwrap();
+    fs::write(format!("{}/passwd", etc_root), "root:x:0:0::/root:/bin/sh\nalice:x:1000:1000::/home/alice:/bin/sh\n").unwrap();
+    fs::write(format!("{}/group", etc_root), "root:x:0:\nstaff:x:1000:\n").unwrap();
+    procfs.link("1/ns/mnt", &format!("mnt:[{}]", HOST_MNT))
+        .write("10/root/etc/passwd", b"root:x:0:0::/root:/bin/sh\nnginx:x:1000:1000::/var/cache/nginx:/sbin/nologin\n")
+        .write("10/root/etc/group", b"nginx:x:1000:\n")
+        // ~ minimal images may lack the group database
+        .write("11/root/etc/passwd", b"app:x:1000:1000::/app:/bin/sh\n");
+    (procfs, etc_root)
+}
+
+fn names(resolved: (Account, Account)) -> (Option<String>, Option<String>) {
+    (resolved.0.name, resolved.1.name)
+}
+
+fn some(user: &str, group: &str) -> (Option<String>, Option<String>) {
+    (Some(user.to_string()), Some(group.to_string()))
+}
+
+#[test]
+fn resolves_the_ids_through_the_root_of_the_container() {
+    let (procfs, etc_root) = fixture("users-root");
+    let mut resolver = UserResolver::new(procfs.root(), &etc_root, &TableConfig::default());
+
+    assert_eq!(names(resolver.resolve(10, Some(4026532500), 1000, 1000)), some("nginx", "nginx"));
+    assert_eq!(names(resolver.resolve(11, Some(4026532600), 1000, 1000)), (Some("app".to_string()), None));
+    assert_eq!(names(resolver.resolve(1, Some(HOST_MNT), 1000, 1000)), some("alice", "staff"));
+    // ~ the mount namespace is unknown
+    assert_eq!(names(resolver.resolve(10, None, 0, 1000)), some("root", "staff"));
+    // ~ the process exited before its databases were read
+    assert_eq!(names(resolver.resolve(12, Some(4026532700), 1000, 1000)), (None, None));
+    assert_eq!(resolver.resolve(12, Some(4026532700), 1000, 1000).0.id, 1000);
+}
+
+#[test]
+fn resolves_the_accounts_of_the_collected_threads() {
+    let (procfs, etc_root) = fixture("users-registry");
+    procfs.process(1, 0, "systemd").process(10, 1, "nginx")
+        .link("1/task/1/ns/mnt", &format!("mnt:[{}]", HOST_MNT))
+        .link("10/task/10/ns/mnt", "mnt:[4026532500]");
+    let mut registry = ThreadRegistry::with_roots(procfs.root(), &etc_root);
+    registry.collect();
+
+    let user = |tid: u64| registry.threads.get(&tid).and_then(|ti| ti.user.as_ref()).and_then(|u| u.name.clone());
+    assert_eq!(user(10).as_deref(), Some("nginx"));
+    assert_eq!(user(1).as_deref(), Some("alice"));
+}
+
+#[test]
+fn evicts_the_least_recently_used_namespaces() {
+    let (procfs, etc_root) = fixture("users-evict");
+    let processes = TableConfig { capacity: 1, ..TableConfig::default() };
+    let mut resolver = UserResolver::new(procfs.root(), &etc_root, &processes);
+    assert_eq!(names(resolver.resolve(10, Some(4026532500), 1000, 1000)), some("nginx", "nginx"));
+
+    // ~ the cached databases are used while their modification times are unchanged
+    let passwd = procfs.path("10/root/etc/passwd");
+    let mtime = fs::metadata(&passwd).unwrap().modified().unwrap();
+    fs::write(&passwd, "www:x:1000:1000::/:/bin/sh\n").unwrap();
+    File::options().write(true).open(&passwd).unwrap().set_modified(mtime).unwrap();
+    assert_eq!(names(resolver.resolve(10, Some(4026532500), 1000, 1000)).0.as_deref(), Some("nginx"));
+
+    // ~ the namespace of the other container takes the only slot
+    assert_eq!(names(resolver.resolve(11, Some(4026532600), 1000, 1000)).0.as_deref(), Some("app"));
+    assert_eq!(names(resolver.resolve(10, Some(4026532500), 1000, 1000)).0.as_deref(), Some("www"));
+}
+